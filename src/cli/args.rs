@@ -1,3 +1,5 @@
+use crate::transpiler::composition::DEFAULT_MAX_NESTING;
+use crate::transpiler::verification::DEFAULT_MAX_DEPTH;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -24,7 +26,7 @@ pub enum Commands {
         #[arg(value_name = "FILE")]
         input: PathBuf,
 
-        /// Output AST as JSON for debugging
+        /// Print the AST as a versioned JSON document instead of the summary
         #[arg(long)]
         output_ast: bool,
 
@@ -50,6 +52,14 @@ pub enum Commands {
         /// Generate additional validation code
         #[arg(long)]
         include_validators: bool,
+
+        /// Generate code for this role's projection of the protocol only
+        #[arg(short, long)]
+        role: Option<String>,
+
+        /// Also generate a typestate per role so illegal message orders fail to compile
+        #[arg(long)]
+        typestate: bool,
     },
 
     /// Validate a BMPP protocol file
@@ -67,6 +77,55 @@ pub enum Commands {
         flow_check: bool,
     },
 
+    /// Explore every enactment of a BMPP protocol for safety, liveness and non-determinism
+    Verify {
+        /// Input BMPP file to verify
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Number of message sends and receptions explored in each history
+        #[arg(long, default_value_t = DEFAULT_MAX_DEPTH)]
+        max_depth: usize,
+    },
+
+    /// Inline every enactment of a protocol and print it as a single BMPP protocol
+    Flatten {
+        /// Input BMPP file holding the protocol and the ones it enacts
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Protocol to flatten (defaults to the first protocol of the file)
+        #[arg(short, long)]
+        protocol: Option<String>,
+
+        /// Output file for the flattened protocol (optional, defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Deepest chain of nested enactments to inline
+        #[arg(long, default_value_t = DEFAULT_MAX_NESTING)]
+        max_nesting: usize,
+    },
+
+    /// Project a BMPP protocol onto one role and print its local view
+    Project {
+        /// Input BMPP file holding the protocol and the ones it enacts
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Role whose local view to print
+        #[arg(short, long)]
+        role: String,
+
+        /// Protocol to project (defaults to the first protocol of the file)
+        #[arg(short, long)]
+        protocol: Option<String>,
+
+        /// Output file for the projection (optional, defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
     /// Format a BMPP protocol file
     Format {
         /// Input BMPP file to format
@@ -80,6 +139,10 @@ pub enum Commands {
         /// Output formatted code to stdout
         #[arg(long)]
         stdout: bool,
+
+        /// Exit with an error if the file is not formatted, without changing it
+        #[arg(long, conflicts_with_all = ["in_place", "stdout"])]
+        check: bool,
     },
 
     /// Initialize a new BMPP protocol template
@@ -1,11 +1,19 @@
 use crate::cli::args::{Cli, Commands};
 use crate::config::Config;
 use crate::protocol::ast::AstNodeType;
+use crate::protocol::model::Program;
 use crate::runtime::client::LlmClient;
 use crate::runtime::llm_provider::LlmProvider;
-use crate::transpiler::validation::{validate_parameter_flow, validate_protocol_composition};
-use crate::transpiler::{codegen::BmppCodeGenerator, parser};
-use anyhow::{anyhow, Result};
+use crate::transpiler::composition::ProtocolRegistry;
+use crate::transpiler::imports::{ImportResolver, SourceMap};
+use crate::transpiler::lints::LintConfig;
+use crate::transpiler::projection::project;
+use crate::transpiler::validation::{
+    ValidationReport, validate, validate_structure, validate_with,
+};
+use crate::transpiler::verification::verify_program;
+use crate::transpiler::{codegen::BmppCodeGenerator, format_program, format_source, parser};
+use anyhow::{Result, anyhow};
 use clap::Parser;
 use std::fs;
 use std::path::Path;
@@ -28,11 +36,15 @@ pub fn run() -> Result<()> {
             output_dir,
             target,
             include_validators,
+            role,
+            typestate,
         } => transpile_command(
             &input,
             &output_dir,
             &target,
             include_validators,
+            role.as_deref(),
+            typestate,
             cli.verbose,
         ),
         Commands::Validate {
@@ -40,11 +52,37 @@ pub fn run() -> Result<()> {
             semantic_check,
             flow_check,
         } => validate_command(&input, semantic_check, flow_check, cli.verbose),
+        Commands::Verify { input, max_depth } => verify_command(&input, max_depth, cli.verbose),
+        Commands::Flatten {
+            input,
+            protocol,
+            output,
+            max_nesting,
+        } => flatten_command(
+            &input,
+            protocol.as_deref(),
+            output.as_deref(),
+            max_nesting,
+            cli.verbose,
+        ),
+        Commands::Project {
+            input,
+            role,
+            protocol,
+            output,
+        } => project_command(
+            &input,
+            &role,
+            protocol.as_deref(),
+            output.as_deref(),
+            cli.verbose,
+        ),
         Commands::Format {
             input,
             in_place,
             stdout,
-        } => format_command(&input, in_place, stdout, cli.verbose),
+            check,
+        } => format_command(&input, in_place, stdout, check, cli.verbose),
         Commands::Init {
             name,
             output,
@@ -73,76 +111,37 @@ pub fn run() -> Result<()> {
 }
 
 fn parse_command(input: &Path, output_ast: bool, validate: bool, verbose: bool) -> Result<()> {
-    if verbose {
+    if verbose && !output_ast {
         println!("🔍 Parsing BMPP protocol file: {}", input.display());
     }
 
-    let source =
-        fs::read_to_string(input).map_err(|e| anyhow!("Failed to read input file: {}", e))?;
+    let (ast, sources) = load_reporting_all(input)?;
+    let program = Program::from_ast(&ast)?;
 
-    let ast = parser::parse_source(&source)?;
-
-    if ast.node_type != AstNodeType::Program {
-        return Err(anyhow!("Invalid AST root node type"));
+    // The JSON document is the only thing written to stdout so it can be piped to other tools
+    if output_ast {
+        if validate {
+            validate_protocol_semantics(&ast)?;
+            validate_reporting_all(&ast, &sources, input)?;
+        }
+        println!("{}", ast.to_json()?);
+        return Ok(());
     }
 
     println!("✅ Successfully parsed BMPP protocol");
 
-    // Extract protocol information from new AST structure
-    if let Some(protocol_node) = ast.children.first() {
-        if protocol_node.node_type == AstNodeType::Protocol {
-            // Extract protocol name from ProtocolName child
-            let mut protocol_name = "Unknown".to_string();
-            let mut protocol_description = "No description".to_string();
-
-            for child in &protocol_node.children {
-                match child.node_type {
-                    AstNodeType::ProtocolName => {
-                        if let Some(name) = child.get_string("name") {
-                            protocol_name = name.clone();
-                        }
-                    }
-                    AstNodeType::Annotation => {
-                        if let Some(desc) = child.get_string("description") {
-                            protocol_description = desc.clone();
-                        }
-                    }
-                    _ => {}
-                }
-            }
-
-            println!("📋 Protocol: {}", protocol_name);
-            println!("📝 Description: {}", protocol_description);
-
-            // Count sections in new structure
-            let mut roles_count = 0;
-            let mut params_count = 0;
-            let mut interactions_count = 0;
-
-            for child in &protocol_node.children {
-                match child.node_type {
-                    AstNodeType::RolesSection => roles_count = child.children.len(),
-                    AstNodeType::ParametersSection => params_count = child.children.len(),
-                    AstNodeType::InteractionSection => interactions_count = child.children.len(),
-                    _ => {}
-                }
-            }
-
-            println!("👥 Roles: {}", roles_count);
-            println!("📊 Parameters: {}", params_count);
-            println!("🔄 Interactions: {}", interactions_count);
-        }
-    }
-
-    if output_ast {
-        println!("\n--- AST Debug Output --- \n {}", &ast);
+    if let Some(protocol) = program.protocols.first() {
+        println!("📋 Protocol: {}", protocol.name);
+        println!("📝 Description: {}", protocol.description);
+        println!("👥 Roles: {}", protocol.roles.len());
+        println!("📊 Parameters: {}", protocol.parameters.len());
+        println!("🔄 Interactions: {}", protocol.interactions.len());
     }
 
     if validate {
         println!("🔍 Validating protocol semantics...");
         validate_protocol_semantics(&ast)?;
-        validate_parameter_flow(&ast)?;
-        validate_protocol_composition(&ast)?;
+        validate_reporting_all(&ast, &sources, input)?;
         println!("✅ Protocol validation passed");
     }
 
@@ -154,6 +153,8 @@ fn transpile_command(
     output_dir: &Path,
     target: &str,
     include_validators: bool,
+    role: Option<&str>,
+    typestate: bool,
     verbose: bool,
 ) -> Result<()> {
     if verbose {
@@ -165,20 +166,37 @@ fn transpile_command(
         println!("🎯 Target: {}", target);
     }
 
-    let source = fs::read_to_string(input)?;
-    let ast = parser::parse_source(&source)?;
+    let (ast, sources) = load_reporting_all(input)?;
 
     // Validate before transpiling
-    validate_parameter_flow(&ast)?;
-    validate_protocol_composition(&ast)?;
+    validate_reporting_all(&ast, &sources, input)?;
 
     // Create output directory
     fs::create_dir_all(output_dir)?;
 
-    // Generate code based on target
-    let generator = BmppCodeGenerator::new();
+    // Generate code based on target, for a single role's projection if one is given
+    let program = match role {
+        Some(role) => {
+            let name = protocol_name(&Program::from_ast(&ast)?, None, input)?;
+            let registry = ProtocolRegistry::from_program(&ast)?;
+            let local = project(&registry, &name, role).map_err(|e| report(e, &sources, input))?;
+            if verbose {
+                println!("👤 Projected '{}' onto role '{}'", name, role);
+            }
+            Program {
+                imports: Vec::new(),
+                protocols: vec![local],
+                span: ast.span,
+            }
+        }
+        None => Program::from_ast(&ast)?,
+    };
+    let mut generator = BmppCodeGenerator::new().with_typestate(typestate);
+    if let Some(role) = role {
+        generator = generator.with_role(role);
+    }
     let generated_code = match target {
-        "rust" => generator.generate(&ast)?,
+        "rust" => generator.generate_program(&program)?,
         _ => return Err(anyhow!("Unsupported target language: {}", target)),
     };
 
@@ -209,8 +227,7 @@ fn validate_command(
         println!("🔍 Validating BMPP file: {}", input.display());
     }
 
-    let source = fs::read_to_string(input)?;
-    let ast = parser::parse_source(&source)?;
+    let (ast, sources) = load_reporting_all(input)?;
 
     println!("🔍 Running validation checks...");
 
@@ -223,8 +240,7 @@ fn validate_command(
     }
 
     if flow_check {
-        validate_parameter_flow(&ast)?;
-        validate_protocol_composition(&ast)?;
+        validate_reporting_all(&ast, &sources, input)?;
         println!("✅ Parameter flow validation passed");
         println!("✅ Protocol composition validation passed");
     }
@@ -234,24 +250,197 @@ fn validate_command(
     Ok(())
 }
 
-fn format_command(input: &Path, in_place: bool, stdout: bool, verbose: bool) -> Result<()> {
+fn verify_command(input: &Path, max_depth: usize, verbose: bool) -> Result<()> {
     if verbose {
-        println!("🎨 Formatting BMPP file: {}", input.display());
+        println!("🔎 Verifying BMPP file: {}", input.display());
+        println!("📏 Max depth: {}", max_depth);
+    }
+
+    let (ast, sources) = load_reporting_all(input)?;
+    let lints = LintConfig::for_input(input)?;
+
+    // Histories of an ill-formed program say nothing about the protocol meant
+    let program = Program::from_ast(&ast)?;
+    let structure = validate_structure(&program);
+    for diagnostic in structure.diagnostics() {
+        eprint!("{}", sources.render_diagnostic(diagnostic));
+    }
+    match structure.errors().count() {
+        0 => {}
+        1 => {
+            return Err(anyhow!(
+                "could not verify '{}' due to the previous error",
+                input.display()
+            ));
+        }
+        errors => {
+            return Err(anyhow!(
+                "could not verify '{}' due to {} previous errors",
+                input.display(),
+                errors
+            ));
+        }
     }
 
-    let source = fs::read_to_string(input)?;
-    let ast = parser::parse_source(&source)?;
+    let mut report = ValidationReport::new();
+    for verification in verify_program(&program, max_depth) {
+        println!(
+            "🔎 Protocol '{}': {} states explored",
+            verification.protocol, verification.states
+        );
+        if !verification.exhaustive {
+            println!(
+                "⚠️  Some histories are longer than {} steps and were not explored in full",
+                max_depth
+            );
+        }
+        for diagnostic in verification.report().into_diagnostics() {
+            report.push(diagnostic);
+        }
+    }
+
+    let report = lints.apply(&ast, report);
+    for diagnostic in report.diagnostics() {
+        eprint!("{}", sources.render_diagnostic(diagnostic));
+    }
+    match report.errors().count() {
+        0 => {
+            println!("🎉 No safety or liveness violations found!");
+            Ok(())
+        }
+        errors => Err(anyhow!(
+            "verification of '{}' found {} violation(s)",
+            input.display(),
+            errors
+        )),
+    }
+}
 
-    // Generate formatted output
-    let formatted = format_ast(&ast)?;
+fn flatten_command(
+    input: &Path,
+    protocol: Option<&str>,
+    output: Option<&Path>,
+    max_nesting: usize,
+    verbose: bool,
+) -> Result<()> {
+    if verbose {
+        eprintln!("🧩 Flattening BMPP file: {}", input.display());
+    }
+
+    let (ast, sources) = load_reporting_all(input)?;
+    let program = Program::from_ast(&ast)?;
+    let name = protocol_name(&program, protocol, input)?;
+
+    let registry = ProtocolRegistry::from_program(&ast)?.with_max_nesting(max_nesting);
+    let flat = registry
+        .flatten(&name)
+        .map_err(|e| report(e, &sources, input))?;
+    let flattened = format_program(&Program {
+        imports: Vec::new(),
+        protocols: vec![flat],
+        span: program.span,
+    });
+
+    match output {
+        Some(path) => {
+            fs::write(path, flattened)?;
+            println!("✅ Flattened '{}' written to: {}", name, path.display());
+        }
+        None => print!("{}", flattened),
+    }
+
+    Ok(())
+}
+
+fn project_command(
+    input: &Path,
+    role: &str,
+    protocol: Option<&str>,
+    output: Option<&Path>,
+    verbose: bool,
+) -> Result<()> {
+    if verbose {
+        eprintln!(
+            "👤 Projecting BMPP file: {} onto role {}",
+            input.display(),
+            role
+        );
+    }
 
-    if stdout {
-        println!("{}", formatted);
-    } else if in_place {
-        fs::write(input, formatted)?;
+    let (ast, sources) = load_reporting_all(input)?;
+    let program = Program::from_ast(&ast)?;
+    let name = protocol_name(&program, protocol, input)?;
+
+    let registry = ProtocolRegistry::from_program(&ast)?;
+    let local = project(&registry, &name, role).map_err(|e| report(e, &sources, input))?;
+    let projected = format_program(&Program {
+        imports: Vec::new(),
+        protocols: vec![local],
+        span: program.span,
+    });
+
+    match output {
+        Some(path) => {
+            fs::write(path, projected)?;
+            println!(
+                "✅ Projection of '{}' onto '{}' written to: {}",
+                name,
+                role,
+                path.display()
+            );
+        }
+        None => print!("{}", projected),
+    }
+
+    Ok(())
+}
+
+/// Protocol a command works on: the one named, or the first protocol of the file
+fn protocol_name(program: &Program, protocol: Option<&str>, input: &Path) -> Result<String> {
+    match protocol {
+        Some(name) => Ok(name.to_string()),
+        None => program
+            .protocols
+            .first()
+            .map(|protocol| protocol.name.value.clone())
+            .ok_or_else(|| anyhow!("No protocols found in '{}'", input.display())),
+    }
+}
+
+fn format_command(
+    input: &Path,
+    in_place: bool,
+    stdout: bool,
+    check: bool,
+    verbose: bool,
+) -> Result<()> {
+    if verbose {
+        println!("🎨 Formatting BMPP file: {}", input.display());
+    }
+
+    let source = fs::read_to_string(input)?;
+    // Report every syntax error before formatting
+    parse_reporting_all(&source, input)?;
+    let formatted = format_source(&source)?;
+
+    if check {
+        if formatted != source {
+            return Err(anyhow!(
+                "{} is not formatted; run `bmpp format --in-place {}`",
+                input.display(),
+                input.display()
+            ));
+        }
+        if verbose {
+            println!("✅ Already formatted: {}", input.display());
+        }
+    } else if in_place && !stdout {
+        if formatted != source {
+            fs::write(input, formatted)?;
+        }
         println!("✅ Formatted file in place: {}", input.display());
     } else {
-        println!("{}", formatted);
+        print!("{}", formatted);
     }
 
     Ok(())
@@ -388,17 +577,16 @@ fn to_protocol_command(
 
         // Validate the generated protocol if not skipped
         if !skip_validation {
-            match parser::parse_source(&generated_protocol) {
-                Ok(ast) => {
+            let outcome = parser::parse_with_recovery(&generated_protocol);
+            match outcome.errors.as_slice() {
+                [] => {
+                    let ast = &outcome.ast;
                     if ast.node_type == AstNodeType::Program && !ast.children.is_empty() {
                         // Run comprehensive validation
-                        if let Err(e) = validate_parameter_flow(&ast) {
-                            if verbose {
-                                println!("⚠️  Parameter flow validation failed: {}", e);
-                            }
-                        } else if let Err(e) = validate_protocol_composition(&ast) {
+                        let report = validate(ast)?;
+                        if let Some(e) = report.errors().next() {
                             if verbose {
-                                println!("⚠️  Protocol composition validation failed: {}", e);
+                                println!("⚠️  Protocol validation failed: {}", e);
                             }
                         } else {
                             println!("✅ Generated protocol passed all validations!");
@@ -410,9 +598,11 @@ fn to_protocol_command(
                         }
                     }
                 }
-                Err(e) => {
+                errors => {
                     if verbose {
-                        println!("⚠️  Syntax validation failed: {}", e);
+                        for e in errors {
+                            println!("⚠️  Syntax validation failed: {}", e);
+                        }
                     }
                 }
             }
@@ -422,10 +612,11 @@ fn to_protocol_command(
         }
 
         attempt += 1;
-        if attempt > max_attempts {
-            if !skip_validation {
-                return Err(anyhow!("Failed to generate valid BMPP protocol after {} attempts. Try increasing --max-attempts or use --skip-validation", max_attempts));
-            }
+        if attempt > max_attempts && !skip_validation {
+            return Err(anyhow!(
+                "Failed to generate valid BMPP protocol after {} attempts. Try increasing --max-attempts or use --skip-validation",
+                max_attempts
+            ));
         }
     }
 
@@ -439,58 +630,24 @@ fn to_protocol_command(
     }
 
     // Provide additional information if verbose
-    if verbose {
-        if let Ok(ast) = parser::parse_source(&generated_protocol) {
-            display_protocol_summary(&ast);
-        }
+    if verbose
+        && let Ok(ast) = parser::parse_source(&generated_protocol)
+        && let Ok(program) = Program::from_ast(&ast)
+    {
+        display_protocol_summary(&program);
     }
 
     Ok(())
 }
 
 // Helper function to display protocol summary
-fn display_protocol_summary(ast: &crate::protocol::ast::AstNode) {
-    if let Some(protocol_node) = ast.children.first() {
-        if protocol_node.node_type == AstNodeType::Protocol {
-            let mut protocol_name = "Unknown".to_string();
-            let mut protocol_description = "No description".to_string();
-
-            for child in &protocol_node.children {
-                match child.node_type {
-                    AstNodeType::ProtocolName => {
-                        if let Some(name) = child.get_string("name") {
-                            protocol_name = name.clone();
-                        }
-                    }
-                    AstNodeType::Annotation => {
-                        if let Some(desc) = child.get_string("description") {
-                            protocol_description = desc.clone();
-                        }
-                    }
-                    _ => {}
-                }
-            }
-
-            println!("\n📋 Protocol Name: {}", protocol_name);
-            println!("📝 Description: {}", protocol_description);
-
-            let mut roles_count = 0;
-            let mut params_count = 0;
-            let mut interactions_count = 0;
-
-            for child in &protocol_node.children {
-                match child.node_type {
-                    AstNodeType::RolesSection => roles_count = child.children.len(),
-                    AstNodeType::ParametersSection => params_count = child.children.len(),
-                    AstNodeType::InteractionSection => interactions_count = child.children.len(),
-                    _ => {}
-                }
-            }
-
-            println!("👥 Roles: {}", roles_count);
-            println!("📊 Parameters: {}", params_count);
-            println!("🔄 Interactions: {}", interactions_count);
-        }
+fn display_protocol_summary(program: &Program) {
+    if let Some(protocol) = program.protocols.first() {
+        println!("\n📋 Protocol Name: {}", protocol.name);
+        println!("📝 Description: {}", protocol.description);
+        println!("👥 Roles: {}", protocol.roles.len());
+        println!("📊 Parameters: {}", protocol.parameters.len());
+        println!("🔄 Interactions: {}", protocol.interactions.len());
     }
 }
 
@@ -499,9 +656,13 @@ fn display_protocol_summary(ast: &crate::protocol::ast::AstNode) {
 fn create_from_protocol_prompt(protocol_content: &str, style: &str) -> String {
     let style_instruction = match style {
         "summary" => "Provide a brief, high-level summary of what this protocol does.",
-        "detailed" => "Provide a comprehensive explanation of this protocol including its purpose, participants, data flow, and interactions.",
-        "technical" => "Provide a technical analysis of this protocol including implementation details and architectural considerations.",
-        _ => "Explain this protocol in clear, accessible language."
+        "detailed" => {
+            "Provide a comprehensive explanation of this protocol including its purpose, participants, data flow, and interactions."
+        }
+        "technical" => {
+            "Provide a technical analysis of this protocol including implementation details and architectural considerations."
+        }
+        _ => "Explain this protocol in clear, accessible language.",
     };
 
     format!(
@@ -549,6 +710,7 @@ ProtocolName <Protocol>("description of the protocol") {{
         AnotherRole <Agent>("description of this role")
 
     parameters
+        id_param <Type> key ("identifier of one enactment of this protocol"),
         param_name <Type>("semantic meaning of this parameter"),
         another_param <Type>("semantic meaning of this parameter")
 
@@ -559,8 +721,9 @@ ProtocolName <Protocol>("description of the protocol") {{
 
 Key rules:
 - All descriptions must be enclosed in parentheses: ("description")
-- Available types: String, Int, Float, Bool
-- Parameter flows use 'in' for inputs and 'out' for outputs
+- Available types: String, Int, Float, Bool, Enum[a, b, c], List<Type>, and record types declared in an optional 'types' section before 'parameters'
+- Parameter flows use 'in' for inputs and 'out' for outputs; 'nil' marks a parameter that must not be known yet and 'opt' one that may be known
+- Mark the parameter(s) identifying an enactment with 'key' after the type
 - Each interaction must specify parameter directions
 - Role names, parameter names, and action names should be descriptive identifiers
 - Protocol composition uses <Enactment> syntax for calling other protocols
@@ -585,49 +748,94 @@ Respond with ONLY the BMPP protocol syntax, no additional explanation.
 }
 
 // Helper functions
+
+/// Print a snippet-style rendering of `error` to stderr and return a short summary error
+fn report(error: anyhow::Error, sources: &SourceMap, input: &Path) -> anyhow::Error {
+    eprint!("{}", sources.render(&error));
+    anyhow!(
+        "could not process '{}' due to the previous error",
+        input.display()
+    )
+}
+
+/// Run every validation check, printing all errors and warnings before failing on errors
+fn validate_reporting_all(
+    ast: &crate::protocol::ast::AstNode,
+    sources: &SourceMap,
+    input: &Path,
+) -> Result<()> {
+    let lints = LintConfig::for_input(input)?;
+    let report = validate_with(ast, &lints)?;
+    for diagnostic in report.diagnostics() {
+        eprint!("{}", sources.render_diagnostic(diagnostic));
+    }
+
+    match report.errors().count() {
+        0 => Ok(()),
+        1 => Err(anyhow!(
+            "could not process '{}' due to the previous error",
+            input.display()
+        )),
+        errors => Err(anyhow!(
+            "could not process '{}' due to {} previous errors",
+            input.display(),
+            errors
+        )),
+    }
+}
+
+/// Parse `source` in recovery mode, printing every syntax error before failing
+fn parse_reporting_all(source: &str, input: &Path) -> Result<crate::protocol::ast::AstNode> {
+    let outcome = parser::parse_with_recovery(source);
+    if !outcome.has_errors() {
+        return Ok(outcome.ast);
+    }
+
+    let origin = input.display().to_string();
+    for error in &outcome.errors {
+        eprintln!("{}", error.render(source, &origin));
+    }
+    Err(anyhow!(
+        "could not parse '{}' due to {} syntax error(s)",
+        input.display(),
+        outcome.errors.len()
+    ))
+}
+
+/// Parse `input` in recovery mode and resolve its imports from the importing file's
+/// directory and `BMPP_PATH`, printing every error before failing
+fn load_reporting_all(input: &Path) -> Result<(crate::protocol::ast::AstNode, SourceMap)> {
+    let source =
+        fs::read_to_string(input).map_err(|e| anyhow!("Failed to read input file: {}", e))?;
+    let ast = parse_reporting_all(&source, input)?;
+
+    let mut sources = SourceMap::new();
+    sources.add(input.to_path_buf(), source);
+    let ast = ImportResolver::from_env()
+        .resolve(ast, input, &mut sources)
+        .map_err(|e| report(e, &sources, input))?;
+    Ok((ast, sources))
+}
+
 fn validate_protocol_semantics(ast: &crate::protocol::ast::AstNode) -> Result<()> {
-    if ast.children.is_empty() {
+    // The typed model rejects protocols missing a required section
+    let program = Program::from_ast(ast)?;
+
+    if program.protocols.is_empty() {
         return Err(anyhow!(
             "Protocol must contain at least one protocol definition"
         ));
     }
 
-    for protocol_node in &ast.children {
-        if protocol_node.node_type == AstNodeType::Protocol {
-            // Validate that protocol has required sections
-            let mut has_roles = false;
-            let mut has_parameters = false;
-            let mut has_interactions = false;
-
-            for child in &protocol_node.children {
-                match child.node_type {
-                    AstNodeType::RolesSection => has_roles = true,
-                    AstNodeType::ParametersSection => has_parameters = true,
-                    AstNodeType::InteractionSection => has_interactions = true,
-                    _ => {}
-                }
-            }
-
-            if !has_roles {
-                return Err(anyhow!("Protocol must have a roles section"));
-            }
-            if !has_parameters {
-                return Err(anyhow!("Protocol must have a parameters section"));
-            }
-            if !has_interactions {
-                return Err(anyhow!("Protocol must have interactions"));
-            }
+    for protocol in &program.protocols {
+        if protocol.interactions.is_empty() {
+            return Err(anyhow!("Protocol must have interactions"));
         }
     }
 
     Ok(())
 }
 
-fn format_ast(_ast: &crate::protocol::ast::AstNode) -> Result<String> {
-    // TODO: Implement proper formatting based on new AST structure
-    Ok("// Formatted BMPP protocol\n// (Formatting not yet implemented)\n".to_string())
-}
-
 fn generate_validators(
     output_dir: &Path,
     _ast: &crate::protocol::ast::AstNode,
@@ -674,22 +882,11 @@ impl ProtocolValidator {
 }
 
 fn generate_cargo_toml(output_dir: &Path, ast: &crate::protocol::ast::AstNode) -> Result<()> {
-    let default = &"generated_protocol".to_string();
-    let mut protocol_name = default.clone();
-
-    // Extract protocol name from new AST structure
-    if let Some(protocol_node) = ast.children.first() {
-        if protocol_node.node_type == AstNodeType::Protocol {
-            for child in &protocol_node.children {
-                if child.node_type == AstNodeType::ProtocolName {
-                    if let Some(name) = child.get_string("name") {
-                        protocol_name = name.clone();
-                        break;
-                    }
-                }
-            }
-        }
-    }
+    let protocol_name = Program::from_ast(ast)?
+        .protocols
+        .first()
+        .map(|protocol| protocol.name.value.clone())
+        .unwrap_or_else(|| "generated_protocol".to_string());
 
     let cargo_toml = format!(
         r#"[package]
@@ -742,12 +939,13 @@ fn generate_multi_party_template(name: &str) -> String {
         request_id <String>("unique identifier for the request"),
         data <String>("the data being processed"),
         status <String>("current status of the operation"),
-        result <Bool>("final result of the operation")
+        result <Bool>("final result of the operation"),
+        outcome <String>("final status reported to the initiator")
     
     Initiator -> Coordinator: initiate <Action>("start the protocol")[out request_id, out data]
     Coordinator -> Participant: delegate <Action>("delegate task to participant")[in request_id, in data, out status]
     Participant -> Coordinator: complete <Action>("report task completion")[in request_id, in data, out result]
-    Coordinator -> Initiator: finalize <Action>("provide final result")[in request_id, in result, out status]
+    Coordinator -> Initiator: finalize <Action>("provide final result")[in request_id, in result, out outcome]
 }}"#,
         name
     )
@@ -1,7 +1,10 @@
-use std::collections::HashMap;
+pub use super::span::{LineIndex, Span};
+use super::trivia::Trivia;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AstNodeType {
     Program,
     Protocol,
@@ -21,15 +24,98 @@ pub enum AstNodeType {
     ParameterFlow,
     RoleRef,
     ActionName,
+    TypesSection,
+    TypeDecl,
+    EnumType,
+    ListType,
+    TypeRef,
+    ImportDecl,
+    ImportPath,
+    BindingAlias,
+    ProtocolSignature,
 }
 
-#[derive(Debug, Clone)]
+impl AstNodeType {
+    /// Whether nodes of this type describe a parameter type
+    pub fn is_type(&self) -> bool {
+        matches!(
+            self,
+            AstNodeType::BasicType
+                | AstNodeType::EnumType
+                | AstNodeType::ListType
+                | AstNodeType::TypeRef
+        )
+    }
+}
+
+/// BSPL adornment of a parameter in an interaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParameterDirection {
+    /// Must be known before the interaction
+    In,
+    /// Becomes known by the interaction
+    Out,
+    /// Must not be known yet when the interaction happens
+    Nil,
+    /// May or may not be known; never blocks the interaction
+    Opt,
+}
+
+impl ParameterDirection {
+    pub fn parse(direction: &str) -> Option<Self> {
+        match direction {
+            "in" => Some(Self::In),
+            "out" => Some(Self::Out),
+            "nil" => Some(Self::Nil),
+            "opt" => Some(Self::Opt),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::In => "in",
+            Self::Out => "out",
+            Self::Nil => "nil",
+            Self::Opt => "opt",
+        }
+    }
+}
+
+impl Display for ParameterDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Node of the parsed tree. It serializes to the versioned JSON schema of
+/// [`AstNode::to_json`]; the `parent` back-pointer is not part of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AstNode {
+    #[serde(rename = "type")]
     pub node_type: AstNodeType,
-    pub children: Vec<Box<AstNode>>,
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "super::json::serialize_sorted"
+    )]
     pub properties: HashMap<String, String>,
+    pub span: Span,
     pub line: usize,
     pub column: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Box<AstNode>>,
+    /// Whitespace and comments before the node
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub leading_trivia: Vec<Trivia>,
+    /// Whitespace and comments after the node, up to the end of its line
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trailing_trivia: Vec<Trivia>,
+    /// Whitespace and comments between tokens of the node that no child touches, keyed by
+    /// the token that follows them (e.g. `"` inside `( "..." )`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inner_trivia: BTreeMap<String, Vec<Trivia>>,
+    #[serde(skip)]
     pub parent: Option<*const AstNode>,
 }
 
@@ -39,12 +125,72 @@ impl AstNode {
             node_type,
             children: Vec::new(),
             properties: HashMap::new(),
+            span: Span::default(),
             line: 0,
             column: 0,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+            inner_trivia: BTreeMap::new(),
             parent: None,
         }
     }
 
+    /// Create a node covering the given source span
+    pub fn with_span(node_type: AstNodeType, span: Span) -> Self {
+        let mut node = Self::new(node_type);
+        node.span = span;
+        node
+    }
+
+    /// Fill `line` and `column` of this node and all its descendants from their spans
+    pub fn assign_positions(&mut self, index: &LineIndex) {
+        let (line, column) = index.line_col(self.span.start);
+        self.line = line;
+        self.column = column;
+        for child in &mut self.children {
+            child.assign_positions(index);
+        }
+    }
+
+    /// Move the spans of this node and all its descendants forward by `offset` bytes
+    pub fn shift_spans(&mut self, offset: usize) {
+        self.span = self.span.offset(offset);
+        for child in &mut self.children {
+            child.shift_spans(offset);
+        }
+    }
+
+    /// Span of the identifier naming this node, falling back to the node's own span
+    pub fn name_span(&self) -> Span {
+        match self.node_type {
+            AstNodeType::Protocol => self
+                .find_child(AstNodeType::ProtocolName)
+                .map(|n| n.span)
+                .unwrap_or(self.span),
+            AstNodeType::StandardInteraction => self
+                .find_child(AstNodeType::ActionName)
+                .map(|n| n.span)
+                .unwrap_or(self.span),
+            AstNodeType::ProtocolComposition => self
+                .find_child(AstNodeType::ProtocolReference)
+                .map(|n| n.name_span())
+                .unwrap_or(self.span),
+            AstNodeType::ImportDecl => self
+                .find_child(AstNodeType::ImportPath)
+                .map(|n| n.span)
+                .unwrap_or(self.span),
+            AstNodeType::RoleDecl
+            | AstNodeType::TypeDecl
+            | AstNodeType::ParameterDecl
+            | AstNodeType::ParameterFlow
+            | AstNodeType::ProtocolReference => self
+                .find_child(AstNodeType::Identifier)
+                .map(|n| n.span)
+                .unwrap_or(self.span),
+            _ => self.span,
+        }
+    }
+
     pub fn set_string(&mut self, key: &str, value: &str) {
         self.properties.insert(key.to_string(), value.to_string());
     }
@@ -55,19 +201,6 @@ impl AstNode {
 }
 
 impl AstNode {
-    fn format_node_name(&self) -> String {
-        let node_type_str = format!("{:?}", self.node_type);
-
-        // Get the primary property value for display
-        let property_value = self.get_primary_property_value();
-
-        if let Some(value) = property_value {
-            format!("{} (\"{}\")", node_type_str, value)
-        } else {
-            node_type_str
-        }
-    }
-
     fn get_primary_property_value(&self) -> Option<&String> {
         match self.node_type {
             AstNodeType::ProtocolName => self.get_string("name"),
@@ -75,10 +208,15 @@ impl AstNode {
             AstNodeType::Identifier => self.get_string("name"),
             AstNodeType::RoleRef => self.get_string("name"),
             AstNodeType::ActionName => self.get_string("name"),
-            AstNodeType::BasicType => self.get_string("type"),
+            AstNodeType::BasicType
+            | AstNodeType::EnumType
+            | AstNodeType::ListType
+            | AstNodeType::TypeRef => self.get_string("type"),
+            AstNodeType::ImportDecl | AstNodeType::ImportPath => self.get_string("path"),
+            AstNodeType::BindingAlias => self.get_string("name"),
             AstNodeType::ParameterFlow => {
                 // For parameter flows, show direction and parameter name
-                if let (Some(direction), Some(param_name)) = (
+                if let (Some(direction), Some(_param_name)) = (
                     self.get_string("direction"),
                     self.children.first()?.get_string("name"),
                 ) {
@@ -101,15 +239,15 @@ impl AstNode {
 // Enhanced display implementation for parameter flows
 impl AstNode {
     pub fn format_parameter_flow(&self) -> Option<String> {
-        if self.node_type == AstNodeType::ParameterFlow {
-            if let Some(direction) = self.get_string("direction") {
-                if let Some(child) = self.children.first() {
-                    if let Some(param_name) = child.get_string("name") {
-                        return Some(format!("{}, {}", direction, param_name));
-                    }
-                }
-                return Some(direction.clone());
+        if self.node_type == AstNodeType::ParameterFlow
+            && let Some(direction) = self.get_string("direction")
+        {
+            if let Some(child) = self.children.first()
+                && let Some(param_name) = child.get_string("name")
+            {
+                return Some(format!("{}, {}", direction, param_name));
             }
+            return Some(direction.clone());
         }
         None
     }
@@ -128,6 +266,9 @@ impl AstNode {
                     node_type_str
                 }
             }
+            AstNodeType::ParameterDecl if self.is_key_parameter() => {
+                format!("{} (key)", node_type_str)
+            }
             AstNodeType::ProtocolReference => {
                 if let Some(child) = self.children.first() {
                     if let Some(name) = child.get_string("name") {
@@ -322,6 +463,29 @@ impl AstNode {
             .cloned()
     }
 
+    /// Get the import declarations at the top of a Program
+    pub fn get_imports(&self) -> Vec<&AstNode> {
+        if self.node_type != AstNodeType::Program {
+            return Vec::new();
+        }
+        self.find_children(AstNodeType::ImportDecl)
+    }
+
+    /// Get the imported path and the protocol names listed by `use ... from`, which are
+    /// empty for a plain `import` of the whole file
+    pub fn get_import_info(&self) -> Option<(String, Vec<String>)> {
+        if self.node_type != AstNodeType::ImportDecl {
+            return None;
+        }
+        let path = self.get_string("path")?.clone();
+        let names = self
+            .find_children(AstNodeType::Identifier)
+            .iter()
+            .filter_map(|name| name.get_string("name").cloned())
+            .collect();
+        Some((path, names))
+    }
+
     /// Get the roles section from a Protocol node
     pub fn get_roles_section(&self) -> Option<&AstNode> {
         if self.node_type != AstNodeType::Protocol {
@@ -330,6 +494,30 @@ impl AstNode {
         self.find_child(AstNodeType::RolesSection)
     }
 
+    /// Get the optional types section from a Protocol node
+    pub fn get_types_section(&self) -> Option<&AstNode> {
+        if self.node_type != AstNodeType::Protocol {
+            return None;
+        }
+        self.find_child(AstNodeType::TypesSection)
+    }
+
+    /// Get all record type declarations from a TypesSection
+    pub fn get_type_declarations(&self) -> Vec<&AstNode> {
+        if self.node_type != AstNodeType::TypesSection {
+            return Vec::new();
+        }
+        self.find_children(AstNodeType::TypeDecl)
+    }
+
+    /// Get the type node (BasicType, EnumType, ListType or TypeRef) of a declaration
+    pub fn get_type_node(&self) -> Option<&AstNode> {
+        self.children
+            .iter()
+            .map(|child| child.as_ref())
+            .find(|child| child.node_type.is_type())
+    }
+
     /// Get the parameters section from a Protocol node
     pub fn get_parameters_section(&self) -> Option<&AstNode> {
         if self.node_type != AstNodeType::Protocol {
@@ -354,9 +542,12 @@ impl AstNode {
         self.find_children(AstNodeType::RoleDecl)
     }
 
-    /// Get all parameter declarations from a ParametersSection
+    /// Get all parameter declarations from a ParametersSection, or the fields of a TypeDecl
     pub fn get_parameter_declarations(&self) -> Vec<&AstNode> {
-        if self.node_type != AstNodeType::ParametersSection {
+        if !matches!(
+            self.node_type,
+            AstNodeType::ParametersSection | AstNodeType::TypeDecl
+        ) {
             return Vec::new();
         }
         self.find_children(AstNodeType::ParameterDecl)
@@ -423,12 +614,13 @@ impl AstNode {
     /// Get the identifier name from various node types
     pub fn get_identifier_name(&self) -> Option<String> {
         match self.node_type {
-            AstNodeType::Identifier | AstNodeType::RoleRef | AstNodeType::ActionName => {
-                self.get_string("name").cloned()
-            }
+            AstNodeType::Identifier
+            | AstNodeType::RoleRef
+            | AstNodeType::ActionName
+            | AstNodeType::TypeRef => self.get_string("name").cloned(),
             AstNodeType::ProtocolName => self.get_string("name").cloned(),
-            AstNodeType::ProtocolReference => {
-                // For ProtocolReference, get name from child Identifier
+            AstNodeType::ProtocolReference | AstNodeType::TypeDecl => {
+                // Name comes from the child Identifier
                 self.find_child(AstNodeType::Identifier)?
                     .get_string("name")
                     .cloned()
@@ -437,6 +629,24 @@ impl AstNode {
         }
     }
 
+    /// Get the `as` alias of an enactment argument, naming the callee role or parameter
+    pub fn get_binding_alias(&self) -> Option<&AstNode> {
+        self.find_child(AstNodeType::BindingAlias)
+    }
+
+    /// Name of the callee role or parameter an enactment argument binds: its alias if it
+    /// has one, otherwise its own name
+    pub fn get_bound_name(&self) -> Option<String> {
+        if let Some(alias) = self.get_binding_alias() {
+            return alias.get_string("name").cloned();
+        }
+        match self.node_type {
+            AstNodeType::Identifier => self.get_string("name").cloned(),
+            AstNodeType::ParameterFlow => self.get_parameter_flow_info().map(|(_, name)| name),
+            _ => None,
+        }
+    }
+
     /// Get parameter flow information (direction and parameter name)
     pub fn get_parameter_flow_info(&self) -> Option<(String, String)> {
         if self.node_type != AstNodeType::ParameterFlow {
@@ -452,6 +662,14 @@ impl AstNode {
         Some((direction, param_name))
     }
 
+    /// Direction of a ParameterFlow node, if it is one of the known adornments
+    pub fn get_parameter_direction(&self) -> Option<ParameterDirection> {
+        if self.node_type != AstNodeType::ParameterFlow {
+            return None;
+        }
+        ParameterDirection::parse(self.get_string("direction")?)
+    }
+
     /// Get role declaration information (name and description)
     pub fn get_role_decl_info(&self) -> Option<(String, String)> {
         if self.node_type != AstNodeType::RoleDecl {
@@ -480,10 +698,7 @@ impl AstNode {
             .find_child(AstNodeType::Identifier)?
             .get_string("name")?
             .clone();
-        let param_type = self
-            .find_child(AstNodeType::BasicType)?
-            .get_string("type")?
-            .clone();
+        let param_type = self.get_type_node()?.get_string("type")?.clone();
         let description = self
             .find_child(AstNodeType::Annotation)?
             .get_string("description")?
@@ -492,6 +707,12 @@ impl AstNode {
         Some((name, param_type, description))
     }
 
+    /// Whether a parameter declaration carries the `key` adornment
+    pub fn is_key_parameter(&self) -> bool {
+        self.node_type == AstNodeType::ParameterDecl
+            && self.get_string("key").is_some_and(|key| key == "true")
+    }
+
     /// Get standard interaction information
     pub fn get_standard_interaction_info(&self) -> Option<StandardInteractionInfo> {
         if self.node_type != AstNodeType::StandardInteraction {
@@ -499,7 +720,7 @@ impl AstNode {
         }
 
         let role_refs = self.get_role_refs();
-        let from_role = role_refs.get(0)?.get_identifier_name()?;
+        let from_role = role_refs.first()?.get_identifier_name()?;
         let to_role = role_refs.get(1)?.get_identifier_name()?;
 
         let action_name = self.get_action_name()?.get_identifier_name()?;
@@ -0,0 +1,184 @@
+//! Versioned JSON form of the AST, for tools that consume parsed protocols without
+//! reimplementing the grammar.
+//!
+//! A document wraps the root node together with its schema version:
+//!
+//! ```json
+//! { "format": "bmpp-ast", "version": 1, "root": { "type": "Program", ... } }
+//! ```
+//!
+//! Every node carries its `type`, `span` (byte offsets), `line` and `column`, plus
+//! `properties`, `children` and the whitespace and comments around it (`leading_trivia`,
+//! `trailing_trivia`, `inner_trivia`) when it has any. Property keys are written in sorted
+//! order so the output is stable. The JSON Schema is [`AST_JSON_SCHEMA`].
+
+use super::ast::AstNode;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// Value of the `format` field of every AST document
+pub const AST_FORMAT: &str = "bmpp-ast";
+
+/// Version of the AST JSON schema written by [`AstNode::to_json`]. It is bumped whenever a
+/// node type or property changes meaning, so older readers can refuse newer documents.
+pub const AST_SCHEMA_VERSION: u64 = 1;
+
+/// JSON Schema (draft 2020-12) describing version 1 documents
+pub const AST_JSON_SCHEMA: &str = include_str!("../grammars/v1/ast.schema.json");
+
+#[derive(Serialize, Deserialize)]
+struct AstDocument {
+    format: String,
+    version: u64,
+    root: AstNode,
+}
+
+impl AstNode {
+    /// Serialize the tree rooted at this node as a pretty-printed AST document
+    pub fn to_json(&self) -> Result<String> {
+        let document = AstDocument {
+            format: AST_FORMAT.to_string(),
+            version: AST_SCHEMA_VERSION,
+            root: self.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&document)?)
+    }
+
+    /// Load a tree written by [`AstNode::to_json`]
+    pub fn from_json(json: &str) -> Result<AstNode> {
+        let value: Value = serde_json::from_str(json).context("Invalid AST JSON")?;
+
+        if value.get("format").and_then(Value::as_str) != Some(AST_FORMAT) {
+            return Err(anyhow!(
+                "Not a BMPP AST document: expected \"format\": \"{}\"",
+                AST_FORMAT
+            ));
+        }
+        match value.get("version").and_then(Value::as_u64) {
+            Some(AST_SCHEMA_VERSION) => {}
+            Some(version) => {
+                return Err(anyhow!(
+                    "Unsupported AST schema version {} (this build reads version {})",
+                    version,
+                    AST_SCHEMA_VERSION
+                ));
+            }
+            None => return Err(anyhow!("AST document has no schema version")),
+        }
+
+        let document: AstDocument = serde_json::from_value(value).context("Invalid AST JSON")?;
+        Ok(document.root)
+    }
+}
+
+/// Serialize node properties with their keys in sorted order
+pub(super) fn serialize_sorted<S: Serializer>(
+    properties: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    properties
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ast::AstNodeType;
+    use crate::protocol::model::Program;
+    use crate::transpiler::parse_source;
+
+    const SOURCE: &str = r#"
+use Ship from "shipping.bmpp"
+
+Order <Protocol>("order") {
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse")
+
+    types
+        Item <Record>("line item") {
+            sku <String>("stock keeping unit"),
+            tags <List<Enum[fragile, heavy]>>("handling tags")
+        }
+
+    parameters
+        order_id <String> key ("order identifier"),
+        items <List<Item>>("ordered items")
+
+    M -> W: order <Action>("place order")[in order_id, out items]
+    Ship <Enactment>[W, M as Carrier, in order_id as ID, in items]
+}
+"#;
+
+    #[test]
+    fn test_json_round_trip() {
+        let ast = parse_source(SOURCE).unwrap();
+        let json = ast.to_json().unwrap();
+        let loaded = AstNode::from_json(&json).unwrap();
+
+        assert_eq!(loaded.to_json().unwrap(), json);
+        assert_eq!(
+            Program::from_ast(&loaded).unwrap(),
+            Program::from_ast(&ast).unwrap()
+        );
+        let protocol = loaded.find_child(AstNodeType::Protocol).unwrap();
+        assert_eq!((protocol.line, protocol.column), (4, 1));
+    }
+
+    #[test]
+    fn test_document_shape() {
+        let ast = parse_source(SOURCE).unwrap();
+        let document: Value = serde_json::from_str(&ast.to_json().unwrap()).unwrap();
+
+        assert_eq!(document["format"], "bmpp-ast");
+        assert_eq!(document["version"], 1);
+        let protocol = &document["root"]["children"][1];
+        assert_eq!(protocol["type"], "Protocol");
+        assert_eq!(protocol["children"][0]["type"], "ProtocolName");
+        assert_eq!(protocol["children"][0]["properties"]["name"], "Order");
+        assert!(protocol["span"]["start"].is_u64());
+        assert!(protocol.get("properties").is_none());
+        assert!(protocol.get("parent").is_none());
+    }
+
+    #[test]
+    fn test_rejects_other_versions_and_formats() {
+        let json = parse_source(SOURCE).unwrap().to_json().unwrap();
+
+        let newer = json.replacen("\"version\": 1", "\"version\": 2", 1);
+        assert_eq!(
+            AstNode::from_json(&newer).unwrap_err().to_string(),
+            "Unsupported AST schema version 2 (this build reads version 1)"
+        );
+
+        let other = json.replacen("bmpp-ast", "something-else", 1);
+        assert!(AstNode::from_json(&other).is_err());
+
+        let unknown_node = json.replacen("\"ProtocolName\"", "\"Banner\"", 1);
+        assert!(AstNode::from_json(&unknown_node).is_err());
+    }
+
+    #[test]
+    fn test_schema_lists_every_node_type() {
+        let schema: Value = serde_json::from_str(AST_JSON_SCHEMA).unwrap();
+        let listed: Vec<&str> = schema["$defs"]["node"]["properties"]["type"]["enum"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+
+        let ast = parse_source(SOURCE).unwrap();
+        let mut pending = vec![&ast];
+        while let Some(node) = pending.pop() {
+            let name = format!("{:?}", node.node_type);
+            assert!(listed.contains(&name.as_str()), "{} is missing", name);
+            pending.extend(node.children.iter().map(|child| child.as_ref()));
+        }
+        assert_eq!(schema["properties"]["version"]["const"], AST_SCHEMA_VERSION);
+    }
+}
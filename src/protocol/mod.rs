@@ -1 +1,7 @@
 pub mod ast;
+pub mod json;
+pub mod model;
+pub mod printer;
+pub mod span;
+pub mod trivia;
+pub mod types;
@@ -0,0 +1,1022 @@
+//! Typed model of a BMPP program.
+//!
+//! The parser produces a property-map [`AstNode`] tree; this module gives the same program
+//! a typed shape (`Program`, `Protocol`, `Role`, `Parameter`, `Interaction`, `Enactment`)
+//! so tools can read a protocol without walking children and looking up properties.
+//! Every element keeps the spans of the nodes it was built from, which makes the
+//! conversion lossless: `Program::from_ast(ast)?.to_ast()` rebuilds the same tree.
+//! Whitespace and comments are not part of the model; they stay on the [`AstNode`] tree
+//! (see [`super::trivia`]).
+
+use super::ast::{AstNode, AstNodeType, ParameterDirection, Span};
+use super::types::BmppType;
+use anyhow::{Result, anyhow};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::ops::Deref;
+
+/// A value together with the source span it was parsed from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Self { value, span }
+    }
+}
+
+impl Spanned<String> {
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+}
+
+impl<T: Display> Display for Spanned<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        self.value.fmt(f)
+    }
+}
+
+/// Items of a protocol section (the signature, `roles`, `types`, `parameters` or the
+/// interactions) with the span of the whole section
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section<T> {
+    pub items: Vec<T>,
+    pub span: Span,
+}
+
+impl<T> Section<T> {
+    pub fn new(items: Vec<T>, span: Span) -> Self {
+        Self { items, span }
+    }
+}
+
+impl<T> Deref for Section<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Section<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub imports: Vec<Import>,
+    pub protocols: Vec<Protocol>,
+    pub span: Span,
+}
+
+/// `import "file.bmpp"`, or `use A, B from "file.bmpp"` when `names` is not empty
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub names: Vec<Spanned<String>>,
+    pub path: Spanned<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Protocol {
+    pub name: Spanned<String>,
+    pub description: Spanned<String>,
+    /// Public parameters, when the header declares them: `[in ID key, out tag]`
+    pub signature: Option<Section<PublicParameter>>,
+    pub roles: Section<Role>,
+    pub types: Option<Section<TypeDecl>>,
+    pub parameters: Section<Parameter>,
+    pub interactions: Section<InteractionItem>,
+    pub span: Span,
+}
+
+/// Parameter of a protocol signature, exchanged with the enacting protocol. `in` parameters
+/// are provided by it and `out` parameters handed back to it; the others stay private.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicParameter {
+    pub direction: ParameterDirection,
+    pub name: Spanned<String>,
+    pub key: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Role {
+    pub name: Spanned<String>,
+    pub description: Spanned<String>,
+    pub span: Span,
+}
+
+/// Record type declared in the `types` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDecl {
+    pub name: Spanned<String>,
+    pub description: Spanned<String>,
+    pub fields: Vec<Parameter>,
+    pub span: Span,
+}
+
+/// Protocol parameter, or a field of a record type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: Spanned<String>,
+    pub ty: TypeExpr,
+    pub key: bool,
+    pub description: Spanned<String>,
+    pub span: Span,
+}
+
+/// Type of a parameter as written in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeExpr {
+    pub kind: TypeKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    /// `String`, `Int`, `Float` or `Bool`
+    Basic(BmppType),
+    Enum(Vec<Spanned<String>>),
+    List(Box<TypeExpr>),
+    /// Reference to a record type
+    Named(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InteractionItem {
+    Interaction(Interaction),
+    Enactment(Enactment),
+}
+
+/// Message from one role to another: `From -> To: action <Action>("...")[flows]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interaction {
+    pub from: Spanned<String>,
+    pub to: Spanned<String>,
+    pub action: Spanned<String>,
+    pub description: Spanned<String>,
+    pub flows: Vec<Flow>,
+    pub span: Span,
+}
+
+/// Adorned parameter of an interaction or enactment, e.g. `in order as purchase_order`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flow {
+    pub direction: ParameterDirection,
+    pub parameter: Spanned<String>,
+    /// Callee parameter bound with `as`; only enactments bind parameters
+    pub alias: Option<Spanned<String>>,
+    pub span: Span,
+}
+
+/// Enactment of another protocol: `Name <Enactment>[roles and flows]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enactment {
+    pub protocol: Spanned<String>,
+    /// Span of `Name <Enactment>`
+    pub reference_span: Span,
+    pub arguments: Vec<Argument>,
+    pub span: Span,
+}
+
+/// Argument of an enactment, in source order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Role(RoleBinding),
+    Parameter(Flow),
+}
+
+/// Caller role passed to an enactment, e.g. `W as Warehouse`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoleBinding {
+    pub name: Spanned<String>,
+    pub alias: Option<Spanned<String>>,
+    pub span: Span,
+}
+
+impl Program {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::Program)?;
+        let mut program = Self {
+            imports: Vec::new(),
+            protocols: Vec::new(),
+            span: node.span,
+        };
+        for child in &node.children {
+            match child.node_type {
+                AstNodeType::ImportDecl => program.imports.push(Import::from_ast(child)?),
+                AstNodeType::Protocol => program.protocols.push(Protocol::from_ast(child)?),
+                _ => return Err(unexpected(child, node)),
+            }
+        }
+        Ok(program)
+    }
+
+    /// Rebuild the AST; line and column are left for [`AstNode::assign_positions`]
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::Program, self.span);
+        push_all(&mut node, self.imports.iter().map(Import::to_ast));
+        push_all(&mut node, self.protocols.iter().map(Protocol::to_ast));
+        node
+    }
+
+    pub fn protocol(&self, name: &str) -> Option<&Protocol> {
+        self.protocols.iter().find(|p| p.name.value == name)
+    }
+}
+
+impl Import {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::ImportDecl)?;
+        let path_node = required_child(node, AstNodeType::ImportPath)?;
+        Ok(Self {
+            names: node
+                .find_children(AstNodeType::Identifier)
+                .into_iter()
+                .map(name_of)
+                .collect::<Result<_>>()?,
+            path: Spanned::new(required(path_node, "path")?, path_node.span),
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::ImportDecl, self.span);
+        node.set_string("path", &self.path.value);
+        push_all(
+            &mut node,
+            self.names
+                .iter()
+                .map(|name| named(AstNodeType::Identifier, name)),
+        );
+        let mut path = AstNode::with_span(AstNodeType::ImportPath, self.path.span);
+        path.set_string("path", &self.path.value);
+        node.children.push(Box::new(path));
+        node
+    }
+}
+
+impl Protocol {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::Protocol)?;
+        let roles = required_child(node, AstNodeType::RolesSection)?;
+        let parameters = required_child(node, AstNodeType::ParametersSection)?;
+        let interactions = required_child(node, AstNodeType::InteractionSection)?;
+
+        Ok(Self {
+            name: name_of(required_child(node, AstNodeType::ProtocolName)?)?,
+            description: description_of(node)?,
+            signature: node
+                .find_child(AstNodeType::ProtocolSignature)
+                .map(|signature| {
+                    section(
+                        signature,
+                        AstNodeType::ParameterFlow,
+                        PublicParameter::from_ast,
+                    )
+                })
+                .transpose()?,
+            roles: section(roles, AstNodeType::RoleDecl, Role::from_ast)?,
+            types: node
+                .find_child(AstNodeType::TypesSection)
+                .map(|types| section(types, AstNodeType::TypeDecl, TypeDecl::from_ast))
+                .transpose()?,
+            parameters: section(parameters, AstNodeType::ParameterDecl, Parameter::from_ast)?,
+            interactions: section(
+                interactions,
+                AstNodeType::InteractionItem,
+                InteractionItem::from_ast,
+            )?,
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::Protocol, self.span);
+        node.children
+            .push(Box::new(named(AstNodeType::ProtocolName, &self.name)));
+        node.children.push(Box::new(annotation(&self.description)));
+        if let Some(signature) = &self.signature {
+            node.children.push(Box::new(section_ast(
+                AstNodeType::ProtocolSignature,
+                signature,
+                PublicParameter::to_ast,
+            )));
+        }
+        node.children.push(Box::new(section_ast(
+            AstNodeType::RolesSection,
+            &self.roles,
+            Role::to_ast,
+        )));
+        if let Some(types) = &self.types {
+            node.children.push(Box::new(section_ast(
+                AstNodeType::TypesSection,
+                types,
+                TypeDecl::to_ast,
+            )));
+        }
+        node.children.push(Box::new(section_ast(
+            AstNodeType::ParametersSection,
+            &self.parameters,
+            Parameter::to_ast,
+        )));
+        node.children.push(Box::new(section_ast(
+            AstNodeType::InteractionSection,
+            &self.interactions,
+            InteractionItem::to_ast,
+        )));
+        node
+    }
+
+    pub fn role(&self, name: &str) -> Option<&Role> {
+        self.roles.iter().find(|role| role.name.value == name)
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&Parameter> {
+        self.parameters
+            .iter()
+            .find(|param| param.name.value == name)
+    }
+
+    /// Parameters declared with the `key` adornment
+    pub fn keys(&self) -> impl Iterator<Item = &Parameter> {
+        self.parameters.iter().filter(|param| param.key)
+    }
+
+    /// Entry of the signature for `name`, if the protocol declares one listing it
+    pub fn public_parameter(&self, name: &str) -> Option<&PublicParameter> {
+        self.signature
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .find(|param| param.name.value == name)
+    }
+
+    /// Whether the enacting protocol can provide `name`: a key, or an `in` parameter of
+    /// the signature
+    pub fn is_input(&self, name: &str) -> bool {
+        self.parameter(name).is_some_and(|param| param.key)
+            || self
+                .public_parameter(name)
+                .is_some_and(|param| param.direction == ParameterDirection::In)
+    }
+
+    /// Record types of the `types` section, if any
+    pub fn type_decls(&self) -> &[TypeDecl] {
+        self.types.as_deref().unwrap_or_default()
+    }
+
+    /// Messages of the protocol, without its enactments
+    pub fn messages(&self) -> impl Iterator<Item = &Interaction> {
+        self.interactions
+            .iter()
+            .filter_map(InteractionItem::as_interaction)
+    }
+
+    pub fn enactments(&self) -> impl Iterator<Item = &Enactment> {
+        self.interactions
+            .iter()
+            .filter_map(InteractionItem::as_enactment)
+    }
+}
+
+impl PublicParameter {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        let flow = Flow::from_ast(node)?;
+        Ok(Self {
+            direction: flow.direction,
+            name: flow.parameter,
+            key: node.get_string("key").is_some_and(|key| key == "true"),
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::ParameterFlow, self.span);
+        node.set_string("direction", self.direction.as_str());
+        if self.key {
+            node.set_string("key", "true");
+        }
+        node.children
+            .push(Box::new(named(AstNodeType::Identifier, &self.name)));
+        node
+    }
+}
+
+impl Role {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::RoleDecl)?;
+        Ok(Self {
+            name: name_of(required_child(node, AstNodeType::Identifier)?)?,
+            description: description_of(node)?,
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::RoleDecl, self.span);
+        node.children
+            .push(Box::new(named(AstNodeType::Identifier, &self.name)));
+        node.children.push(Box::new(annotation(&self.description)));
+        node
+    }
+}
+
+impl TypeDecl {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::TypeDecl)?;
+        Ok(Self {
+            name: name_of(required_child(node, AstNodeType::Identifier)?)?,
+            description: description_of(node)?,
+            fields: node
+                .find_children(AstNodeType::ParameterDecl)
+                .into_iter()
+                .map(Parameter::from_ast)
+                .collect::<Result<_>>()?,
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::TypeDecl, self.span);
+        node.children
+            .push(Box::new(named(AstNodeType::Identifier, &self.name)));
+        node.children.push(Box::new(annotation(&self.description)));
+        push_all(&mut node, self.fields.iter().map(Parameter::to_ast));
+        node
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Parameter> {
+        self.fields.iter().find(|field| field.name.value == name)
+    }
+}
+
+impl Parameter {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::ParameterDecl)?;
+        let type_node = node
+            .get_type_node()
+            .ok_or_else(|| anyhow!("Malformed ParameterDecl node: missing its type"))?;
+        Ok(Self {
+            name: name_of(required_child(node, AstNodeType::Identifier)?)?,
+            ty: TypeExpr::from_ast(type_node)?,
+            key: node.is_key_parameter(),
+            description: description_of(node)?,
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::ParameterDecl, self.span);
+        if self.key {
+            node.set_string("key", "true");
+        }
+        node.children
+            .push(Box::new(named(AstNodeType::Identifier, &self.name)));
+        node.children.push(Box::new(self.ty.to_ast()));
+        node.children.push(Box::new(annotation(&self.description)));
+        node
+    }
+}
+
+impl TypeExpr {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        let kind = match node.node_type {
+            AstNodeType::BasicType => {
+                let name = required(node, "type")?;
+                match BmppType::from_node(node) {
+                    Some(ty) => TypeKind::Basic(ty),
+                    None => return Err(anyhow!("Unknown basic type '{}'", name)),
+                }
+            }
+            AstNodeType::EnumType => TypeKind::Enum(
+                node.find_children(AstNodeType::Identifier)
+                    .into_iter()
+                    .map(name_of)
+                    .collect::<Result<_>>()?,
+            ),
+            AstNodeType::ListType => {
+                let element = node
+                    .get_type_node()
+                    .ok_or_else(|| anyhow!("Malformed ListType node: missing its element type"))?;
+                TypeKind::List(Box::new(Self::from_ast(element)?))
+            }
+            AstNodeType::TypeRef => TypeKind::Named(required(node, "name")?),
+            _ => {
+                return Err(anyhow!("Expected a type node, got {:?}", node.node_type));
+            }
+        };
+        Ok(Self {
+            kind,
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let (node_type, children) = match &self.kind {
+            TypeKind::Basic(_) => (AstNodeType::BasicType, Vec::new()),
+            TypeKind::Enum(variants) => (
+                AstNodeType::EnumType,
+                variants
+                    .iter()
+                    .map(|variant| named(AstNodeType::Identifier, variant))
+                    .collect(),
+            ),
+            TypeKind::List(element) => (AstNodeType::ListType, vec![element.to_ast()]),
+            TypeKind::Named(name) => {
+                let mut node = named(AstNodeType::TypeRef, &Spanned::new(name.clone(), self.span));
+                node.set_string("type", name);
+                return node;
+            }
+        };
+        let mut node = AstNode::with_span(node_type, self.span);
+        node.set_string("type", &self.ty().to_string());
+        push_all(&mut node, children);
+        node
+    }
+
+    /// The type this expression denotes
+    pub fn ty(&self) -> BmppType {
+        match &self.kind {
+            TypeKind::Basic(ty) => ty.clone(),
+            TypeKind::Enum(variants) => {
+                BmppType::Enum(variants.iter().map(|v| v.value.clone()).collect())
+            }
+            TypeKind::List(element) => BmppType::List(Box::new(element.ty())),
+            TypeKind::Named(name) => BmppType::Named(name.clone()),
+        }
+    }
+}
+
+impl InteractionItem {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::InteractionItem)?;
+        if let Some(interaction) = node.get_standard_interaction() {
+            Ok(Self::Interaction(Interaction::from_ast(interaction)?))
+        } else if let Some(enactment) = node.get_protocol_composition() {
+            Ok(Self::Enactment(Enactment::from_ast(enactment)?))
+        } else {
+            Err(anyhow!(
+                "No valid interaction type found in InteractionItem"
+            ))
+        }
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let inner = match self {
+            Self::Interaction(interaction) => interaction.to_ast(),
+            Self::Enactment(enactment) => enactment.to_ast(),
+        };
+        let mut node = AstNode::with_span(AstNodeType::InteractionItem, inner.span);
+        node.children.push(Box::new(inner));
+        node
+    }
+
+    /// Action name of a message, or the enacted protocol's name
+    pub fn name(&self) -> &Spanned<String> {
+        match self {
+            Self::Interaction(interaction) => &interaction.action,
+            Self::Enactment(enactment) => &enactment.protocol,
+        }
+    }
+
+    /// Adorned parameters, in source order
+    pub fn flows(&self) -> Vec<&Flow> {
+        match self {
+            Self::Interaction(interaction) => interaction.flows.iter().collect(),
+            Self::Enactment(enactment) => enactment.flows().collect(),
+        }
+    }
+
+    /// Roles taking part, in source order: sender and receiver of a message, or the
+    /// caller roles passed to an enactment
+    pub fn roles(&self) -> Vec<&str> {
+        match self {
+            Self::Interaction(interaction) => {
+                vec![interaction.from.as_str(), interaction.to.as_str()]
+            }
+            Self::Enactment(enactment) => enactment.roles().map(|r| r.name.as_str()).collect(),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Interaction(interaction) => interaction.span,
+            Self::Enactment(enactment) => enactment.span,
+        }
+    }
+
+    pub fn as_interaction(&self) -> Option<&Interaction> {
+        match self {
+            Self::Interaction(interaction) => Some(interaction),
+            Self::Enactment(_) => None,
+        }
+    }
+
+    pub fn as_enactment(&self) -> Option<&Enactment> {
+        match self {
+            Self::Enactment(enactment) => Some(enactment),
+            Self::Interaction(_) => None,
+        }
+    }
+}
+
+impl Interaction {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::StandardInteraction)?;
+        let roles = node.get_role_refs();
+        let [from, to] = roles.as_slice() else {
+            return Err(anyhow!(
+                "Malformed StandardInteraction node: expected 2 RoleRef nodes, got {}",
+                roles.len()
+            ));
+        };
+        Ok(Self {
+            from: name_of(from)?,
+            to: name_of(to)?,
+            action: name_of(required_child(node, AstNodeType::ActionName)?)?,
+            description: description_of(node)?,
+            flows: node
+                .get_parameter_flows()
+                .into_iter()
+                .map(Flow::from_ast)
+                .collect::<Result<_>>()?,
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::StandardInteraction, self.span);
+        node.children
+            .push(Box::new(named(AstNodeType::RoleRef, &self.from)));
+        node.children
+            .push(Box::new(named(AstNodeType::RoleRef, &self.to)));
+        node.children
+            .push(Box::new(named(AstNodeType::ActionName, &self.action)));
+        node.children.push(Box::new(annotation(&self.description)));
+        push_all(&mut node, self.flows.iter().map(Flow::to_ast));
+        node
+    }
+
+    /// Parameters adorned with `direction`, in source order
+    pub fn parameters(&self, direction: ParameterDirection) -> impl Iterator<Item = &str> {
+        self.flows
+            .iter()
+            .filter(move |flow| flow.direction == direction)
+            .map(|flow| flow.parameter.as_str())
+    }
+}
+
+impl Flow {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::ParameterFlow)?;
+        let parameter = name_of(required_child(node, AstNodeType::Identifier)?)?;
+        let direction = required(node, "direction")?;
+        Ok(Self {
+            direction: ParameterDirection::parse(&direction).ok_or_else(|| {
+                anyhow!(
+                    "Invalid parameter direction '{}' for parameter '{}'",
+                    direction,
+                    parameter
+                )
+            })?,
+            parameter,
+            alias: alias_of(node)?,
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::ParameterFlow, self.span);
+        node.set_string("direction", self.direction.as_str());
+        node.children
+            .push(Box::new(named(AstNodeType::Identifier, &self.parameter)));
+        push_alias(&mut node, &self.alias);
+        node
+    }
+
+    /// Callee parameter an enactment argument binds: its alias, or its own name
+    pub fn bound_name(&self) -> &str {
+        self.alias.as_ref().unwrap_or(&self.parameter).as_str()
+    }
+}
+
+impl Enactment {
+    pub fn from_ast(node: &AstNode) -> Result<Self> {
+        expect_type(node, AstNodeType::ProtocolComposition)?;
+        let reference = required_child(node, AstNodeType::ProtocolReference)?;
+        let mut arguments = Vec::new();
+        for child in &node.children {
+            match child.node_type {
+                AstNodeType::ProtocolReference => {}
+                AstNodeType::Identifier => arguments.push(Argument::Role(RoleBinding {
+                    name: name_of(child)?,
+                    alias: alias_of(child)?,
+                    span: child.span,
+                })),
+                AstNodeType::ParameterFlow => {
+                    arguments.push(Argument::Parameter(Flow::from_ast(child)?))
+                }
+                _ => return Err(unexpected(child, node)),
+            }
+        }
+        Ok(Self {
+            protocol: name_of(required_child(reference, AstNodeType::Identifier)?)?,
+            reference_span: reference.span,
+            arguments,
+            span: node.span,
+        })
+    }
+
+    pub fn to_ast(&self) -> AstNode {
+        let mut node = AstNode::with_span(AstNodeType::ProtocolComposition, self.span);
+        let mut reference = AstNode::with_span(AstNodeType::ProtocolReference, self.reference_span);
+        reference
+            .children
+            .push(Box::new(named(AstNodeType::Identifier, &self.protocol)));
+        node.children.push(Box::new(reference));
+        for argument in &self.arguments {
+            let child = match argument {
+                Argument::Role(role) => {
+                    let mut child = named(AstNodeType::Identifier, &role.name);
+                    child.span = role.span;
+                    push_alias(&mut child, &role.alias);
+                    child
+                }
+                Argument::Parameter(flow) => flow.to_ast(),
+            };
+            node.children.push(Box::new(child));
+        }
+        node
+    }
+
+    /// Caller roles passed to the enacted protocol, in source order
+    pub fn roles(&self) -> impl Iterator<Item = &RoleBinding> {
+        self.arguments.iter().filter_map(|argument| match argument {
+            Argument::Role(role) => Some(role),
+            Argument::Parameter(_) => None,
+        })
+    }
+
+    /// Parameters passed to the enacted protocol, in source order
+    pub fn flows(&self) -> impl Iterator<Item = &Flow> {
+        self.arguments.iter().filter_map(|argument| match argument {
+            Argument::Parameter(flow) => Some(flow),
+            Argument::Role(_) => None,
+        })
+    }
+}
+
+impl RoleBinding {
+    /// Callee role this argument binds, if it is bound by name
+    pub fn bound_name(&self) -> Option<&str> {
+        self.alias.as_ref().map(Spanned::as_str)
+    }
+}
+
+fn expect_type(node: &AstNode, node_type: AstNodeType) -> Result<()> {
+    if node.node_type == node_type {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Expected {:?} node, got {:?}",
+            node_type,
+            node.node_type
+        ))
+    }
+}
+
+fn unexpected(child: &AstNode, parent: &AstNode) -> anyhow::Error {
+    anyhow!(
+        "Unexpected {:?} node in {:?}",
+        child.node_type,
+        parent.node_type
+    )
+}
+
+fn required_child(node: &AstNode, node_type: AstNodeType) -> Result<&AstNode> {
+    let missing = format!(
+        "Malformed {:?} node: missing {:?}",
+        node.node_type, node_type
+    );
+    node.find_child(node_type).ok_or_else(|| anyhow!(missing))
+}
+
+fn required(node: &AstNode, property: &str) -> Result<String> {
+    node.get_string(property).cloned().ok_or_else(|| {
+        anyhow!(
+            "Malformed {:?} node: missing property '{}'",
+            node.node_type,
+            property
+        )
+    })
+}
+
+fn name_of(node: &AstNode) -> Result<Spanned<String>> {
+    Ok(Spanned::new(required(node, "name")?, node.span))
+}
+
+fn description_of(node: &AstNode) -> Result<Spanned<String>> {
+    let annotation = required_child(node, AstNodeType::Annotation)?;
+    Ok(Spanned::new(
+        required(annotation, "description")?,
+        annotation.span,
+    ))
+}
+
+fn alias_of(node: &AstNode) -> Result<Option<Spanned<String>>> {
+    node.get_binding_alias().map(name_of).transpose()
+}
+
+fn section<T>(
+    node: &AstNode,
+    item_type: AstNodeType,
+    convert: impl Fn(&AstNode) -> Result<T>,
+) -> Result<Section<T>> {
+    let mut items = Vec::new();
+    for child in &node.children {
+        if child.node_type != item_type {
+            return Err(unexpected(child, node));
+        }
+        items.push(convert(child)?);
+    }
+    Ok(Section::new(items, node.span))
+}
+
+fn section_ast<T>(
+    node_type: AstNodeType,
+    section: &Section<T>,
+    convert: impl Fn(&T) -> AstNode,
+) -> AstNode {
+    let mut node = AstNode::with_span(node_type, section.span);
+    push_all(&mut node, section.iter().map(convert));
+    node
+}
+
+fn named(node_type: AstNodeType, name: &Spanned<String>) -> AstNode {
+    let mut node = AstNode::with_span(node_type, name.span);
+    node.set_string("name", &name.value);
+    node
+}
+
+fn annotation(description: &Spanned<String>) -> AstNode {
+    let mut node = AstNode::with_span(AstNodeType::Annotation, description.span);
+    node.set_string("description", &description.value);
+    node
+}
+
+fn push_alias(node: &mut AstNode, alias: &Option<Spanned<String>>) {
+    if let Some(alias) = alias {
+        node.children
+            .push(Box::new(named(AstNodeType::BindingAlias, alias)));
+    }
+}
+
+fn push_all(node: &mut AstNode, children: impl IntoIterator<Item = AstNode>) {
+    node.children.extend(children.into_iter().map(Box::new));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ast::LineIndex;
+    use crate::transpiler::parse_source;
+
+    const SOURCE: &str = r#"
+import "common.bmpp"
+use Ship from "shipping.bmpp"
+
+Order <Protocol>("order with shipping") [in order_id key, out items] {
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse")
+
+    types
+        Item <Record>("line item") {
+            sku <String>("stock keeping unit"),
+            tags <List<Enum[fragile, heavy]>>("handling tags")
+        }
+
+    parameters
+        order_id <String> key ("order identifier"),
+        items <List<Item>>("ordered items"),
+        note <String>("note")
+
+    M -> W: order <Action>("place order")[in order_id, out items, opt note]
+    Ship <Enactment>[W, M as Carrier, in order_id as ID, in items]
+}
+"#;
+
+    /// Compare two trees node by node, including properties, spans and positions
+    fn assert_same_tree(expected: &AstNode, actual: &AstNode) {
+        assert_eq!(expected.node_type, actual.node_type);
+        assert_eq!(
+            expected.properties, actual.properties,
+            "{:?}",
+            expected.node_type
+        );
+        assert_eq!(expected.span, actual.span, "{:?}", expected.node_type);
+        assert_eq!(
+            (expected.line, expected.column),
+            (actual.line, actual.column)
+        );
+        assert_eq!(expected.children.len(), actual.children.len());
+        for (expected, actual) in expected.children.iter().zip(&actual.children) {
+            assert_same_tree(expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_is_lossless() {
+        let ast = parse_source(SOURCE).unwrap();
+        let program = Program::from_ast(&ast).unwrap();
+
+        let mut rebuilt = program.to_ast();
+        rebuilt.assign_positions(&LineIndex::new(SOURCE));
+        assert_same_tree(&ast, &rebuilt);
+        assert_eq!(Program::from_ast(&rebuilt).unwrap(), program);
+    }
+
+    #[test]
+    fn test_typed_access() {
+        let program = Program::from_ast(&parse_source(SOURCE).unwrap()).unwrap();
+        assert_eq!(program.imports.len(), 2);
+        assert_eq!(program.imports[1].names[0].as_str(), "Ship");
+
+        let order = program.protocol("Order").unwrap();
+        assert_eq!(order.roles.len(), 2);
+        let public = order.public_parameter("order_id").unwrap();
+        assert_eq!(
+            (public.direction, public.key),
+            (ParameterDirection::In, true)
+        );
+        assert!(order.public_parameter("note").is_none());
+        assert!(order.is_input("order_id"));
+        assert!(!order.is_input("items"));
+        assert_eq!(
+            order.keys().map(|k| k.name.as_str()).collect::<Vec<_>>(),
+            ["order_id"]
+        );
+        assert_eq!(
+            order.parameter("items").unwrap().ty.ty().to_string(),
+            "List<Item>"
+        );
+        assert_eq!(
+            order.type_decls()[0]
+                .field("tags")
+                .unwrap()
+                .ty
+                .ty()
+                .to_string(),
+            "List<Enum[fragile, heavy]>"
+        );
+
+        let message = order.messages().next().unwrap();
+        assert_eq!((message.from.as_str(), message.to.as_str()), ("M", "W"));
+        assert_eq!(
+            message
+                .parameters(ParameterDirection::Out)
+                .collect::<Vec<_>>(),
+            ["items"]
+        );
+
+        let enactment = order.enactments().next().unwrap();
+        assert_eq!(enactment.protocol.as_str(), "Ship");
+        let roles: Vec<_> = enactment
+            .roles()
+            .map(|r| (r.name.as_str(), r.bound_name()))
+            .collect();
+        assert_eq!(roles, [("W", None), ("M", Some("Carrier"))]);
+        let flows: Vec<_> = enactment.flows().map(Flow::bound_name).collect();
+        assert_eq!(flows, ["ID", "items"]);
+        assert_eq!(
+            &SOURCE[enactment.reference_span.start..enactment.reference_span.end],
+            "Ship <Enactment>"
+        );
+    }
+
+    #[test]
+    fn test_malformed_tree_is_rejected() {
+        let mut ast = parse_source(SOURCE).unwrap();
+        let protocol = ast.children.last_mut().unwrap();
+        protocol
+            .children
+            .retain(|c| c.node_type != AstNodeType::RolesSection);
+
+        let error = Program::from_ast(&ast).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Malformed Protocol node: missing RolesSection"
+        );
+        assert!(Program::from_ast(&AstNode::new(AstNodeType::Protocol)).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! Lossless printer: turns an AST back into BMPP source.
+//!
+//! Tokens are rebuilt from node types and properties, and the trivia attached by the parser
+//! is put back between them, so printing a freshly parsed tree gives back the original text
+//! byte for byte. Trees built in code carry no trivia; they print with a single space
+//! wherever two words would otherwise run together.
+
+use super::ast::{AstNode, AstNodeType};
+use super::trivia::{Trivia, trivia_key};
+
+impl AstNode {
+    /// Print the tree rooted at this node as BMPP source
+    pub fn to_source(&self) -> String {
+        let mut printer = SourcePrinter::default();
+        printer.node(self, None);
+        printer.out
+    }
+}
+
+#[derive(Default)]
+struct SourcePrinter {
+    out: String,
+}
+
+impl SourcePrinter {
+    /// Print `node` with its trivia; `separator` is the token that follows it in a list
+    fn node(&mut self, node: &AstNode, separator: Option<&str>) {
+        self.trivia(&node.leading_trivia);
+        self.body(node);
+        if let Some(separator) = separator {
+            self.token(node, separator);
+        }
+        self.trivia(&node.trailing_trivia);
+    }
+
+    fn body(&mut self, node: &AstNode) {
+        let children: Vec<&AstNode> = node.children.iter().map(|child| child.as_ref()).collect();
+        match node.node_type {
+            AstNodeType::Program
+            | AstNodeType::InteractionSection
+            | AstNodeType::InteractionItem => self.nodes(&children),
+            AstNodeType::ImportDecl => {
+                let (names, path) = children.split_at(children.len().saturating_sub(1));
+                if names.is_empty() {
+                    self.token(node, "import");
+                } else {
+                    self.token(node, "use");
+                    self.list(names, ",");
+                    self.token(node, "from");
+                }
+                self.nodes(path);
+            }
+            AstNodeType::ImportPath => {
+                self.token(node, &quoted(node.get_string("path")));
+            }
+            AstNodeType::Protocol => {
+                self.nodes(&children[..1]);
+                self.token(node, "<Protocol>");
+                let header = match children.get(2) {
+                    Some(child) if child.node_type == AstNodeType::ProtocolSignature => 3,
+                    _ => 2,
+                };
+                self.nodes(&children[1..header]);
+                self.token(node, "{");
+                self.nodes(&children[header..]);
+                self.token(node, "}");
+            }
+            AstNodeType::ProtocolSignature => {
+                self.token(node, "[");
+                self.list(&children, ",");
+                self.token(node, "]");
+            }
+            AstNodeType::Annotation => {
+                self.token(node, "(");
+                self.token(node, &quoted(node.get_string("description")));
+                self.token(node, ")");
+            }
+            AstNodeType::RolesSection => self.section(node, "roles", &children),
+            AstNodeType::TypesSection => self.section(node, "types", &children),
+            AstNodeType::ParametersSection => self.section(node, "parameters", &children),
+            AstNodeType::RoleDecl => {
+                self.nodes(&children[..1]);
+                self.token(node, "<Agent>");
+                self.nodes(&children[1..]);
+            }
+            AstNodeType::TypeDecl => {
+                self.nodes(&children[..1]);
+                self.token(node, "<Record>");
+                self.nodes(&children[1..2]);
+                self.token(node, "{");
+                self.list(&children[2..], ",");
+                self.token(node, "}");
+            }
+            AstNodeType::ParameterDecl => {
+                self.nodes(&children[..1]);
+                self.token(node, "<");
+                self.nodes(&children[1..2]);
+                self.token(node, ">");
+                if node.get_string("key").is_some_and(|key| key == "true") {
+                    self.token(node, "key");
+                }
+                self.nodes(&children[2..]);
+            }
+            AstNodeType::BasicType => {
+                self.token(node, node.get_string("type").map_or("", String::as_str));
+            }
+            AstNodeType::EnumType => {
+                self.token(node, "Enum");
+                self.token(node, "[");
+                self.list(&children, ",");
+                self.token(node, "]");
+            }
+            AstNodeType::ListType => {
+                self.token(node, "List");
+                self.token(node, "<");
+                self.nodes(&children);
+                self.token(node, ">");
+            }
+            AstNodeType::StandardInteraction => {
+                self.nodes(&children[..1]);
+                self.token(node, "->");
+                self.nodes(&children[1..2]);
+                self.token(node, ":");
+                self.nodes(&children[2..3]);
+                self.token(node, "<Action>");
+                self.nodes(&children[3..4]);
+                self.token(node, "[");
+                self.list(&children[4..], ",");
+                self.token(node, "]");
+            }
+            AstNodeType::ParameterFlow => {
+                self.token(
+                    node,
+                    node.get_string("direction").map_or("", String::as_str),
+                );
+                self.aliased(node, &children);
+                if node.get_string("key").is_some_and(|key| key == "true") {
+                    self.token(node, "key");
+                }
+            }
+            AstNodeType::ProtocolComposition => {
+                self.nodes(&children[..1]);
+                self.token(node, "[");
+                self.list(&children[1..], ",");
+                self.token(node, "]");
+            }
+            AstNodeType::ProtocolReference => {
+                self.nodes(&children);
+                self.token(node, "<Enactment>");
+            }
+            AstNodeType::Identifier
+            | AstNodeType::ProtocolName
+            | AstNodeType::RoleRef
+            | AstNodeType::ActionName
+            | AstNodeType::TypeRef
+            | AstNodeType::BindingAlias => {
+                self.token(node, node.get_string("name").map_or("", String::as_str));
+                self.aliased(node, &children);
+            }
+        }
+    }
+
+    /// Children of a flow or enactment argument, with `as` before a binding alias
+    fn aliased(&mut self, node: &AstNode, children: &[&AstNode]) {
+        for child in children {
+            if child.node_type == AstNodeType::BindingAlias {
+                self.token(node, "as");
+            }
+            self.node(child, None);
+        }
+    }
+
+    fn section(&mut self, node: &AstNode, keyword: &str, items: &[&AstNode]) {
+        self.token(node, keyword);
+        self.list(items, ",");
+    }
+
+    fn nodes(&mut self, nodes: &[&AstNode]) {
+        for node in nodes {
+            self.node(node, None);
+        }
+    }
+
+    fn list(&mut self, items: &[&AstNode], separator: &str) {
+        for (index, item) in items.iter().enumerate() {
+            let separator = (index + 1 < items.len()).then_some(separator);
+            self.node(item, separator);
+        }
+    }
+
+    /// Print a token of `node` after the inner trivia that precedes it
+    fn token(&mut self, node: &AstNode, token: &str) {
+        if let Some(trivia) = node.inner_trivia.get(trivia_key(token)) {
+            self.trivia(trivia);
+        }
+        let joins = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        if self.out.ends_with(joins) && token.starts_with(joins) {
+            self.out.push(' ');
+        }
+        self.out.push_str(token);
+    }
+
+    fn trivia(&mut self, trivia: &[Trivia]) {
+        for piece in trivia {
+            self.out.push_str(&piece.text);
+        }
+    }
+}
+
+fn quoted(text: Option<&String>) -> String {
+    format!("\"{}\"", text.map_or("", String::as_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::model::Program;
+    use crate::transpiler::parse_source;
+
+    const STARTER: &str = include_str!("../../examples/STARTER-PROTOCOL.bmpp");
+    const COMPOSED: &str = include_str!("../../examples/STARTER-PROTOCOL_COMPOSED.bmpp");
+
+    const ODD_LAYOUT: &str = "use Ship , Track from \"ship.bmpp\" // imported\nimport \"base.bmpp\"\r\n\r\n\tOrder<Protocol>( \"o\" ){roles M<Agent>(\"m\") , W <Agent>(\"w\")\n types Item <Record> (\"i\") { sku < String > (\"s\") , n <Int>key(\"n\") }\n parameters id <String> key (\"id\"), tags < List < Enum [ a , b ] > > (\"t\") // tags\n M -> W : go <Action> ( \"g\" ) [ ] W->M:back<Action>(\"b\")[out id,in tags]\n Ship <Enactment> [ W as Shipper , M , in id as ID ]\n // last\n}   \n// trailing\n";
+
+    #[test]
+    fn test_parse_then_print_is_byte_for_byte() {
+        for source in [STARTER, COMPOSED, ODD_LAYOUT] {
+            assert_eq!(parse_source(source).unwrap().to_source(), source);
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip_keeps_trivia() {
+        let ast = parse_source(ODD_LAYOUT).unwrap();
+        let loaded = crate::protocol::ast::AstNode::from_json(&ast.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_source(), ODD_LAYOUT);
+    }
+
+    #[test]
+    fn test_trees_without_trivia_print_parseable_source() {
+        let ast = parse_source(STARTER).unwrap();
+        let rebuilt = Program::from_ast(&ast).unwrap().to_ast();
+        let printed = rebuilt.to_source();
+
+        assert!(!printed.contains("//"));
+        let reparsed = Program::from_ast(&parse_source(&printed).unwrap()).unwrap();
+        let original = Program::from_ast(&ast).unwrap();
+        assert_eq!(reparsed.protocols.len(), original.protocols.len());
+        assert_eq!(
+            reparsed.protocols[0].messages().count(),
+            original.protocols[0].messages().count()
+        );
+        assert!(printed.starts_with("Purchase<Protocol>(\"the generic action"));
+    }
+}
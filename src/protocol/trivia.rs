@@ -0,0 +1,414 @@
+//! Whitespace and comments of the parsed source, kept on the AST so that
+//! [`AstNode::to_source`] gives back the original text byte for byte.
+//!
+//! Every run of trivia between two tokens is attached to exactly one node:
+//!
+//! - to the node that ends right before it as `trailing_trivia`, up to and including the
+//!   first line break, so a `// comment` after an element stays with that element. An
+//!   element of a comma separated list ends after its comma, and trivia between the element
+//!   and its comma is its `inner_trivia` for `,`;
+//! - the rest to the node that starts right after it as `leading_trivia`, so comment lines
+//!   above an element stay with it;
+//! - when no node touches the run (e.g. inside `( "..." )`), to the innermost node around
+//!   it as `inner_trivia`, keyed by the token that follows the run.
+//!
+//! When several nodes end or start at the same place, the outermost one that is not a
+//! section takes the trivia: a comment above an interaction belongs to its
+//! `InteractionItem`, not to the `RoleRef` it starts with.
+
+use super::ast::{AstNode, AstNodeType};
+use super::span::Span;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TriviaKind {
+    /// Spaces, tabs and line breaks, including blank lines
+    Whitespace,
+    /// `// ...` up to, but not including, the line break
+    Comment,
+}
+
+/// Piece of source text between tokens
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+impl Trivia {
+    pub fn is_comment(&self) -> bool {
+        self.kind == TriviaKind::Comment
+    }
+}
+
+impl AstNode {
+    /// All trivia of the tree rooted at this node, in source order
+    pub fn all_trivia(&self) -> Vec<&Trivia> {
+        fn collect<'a>(node: &'a AstNode, trivia: &mut Vec<&'a Trivia>) {
+            trivia.extend(&node.leading_trivia);
+            trivia.extend(node.inner_trivia.values().flatten());
+            for child in &node.children {
+                collect(child, trivia);
+            }
+            trivia.extend(&node.trailing_trivia);
+        }
+
+        let mut trivia = Vec::new();
+        collect(self, &mut trivia);
+        trivia.sort_by_key(|piece| piece.span.start);
+        trivia
+    }
+
+    /// Comments of the tree rooted at this node, in source order
+    pub fn comments(&self) -> Vec<&Trivia> {
+        self.all_trivia()
+            .into_iter()
+            .filter(|piece| piece.is_comment())
+            .collect()
+    }
+}
+
+/// Attach the whitespace and comments of `source` to the tree parsed from it
+pub fn attach_trivia(root: &mut AstNode, source: &str) {
+    let mut nodes = Vec::new();
+    flatten(root, &mut Vec::new(), &mut nodes);
+    for node in &mut nodes {
+        let next = next_token(source, node.span.end);
+        if source[next..].starts_with(',') {
+            node.end = next + 1;
+        }
+    }
+
+    for run in scan_runs(source) {
+        let (start, end) = (run[0].span.start, run[run.len() - 1].span.end);
+        let key = trivia_key(&source[end..]);
+        if key == ","
+            && let Some(element) = boundary_node(&nodes, |node| node.span.end == start)
+        {
+            let node = node_at(root, &element.path);
+            node.inner_trivia
+                .entry(key.to_string())
+                .or_default()
+                .extend(run);
+            continue;
+        }
+
+        let ending = boundary_node(&nodes, |node| node.end == start);
+        let starting = boundary_node(&nodes, |node| node.span.start == end);
+
+        match (ending, starting) {
+            (Some(ending), Some(starting)) => {
+                let (trailing, leading) = split_at_line_end(run);
+                node_at(root, &ending.path).trailing_trivia.extend(trailing);
+                node_at(root, &starting.path).leading_trivia.extend(leading);
+            }
+            (Some(ending), None) => node_at(root, &ending.path).trailing_trivia.extend(run),
+            (None, Some(starting)) => node_at(root, &starting.path).leading_trivia.extend(run),
+            (None, None) => {
+                let container = nodes
+                    .iter()
+                    .filter(|node| node.span.start < start && node.span.end > end)
+                    .max_by_key(|node| node.path.len());
+                let node = match container {
+                    Some(container) => node_at(root, &container.path),
+                    None => root,
+                };
+                node.inner_trivia
+                    .entry(key.to_string())
+                    .or_default()
+                    .extend(run);
+            }
+        }
+    }
+}
+
+/// Key of the inner trivia printed before `token`: the whole word for keywords, the first
+/// character for punctuation and string literals
+pub(super) fn trivia_key(token: &str) -> &str {
+    let word = token
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(token.len());
+    match word {
+        0 => token.get(..1).unwrap_or(""),
+        _ => &token[..word],
+    }
+}
+
+struct FlatNode {
+    path: Vec<usize>,
+    span: Span,
+    /// End of the node including the comma that follows it in a list
+    end: usize,
+    node_type: AstNodeType,
+}
+
+fn flatten(node: &AstNode, path: &mut Vec<usize>, nodes: &mut Vec<FlatNode>) {
+    nodes.push(FlatNode {
+        path: path.clone(),
+        span: node.span,
+        end: node.span.end,
+        node_type: node.node_type.clone(),
+    });
+    for (index, child) in node.children.iter().enumerate() {
+        path.push(index);
+        flatten(child, path, nodes);
+        path.pop();
+    }
+}
+
+/// Outermost node at a boundary, preferring elements over the sections that hold them
+fn boundary_node(nodes: &[FlatNode], at: impl Fn(&FlatNode) -> bool) -> Option<&FlatNode> {
+    let candidates: Vec<&FlatNode> = nodes
+        .iter()
+        .filter(|node| node.node_type != AstNodeType::Program && at(node))
+        .collect();
+    let outermost = |elements_only: bool| {
+        candidates
+            .iter()
+            .copied()
+            .filter(|node| !(elements_only && is_section(&node.node_type)))
+            .min_by_key(|node| node.path.len())
+    };
+    outermost(true).or_else(|| outermost(false))
+}
+
+fn is_section(node_type: &AstNodeType) -> bool {
+    matches!(
+        node_type,
+        AstNodeType::RolesSection
+            | AstNodeType::TypesSection
+            | AstNodeType::ParametersSection
+            | AstNodeType::InteractionSection
+    )
+}
+
+fn node_at<'a>(root: &'a mut AstNode, path: &[usize]) -> &'a mut AstNode {
+    path.iter()
+        .fold(root, |node, &index| node.children[index].as_mut())
+}
+
+/// Split a run after its first line break; a run without one is all trailing
+fn split_at_line_end(run: Vec<Trivia>) -> (Vec<Trivia>, Vec<Trivia>) {
+    let mut trailing = Vec::new();
+    let mut pieces = run.into_iter();
+    for piece in pieces.by_ref() {
+        match piece.text.find('\n') {
+            Some(newline) if piece.kind == TriviaKind::Whitespace => {
+                let split = piece.span.start + newline + 1;
+                trailing.push(Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: piece.text[..=newline].to_string(),
+                    span: Span::new(piece.span.start, split),
+                });
+                let mut leading = Vec::new();
+                if split < piece.span.end {
+                    leading.push(Trivia {
+                        kind: TriviaKind::Whitespace,
+                        text: piece.text[newline + 1..].to_string(),
+                        span: Span::new(split, piece.span.end),
+                    });
+                }
+                leading.extend(pieces);
+                return (trailing, leading);
+            }
+            _ => trailing.push(piece),
+        }
+    }
+    (trailing, Vec::new())
+}
+
+/// Offset of the first token at or after `offset`
+fn next_token(source: &str, mut offset: usize) -> usize {
+    loop {
+        let rest = &source[offset..];
+        let trimmed = rest.trim_start_matches([' ', '\t', '\n', '\r']);
+        offset += rest.len() - trimmed.len();
+        if !trimmed.starts_with("//") {
+            return offset;
+        }
+        offset += trimmed.find(['\n', '\r']).unwrap_or(trimmed.len());
+    }
+}
+
+/// Maximal runs of whitespace and comments outside string literals
+fn scan_runs(source: &str) -> Vec<Vec<Trivia>> {
+    let bytes = source.as_bytes();
+    let mut runs = Vec::new();
+    let mut run: Vec<Trivia> = Vec::new();
+    let mut in_string = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if in_string {
+            in_string = bytes[i] != b'"';
+            i += 1;
+            continue;
+        }
+        match bytes[i] {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                let end = source[i..]
+                    .find(|c| !matches!(c, ' ' | '\t' | '\n' | '\r'))
+                    .map_or(source.len(), |offset| i + offset);
+                run.push(piece(source, TriviaKind::Whitespace, i, end));
+                i = end;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                let end = source[i..]
+                    .find(['\n', '\r'])
+                    .map_or(source.len(), |offset| i + offset);
+                run.push(piece(source, TriviaKind::Comment, i, end));
+                i = end;
+            }
+            byte => {
+                if !run.is_empty() {
+                    runs.push(std::mem::take(&mut run));
+                }
+                in_string = byte == b'"';
+                i += 1;
+            }
+        }
+    }
+    if !run.is_empty() {
+        runs.push(run);
+    }
+    runs
+}
+
+fn piece(source: &str, kind: TriviaKind, start: usize, end: usize) -> Trivia {
+    Trivia {
+        kind,
+        text: source[start..end].to_string(),
+        span: Span::new(start, end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpiler::parse_source;
+
+    const SOURCE: &str = r#"// Purchase protocol
+
+Purchase <Protocol>("buy things") { // header
+    roles
+        B <Agent>( "buyer" ), // the buyer
+        S <Agent>("seller")
+
+    parameters
+        ID <String>   key ("id"),
+        tags <List <Enum [ a,b ]>>("tags")
+
+    // Buyer opens
+    B->S : rfq <Action>("ask")[ out ID , out tags ] // first
+
+    Pay <Enactment>[ B as Payer , in ID as ref ]
+    // the end
+}
+"#;
+
+    fn item(ast: &AstNode, index: usize) -> &AstNode {
+        let protocol = ast.find_child(AstNodeType::Protocol).unwrap();
+        let section = protocol
+            .find_child(AstNodeType::InteractionSection)
+            .unwrap();
+        &section.children[index]
+    }
+
+    fn texts(trivia: &[Trivia]) -> Vec<&str> {
+        trivia.iter().map(|piece| piece.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_comments_attach_to_their_elements() {
+        let ast = parse_source(SOURCE).unwrap();
+        let protocol = ast.find_child(AstNodeType::Protocol).unwrap();
+        assert_eq!(
+            texts(&protocol.leading_trivia),
+            vec!["// Purchase protocol", "\n\n"]
+        );
+
+        let roles = protocol.find_child(AstNodeType::RolesSection).unwrap();
+        assert_eq!(
+            texts(&roles.leading_trivia),
+            vec![" ", "// header", "\n    "]
+        );
+        assert_eq!(
+            texts(&roles.children[0].trailing_trivia),
+            vec![" ", "// the buyer", "\n"]
+        );
+
+        let first = item(&ast, 0);
+        assert_eq!(first.node_type, AstNodeType::InteractionItem);
+        assert_eq!(
+            texts(&first.leading_trivia),
+            vec!["\n    ", "// Buyer opens", "\n    "]
+        );
+        assert_eq!(texts(&first.trailing_trivia), vec![" ", "// first", "\n"]);
+
+        let last = item(&ast, 1);
+        assert_eq!(
+            texts(&last.trailing_trivia),
+            vec!["\n    ", "// the end", "\n"]
+        );
+    }
+
+    #[test]
+    fn test_inner_trivia_is_keyed_by_the_next_token() {
+        let ast = parse_source(SOURCE).unwrap();
+        let protocol = ast.find_child(AstNodeType::Protocol).unwrap();
+        let roles = protocol.find_child(AstNodeType::RolesSection).unwrap();
+        let annotation = roles.children[0]
+            .find_child(AstNodeType::Annotation)
+            .unwrap();
+        assert_eq!(texts(&annotation.inner_trivia["\""]), vec![" "]);
+        assert_eq!(texts(&annotation.inner_trivia[")"]), vec![" "]);
+
+        let parameters = protocol.find_child(AstNodeType::ParametersSection).unwrap();
+        assert_eq!(
+            texts(&parameters.children[0].inner_trivia["key"]),
+            vec!["   "]
+        );
+
+        let payer = &item(&ast, 1).children[0].children[1];
+        assert_eq!(texts(&payer.inner_trivia["as"]), vec![" "]);
+    }
+
+    #[test]
+    fn test_comments_in_source_order() {
+        let ast = parse_source(SOURCE).unwrap();
+        let comments: Vec<&str> = ast
+            .comments()
+            .into_iter()
+            .map(|comment| comment.text.as_str())
+            .collect();
+        assert_eq!(
+            comments,
+            vec![
+                "// Purchase protocol",
+                "// header",
+                "// the buyer",
+                "// Buyer opens",
+                "// first",
+                "// the end"
+            ]
+        );
+
+        // Every byte outside tokens is accounted for exactly once
+        let mut end = 0;
+        for piece in ast.all_trivia() {
+            assert!(piece.span.start >= end);
+            assert_eq!(&SOURCE[piece.span.start..piece.span.end], piece.text);
+            end = piece.span.end;
+        }
+    }
+
+    #[test]
+    fn test_trivia_key() {
+        assert_eq!(trivia_key("key (\"id\")"), "key");
+        assert_eq!(trivia_key("\"text\")"), "\"");
+        assert_eq!(trivia_key("]"), "]");
+        assert_eq!(trivia_key(""), "");
+    }
+}
@@ -0,0 +1,105 @@
+use super::ast::{AstNode, AstNodeType};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Type of a BMPP parameter or record field
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BmppType {
+    String,
+    Int,
+    Float,
+    Bool,
+    /// `Enum[a, b, c]`
+    Enum(Vec<String>),
+    /// `List<T>`
+    List(Box<BmppType>),
+    /// Record type declared in the `types` section
+    Named(String),
+}
+
+impl BmppType {
+    /// Build the type described by a type node (BasicType, EnumType, ListType or TypeRef)
+    pub fn from_node(node: &AstNode) -> Option<Self> {
+        match node.node_type {
+            AstNodeType::BasicType => Self::basic(node.get_string("type")?),
+            AstNodeType::EnumType => Some(Self::Enum(
+                node.find_children(AstNodeType::Identifier)
+                    .iter()
+                    .filter_map(|variant| variant.get_string("name").cloned())
+                    .collect(),
+            )),
+            AstNodeType::ListType => {
+                let element = node.children.iter().find(|c| c.node_type.is_type())?;
+                Some(Self::List(Box::new(Self::from_node(element)?)))
+            }
+            AstNodeType::TypeRef => Some(Self::Named(node.get_string("name")?.clone())),
+            _ => None,
+        }
+    }
+
+    fn basic(name: &str) -> Option<Self> {
+        match name {
+            "String" => Some(Self::String),
+            "Int" => Some(Self::Int),
+            "Float" => Some(Self::Float),
+            "Bool" => Some(Self::Bool),
+            _ => None,
+        }
+    }
+
+    /// Names of the record types this type refers to, including inside lists
+    pub fn referenced_names(&self) -> Vec<&str> {
+        match self {
+            Self::Named(name) => vec![name.as_str()],
+            Self::List(element) => element.referenced_names(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Record types that are embedded by value, i.e. not behind a list
+    pub fn embedded_name(&self) -> Option<&str> {
+        match self {
+            Self::Named(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl Display for BmppType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::String => write!(f, "String"),
+            Self::Int => write!(f, "Int"),
+            Self::Float => write!(f, "Float"),
+            Self::Bool => write!(f, "Bool"),
+            Self::Enum(variants) => write!(f, "Enum[{}]", variants.join(", ")),
+            Self::List(element) => write!(f, "List<{}>", element),
+            Self::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_display() {
+        let ty = BmppType::List(Box::new(BmppType::Enum(vec![
+            "pending".to_string(),
+            "shipped".to_string(),
+        ])));
+        assert_eq!(ty.to_string(), "List<Enum[pending, shipped]>");
+        assert_eq!(
+            BmppType::Named("Address".to_string()).to_string(),
+            "Address"
+        );
+    }
+
+    #[test]
+    fn test_referenced_names() {
+        let ty = BmppType::List(Box::new(BmppType::Named("Item".to_string())));
+        assert_eq!(ty.referenced_names(), vec!["Item"]);
+        assert_eq!(ty.embedded_name(), None);
+        assert!(BmppType::Int.referenced_names().is_empty());
+    }
+}
@@ -39,7 +39,7 @@ pub fn run_file<P: AsRef<Path>>(source_path: P, output_dir: P, as_lib: bool) ->
     let project_builder = ProjectBuilder::new(&llm_client);
     project_builder.build(output_dir, &source_code, &generated_code, as_lib)?;
 
-    if as_lib == true {
+    if as_lib {
         println!("\n✅ Library file has been created at {:?}", output_dir);
         return Ok(());
     }
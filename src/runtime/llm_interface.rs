@@ -1,6 +1,6 @@
-use anyhow::{anyhow, Result};
+use anyhow::{Result, anyhow};
 use reqwest;
-use serde_json::{json, Value};
+use serde_json::{Value, json};
 use std::collections::HashMap;
 use std::env;
 
@@ -57,7 +57,7 @@ impl LlmInterface {
     fn is_ollama_available(url: &str) -> bool {
         let client = reqwest::blocking::Client::new();
         match client
-            .get(&format!("{}/api/tags", url))
+            .get(format!("{}/api/tags", url))
             .timeout(std::time::Duration::from_secs(2))
             .send()
         {
@@ -85,7 +85,7 @@ impl LlmInterface {
 
         let response = self
             .client
-            .post(&format!("{}/chat/completions", self.base_url))
+            .post(format!("{}/chat/completions", self.base_url))
             .header("Content-Type", "application/json")
             .json(&request_body)
             .send();
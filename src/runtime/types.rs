@@ -94,6 +94,7 @@ mod tests {
 
     // --- Tests for into_f64 ---
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_vibe_value_into_f64() {
         assert_eq!(VibeValue::Number(3.14).into_f64(), 3.14);
         assert_eq!(VibeValue::String("99.9".to_string()).into_f64(), 99.9);
@@ -102,9 +103,9 @@ mod tests {
     // --- Tests for into_bool ---
     #[test]
     fn test_vibe_value_into_bool() {
-        assert_eq!(VibeValue::Boolean(true).into_bool(), true);
-        assert_eq!(VibeValue::String("true".to_string()).into_bool(), true);
-        assert_eq!(VibeValue::String("FALSE".to_string()).into_bool(), false);
+        assert!(VibeValue::Boolean(true).into_bool());
+        assert!(VibeValue::String("true".to_string()).into_bool());
+        assert!(!VibeValue::String("FALSE".to_string()).into_bool());
     }
 
     #[test]
@@ -1,357 +1,205 @@
-use crate::protocol::ast::{AstNode, AstNodeType};
-use anyhow::{anyhow, Result};
+use crate::protocol::ast::{AstNode, ParameterDirection};
+use crate::protocol::model::{self, Enactment, Interaction, InteractionItem, Program, Protocol};
+use crate::protocol::types::BmppType;
+use crate::transpiler::composition::{bind_enactment, enactment_types};
+use crate::transpiler::validation::enactment_inputs;
+use anyhow::{Result, anyhow};
 use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Serialize)]
-struct Protocol {
-    name: String,
-    description: String,
-    roles: Vec<Role>,
+/// Rust types chosen for the parameters, records and messages of one protocol
+struct ProtocolTypes {
+    type_definitions: Vec<TypeDefinition>,
     parameters: Vec<Parameter>,
-    interactions: Vec<InteractionItem>,
-}
-
-#[derive(Serialize)]
-struct Role {
-    name: String,
-    description: String,
+    /// Name of the message struct generated for each action
+    messages: HashMap<String, String>,
 }
 
 #[derive(Serialize)]
 struct Parameter {
     name: String,
     param_type: String,
+    rust_type: String,
+    default_value: String,
     description: String,
 }
 
+/// Rust type generated for an inline enum or a record from the types section
 #[derive(Serialize)]
-#[serde(tag = "type")]
-enum InteractionItem {
-    StandardInteraction(StandardInteraction),
-    ProtocolComposition(ProtocolComposition),
+#[serde(tag = "kind")]
+enum TypeDefinition {
+    Enum {
+        name: String,
+        variants: Vec<String>,
+    },
+    Record {
+        name: String,
+        description: String,
+        fields: Vec<Parameter>,
+    },
 }
 
-#[derive(Serialize)]
-struct StandardInteraction {
-    from_role: String,
-    to_role: String,
-    action: String,
-    description: String,
-    parameter_flows: Vec<ParameterFlow>,
+impl TypeDefinition {
+    fn name(&self) -> &str {
+        match self {
+            Self::Enum { name, .. } | Self::Record { name, .. } => name,
+        }
+    }
 }
 
-#[derive(Serialize)]
-struct ProtocolComposition {
-    protocol_name: String,
-    roles: Vec<String>,
-    parameter_flows: Vec<ParameterFlow>,
+pub struct BmppCodeGenerator {
+    typestate: bool,
+    /// Role whose trait and typestate are generated, or every role when unset
+    role: Option<String>,
 }
 
-#[derive(Serialize)]
-struct ParameterFlow {
-    direction: String,
-    parameter: String,
+impl Default for BmppCodeGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub struct BmppCodeGenerator;
-
 impl BmppCodeGenerator {
     pub fn new() -> Self {
-        Self
-    }
-
-    pub fn generate(&self, ast: &AstNode) -> Result<String> {
-        match ast.node_type {
-            AstNodeType::Program => self.generate_program(ast),
-            _ => Err(anyhow!("Expected Program node, got {:?}", ast.node_type)),
-        }
-    }
-
-    fn generate_program(&self, ast: &AstNode) -> Result<String> {
-        let mut protocols = Vec::new();
-
-        // Process each protocol in the program
-        for protocol_node in &ast.children {
-            if protocol_node.node_type == AstNodeType::Protocol {
-                let protocol = self.process_protocol(protocol_node)?;
-                protocols.push(protocol);
-            }
-        }
-
-        if protocols.is_empty() {
-            return Err(anyhow!("No protocols found in AST"));
-        }
-
-        self.generate_rust_code(&protocols)
-    }
-
-    fn process_protocol(&self, node: &AstNode) -> Result<Protocol> {
-        let mut name = "UnknownProtocol".to_string();
-        let mut description = "No description".to_string();
-        let mut roles = Vec::new();
-        let mut parameters = Vec::new();
-        let mut interactions = Vec::new();
-
-        for child in &node.children {
-            match child.node_type {
-                AstNodeType::ProtocolName => {
-                    if let Some(protocol_name) = child.get_string("name") {
-                        name = protocol_name.clone();
-                    }
-                }
-                AstNodeType::Annotation => {
-                    if let Some(desc) = child.get_string("description") {
-                        description = desc.clone();
-                    }
-                }
-                AstNodeType::RolesSection => {
-                    roles = self.process_roles(child)?;
-                }
-                AstNodeType::ParametersSection => {
-                    parameters = self.process_parameters(child)?;
-                }
-                AstNodeType::InteractionSection => {
-                    interactions = self.process_interaction_section(child)?;
-                }
-                _ => {}
-            }
-        }
-
-        Ok(Protocol {
-            name,
-            description,
-            roles,
-            parameters,
-            interactions,
-        })
-    }
-
-    fn process_roles(&self, node: &AstNode) -> Result<Vec<Role>> {
-        let mut roles = Vec::new();
-
-        for child in &node.children {
-            if child.node_type == AstNodeType::RoleDecl {
-                let role = self.process_role_decl(child)?;
-                roles.push(role);
-            }
+        Self {
+            typestate: false,
+            role: None,
         }
-
-        Ok(roles)
     }
 
-    fn process_role_decl(&self, node: &AstNode) -> Result<Role> {
-        let mut name = "UnknownRole".to_string();
-        let mut description = "No description".to_string();
-
-        for child in &node.children {
-            match child.node_type {
-                AstNodeType::Identifier => {
-                    if let Some(role_name) = child.get_string("name") {
-                        name = role_name.clone();
-                    }
-                }
-                AstNodeType::Annotation => {
-                    if let Some(desc) = child.get_string("description") {
-                        description = desc.clone();
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        Ok(Role { name, description })
+    /// Also generate a typestate per role, so that sending a message before the ones it
+    /// depends on is a compile error
+    pub fn with_typestate(mut self, typestate: bool) -> Self {
+        self.typestate = typestate;
+        self
     }
 
-    fn process_parameters(&self, node: &AstNode) -> Result<Vec<Parameter>> {
-        let mut parameters = Vec::new();
-
-        for child in &node.children {
-            if child.node_type == AstNodeType::ParameterDecl {
-                let parameter = self.process_parameter_decl(child)?;
-                parameters.push(parameter);
-            }
-        }
-
-        Ok(parameters)
+    /// Generate the trait and typestate of `role` only, for code generated from its
+    /// projection
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.role = Some(role.to_string());
+        self
     }
 
-    fn process_parameter_decl(&self, node: &AstNode) -> Result<Parameter> {
-        let mut name = "unknown_param".to_string();
-        let mut param_type = "String".to_string();
-        let mut description = "No description".to_string();
-
-        for child in &node.children {
-            match child.node_type {
-                AstNodeType::Identifier => {
-                    if let Some(param_name) = child.get_string("name") {
-                        name = param_name.clone();
-                    }
-                }
-                AstNodeType::BasicType => {
-                    if let Some(type_name) = child.get_string("type") {
-                        param_type = type_name.clone();
-                    }
-                }
-                AstNodeType::Annotation => {
-                    if let Some(desc) = child.get_string("description") {
-                        description = desc.clone();
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        Ok(Parameter {
-            name,
-            param_type,
-            description,
-        })
+    pub fn generate(&self, ast: &AstNode) -> Result<String> {
+        self.generate_program(&Program::from_ast(ast)?)
     }
 
-    fn process_interaction_section(&self, node: &AstNode) -> Result<Vec<InteractionItem>> {
-        let mut interactions = Vec::new();
-
-        for child in &node.children {
-            if child.node_type == AstNodeType::InteractionItem {
-                let interaction_item = self.process_interaction_item(child)?;
-                interactions.push(interaction_item);
-            }
+    pub fn generate_program(&self, program: &Program) -> Result<String> {
+        if program.protocols.is_empty() {
+            return Err(anyhow!("No protocols found in AST"));
         }
 
-        Ok(interactions)
-    }
+        let mut types = program
+            .protocols
+            .iter()
+            .map(|protocol| self.process_types(protocol))
+            .collect::<Result<Vec<_>>>()?;
 
-    fn process_interaction_item(&self, node: &AstNode) -> Result<InteractionItem> {
-        for child in &node.children {
-            match child.node_type {
-                AstNodeType::StandardInteraction => {
-                    let interaction = self.process_standard_interaction(child)?;
-                    return Ok(InteractionItem::StandardInteraction(interaction));
-                }
-                AstNodeType::ProtocolComposition => {
-                    let composition = self.process_protocol_composition(child)?;
-                    return Ok(InteractionItem::ProtocolComposition(composition));
+        // Message structs are named after their action, unless another type or message
+        // already took that name; the protocol name is then put in front of it
+        let mut taken: HashSet<String> = types
+            .iter()
+            .flat_map(|protocol_types| &protocol_types.type_definitions)
+            .map(|definition| definition.name().to_string())
+            .chain(["Agent", "ProtocolError", "Pending", "Done", "Self"].map(String::from))
+            .chain(program.protocols.iter().flat_map(|protocol| {
+                [
+                    format!("{}Protocol", protocol.name),
+                    format!("{}Message", protocol.name),
+                ]
+            }))
+            .collect();
+        for (protocol, protocol_types) in program.protocols.iter().zip(&mut types) {
+            for message in protocol.messages() {
+                let mut name = to_pascal_case(message.action.as_str());
+                if !taken.insert(name.clone()) {
+                    name = format!("{}{}", protocol.name, name);
+                    taken.insert(name.clone());
                 }
-                _ => {}
+                protocol_types
+                    .messages
+                    .insert(message.action.value.clone(), name);
             }
         }
 
-        Err(anyhow!(
-            "No valid interaction type found in InteractionItem"
-        ))
+        self.generate_rust_code(&program.protocols, &types)
     }
 
-    fn process_standard_interaction(&self, node: &AstNode) -> Result<StandardInteraction> {
-        let mut from_role = "Unknown".to_string();
-        let mut to_role = "Unknown".to_string();
-        let mut action = "unknown_action".to_string();
-        let mut description = "No description".to_string();
-        let mut parameter_flows = Vec::new();
-
-        for child in &node.children {
-            match child.node_type {
-                AstNodeType::RoleRef => {
-                    if let Some(name) = child.get_string("name") {
-                        if from_role == "Unknown" {
-                            from_role = name.clone();
-                        } else if to_role == "Unknown" {
-                            to_role = name.clone();
-                        }
-                    }
-                }
-                AstNodeType::ActionName => {
-                    if let Some(name) = child.get_string("name") {
-                        action = name.clone();
-                    }
-                }
-                AstNodeType::Annotation => {
-                    if let Some(desc) = child.get_string("description") {
-                        description = desc.clone();
-                    }
-                }
-                AstNodeType::ParameterFlow => {
-                    let param_flow = self.process_parameter_flow(child)?;
-                    parameter_flows.push(param_flow);
-                }
-                _ => {}
-            }
+    fn process_types(&self, protocol: &Protocol) -> Result<ProtocolTypes> {
+        // Record names must be known before any parameter can refer to them
+        let records: HashSet<&str> = protocol
+            .type_decls()
+            .iter()
+            .map(|decl| decl.name.as_str())
+            .collect();
+
+        let mut type_definitions = Vec::new();
+        for decl in protocol.type_decls() {
+            let name = decl.name.value.clone();
+            let fields = decl
+                .fields
+                .iter()
+                .map(|field| self.process_parameter(field, &name, &records, &mut type_definitions))
+                .collect::<Result<_>>()?;
+            type_definitions.push(TypeDefinition::Record {
+                name,
+                description: decl.description.value.clone(),
+                fields,
+            });
         }
 
-        Ok(StandardInteraction {
-            from_role,
-            to_role,
-            action,
-            description,
-            parameter_flows,
-        })
-    }
-
-    fn process_protocol_composition(&self, node: &AstNode) -> Result<ProtocolComposition> {
-        let mut protocol_name = "UnknownProtocol".to_string();
-        let mut roles = Vec::new();
-        let mut parameter_flows = Vec::new();
-
-        for child in &node.children {
-            match child.node_type {
-                AstNodeType::ProtocolReference => {
-                    // Extract protocol name from ProtocolReference
-                    for ref_child in &child.children {
-                        if ref_child.node_type == AstNodeType::Identifier {
-                            if let Some(name) = ref_child.get_string("name") {
-                                protocol_name = name.clone();
-                            }
-                        }
-                    }
-                }
-                AstNodeType::Identifier => {
-                    // This is a role identifier in the composition
-                    if let Some(name) = child.get_string("name") {
-                        roles.push(name.clone());
-                    }
-                }
-                AstNodeType::ParameterFlow => {
-                    let param_flow = self.process_parameter_flow(child)?;
-                    parameter_flows.push(param_flow);
-                }
-                _ => {}
-            }
-        }
+        let parameters = protocol
+            .parameters
+            .iter()
+            .map(|param| {
+                self.process_parameter(param, &protocol.name.value, &records, &mut type_definitions)
+            })
+            .collect::<Result<_>>()?;
 
-        Ok(ProtocolComposition {
-            protocol_name,
-            roles,
-            parameter_flows,
+        Ok(ProtocolTypes {
+            type_definitions,
+            parameters,
+            messages: HashMap::new(),
         })
     }
 
-    fn process_parameter_flow(&self, node: &AstNode) -> Result<ParameterFlow> {
-        let mut direction = "unknown".to_string();
-        let mut parameter = "unknown".to_string();
-
-        if let Some(dir) = node.get_string("direction") {
-            direction = dir.clone();
-        }
-
-        for child in &node.children {
-            if child.node_type == AstNodeType::Identifier {
-                if let Some(name) = child.get_string("name") {
-                    parameter = name.clone();
-                }
-            }
-        }
+    /// Rust view of a parameter or record field; inline enums are named after `owner` and
+    /// the parameter
+    fn process_parameter(
+        &self,
+        param: &model::Parameter,
+        owner: &str,
+        records: &HashSet<&str>,
+        type_definitions: &mut Vec<TypeDefinition>,
+    ) -> Result<Parameter> {
+        let name = param.name.value.clone();
+        let bmpp_type = param.ty.ty();
+
+        let enum_name = format!("{}{}", owner, to_pascal_case(&name));
+        let rust_type = self
+            .map_bmpp_type_to_rust(&bmpp_type, &enum_name, records, type_definitions)
+            .map_err(|e| anyhow!("{} for parameter '{}' of '{}'", e, name, owner))?;
 
-        Ok(ParameterFlow {
-            direction,
-            parameter,
+        Ok(Parameter {
+            name,
+            param_type: bmpp_type.to_string(),
+            rust_type,
+            default_value: self.get_default_value(&bmpp_type).to_string(),
+            description: param.description.value.clone(),
         })
     }
 
-    fn generate_rust_code(&self, protocols: &[Protocol]) -> Result<String> {
+    fn generate_rust_code(
+        &self,
+        protocols: &[Protocol],
+        types: &[ProtocolTypes],
+    ) -> Result<String> {
         let mut code = String::new();
 
         code.push_str("// Generated BMPP Protocol Implementation\n");
         code.push_str("use serde::{Serialize, Deserialize};\n");
-        code.push_str("use std::collections::HashMap;\n");
+        code.push_str("use std::collections::{HashMap, HashSet};\n");
         code.push_str("use anyhow::Result;\n\n");
 
         // Generate Agent struct first
@@ -360,18 +208,65 @@ impl BmppCodeGenerator {
         code.push_str("    pub id: String,\n");
         code.push_str("    pub name: String,\n");
         code.push_str("}\n\n");
+        code.push_str(PROTOCOL_ERROR);
+        if self.typestate {
+            code.push_str(TYPESTATE_MARKERS);
+        }
+
+        // Generate enums and records, once per name across protocols
+        let mut emitted = HashSet::new();
+        for protocol_types in types {
+            for definition in &protocol_types.type_definitions {
+                if emitted.insert(definition.name().to_string()) {
+                    code.push_str(&self.generate_type_definition(definition));
+                }
+            }
+        }
 
         // Generate code for each protocol
-        for protocol in protocols {
-            code.push_str(&self.generate_protocol_code(protocol)?);
+        for (protocol, protocol_types) in protocols.iter().zip(types) {
+            code.push_str(&self.generate_protocol_code(
+                protocol,
+                protocol_types,
+                protocols,
+                types,
+            )?);
         }
 
         Ok(code)
     }
 
-    fn generate_protocol_code(&self, protocol: &Protocol) -> Result<String> {
+    fn generate_protocol_code(
+        &self,
+        protocol: &Protocol,
+        types: &ProtocolTypes,
+        protocols: &[Protocol],
+        all_types: &[ProtocolTypes],
+    ) -> Result<String> {
         let mut code = String::new();
 
+        // Generate a struct per message and the enum of all of them
+        for message in protocol.messages() {
+            code.push_str(&self.generate_message_struct(message, types));
+        }
+        if protocol.messages().next().is_some() {
+            code.push_str(&format!(
+                "/// Message of the {} protocol, tagged with its action\n",
+                protocol.name
+            ));
+            code.push_str("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+            code.push_str("#[serde(tag = \"action\")]\n");
+            code.push_str(&format!("pub enum {}Message {{\n", protocol.name));
+            for message in protocol.messages() {
+                let name = &types.messages[message.action.as_str()];
+                if name != message.action.as_str() {
+                    code.push_str(&format!("    #[serde(rename = \"{}\")]\n", message.action));
+                }
+                code.push_str(&format!("    {}({}),\n", name, name));
+            }
+            code.push_str("}\n\n");
+        }
+
         // Generate protocol struct
         code.push_str(&format!(
             "/// {}\n#[derive(Debug, Clone, Serialize, Deserialize)]\npub struct {}Protocol {{\n",
@@ -380,236 +275,1154 @@ impl BmppCodeGenerator {
 
         // Add roles as fields
         for role in &protocol.roles {
-            code.push_str(&format!(
-                "    /// {}\n    pub {}: Agent,\n",
-                role.description,
-                role.name.to_lowercase()
+            code.push_str(&struct_field(
+                &role.description.value,
+                role.name.as_str(),
+                "Agent",
             ));
         }
 
         // Add parameters as fields
-        for param in &protocol.parameters {
-            let rust_type = self.map_bmpp_type_to_rust(&param.param_type);
-            code.push_str(&format!(
-                "    /// {}\n    pub {}: {},\n",
-                param.description,
-                param.name.to_lowercase(),
-                rust_type
+        for param in &types.parameters {
+            code.push_str(&struct_field(
+                &param.description,
+                &param.name,
+                &param.rust_type,
             ));
         }
 
+        code.push_str(
+            "    /// Parameters bound so far in each enactment, by the values of its keys\n",
+        );
+        code.push_str("    pub history: HashMap<String, HashSet<String>>,\n");
         code.push_str("}\n\n");
 
         // Generate implementation
         code.push_str(&format!("impl {}Protocol {{\n", protocol.name));
 
+        let inputs: Vec<String> = enactor_inputs(protocol)
+            .iter()
+            .map(|input| format!("\"{}\"", input))
+            .collect();
+        code.push_str(
+            "    /// Parameters provided by the enactor, known from the start of every enactment\n",
+        );
+        code.push_str(&format!(
+            "    const INPUTS: &[&str] = &[{}];\n\n",
+            inputs.join(", ")
+        ));
+
         // Generate constructor
         code.push_str("    pub fn new() -> Self {\n");
         code.push_str("        Self {\n");
         for role in &protocol.roles {
             code.push_str(&format!(
                 "            {}: Agent {{ id: String::new(), name: \"{}\".to_string() }},\n",
-                role.name.to_lowercase(),
+                rust_identifier(role.name.as_str()),
                 role.name
             ));
         }
-        for param in &protocol.parameters {
-            let default_value = self.get_default_value(&param.param_type);
+        for param in &types.parameters {
             code.push_str(&format!(
                 "            {}: {},\n",
-                param.name.to_lowercase(),
-                default_value
+                rust_identifier(&param.name),
+                param.default_value
             ));
         }
+        code.push_str("            history: HashMap::new(),\n");
         code.push_str("        }\n");
         code.push_str("    }\n\n");
+        code.push_str(BIND_METHOD);
+        if !inputs.is_empty() {
+            code.push_str(&self.generate_start_method(protocol, types));
+        }
+        if protocol.messages().next().is_some() {
+            code.push_str(&self.generate_receive_method(protocol, types));
+        }
 
         // Generate methods for each interaction
         for interaction in &protocol.interactions {
             match interaction {
-                InteractionItem::StandardInteraction(standard) => {
-                    code.push_str(&self.generate_standard_interaction_method(standard, protocol)?);
+                InteractionItem::Interaction(standard) => {
+                    code.push_str(
+                        &self.generate_standard_interaction_method(standard, protocol, types),
+                    );
                 }
-                InteractionItem::ProtocolComposition(composition) => {
-                    code.push_str(&self.generate_composition_method(composition, protocol)?);
+                InteractionItem::Enactment(composition) => {
+                    code.push_str(&self.generate_composition_method(
+                        composition,
+                        protocol,
+                        types,
+                        protocols,
+                        all_types,
+                    )?);
                 }
             }
         }
 
         code.push_str("}\n\n");
 
+        let roles: Vec<&model::Role> = protocol
+            .roles
+            .iter()
+            .filter(|role| {
+                self.role
+                    .as_ref()
+                    .is_none_or(|name| role.name.as_str() == name)
+            })
+            .collect();
+        for role in &roles {
+            code.push_str(&self.generate_role_trait(protocol, role, types, protocols));
+        }
+        if self.typestate {
+            for role in &roles {
+                code.push_str(&self.generate_typestate(protocol, role, types, protocols));
+            }
+        }
+
         Ok(code)
     }
 
-    fn generate_standard_interaction_method(
+    /// Trait implemented by the agent playing `role`: an emitter for each message it sends,
+    /// taking the `in` and `opt` parameters and returning the message, and a handler for
+    /// each message it receives. The traits of the roles it plays in enacted protocols are
+    /// its supertraits.
+    fn generate_role_trait(
         &self,
-        interaction: &StandardInteraction,
         protocol: &Protocol,
-    ) -> Result<String> {
-        let mut code = String::new();
+        role: &model::Role,
+        types: &ProtocolTypes,
+        protocols: &[Protocol],
+    ) -> String {
+        let role_name = role.name.as_str();
+        let mut supertraits = Vec::new();
+        for enactment in protocol.enactments() {
+            let Some(callee) = protocols
+                .iter()
+                .find(|p| p.name.as_str() == enactment.protocol.as_str())
+            else {
+                continue;
+            };
+            let Ok(bindings) = bind_enactment(enactment, callee) else {
+                continue;
+            };
+            for callee_role in &callee.roles {
+                let name = callee_role.name.as_str();
+                let supertrait = role_trait_name(callee, name);
+                if bindings.roles.get(name).map(String::as_str) == Some(role_name)
+                    && !supertraits.contains(&supertrait)
+                {
+                    supertraits.push(supertrait);
+                }
+            }
+        }
 
-        // Collect input and output parameters
-        let mut input_params = Vec::new();
-        let mut output_params = Vec::new();
+        let mut code = format!("/// {}\n", role.description);
+        code.push_str(&format!(
+            "pub trait {}",
+            role_trait_name(protocol, role_name)
+        ));
+        if !supertraits.is_empty() {
+            code.push_str(&format!(": {}", supertraits.join(" + ")));
+        }
 
-        for param in &interaction.parameter_flows {
-            if param.direction == "in" {
-                input_params.push(&param.parameter);
-            } else if param.direction == "out" {
-                output_params.push(&param.parameter);
+        let mut methods = Vec::new();
+        for message in protocol.messages() {
+            let flows: Vec<&model::Flow> = message.flows.iter().collect();
+            let action = to_snake_case(message.action.as_str());
+            let message_name = &types.messages[message.action.as_str()];
+            if message.from.as_str() == role_name {
+                let (arguments, return_type) =
+                    self.method_parameters(&flows, types, Some(message_name));
+                methods.push(format!(
+                    "    /// {} (to {})\n    fn emit_{}(&mut self{}) -> {};\n",
+                    message.description, message.to, action, arguments, return_type
+                ));
+            }
+            if message.to.as_str() == role_name {
+                methods.push(format!(
+                    "    /// {} (from {})\n    fn on_{}(&mut self, message: {}) -> Result<()>;\n",
+                    message.description, message.from, action, message_name
+                ));
             }
         }
 
-        // Generate method signature with proper types
-        let method_name = interaction.action.to_lowercase();
-        let mut signature = format!("    pub fn {}(&mut self", method_name);
+        if methods.is_empty() {
+            code.push_str(" {}\n\n");
+        } else {
+            code.push_str(&format!(" {{\n{}}}\n\n", methods.join("\n")));
+        }
+        code
+    }
 
-        // Add input parameters
-        for input_param in &input_params {
-            let rust_type = self.get_parameter_type(input_param, protocol);
-            signature.push_str(&format!(", {}: {}", input_param.to_lowercase(), rust_type));
+    /// Typestate of `role`, with a type parameter per message it sends or receives and per
+    /// enactment it takes part in, `Pending` until it happened and `Done` after. Receiving a
+    /// message is always possible once. Sending a message, or initiating an enactment whose
+    /// `in` parameters the role's callee role needs, also requires, for each of those the
+    /// enactor does not provide, one of the events telling the role about it to be done, and
+    /// those telling it about an `out` or `nil` parameter to be pending.
+    fn generate_typestate<'a>(
+        &self,
+        protocol: &'a Protocol,
+        role: &model::Role,
+        types: &'a ProtocolTypes,
+        protocols: &'a [Protocol],
+    ) -> String {
+        let role_name = role.name.as_str();
+        // `in` parameters needed by the role and `out` and `nil` ones it must not know yet
+        let requires = |ins: Vec<&'a str>, item: &'a InteractionItem| {
+            let unbound = item
+                .flows()
+                .into_iter()
+                .filter(|flow| {
+                    matches!(
+                        flow.direction,
+                        ParameterDirection::Out | ParameterDirection::Nil
+                    )
+                })
+                .map(|flow| flow.parameter.as_str())
+                .collect();
+            Some((ins, unbound))
+        };
+        let mut events = Vec::new();
+        for item in &protocol.interactions {
+            let (kind, node, description, requires) = match item {
+                InteractionItem::Interaction(message) if message.from.as_str() == role_name => (
+                    EventKind::Send(&types.messages[message.action.as_str()]),
+                    message.action.as_str(),
+                    message.description.as_str(),
+                    requires(
+                        message
+                            .flows
+                            .iter()
+                            .filter(|flow| flow.direction == ParameterDirection::In)
+                            .map(|flow| flow.parameter.as_str())
+                            .collect(),
+                        item,
+                    ),
+                ),
+                InteractionItem::Interaction(message) if message.to.as_str() == role_name => (
+                    EventKind::Receive(&types.messages[message.action.as_str()]),
+                    message.action.as_str(),
+                    message.description.as_str(),
+                    None,
+                ),
+                InteractionItem::Enactment(enactment)
+                    if enactment.roles().any(|r| r.name.as_str() == role_name) =>
+                {
+                    // The role initiates the enactment when its callee role needs some of
+                    // the `in` parameters
+                    let needed: Vec<&str> = enactment_inputs(protocols, enactment)
+                        .into_iter()
+                        .filter(|(_, roles)| roles.contains(&role_name))
+                        .map(|(parameter, _)| parameter)
+                        .collect();
+                    let requires = if needed.is_empty() {
+                        None
+                    } else {
+                        requires(needed, item)
+                    };
+                    (EventKind::Enact, enactment.protocol.as_str(), "", requires)
+                }
+                _ => continue,
+            };
+            events.push(TypestateEvent {
+                kind,
+                node,
+                description,
+                flows: item.flows(),
+                requires,
+            });
+        }
+        if events.is_empty() {
+            return String::new();
         }
 
-        // Determine return type
-        let return_type = match output_params.len() {
-            0 => "Result<()>".to_string(),
-            1 => {
-                let rust_type = self.get_parameter_type(&output_params[0], protocol);
-                format!("Result<{}>", rust_type)
-            }
-            _ => {
-                let types: Vec<String> = output_params
+        let state = format!("{}{}State", protocol.name, to_pascal_case(role_name));
+        let parameters: Vec<String> = (0..events.len()).map(|i| format!("E{}", i)).collect();
+        let nodes: Vec<&str> = events.iter().map(|event| event.node).collect();
+        let mut code = format!(
+            "/// Typestate of role {} in protocol {}, tracking {}\n",
+            role_name,
+            protocol.name,
+            nodes.join(", ")
+        );
+        code.push_str(&format!(
+            "pub struct {}<{}> {{\n    events: std::marker::PhantomData<({},)>,\n}}\n\n",
+            state,
+            parameters
+                .iter()
+                .map(|parameter| format!("{} = Pending", parameter))
+                .collect::<Vec<_>>()
+                .join(", "),
+            parameters.join(", ")
+        ));
+        code.push_str(&format!(
+            "impl {} {{\n    pub fn new() -> Self {{\n        Self {{ events: std::marker::PhantomData }}\n    }}\n}}\n\n",
+            state
+        ));
+
+        // Provided by the enactor, so known without any event
+        let inputs = enactor_inputs(protocol);
+        for (index, event) in events.iter().enumerate() {
+            // Events of the role other than this one telling it about `parameter`
+            let telling = |parameter: &str| -> Vec<usize> {
+                (0..events.len())
+                    .filter(|&i| i != index && events[i].tells(parameter))
+                    .collect()
+            };
+
+            // Events required done and pending, one pair per impl. Any event telling the
+            // role about an `in` parameter will do; requiring the earlier ones to be pending
+            // keeps the impls of the alternatives from overlapping.
+            let mut requirements = vec![(HashSet::new(), HashSet::new())];
+            if let Some((ins, unbound)) = &event.requires {
+                let unbound: HashSet<usize> = unbound
                     .iter()
-                    .map(|param| self.get_parameter_type(param, protocol))
+                    .flat_map(|parameter| telling(parameter))
                     .collect();
-                format!("Result<({})>", types.join(", "))
+                requirements = vec![(HashSet::new(), unbound)];
+                for parameter in ins {
+                    let alternatives = telling(parameter);
+                    if inputs.contains(parameter) || alternatives.is_empty() {
+                        continue;
+                    }
+                    requirements = requirements
+                        .iter()
+                        .flat_map(|(done, pending)| {
+                            let alternatives = &alternatives;
+                            alternatives.iter().enumerate().filter_map(move |(k, &i)| {
+                                let mut done: HashSet<usize> = done.clone();
+                                let mut pending: HashSet<usize> = pending.clone();
+                                done.insert(i);
+                                pending.extend(&alternatives[..k]);
+                                done.is_disjoint(&pending).then_some((done, pending))
+                            })
+                        })
+                        .collect();
+                }
             }
+
+            for requirement in &requirements {
+                code.push_str(&self.generate_typestate_event(
+                    protocol,
+                    &state,
+                    &parameters,
+                    index,
+                    event,
+                    requirement,
+                ));
+            }
+        }
+        code
+    }
+
+    /// Impl of the method for one event of a typestate, in the states where the events in
+    /// `done` happened and those in `pending` did not
+    fn generate_typestate_event(
+        &self,
+        protocol: &Protocol,
+        state: &str,
+        parameters: &[String],
+        index: usize,
+        event: &TypestateEvent,
+        (done, pending): &(HashSet<usize>, HashSet<usize>),
+    ) -> String {
+        let state_of = |happened: bool| {
+            let arguments: Vec<&str> = (0..parameters.len())
+                .map(|i| match i {
+                    i if i == index => {
+                        if happened {
+                            "Done"
+                        } else {
+                            "Pending"
+                        }
+                    }
+                    i if done.contains(&i) => "Done",
+                    i if pending.contains(&i) => "Pending",
+                    i => parameters[i].as_str(),
+                })
+                .collect();
+            format!("{}<{}>", state, arguments.join(", "))
+        };
+        let free: Vec<&str> = (0..parameters.len())
+            .filter(|i| *i != index && !done.contains(i) && !pending.contains(i))
+            .map(|i| parameters[i].as_str())
+            .collect();
+        let generics = if free.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", free.join(", "))
+        };
+
+        let mut code = String::new();
+        let next = state_of(true);
+        code.push_str(&format!("impl{} {} {{\n", generics, state_of(false)));
+        if !event.description.is_empty() {
+            code.push_str(&format!("    /// {}\n", event.description));
+        }
+        let snake = to_snake_case(event.node);
+        let method = match event.kind {
+            EventKind::Send(message) => format!(
+                "    pub fn send_{}(self, message: {}) -> ({}, {}Message) {{\n        ({} {{ events: std::marker::PhantomData }}, {}Message::{}(message))\n    }}\n",
+                snake, message, next, protocol.name, state, protocol.name, message
+            ),
+            EventKind::Receive(message) => format!(
+                "    pub fn receive_{}(self, _message: &{}) -> {} {{\n        {} {{ events: std::marker::PhantomData }}\n    }}\n",
+                snake, message, next, state
+            ),
+            EventKind::Enact => format!(
+                "    pub fn enact_{}(self) -> {} {{\n        {} {{ events: std::marker::PhantomData }}\n    }}\n",
+                snake, next, state
+            ),
         };
+        code.push_str(&method);
+        code.push_str("}\n\n");
+        code
+    }
 
-        signature.push_str(&format!(") -> {} {{", return_type));
+    /// Method sending a message: it is rejected unless the `in` parameters are bound in the
+    /// message's enactment and its `out` and `nil` parameters are not, and returns the
+    /// message ready for transport
+    fn generate_standard_interaction_method(
+        &self,
+        interaction: &Interaction,
+        protocol: &Protocol,
+        types: &ProtocolTypes,
+    ) -> String {
+        let message = &types.messages[interaction.action.as_str()];
+        let flows: Vec<&model::Flow> = interaction.flows.iter().collect();
 
-        // Generate method
-        code.push_str(&format!("    /// {}\n", interaction.description));
-        code.push_str(&format!("{}\n", signature));
-        code.push_str("        // Protocol interaction implementation\n");
+        let mut code = format!("    /// {}\n", interaction.description);
+        code.push_str(&format!(
+            "    pub fn {}(&mut self, message: {}) -> Result<{}Message, ProtocolError> {{\n",
+            rust_identifier(interaction.action.as_str()),
+            message,
+            protocol.name
+        ));
+        code.push_str(&format!(
+            "        let key = {};\n",
+            key_expression(protocol, &flows, "message.")
+        ));
+        code.push_str(&format!(
+            "        self.bind(\"{}\", key, {})?;\n",
+            interaction.action,
+            bound_parameters(&flows)
+        ));
         code.push_str(&format!(
-            "        println!(\"Executing interaction: {} -> {} ({})\");\n",
-            interaction.from_role, interaction.to_role, interaction.action
+            "        Ok({}Message::{}(message))\n",
+            protocol.name, message
         ));
+        code.push_str("    }\n\n");
+        code
+    }
 
-        // Log input parameters
-        for input_param in &input_params {
+    /// Method recording the `in` and `out` parameters of a received message as bound in its
+    /// enactment
+    fn generate_receive_method(&self, protocol: &Protocol, types: &ProtocolTypes) -> String {
+        let mut code = String::new();
+        code.push_str("    /// Record the parameters a received message binds in its enactment\n");
+        code.push_str(&format!(
+            "    pub fn receive(&mut self, message: &{}Message) {{\n",
+            protocol.name
+        ));
+        code.push_str("        let (key, parameters): (String, &[&str]) = match message {\n");
+        for interaction in protocol.messages() {
+            let flows: Vec<&model::Flow> = interaction.flows.iter().collect();
+            let carried: Vec<String> = flows
+                .iter()
+                .filter(|flow| {
+                    matches!(
+                        flow.direction,
+                        ParameterDirection::In | ParameterDirection::Out
+                    )
+                })
+                .map(|flow| format!("\"{}\"", flow.parameter))
+                .collect();
             code.push_str(&format!(
-                "        println!(\"Input parameter {}: {{:?}}\", {});\n",
-                input_param,
-                input_param.to_lowercase()
+                "            {}Message::{}(message) => ({}, &[{}]),\n",
+                protocol.name,
+                types.messages[interaction.action.as_str()],
+                key_expression(protocol, &flows, "message."),
+                carried.join(", ")
             ));
         }
+        code.push_str("        };\n");
+        code.push_str("        let bound = self.history_of(key);\n");
+        code.push_str(
+            "        bound.extend(parameters.iter().map(|parameter| parameter.to_string()));\n",
+        );
+        code.push_str("    }\n\n");
+        code
+    }
 
-        // Generate return value
-        match output_params.len() {
-            0 => code.push_str("        Ok(())\n"),
-            1 => {
-                let default = self.get_parameter_default(&output_params[0], protocol);
-                code.push_str(&format!(
-                    "        // Output parameter: {}\n",
-                    output_params[0]
-                ));
-                code.push_str(&format!("        Ok({})\n", default));
-            }
-            _ => {
-                let defaults: Vec<String> = output_params
-                    .iter()
-                    .map(|param| {
-                        code.push_str(&format!("        // Output parameter: {}\n", param));
-                        self.get_parameter_default(param, protocol)
-                    })
-                    .collect();
-                code.push_str(&format!("        Ok(({}))\\n", defaults.join(", ")));
-            }
+    /// Struct of the parameters a message carries: its `in` and `out` parameters, and its
+    /// `opt` ones when known
+    fn generate_message_struct(&self, message: &Interaction, protocol: &ProtocolTypes) -> String {
+        let mut code = format!("/// {}\n", message.description);
+        code.push_str("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+        code.push_str(&format!(
+            "pub struct {} {{\n",
+            protocol.messages[message.action.as_str()]
+        ));
+        for flow in &message.flows {
+            let name = flow.parameter.as_str();
+            let rust_type = self.get_parameter_type(name, protocol);
+            let description = protocol
+                .parameters
+                .iter()
+                .find(|param| param.name == name)
+                .map_or("", |param| param.description.as_str());
+            let field_type = match flow.direction {
+                ParameterDirection::Nil => continue,
+                ParameterDirection::Opt => format!("Option<{}>", rust_type),
+                _ => rust_type,
+            };
+            code.push_str(&struct_field(description, name, &field_type));
         }
+        code.push_str("}\n\n");
+        code
+    }
 
+    /// Method starting an enactment with the parameters its enactor provides. It is rejected
+    /// when an enactment with the same keys already started.
+    fn generate_start_method(&self, protocol: &Protocol, types: &ProtocolTypes) -> String {
+        let inputs = enactor_inputs(protocol);
+        let keys: Vec<&str> = inputs
+            .iter()
+            .copied()
+            .filter(|input| protocol.parameter(input).is_some_and(|param| param.key))
+            .collect();
+        let arguments: String = inputs
+            .iter()
+            .map(|input| {
+                format!(
+                    ", {}: {}",
+                    rust_identifier(input),
+                    self.get_parameter_type(input, types)
+                )
+            })
+            .collect();
+
+        let mut code = String::new();
+        code.push_str(
+            "    /// Start the enactment identified by the keys, with the parameters provided by its\n",
+        );
+        code.push_str("    /// enactor\n");
+        code.push_str(&format!(
+            "    pub fn start_enactment(&mut self{}) -> Result<(), ProtocolError> {{\n",
+            arguments
+        ));
+        code.push_str(&format!(
+            "        let key = {};\n",
+            keys_expression(&keys, "")
+        ));
+        code.push_str("        if self.history.contains_key(&key) {\n");
+        code.push_str(&format!(
+            "            return Err(ProtocolError::Bound {{ action: \"start_enactment\", parameter: \"{}\" }});\n",
+            keys.first().unwrap_or(&inputs[0])
+        ));
+        code.push_str("        }\n");
+        code.push_str("        self.history_of(key);\n");
+        for input in &inputs {
+            let field = rust_identifier(input);
+            code.push_str(&format!("        self.{} = {};\n", field, field));
+        }
+        code.push_str("        Ok(())\n");
         code.push_str("    }\n\n");
+        code
+    }
 
-        Ok(code)
+    /// Arguments for the `in` and `opt` parameters of `flows`, each preceded by a comma, and
+    /// the return type: the `message` struct, or the `out` parameters without one
+    fn method_parameters(
+        &self,
+        flows: &[&model::Flow],
+        protocol: &ProtocolTypes,
+        message: Option<&str>,
+    ) -> (String, String) {
+        let mut arguments = String::new();
+        let mut outputs = Vec::new();
+        for direction in [ParameterDirection::In, ParameterDirection::Opt] {
+            for flow in flows.iter().filter(|flow| flow.direction == direction) {
+                let param = flow.parameter.as_str();
+                let rust_type = self.get_parameter_type(param, protocol);
+                match direction {
+                    ParameterDirection::Opt => arguments.push_str(&format!(
+                        ", {}: Option<{}>",
+                        rust_identifier(param),
+                        rust_type
+                    )),
+                    _ => {
+                        arguments.push_str(&format!(", {}: {}", rust_identifier(param), rust_type))
+                    }
+                }
+            }
+        }
+        for flow in flows {
+            if flow.direction == ParameterDirection::Out {
+                outputs.push(self.get_parameter_type(flow.parameter.as_str(), protocol));
+            }
+        }
+
+        let return_type = match (message, outputs.len()) {
+            (Some(message), _) => format!("Result<{}>", message),
+            (None, 0) => "Result<()>".to_string(),
+            (None, 1) => format!("Result<{}>", outputs[0]),
+            (None, _) => format!("Result<({})>", outputs.join(", ")),
+        };
+        (arguments, return_type)
     }
 
+    /// Methods enacting a sub-protocol and recording its outcome. Enacting it takes its `in`
+    /// and `opt` parameters and returns the enacted protocol, started with those it provides;
+    /// completing it takes the `out` parameters once the enactment produced them. Both are
+    /// guarded like messages. Arguments take the types unified across the enactment
+    /// boundary, spelled as the enacting protocol declares them.
     fn generate_composition_method(
         &self,
-        composition: &ProtocolComposition,
+        composition: &Enactment,
         protocol: &Protocol,
+        types: &ProtocolTypes,
+        protocols: &[Protocol],
+        all_types: &[ProtocolTypes],
     ) -> Result<String> {
-        let mut code = String::new();
+        let protocol_name = composition.protocol.as_str();
+        let callee = protocols
+            .iter()
+            .zip(all_types)
+            .find(|(p, _)| p.name.as_str() == protocol_name);
+        if let Some((callee, _)) = callee {
+            let (_, mismatches) = enactment_types(composition, protocol, callee);
+            if let Some(mismatch) = mismatches.into_iter().next() {
+                return Err(mismatch.into());
+            }
+        }
 
-        let method_name = format!("enact_{}", composition.protocol_name.to_lowercase());
+        let roles: Vec<&str> = composition.roles().map(|role| role.name.as_str()).collect();
+        let flows: Vec<&model::Flow> = composition.flows().collect();
+        let names = |direction: ParameterDirection| -> Vec<&str> {
+            flows
+                .iter()
+                .filter(|flow| flow.direction == direction)
+                .map(|flow| flow.parameter.as_str())
+                .collect()
+        };
+        let (ins, outs) = (
+            names(ParameterDirection::In),
+            names(ParameterDirection::Out),
+        );
+        let quoted = |names: &[&str]| -> String {
+            let names: Vec<String> = names.iter().map(|name| format!("\"{}\"", name)).collect();
+            format!("&[{}]", names.join(", "))
+        };
+        let method_name = format!("enact_{}", protocol_name.to_lowercase());
+        let return_type = match callee {
+            Some(_) => format!("{}Protocol", protocol_name),
+            None => "()".to_string(),
+        };
+        let (arguments, _) = self.method_parameters(&flows, types, None);
+
+        let mut body = format!(
+            "        let key = {};\n",
+            key_expression(protocol, &flows, "")
+        );
+        // The `out` parameters may not be bound yet, but only completing binds them
+        let unbound: Vec<&str> = outs
+            .iter()
+            .copied()
+            .chain(names(ParameterDirection::Nil))
+            .collect();
+        body.push_str(&format!(
+            "        self.bind(\"{}\", key, {}, &[], {})?;\n",
+            method_name,
+            quoted(&ins),
+            quoted(&unbound)
+        ));
+        let mut attributes = String::new();
+        match callee {
+            Some((callee, callee_types)) => {
+                let bindings = bind_enactment(composition, callee)?;
+                // Caller value as the callee declares it: unified enums and records of
+                // another name share their JSON
+                let convert = |name: &str, callee_param: &str| {
+                    let argument = rust_identifier(name);
+                    if self.get_parameter_type(name, types)
+                        == self.get_parameter_type(callee_param, callee_types)
+                    {
+                        argument
+                    } else {
+                        format!(
+                            "serde_json::to_value(&{}).and_then(serde_json::from_value).unwrap_or_default()",
+                            argument
+                        )
+                    }
+                };
+                let callee_inputs = enactor_inputs(callee);
+                let inputs: Vec<String> = callee_inputs
+                    .iter()
+                    .map(|input| {
+                        match bindings
+                            .parameters
+                            .get(*input)
+                            .filter(|name| ins.contains(&name.as_str()))
+                        {
+                            Some(name) => convert(name, input),
+                            None => self.get_parameter_default(input, callee_types),
+                        }
+                    })
+                    .collect();
+                body.push_str(&format!(
+                    "        let mut enactment = {}Protocol::new();\n",
+                    protocol_name
+                ));
+                if !inputs.is_empty() {
+                    body.push_str(&format!(
+                        "        enactment.start_enactment({})?;\n",
+                        inputs.join(", ")
+                    ));
+                }
+                // Other provided values are handed over as they are
+                for flow in &flows {
+                    let (name, callee_param) = (flow.parameter.as_str(), flow.bound_name());
+                    let field = rust_identifier(callee_param);
+                    match flow.direction {
+                        ParameterDirection::In if !callee_inputs.contains(&callee_param) => body
+                            .push_str(&format!(
+                                "        enactment.{} = {};\n",
+                                field,
+                                convert(name, callee_param)
+                            )),
+                        ParameterDirection::Opt => body.push_str(&format!(
+                            "        if let Some({}) = {} {{\n            enactment.{} = {};\n        }}\n",
+                            rust_identifier(name),
+                            rust_identifier(name),
+                            field,
+                            convert(name, callee_param)
+                        )),
+                        _ => {}
+                    }
+                }
+                body.push_str("        Ok(enactment)\n");
+            }
+            None => {
+                // Nothing to hand the provided values to
+                attributes.push_str("    #[allow(unused_variables)]\n");
+                body.push_str("        Ok(())\n");
+            }
+        }
 
-        // Generate method signature
-        code.push_str(&format!(
+        let mut code = format!(
             "    /// Enacts the {} protocol with roles: {}\n",
-            composition.protocol_name,
-            composition.roles.join(", ")
+            protocol_name,
+            roles.join(", ")
+        );
+        code.push_str(&attributes);
+        code.push_str(&format!(
+            "    pub fn {}(&mut self{}) -> Result<{}, ProtocolError> {{\n",
+            method_name, arguments, return_type
         ));
+        code.push_str(&body);
+        code.push_str("    }\n\n");
 
+        if outs.is_empty() {
+            return Ok(code);
+        }
+        let method_name = format!("complete_{}", protocol_name.to_lowercase());
+        let arguments: String = ins
+            .iter()
+            .filter(|name| protocol.parameter(name).is_some_and(|param| param.key))
+            .chain(&outs)
+            .map(|name| {
+                format!(
+                    ", {}: {}",
+                    rust_identifier(name),
+                    self.get_parameter_type(name, types)
+                )
+            })
+            .collect();
         code.push_str(&format!(
-            "    pub fn {}(&mut self) -> Result<()> {{\n",
-            method_name
+            "    /// Records the `out` parameters produced by an enactment of the {} protocol\n",
+            protocol_name
         ));
-        code.push_str("        // Protocol composition enactment\n");
         code.push_str(&format!(
-            "        println!(\"Enacting protocol: {} with roles: {}]\");\n",
-            composition.protocol_name,
-            composition.roles.join(", ")
+            "    pub fn {}(&mut self{}) -> Result<(), ProtocolError> {{\n",
+            method_name, arguments
         ));
-
-        // Generate parameter flow logging
-        for param_flow in &composition.parameter_flows {
-            code.push_str(&format!(
-                "        println!(\"Parameter flow: {} {}\");\n",
-                param_flow.direction, param_flow.parameter
-            ));
+        code.push_str(&format!(
+            "        let key = {};\n",
+            key_expression(protocol, &flows, "")
+        ));
+        code.push_str(&format!(
+            "        self.bind(\"{}\", key, {})?;\n",
+            method_name,
+            bound_parameters(&flows)
+        ));
+        for name in &outs {
+            let field = rust_identifier(name);
+            code.push_str(&format!("        self.{} = {};\n", field, field));
         }
-
         code.push_str("        Ok(())\n");
         code.push_str("    }\n\n");
-
         Ok(code)
     }
 
-    fn get_parameter_type(&self, param_name: &str, protocol: &Protocol) -> String {
+    fn generate_type_definition(&self, definition: &TypeDefinition) -> String {
+        let mut code = String::new();
+
+        match definition {
+            TypeDefinition::Enum { name, variants } => {
+                code.push_str(
+                    "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]\n",
+                );
+                code.push_str(&format!("pub enum {} {{\n", name));
+                for (index, variant) in variants.iter().enumerate() {
+                    if index == 0 {
+                        code.push_str("    #[default]\n");
+                    }
+                    code.push_str(&format!(
+                        "    #[serde(rename = \"{}\")]\n    {},\n",
+                        variant,
+                        to_pascal_case(variant)
+                    ));
+                }
+            }
+            TypeDefinition::Record {
+                name,
+                description,
+                fields,
+            } => {
+                code.push_str(&format!("/// {}\n", description));
+                code.push_str(
+                    "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]\n",
+                );
+                code.push_str(&format!("pub struct {} {{\n", name));
+                for field in fields {
+                    code.push_str(&struct_field(
+                        &field.description,
+                        &field.name,
+                        &field.rust_type,
+                    ));
+                }
+            }
+        }
+
+        code.push_str("}\n\n");
+        code
+    }
+
+    fn get_parameter_type(&self, param_name: &str, protocol: &ProtocolTypes) -> String {
         protocol
             .parameters
             .iter()
             .find(|p| p.name == param_name)
-            .map(|p| self.map_bmpp_type_to_rust(&p.param_type).to_string())
+            .map(|p| p.rust_type.clone())
             .unwrap_or_else(|| "String".to_string())
     }
 
-    fn get_parameter_default(&self, param_name: &str, protocol: &Protocol) -> String {
+    fn get_parameter_default(&self, param_name: &str, protocol: &ProtocolTypes) -> String {
         protocol
             .parameters
             .iter()
             .find(|p| p.name == param_name)
-            .map(|p| self.get_default_value(&p.param_type).to_string())
+            .map(|p| p.default_value.clone())
             .unwrap_or_else(|| "String::new()".to_string())
     }
 
-    fn map_bmpp_type_to_rust(&self, bmpp_type: &str) -> &str {
+    /// Rust spelling of a BMPP type. Inline enums are registered in `type_definitions`
+    /// under `enum_name`; record references must name a declared record type.
+    fn map_bmpp_type_to_rust(
+        &self,
+        bmpp_type: &BmppType,
+        enum_name: &str,
+        records: &HashSet<&str>,
+        type_definitions: &mut Vec<TypeDefinition>,
+    ) -> Result<String> {
+        Ok(match bmpp_type {
+            BmppType::String => "String".to_string(),
+            BmppType::Int => "i32".to_string(),
+            BmppType::Float => "f64".to_string(),
+            BmppType::Bool => "bool".to_string(),
+            BmppType::Enum(variants) => {
+                type_definitions.push(TypeDefinition::Enum {
+                    name: enum_name.to_string(),
+                    variants: variants.clone(),
+                });
+                enum_name.to_string()
+            }
+            BmppType::List(element) => format!(
+                "Vec<{}>",
+                self.map_bmpp_type_to_rust(element, enum_name, records, type_definitions)?
+            ),
+            BmppType::Named(name) => {
+                if !records.contains(name.as_str()) {
+                    return Err(anyhow!("Unknown type '{}'", name));
+                }
+                name.clone()
+            }
+        })
+    }
+
+    fn get_default_value(&self, bmpp_type: &BmppType) -> &str {
         match bmpp_type {
-            "String" => "String",
-            "Int" => "i32",
-            "Float" => "f64",
-            "Bool" => "bool",
-            _ => "String",
+            BmppType::String => "String::new()",
+            BmppType::Int => "0",
+            BmppType::Float => "0.0",
+            BmppType::Bool => "false",
+            _ => "Default::default()",
         }
     }
+}
 
-    fn get_default_value(&self, bmpp_type: &str) -> &str {
-        match bmpp_type {
-            "String" => "String::new()",
-            "Int" => "0",
-            "Float" => "0.0",
-            "Bool" => "false",
-            _ => "String::new()",
+/// Error of the generated code for messages and enactments that are not enabled
+const PROTOCOL_ERROR: &str = r#"/// Why a message or enactment is not enabled in the local history of its enactment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// An `in` parameter is not known yet
+    Unknown { action: &'static str, parameter: &'static str },
+    /// An `out` or `nil` parameter is already bound
+    Bound { action: &'static str, parameter: &'static str },
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::Unknown { action, parameter } => {
+                write!(f, "'{}' is not enabled: '{}' is not known yet", action, parameter)
+            }
+            ProtocolError::Bound { action, parameter } => {
+                write!(f, "'{}' is not enabled: '{}' is already bound", action, parameter)
+            }
         }
     }
 }
+
+impl std::error::Error for ProtocolError {}
+
+"#;
+
+/// Guard shared by the messages and enactments of a generated protocol, following BSPL
+/// information semantics
+const BIND_METHOD: &str = r#"    /// Check that `action` is enabled in the enactment identified by `key` and record
+    /// the parameters it binds
+    fn bind(
+        &mut self,
+        action: &'static str,
+        key: String,
+        ins: &[&'static str],
+        outs: &[&'static str],
+        nils: &[&'static str],
+    ) -> Result<(), ProtocolError> {
+        let bound = self.history_of(key);
+        if let Some(parameter) = ins.iter().find(|parameter| !bound.contains(**parameter)) {
+            return Err(ProtocolError::Unknown { action, parameter });
+        }
+        if let Some(parameter) = outs
+            .iter()
+            .chain(nils)
+            .find(|parameter| bound.contains(**parameter))
+        {
+            return Err(ProtocolError::Bound { action, parameter });
+        }
+        bound.extend(outs.iter().map(|parameter| parameter.to_string()));
+        Ok(())
+    }
+
+    /// History of the enactment identified by `key`, which knows the parameters provided by
+    /// its enactor from the start
+    fn history_of(&mut self, key: String) -> &mut HashSet<String> {
+        self.history.entry(key).or_insert_with(|| {
+            Self::INPUTS
+                .iter()
+                .map(|parameter| parameter.to_string())
+                .collect()
+        })
+    }
+
+"#;
+
+/// Expression identifying the enactment of a message or enactment: the JSON of the values
+/// of the keys of `protocol` among `flows`, read from variables or fields after `prefix`
+fn key_expression(protocol: &Protocol, flows: &[&model::Flow], prefix: &str) -> String {
+    let keys: Vec<&str> = flows
+        .iter()
+        .filter(|flow| {
+            matches!(
+                flow.direction,
+                ParameterDirection::In | ParameterDirection::Out
+            ) && protocol
+                .parameter(flow.parameter.as_str())
+                .is_some_and(|param| param.key)
+        })
+        .map(|flow| flow.parameter.as_str())
+        .collect();
+    keys_expression(&keys, prefix)
+}
+
+/// JSON of the values of `keys`, read from variables or fields after `prefix`
+fn keys_expression(keys: &[&str], prefix: &str) -> String {
+    let keys: Vec<String> = keys
+        .iter()
+        .map(|key| format!("&{}{}, ", prefix, rust_identifier(key)))
+        .collect();
+    if keys.is_empty() {
+        return "String::new()".to_string();
+    }
+    format!(
+        "serde_json::to_string(&({})).unwrap_or_default()",
+        keys.concat().trim_end()
+    )
+}
+
+/// Parameters the enactor of `protocol` provides: its keys and the `in` parameters of its
+/// signature, unless one of its messages or enactments produces them
+fn enactor_inputs(protocol: &Protocol) -> Vec<&str> {
+    let produced: HashSet<&str> = protocol
+        .interactions
+        .iter()
+        .flat_map(|interaction| match interaction {
+            InteractionItem::Interaction(message) => message.flows.iter().collect::<Vec<_>>(),
+            InteractionItem::Enactment(enactment) => enactment.flows().collect(),
+        })
+        .filter(|flow| flow.direction == ParameterDirection::Out)
+        .map(|flow| flow.parameter.as_str())
+        .collect();
+    protocol
+        .parameters
+        .iter()
+        .map(|param| param.name.as_str())
+        .filter(|name| protocol.is_input(name) && !produced.contains(name))
+        .collect()
+}
+
+/// `in`, `out` and `nil` parameters of `flows`, as arguments of the generated `bind`
+fn bound_parameters(flows: &[&model::Flow]) -> String {
+    let names = |direction: ParameterDirection| {
+        let names: Vec<String> = flows
+            .iter()
+            .filter(|flow| flow.direction == direction)
+            .map(|flow| format!("\"{}\"", flow.parameter))
+            .collect();
+        format!("&[{}]", names.join(", "))
+    };
+    format!(
+        "{}, {}, {}",
+        names(ParameterDirection::In),
+        names(ParameterDirection::Out),
+        names(ParameterDirection::Nil)
+    )
+}
+
+/// Markers of the generated typestates
+const TYPESTATE_MARKERS: &str = r#"/// Typestate marker of an event that has not happened yet
+pub struct Pending;
+
+/// Typestate marker of an event that has happened
+pub struct Done;
+
+"#;
+
+/// Message sent or received, or enactment taken part in, by the role of a typestate
+struct TypestateEvent<'a> {
+    kind: EventKind<'a>,
+    /// Name of the interaction or enactment
+    node: &'a str,
+    description: &'a str,
+    flows: Vec<&'a model::Flow>,
+    /// For an event the role initiates, the `in` parameters it needs and the `out` and
+    /// `nil` ones it must not know yet
+    requires: Option<(Vec<&'a str>, Vec<&'a str>)>,
+}
+
+impl TypestateEvent<'_> {
+    /// Whether the role knows `parameter` once the event happened: it received it, or
+    /// produced it by sending or enacting
+    fn tells(&self, parameter: &str) -> bool {
+        self.flows.iter().any(|flow| {
+            flow.parameter.as_str() == parameter
+                && match self.kind {
+                    EventKind::Receive(_) => matches!(
+                        flow.direction,
+                        ParameterDirection::In | ParameterDirection::Out
+                    ),
+                    _ => flow.direction == ParameterDirection::Out,
+                }
+        })
+    }
+}
+
+/// What the role of a typestate does in an event; messages carry their struct name
+enum EventKind<'a> {
+    Send(&'a str),
+    Receive(&'a str),
+    Enact,
+}
+
+/// Rust keywords, which BMPP names can spell but Rust identifiers cannot
+const RUST_KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Rust identifier of a BMPP parameter or role: its lowercase name, written as a raw
+/// identifier when it is a keyword, or with a trailing underscore for the keywords raw
+/// identifiers cannot spell
+fn rust_identifier(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.as_str() {
+        "crate" | "self" | "super" => format!("{}_", name),
+        keyword if RUST_KEYWORDS.contains(&keyword) => format!("r#{}", name),
+        _ => name,
+    }
+}
+
+/// Public struct field for a BMPP parameter or role, renamed for serde when its identifier
+/// is not its lowercase name
+fn struct_field(description: &str, name: &str, rust_type: &str) -> String {
+    let identifier = rust_identifier(name);
+    let mut field = format!("    /// {}\n", description);
+    if identifier.ends_with('_') && !name.ends_with('_') {
+        field.push_str(&format!(
+            "    #[serde(rename = \"{}\")]\n",
+            name.to_lowercase()
+        ));
+    }
+    field.push_str(&format!("    pub {}: {},\n", identifier, rust_type));
+    field
+}
+
+/// Name of the trait implemented by the agent playing `role` in `protocol`
+fn role_trait_name(protocol: &Protocol, role: &str) -> String {
+    format!("{}{}Agent", protocol.name, to_pascal_case(role))
+}
+
+/// Convert a PascalCase or camelCase BMPP identifier into a Rust function name
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() && previous_lower {
+            snake.push('_');
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// Convert a snake_case or camelCase BMPP identifier into a Rust type name
+fn to_pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
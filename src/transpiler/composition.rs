@@ -1,34 +1,51 @@
-use crate::protocol::ast::{AstNode, AstNodeType};
-use anyhow::{anyhow, Result};
+use crate::protocol::ast::{AstNode, AstNodeType, ParameterDirection, Span};
+use crate::protocol::model::{
+    Argument, Enactment, Flow, InteractionItem, Program, Protocol, Section, Spanned, TypeDecl,
+    TypeExpr, TypeKind,
+};
+use crate::protocol::types::BmppType;
+use crate::transpiler::diagnostics::{Diagnostic, DiagnosticCode, Note};
+use anyhow::{Result, anyhow};
 use std::collections::{HashMap, HashSet};
 
+/// Deepest chain of nested enactments a registry expands unless configured otherwise
+pub const DEFAULT_MAX_NESTING: usize = 32;
+
 #[derive(Debug, Clone)]
 pub struct ProtocolRegistry {
-    protocols: HashMap<String, AstNode>,
+    protocols: HashMap<String, Protocol>,
+    max_nesting: usize,
 }
 
-#[derive(Debug, Clone)]
-pub struct CompositionParameter {
-    pub parameter_type: CompositionParameterType,
-    pub name: String,
-    pub direction: Option<String>, // Some("in"/"out") for parameter flows, None for role identifiers
+/// Callee roles and parameters of one enactment, each mapped to the caller's name bound to it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnactmentBindings {
+    pub roles: HashMap<String, String>,
+    pub parameters: HashMap<String, String>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum CompositionParameterType {
-    RoleIdentifier,
-    ParameterFlow,
+impl Default for ProtocolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProtocolRegistry {
     pub fn new() -> Self {
         Self {
             protocols: HashMap::new(),
+            max_nesting: DEFAULT_MAX_NESTING,
         }
     }
 
+    /// Limit how deeply enactments may nest when references are resolved
+    pub fn with_max_nesting(mut self, max_nesting: usize) -> Self {
+        self.max_nesting = max_nesting;
+        self
+    }
+
     /// Register a protocol in the registry for later reference
-    pub fn register_protocol(&mut self, name: String, protocol: AstNode) {
+    pub fn register_protocol(&mut self, name: String, protocol: Protocol) {
         self.protocols.insert(name, protocol);
     }
 
@@ -36,50 +53,46 @@ impl ProtocolRegistry {
     pub fn from_program(program: &AstNode) -> Result<Self> {
         let mut registry = Self::new();
 
-        if program.node_type != AstNodeType::Program {
-            return Err(anyhow!(
-                "Expected Program node, got {:?}",
-                program.node_type
-            ));
-        }
-
-        for protocol_node in &program.children {
-            if protocol_node.node_type == AstNodeType::Protocol {
-                let protocol_name = Self::extract_protocol_name(protocol_node)?;
-                registry.register_protocol(protocol_name, (**protocol_node).clone());
-            }
+        for protocol in Program::from_ast(program)?.protocols {
+            registry.register_protocol(protocol.name.value.clone(), protocol);
         }
 
         Ok(registry)
     }
 
-    /// Extract protocol name from Protocol AST node
-    fn extract_protocol_name(protocol_node: &AstNode) -> Result<String> {
-        for child in &protocol_node.children {
-            if child.node_type == AstNodeType::ProtocolName {
-                if let Some(name) = child.get_string("name") {
-                    return Ok(name.clone());
-                }
-            }
-        }
-        Err(anyhow!("Protocol name not found"))
-    }
-
-    /// Resolve all protocol references in a protocol AST
+    /// Resolve all protocol references in a protocol AST. Fails when a protocol ends up
+    /// enacting itself or enactments nest deeper than the configured limit.
     pub fn resolve_protocol_references(&self, protocol: &mut AstNode) -> Result<()> {
-        self.resolve_references_recursive(protocol)?;
+        self.resolve_references_recursive(protocol, &mut Vec::new())?;
         Ok(())
     }
 
-    fn resolve_references_recursive(&self, node: &mut AstNode) -> Result<()> {
+    /// `enacting` holds the protocols being expanded, outermost first
+    fn resolve_references_recursive(
+        &self,
+        node: &mut AstNode,
+        enacting: &mut Vec<String>,
+    ) -> Result<()> {
         match node.node_type {
             AstNodeType::ProtocolComposition => {
-                self.resolve_composition(node)?;
+                self.resolve_composition(node, enacting)?;
+            }
+            AstNodeType::Protocol => {
+                let name = node
+                    .find_child(AstNodeType::ProtocolName)
+                    .and_then(|name| name.get_string("name"))
+                    .cloned()
+                    .unwrap_or_default();
+                enacting.push(name);
+                for child in &mut node.children {
+                    self.resolve_references_recursive(child, enacting)?;
+                }
+                enacting.pop();
             }
             _ => {
                 // Recursively process child nodes
                 for child in &mut node.children {
-                    self.resolve_references_recursive(child)?;
+                    self.resolve_references_recursive(child, enacting)?;
                 }
             }
         }
@@ -87,32 +100,25 @@ impl ProtocolRegistry {
     }
 
     /// Resolve a single protocol composition
-    fn resolve_composition(&self, composition_node: &mut AstNode) -> Result<()> {
-        // Extract the protocol reference name
-        let protocol_name = self.extract_composition_protocol_name(composition_node)?;
+    fn resolve_composition(
+        &self,
+        composition_node: &mut AstNode,
+        enacting: &mut Vec<String>,
+    ) -> Result<()> {
+        let enactment = Enactment::from_ast(composition_node)?;
+        self.check_nesting(&enactment, enacting)?;
 
         // Find the referenced protocol
         let referenced_protocol = self
             .protocols
-            .get(&protocol_name)
-            .ok_or_else(|| anyhow!("Undefined protocol: {}", protocol_name))?;
-
-        // Extract composition parameters
-        let composition_params = self.extract_composition_parameters(composition_node)?;
+            .get(enactment.protocol.as_str())
+            .ok_or_else(|| anyhow!("Undefined protocol: {}", enactment.protocol))?;
 
-        // Validate composition parameters against the referenced protocol
-        self.validate_composition_parameters(
-            referenced_protocol,
-            &composition_params,
-            &protocol_name,
-        )?;
+        // Bind the composition parameters to the referenced protocol's roles and parameters
+        let bindings = bind_enactment(&enactment, referenced_protocol)?;
 
         // Create an instance of the referenced protocol with parameter bindings
-        let instance = self.create_protocol_instance(
-            referenced_protocol,
-            &composition_params,
-            &protocol_name,
-        )?;
+        let instance = self.create_protocol_instance(referenced_protocol, &bindings, enacting)?;
 
         // Replace the composition node with the expanded instance
         *composition_node = instance;
@@ -120,421 +126,804 @@ impl ProtocolRegistry {
         Ok(())
     }
 
-    /// Extract protocol name from ProtocolComposition node
-    fn extract_composition_protocol_name(&self, composition_node: &AstNode) -> Result<String> {
-        for child in &composition_node.children {
-            if child.node_type == AstNodeType::ProtocolReference {
-                // ProtocolReference contains an Identifier child with the protocol name
-                for ref_child in &child.children {
-                    if ref_child.node_type == AstNodeType::Identifier {
-                        if let Some(name) = ref_child.get_string("name") {
-                            return Ok(name.clone());
-                        }
-                    }
-                }
-            }
+    /// Fail when the protocols in `enacting`, outermost first, enacting `enactment` would
+    /// make a protocol enact itself or nest deeper than the configured limit
+    fn check_nesting(&self, enactment: &Enactment, enacting: &[String]) -> Result<()> {
+        let callee = enactment.protocol.as_str();
+        if let Some(start) = enacting.iter().position(|name| name == callee) {
+            let cycle = enactment_path(&enacting[start..], callee);
+            return Err(Diagnostic::new(
+                DiagnosticCode::RecursiveEnactment,
+                format!(
+                    "Protocol '{}' enacts itself through {} - recursive enactment not allowed",
+                    callee, cycle
+                ),
+            )
+            .with_label(enactment.protocol.span, "already being enacted")
+            .into());
         }
-        Err(anyhow!("Protocol reference name not found in composition"))
+        // The outermost protocol is not nested in anything
+        if enacting.len() > self.max_nesting {
+            return Err(Diagnostic::new(
+                DiagnosticCode::EnactmentTooDeep,
+                format!(
+                    "Enactments nest deeper than {} protocols: {}",
+                    self.max_nesting,
+                    enactment_path(enacting, callee)
+                ),
+            )
+            .with_label(enactment.protocol.span, "nested too deeply")
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Inline every enactment of protocol `name`, recursively, into a single protocol with
+    /// its name, description, signature and roles.
+    ///
+    /// Messages of an enacted protocol take the caller's roles and parameters bound to
+    /// them. Its private parameters are declared in the result with the enacted protocol's
+    /// name as prefix, e.g. `Pack_data`, and so are its messages whose name is already taken;
+    /// a number is appended when the prefixed name is taken too. Record types it declares
+    /// and the caller does not are added.
+    pub fn flatten(&self, name: &str) -> Result<Protocol> {
+        let protocol = self
+            .protocols
+            .get(name)
+            .ok_or_else(|| anyhow!("Undefined protocol: {}", name))?;
+        self.flatten_protocol(protocol, &mut vec![name.to_string()])
     }
 
-    /// Extract composition parameters from ProtocolComposition node
-    fn extract_composition_parameters(
+    fn flatten_protocol(
         &self,
-        composition_node: &AstNode,
-    ) -> Result<Vec<CompositionParameter>> {
-        let mut parameters = Vec::new();
-
-        for child in &composition_node.children {
-            match child.node_type {
-                AstNodeType::Identifier => {
-                    // Role identifier
-                    if let Some(name) = child.get_string("name") {
-                        parameters.push(CompositionParameter {
-                            parameter_type: CompositionParameterType::RoleIdentifier,
-                            name: name.clone(),
-                            direction: None,
-                        });
-                    }
-                }
-                AstNodeType::ParameterFlow => {
-                    // Parameter flow (in/out parameter)
-                    if let Some(direction) = child.get_string("direction") {
-                        // Extract parameter name from the Identifier child
-                        for flow_child in &child.children {
-                            if flow_child.node_type == AstNodeType::Identifier {
-                                if let Some(param_name) = flow_child.get_string("name") {
-                                    parameters.push(CompositionParameter {
-                                        parameter_type: CompositionParameterType::ParameterFlow,
-                                        name: param_name.clone(),
-                                        direction: Some(direction.clone()),
-                                    });
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-                AstNodeType::ProtocolReference => {
-                    // Skip - handled separately
-                }
-                _ => {
-                    return Err(anyhow!(
-                        "Unexpected child node type in composition: {:?}",
-                        child.node_type
-                    ));
+        protocol: &Protocol,
+        enacting: &mut Vec<String>,
+    ) -> Result<Protocol> {
+        let mut flat = protocol.clone();
+        flat.interactions.items.clear();
+        for item in &protocol.interactions {
+            match item {
+                InteractionItem::Interaction(_) => flat.interactions.items.push(item.clone()),
+                InteractionItem::Enactment(enactment) => {
+                    self.inline_enactment(&mut flat, enactment, enacting)?
                 }
             }
         }
-
-        Ok(parameters)
+        Ok(flat)
     }
 
-    /// Validate composition parameters against the referenced protocol
-    fn validate_composition_parameters(
+    fn inline_enactment(
         &self,
-        referenced_protocol: &AstNode,
-        composition_params: &[CompositionParameter],
-        protocol_name: &str,
+        flat: &mut Protocol,
+        enactment: &Enactment,
+        enacting: &mut Vec<String>,
     ) -> Result<()> {
-        // Extract protocol's declared roles and parameters
-        let protocol_roles = self.extract_protocol_roles(referenced_protocol);
-        let protocol_parameters = self.extract_protocol_parameters(referenced_protocol);
-
-        // Count role identifiers and parameter flows in composition
-        let role_count = composition_params
-            .iter()
-            .filter(|p| p.parameter_type == CompositionParameterType::RoleIdentifier)
-            .count();
-
-        let param_flow_count = composition_params
-            .iter()
-            .filter(|p| p.parameter_type == CompositionParameterType::ParameterFlow)
-            .count();
-
-        // Validate role count
-        if role_count != protocol_roles.len() {
-            return Err(anyhow!(
-                "Role count mismatch in composition of '{}': expected {}, got {}",
-                protocol_name,
-                protocol_roles.len(),
-                role_count
-            ));
-        }
-
-        // Validate that all parameter flows reference declared parameters
-        for param in composition_params {
-            if param.parameter_type == CompositionParameterType::ParameterFlow {
-                if !protocol_parameters.contains(&param.name) {
-                    return Err(anyhow!(
-                        "Parameter '{}' in composition of '{}' is not declared in the referenced protocol",
-                        param.name, protocol_name
-                    ));
-                }
+        self.check_nesting(enactment, enacting)?;
+        let callee_name = enactment.protocol.as_str();
+        let callee = self
+            .protocols
+            .get(callee_name)
+            .ok_or_else(|| anyhow!("Undefined protocol: {}", callee_name))?;
+        let bindings = bind_enactment(enactment, callee)?;
+
+        enacting.push(callee_name.to_string());
+        let mut instance = self.flatten_protocol(callee, enacting)?;
+        enacting.pop();
+
+        let mut parameters = bindings.parameters;
+        for param in &instance.parameters {
+            if parameters.contains_key(param.name.as_str()) {
+                continue;
             }
+            let fresh = fresh_name(callee_name, param.name.as_str(), |name| {
+                flat.parameter(name).is_some()
+            });
+            let mut private = param.clone();
+            private.name.value = fresh.clone();
+            flat.parameters.items.push(private);
+            parameters.insert(param.name.value.clone(), fresh);
         }
 
-        Ok(())
-    }
-
-    /// Extract role names from a protocol
-    fn extract_protocol_roles(&self, protocol: &AstNode) -> Vec<String> {
-        let mut roles = Vec::new();
-
-        for child in &protocol.children {
-            if child.node_type == AstNodeType::RolesSection {
-                for role_child in &child.children {
-                    if role_child.node_type == AstNodeType::RoleDecl {
-                        // RoleDecl contains an Identifier child with the role name
-                        for role_grandchild in &role_child.children {
-                            if role_grandchild.node_type == AstNodeType::Identifier {
-                                if let Some(role_name) = role_grandchild.get_string("name") {
-                                    roles.push(role_name.clone());
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
+        for decl in instance.type_decls() {
+            if record(flat, decl.name.as_str()).is_none() {
+                flat.types
+                    .get_or_insert_with(|| Section::new(Vec::new(), decl.span))
+                    .items
+                    .push(decl.clone());
             }
         }
 
-        roles
-    }
-
-    /// Extract parameter names from a protocol
-    fn extract_protocol_parameters(&self, protocol: &AstNode) -> Vec<String> {
-        let mut parameters = Vec::new();
-
-        for child in &protocol.children {
-            if child.node_type == AstNodeType::ParametersSection {
-                for param_child in &child.children {
-                    if param_child.node_type == AstNodeType::ParameterDecl {
-                        // ParameterDecl contains an Identifier child with the parameter name
-                        for param_grandchild in &param_child.children {
-                            if param_grandchild.node_type == AstNodeType::Identifier {
-                                if let Some(param_name) = param_grandchild.get_string("name") {
-                                    parameters.push(param_name.clone());
-                                    break;
-                                }
-                            }
-                        }
-                    }
+        apply_mappings(&mut instance, &bindings.roles, &parameters);
+        for mut item in instance.interactions.items {
+            if let InteractionItem::Interaction(interaction) = &mut item {
+                let taken = |name: &str| flat.messages().any(|m| m.action.as_str() == name);
+                if taken(interaction.action.as_str()) {
+                    interaction.action.value =
+                        fresh_name(callee_name, interaction.action.as_str(), taken);
                 }
             }
+            flat.interactions.items.push(item);
         }
-
-        parameters
+        Ok(())
     }
 
     /// Create an instance of a referenced protocol with parameter bindings
     fn create_protocol_instance(
         &self,
-        referenced_protocol: &AstNode,
-        composition_params: &[CompositionParameter],
-        protocol_name: &str,
+        referenced_protocol: &Protocol,
+        bindings: &EnactmentBindings,
+        enacting: &mut Vec<String>,
     ) -> Result<AstNode> {
         let mut instance = referenced_protocol.clone();
 
-        // Create role mappings
-        let role_mappings = self.create_role_mappings(&instance, composition_params)?;
-
-        // Create parameter mappings
-        let parameter_mappings = self.create_parameter_mappings(composition_params);
-
-        // Apply role and parameter mappings to the instance
-        self.apply_mappings(&mut instance, &role_mappings, &parameter_mappings)?;
+        // Rename the callee's roles and parameters to the caller's names bound to them
+        apply_mappings(&mut instance, &bindings.roles, &bindings.parameters);
 
         // Recursively resolve any nested references in the instance
-        self.resolve_references_recursive(&mut instance)?;
+        let mut instance = instance.to_ast();
+        self.resolve_references_recursive(&mut instance, enacting)?;
 
         Ok(instance)
     }
 
-    /// Create role name mappings for protocol instantiation
-    fn create_role_mappings(
-        &self,
-        protocol: &AstNode,
-        composition_params: &[CompositionParameter],
-    ) -> Result<HashMap<String, String>> {
-        let protocol_roles = self.extract_protocol_roles(protocol);
-        let composition_roles: Vec<&str> = composition_params
-            .iter()
-            .filter(|p| p.parameter_type == CompositionParameterType::RoleIdentifier)
-            .map(|p| p.name.as_str())
-            .collect();
-
-        if protocol_roles.len() != composition_roles.len() {
-            return Err(anyhow!(
-                "Role count mismatch: protocol has {}, composition has {}",
-                protocol_roles.len(),
-                composition_roles.len()
-            ));
-        }
-
-        let mappings: HashMap<String, String> = protocol_roles
-            .iter()
-            .zip(composition_roles.iter())
-            .map(|(protocol_role, composition_role)| {
-                (protocol_role.clone(), composition_role.to_string())
-            })
-            .collect();
+    /// Get all registered protocol names
+    pub fn get_protocol_names(&self) -> Vec<String> {
+        self.protocols.keys().cloned().collect()
+    }
 
-        Ok(mappings)
+    /// Check if a protocol is registered
+    pub fn has_protocol(&self, name: &str) -> bool {
+        self.protocols.contains_key(name)
     }
 
-    /// Create parameter name mappings for protocol instantiation
-    fn create_parameter_mappings(
-        &self,
-        composition_params: &[CompositionParameter],
-    ) -> HashMap<String, String> {
-        // For now, parameters keep their original names
-        // In a more sophisticated implementation, this could handle parameter renaming
-        composition_params
-            .iter()
-            .filter(|p| p.parameter_type == CompositionParameterType::ParameterFlow)
-            .map(|p| (p.name.clone(), p.name.clone()))
-            .collect()
+    /// Get a protocol by name
+    pub fn get_protocol(&self, name: &str) -> Option<&Protocol> {
+        self.protocols.get(name)
     }
+}
 
-    /// Apply role and parameter mappings to a protocol instance
-    fn apply_mappings(
-        &self,
-        node: &mut AstNode,
-        role_mappings: &HashMap<String, String>,
-        parameter_mappings: &HashMap<String, String>,
-    ) -> Result<()> {
-        match node.node_type {
-            AstNodeType::RoleRef => {
-                // Update role references in interactions
-                if let Some(role_name) = node.get_string("name") {
-                    if let Some(new_role) = role_mappings.get(role_name) {
-                        node.set_string("name", new_role);
-                    }
-                }
-            }
-            AstNodeType::Identifier => {
-                // Update identifier nodes that reference roles or parameters
-                if let Some(name) = node.get_string("name") {
-                    // Check if it's a role reference
-                    if let Some(new_role) = role_mappings.get(name) {
-                        node.set_string("name", new_role);
-                    }
-                    // Check if it's a parameter reference
-                    else if let Some(new_param) = parameter_mappings.get(name) {
-                        node.set_string("name", new_param);
-                    }
+/// `Prefix_name`, or `Prefix_name_2`, `Prefix_name_3`, ... when taken
+fn fresh_name(prefix: &str, name: &str, taken: impl Fn(&str) -> bool) -> String {
+    let prefixed = format!("{}_{}", prefix, name);
+    (1..)
+        .map(|n| match n {
+            1 => prefixed.clone(),
+            n => format!("{}_{}", prefixed, n),
+        })
+        .find(|candidate| !taken(candidate))
+        .expect("unbounded candidates")
+}
+
+/// Chain of enactments leading to `callee`, e.g. `'A' -> 'B' -> 'A'`
+pub fn enactment_path(enacting: &[String], callee: &str) -> String {
+    enacting
+        .iter()
+        .map(String::as_str)
+        .chain([callee])
+        .map(|name| format!("'{}'", name))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Apply role and parameter mappings to a protocol instance
+fn apply_mappings(
+    protocol: &mut Protocol,
+    role_mappings: &HashMap<String, String>,
+    parameter_mappings: &HashMap<String, String>,
+) {
+    let rename = |name: &mut Spanned<String>, mappings: &HashMap<String, String>| {
+        if let Some(new_name) = mappings.get(&name.value) {
+            name.value = new_name.clone();
+        }
+    };
+
+    for role in &mut protocol.roles.items {
+        rename(&mut role.name, role_mappings);
+    }
+    for param in &mut protocol.parameters.items {
+        rename(&mut param.name, parameter_mappings);
+    }
+    for item in &mut protocol.interactions.items {
+        match item {
+            InteractionItem::Interaction(interaction) => {
+                rename(&mut interaction.from, role_mappings);
+                rename(&mut interaction.to, role_mappings);
+                for flow in &mut interaction.flows {
+                    rename(&mut flow.parameter, parameter_mappings);
                 }
             }
-            AstNodeType::ParameterFlow => {
-                // Update parameter flows
-                for child in &mut node.children {
-                    if child.node_type == AstNodeType::Identifier {
-                        if let Some(param_name) = child.get_string("name") {
-                            if let Some(new_param) = parameter_mappings.get(param_name) {
-                                child.set_string("name", new_param);
-                            }
+            InteractionItem::Enactment(enactment) => {
+                for argument in &mut enactment.arguments {
+                    match argument {
+                        Argument::Role(role) => rename(&mut role.name, role_mappings),
+                        Argument::Parameter(flow) => {
+                            rename(&mut flow.parameter, parameter_mappings)
                         }
                     }
                 }
             }
-            _ => {}
         }
+    }
+}
 
-        // Recursively apply mappings to children
-        for child in &mut node.children {
-            self.apply_mappings(child, role_mappings, parameter_mappings)?;
+/// Validate all protocol compositions in a program
+pub fn validate_protocol_compositions(program: &AstNode) -> Result<()> {
+    let registry = ProtocolRegistry::from_program(program)?;
+
+    // Check all protocols for composition references
+    for protocol in Program::from_ast(program)?.protocols {
+        if let Some(diagnostic) = validate_signature(&protocol).into_iter().next() {
+            return Err(diagnostic.into());
         }
+        for enactment in protocol.enactments() {
+            let Some(referenced_protocol) = registry.get_protocol(enactment.protocol.as_str())
+            else {
+                return Err(anyhow!(
+                    "Unknown protocol '{}' referenced in composition",
+                    enactment.protocol
+                ));
+            };
 
-        Ok(())
+            // Validate composition parameters and their types
+            bind_enactment(enactment, referenced_protocol)?;
+            let (_, mismatches) = enactment_types(enactment, &protocol, referenced_protocol);
+            if let Some(mismatch) = mismatches.into_iter().next() {
+                return Err(mismatch.into());
+            }
+        }
     }
 
-    /// Get all registered protocol names
-    pub fn get_protocol_names(&self) -> Vec<String> {
-        self.protocols.keys().cloned().collect()
+    Ok(())
+}
+
+/// Resolve which callee role and parameter every argument of an enactment binds.
+///
+/// Arguments renamed with `as` bind the callee role or parameter of that name. Other roles
+/// bind the callee role declared at the same position and other parameters the callee
+/// parameter of the same name. Every callee role and public parameter (a key, an `in`
+/// parameter of the callee's signature or, without a signature, one the callee consumes
+/// without producing it) must end up bound exactly once. When the callee declares a
+/// signature, only its parameters can be bound, each with the adornment it declares.
+pub fn bind_enactment(
+    enactment: &Enactment,
+    referenced_protocol: &Protocol,
+) -> Result<EnactmentBindings> {
+    let protocol_name = referenced_protocol.name.as_str();
+    let enactment_span = enactment.protocol.span;
+    let protocol_roles: Vec<&str> = referenced_protocol
+        .roles
+        .iter()
+        .map(|role| role.name.as_str())
+        .collect();
+    let mut bindings = EnactmentBindings::default();
+
+    let role_params: Vec<_> = enactment.roles().collect();
+
+    // Purely positional role lists keep their original count check
+    if role_params.iter().all(|p| p.alias.is_none()) && role_params.len() != protocol_roles.len() {
+        return Err(error_at(
+            enactment_span,
+            "wrong number of roles",
+            format!(
+                "Role count mismatch in composition of '{}': expected {}, got {}",
+                protocol_name,
+                protocol_roles.len(),
+                role_params.len()
+            ),
+        ));
     }
 
-    /// Check if a protocol is registered
-    pub fn has_protocol(&self, name: &str) -> bool {
-        self.protocols.contains_key(name)
+    for (position, param) in role_params.iter().enumerate() {
+        let callee_role = match param.bound_name() {
+            Some(role) => role,
+            None => protocol_roles.get(position).copied().ok_or_else(|| {
+                error_at(
+                    param.span,
+                    "no role at this position",
+                    format!(
+                        "Role '{}' in composition of '{}' has no role at its position to bind",
+                        param.name, protocol_name
+                    ),
+                )
+            })?,
+        };
+        if !protocol_roles.contains(&callee_role) {
+            return Err(error_at(
+                param.span,
+                "unknown role",
+                format!(
+                    "Role '{}' is not declared in protocol '{}'",
+                    callee_role, protocol_name
+                ),
+            ));
+        }
+        if bindings
+            .roles
+            .insert(callee_role.to_string(), param.name.value.clone())
+            .is_some()
+        {
+            return Err(error_at(
+                param.span,
+                "bound again here",
+                format!(
+                    "Role '{}' of '{}' is bound more than once in its enactment",
+                    callee_role, protocol_name
+                ),
+            ));
+        }
     }
 
-    /// Get a protocol by name
-    pub fn get_protocol(&self, name: &str) -> Option<&AstNode> {
-        self.protocols.get(name)
+    for role in &protocol_roles {
+        if !bindings.roles.contains_key(*role) {
+            return Err(error_at(
+                enactment_span,
+                &format!("role '{}' is not bound", role),
+                format!(
+                    "Role '{}' of '{}' is not bound in its enactment",
+                    role, protocol_name
+                ),
+            ));
+        }
     }
-}
 
-/// Validate all protocol compositions in a program
-pub fn validate_protocol_compositions(program: &AstNode) -> Result<()> {
-    let registry = ProtocolRegistry::from_program(program)?;
+    for param in enactment.flows() {
+        let callee_param = param.bound_name();
+        if referenced_protocol.parameter(callee_param).is_none() {
+            return Err(error_at(
+                param.span,
+                "not declared in the referenced protocol",
+                format!(
+                    "Parameter '{}' in composition of '{}' is not declared in the referenced protocol",
+                    callee_param, protocol_name
+                ),
+            ));
+        }
+        if let Some(signature) = &referenced_protocol.signature {
+            match referenced_protocol.public_parameter(callee_param) {
+                None => {
+                    return Err(Diagnostic::new(
+                        DiagnosticCode::PrivateParameter,
+                        format!(
+                            "Parameter '{}' of '{}' is private and cannot be bound in its enactment",
+                            callee_param, protocol_name
+                        ),
+                    )
+                    .with_label(param.span, "not in the signature")
+                    .with_note_at(signature.span, "public parameters declared here")
+                    .into());
+                }
+                Some(public) if public.direction != param.direction => {
+                    return Err(Diagnostic::new(
+                        DiagnosticCode::SignatureMismatch,
+                        format!(
+                            "Parameter '{}' is bound '{}' in the enactment of '{}' but its signature declares it '{}'",
+                            callee_param, param.direction, protocol_name, public.direction
+                        ),
+                    )
+                    .with_label(param.span, format!("bound '{}' here", param.direction))
+                    .with_note_at(public.span, format!("declared '{}' here", public.direction))
+                    .into());
+                }
+                Some(_) => {}
+            }
+        }
+        if bindings
+            .parameters
+            .insert(callee_param.to_string(), param.parameter.value.clone())
+            .is_some()
+        {
+            return Err(error_at(
+                param.span,
+                "bound again here",
+                format!(
+                    "Parameter '{}' of '{}' is bound more than once in its enactment",
+                    callee_param, protocol_name
+                ),
+            ));
+        }
+    }
 
-    // Check all protocols for composition references
-    for protocol_node in &program.children {
-        if protocol_node.node_type == AstNodeType::Protocol {
-            validate_protocol_compositions_recursive(protocol_node, &registry)?;
+    // Keys identify the enactment, so each one must be bound by the caller
+    for key in referenced_protocol.keys() {
+        if !bindings.parameters.contains_key(key.name.as_str()) {
+            return Err(Diagnostic::new(
+                DiagnosticCode::UnboundKey,
+                format!(
+                    "Key parameter '{}' of '{}' is not bound in its enactment",
+                    key.name, protocol_name
+                ),
+            )
+            .with_label(enactment_span, format!("key '{}' is not bound", key.name))
+            .with_note_at(key.name.span, "key declared here")
+            .into());
         }
     }
 
-    Ok(())
+    for param in public_parameters(referenced_protocol) {
+        if !bindings.parameters.contains_key(param) {
+            return Err(error_at(
+                enactment_span,
+                &format!("'{}' is not bound", param),
+                format!(
+                    "Public parameter '{}' of '{}' is not bound in its enactment",
+                    param, protocol_name
+                ),
+            ));
+        }
+    }
+
+    Ok(bindings)
 }
 
-fn validate_protocol_compositions_recursive(
-    node: &AstNode,
-    registry: &ProtocolRegistry,
-) -> Result<()> {
-    match node.node_type {
-        AstNodeType::ProtocolComposition => {
-            // Extract and validate protocol reference
-            let protocol_name = extract_composition_protocol_name_for_validation(node)?;
+/// The innermost declarations where two types met at an enactment boundary disagree
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeMismatch {
+    /// What differs when it is inside the types, e.g. a field of a record
+    pub detail: Option<String>,
+    /// The disagreeing declaration in the caller
+    pub caller: Note,
+    /// The disagreeing declaration in the callee
+    pub callee: Note,
+}
 
-            if !registry.has_protocol(&protocol_name) {
-                return Err(anyhow!(
-                    "Unknown protocol '{}' referenced in composition",
-                    protocol_name
-                ));
+/// Unify the type a caller declares for an enactment argument with the type the callee
+/// declares for the parameter it binds.
+///
+/// Basic types unify with themselves, enums with enums of the same variants in any order,
+/// lists when their elements do and records when they have the same name and fields of the
+/// same names whose types unify. The unified type is spelled as the caller declares it.
+pub fn unify_types(
+    caller: &Protocol,
+    caller_type: &TypeExpr,
+    callee: &Protocol,
+    callee_type: &TypeExpr,
+) -> std::result::Result<BmppType, TypeMismatch> {
+    let mut unifier = Unifier {
+        caller,
+        callee,
+        visiting: HashSet::new(),
+    };
+    unifier.unify(caller_type, callee_type)?;
+    Ok(caller_type.ty())
+}
+
+struct Unifier<'a> {
+    caller: &'a Protocol,
+    callee: &'a Protocol,
+    /// Records being compared, taken as unified when met again through a list
+    visiting: HashSet<String>,
+}
+
+impl Unifier<'_> {
+    fn unify(
+        &mut self,
+        caller_type: &TypeExpr,
+        callee_type: &TypeExpr,
+    ) -> std::result::Result<(), TypeMismatch> {
+        let unified = match (&caller_type.kind, &callee_type.kind) {
+            (TypeKind::Basic(a), TypeKind::Basic(b)) => a == b,
+            (TypeKind::Enum(a), TypeKind::Enum(b)) => {
+                let a: HashSet<&str> = a.iter().map(Spanned::as_str).collect();
+                let b: HashSet<&str> = b.iter().map(Spanned::as_str).collect();
+                a == b
             }
+            (TypeKind::List(a), TypeKind::List(b)) => return self.unify(a, b),
+            (TypeKind::Named(a), TypeKind::Named(b)) if a == b => return self.unify_records(a),
+            _ => false,
+        };
+        if unified {
+            return Ok(());
+        }
+        Err(TypeMismatch {
+            detail: None,
+            caller: declared_as(caller_type, None),
+            callee: declared_as(callee_type, Some(self.callee.name.as_str())),
+        })
+    }
 
-            // Validate composition parameters
-            let composition_params = extract_composition_parameters_for_validation(node)?;
-            let referenced_protocol = registry.get_protocol(&protocol_name).unwrap();
+    fn unify_records(&mut self, name: &str) -> std::result::Result<(), TypeMismatch> {
+        // Undeclared records are reported on their own
+        let (Some(caller_record), Some(callee_record)) =
+            (record(self.caller, name), record(self.callee, name))
+        else {
+            return Ok(());
+        };
+        if !self.visiting.insert(name.to_string()) {
+            return Ok(());
+        }
 
-            registry.validate_composition_parameters(
-                referenced_protocol,
-                &composition_params,
-                &protocol_name,
-            )?;
+        let callee_name = self.callee.name.as_str();
+        for field in &caller_record.fields {
+            let Some(callee_field) = callee_record.field(field.name.as_str()) else {
+                return Err(TypeMismatch {
+                    detail: Some(format!(
+                        "record '{}' has no field '{}' in '{}'",
+                        name, field.name, callee_name
+                    )),
+                    caller: Note {
+                        message: "field declared here".to_string(),
+                        span: Some(field.name.span),
+                    },
+                    callee: Note {
+                        message: format!("'{}' declared without it in '{}'", name, callee_name),
+                        span: Some(callee_record.name.span),
+                    },
+                });
+            };
+            self.unify(&field.ty, &callee_field.ty)
+                .map_err(|mismatch| TypeMismatch {
+                    detail: mismatch.detail.or_else(|| {
+                        Some(format!(
+                            "field '{}' of record '{}' is '{}' but '{}' in '{}'",
+                            field.name,
+                            name,
+                            field.ty.ty(),
+                            callee_field.ty.ty(),
+                            callee_name
+                        ))
+                    }),
+                    ..mismatch
+                })?;
         }
-        _ => {
-            // Recursively validate children
-            for child in &node.children {
-                validate_protocol_compositions_recursive(child, registry)?;
-            }
+        if let Some(extra) = callee_record
+            .fields
+            .iter()
+            .find(|field| caller_record.field(field.name.as_str()).is_none())
+        {
+            return Err(TypeMismatch {
+                detail: Some(format!(
+                    "record '{}' has an extra field '{}' in '{}'",
+                    name, extra.name, callee_name
+                )),
+                caller: Note {
+                    message: format!("'{}' declared without it here", name),
+                    span: Some(caller_record.name.span),
+                },
+                callee: Note {
+                    message: format!("field declared in '{}'", callee_name),
+                    span: Some(extra.name.span),
+                },
+            });
         }
+        Ok(())
     }
+}
 
-    Ok(())
+fn record<'a>(protocol: &'a Protocol, name: &str) -> Option<&'a TypeDecl> {
+    protocol
+        .type_decls()
+        .iter()
+        .find(|decl| decl.name.as_str() == name)
 }
 
-fn extract_composition_protocol_name_for_validation(composition_node: &AstNode) -> Result<String> {
-    for child in &composition_node.children {
-        if child.node_type == AstNodeType::ProtocolReference {
-            for ref_child in &child.children {
-                if ref_child.node_type == AstNodeType::Identifier {
-                    if let Some(name) = ref_child.get_string("name") {
-                        return Ok(name.clone());
-                    }
-                }
-            }
-        }
+fn declared_as(ty: &TypeExpr, protocol: Option<&str>) -> Note {
+    let message = match protocol {
+        Some(protocol) => format!("declared as '{}' in '{}'", ty.ty(), protocol),
+        None => format!("declared as '{}' here", ty.ty()),
+    };
+    Note {
+        message,
+        span: Some(ty.span),
     }
-    Err(anyhow!("Protocol reference name not found in composition"))
 }
 
-fn extract_composition_parameters_for_validation(
-    composition_node: &AstNode,
-) -> Result<Vec<CompositionParameter>> {
-    let mut parameters = Vec::new();
-
-    for child in &composition_node.children {
-        match child.node_type {
-            AstNodeType::Identifier => {
-                if let Some(name) = child.get_string("name") {
-                    parameters.push(CompositionParameter {
-                        parameter_type: CompositionParameterType::RoleIdentifier,
-                        name: name.clone(),
-                        direction: None,
-                    });
-                }
-            }
-            AstNodeType::ParameterFlow => {
-                if let Some(direction) = child.get_string("direction") {
-                    for flow_child in &child.children {
-                        if flow_child.node_type == AstNodeType::Identifier {
-                            if let Some(param_name) = flow_child.get_string("name") {
-                                parameters.push(CompositionParameter {
-                                    parameter_type: CompositionParameterType::ParameterFlow,
-                                    name: param_name.clone(),
-                                    direction: Some(direction.clone()),
-                                });
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
-            AstNodeType::ProtocolReference => {
-                // Skip - handled separately
+/// Unified type of every parameter an enactment passes, and a diagnostic for each one whose
+/// caller and callee types do not unify. Parameters either side does not declare are left
+/// out; they are reported by other checks.
+pub fn enactment_types<'a>(
+    enactment: &'a Enactment,
+    caller: &Protocol,
+    callee: &Protocol,
+) -> (Vec<(&'a Flow, BmppType)>, Vec<Diagnostic>) {
+    let callee_name = callee.name.as_str();
+    let mut types = Vec::new();
+    let mut mismatches = Vec::new();
+
+    for flow in enactment.flows() {
+        let name = flow.parameter.as_str();
+        let bound = flow.bound_name();
+        let (Some(caller_param), Some(callee_param)) =
+            (caller.parameter(name), callee.parameter(bound))
+        else {
+            continue;
+        };
+        let mismatch = match unify_types(caller, &caller_param.ty, callee, &callee_param.ty) {
+            Ok(ty) => {
+                types.push((flow, ty));
+                continue;
             }
+            Err(mismatch) => mismatch,
+        };
+
+        let (caller_type, callee_type) = (caller_param.ty.ty(), callee_param.ty.ty());
+        let binding = if bound == name {
+            String::new()
+        } else {
+            format!(" (bound as '{}')", bound)
+        };
+        let message = match &mismatch.detail {
+            Some(detail) => format!(
+                "Parameter '{}' has type '{}' in protocol '{}' that does not match the one in enacted protocol '{}'{}: {}",
+                name, caller_type, caller.name, callee_name, binding, detail
+            ),
+            None => format!(
+                "Parameter '{}' has type '{}' in protocol '{}' but type '{}' in enacted protocol '{}'{}",
+                name, caller_type, caller.name, callee_type, callee_name, binding
+            ),
+        };
+        let label = if mismatch.detail.is_some() {
+            format!("'{}' differs in '{}'", caller_type, callee_name)
+        } else {
+            format!(
+                "'{}' here, '{}' in '{}'",
+                caller_type, callee_type, callee_name
+            )
+        };
+        let mut diagnostic = Diagnostic::new(DiagnosticCode::CompositionTypeMismatch, message)
+            .with_label(flow.parameter.span, label);
+        diagnostic.notes.push(mismatch.caller);
+        diagnostic.notes.push(mismatch.callee);
+        mismatches.push(diagnostic);
+    }
+    (types, mismatches)
+}
+
+/// Check a protocol's signature against its body: every public parameter is declared and
+/// listed once, `in` parameters are not produced inside, `out` parameters are, and the keys
+/// are exactly the parameters marked `key`
+pub fn validate_signature(protocol: &Protocol) -> Vec<Diagnostic> {
+    let Some(signature) = &protocol.signature else {
+        return Vec::new();
+    };
+    let name = protocol.name.as_str();
+    let mut diagnostics = Vec::new();
+    let producer = |parameter: &str| {
+        protocol.interactions.iter().find_map(|item| {
+            item.flows()
+                .into_iter()
+                .find(|flow| {
+                    flow.direction == ParameterDirection::Out
+                        && flow.parameter.as_str() == parameter
+                })
+                .map(|flow| (item.name(), flow.parameter.span))
+        })
+    };
+
+    let mut listed = HashSet::new();
+    for public in signature {
+        let parameter = public.name.as_str();
+        if !listed.insert(parameter) {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticCode::SignatureMismatch,
+                    format!(
+                        "Parameter '{}' is listed more than once in the signature of protocol '{}'",
+                        parameter, name
+                    ),
+                )
+                .with_label(public.name.span, "listed again here"),
+            );
+            continue;
+        }
+        let Some(declared) = protocol.parameter(parameter) else {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticCode::UndeclaredParameter,
+                    format!(
+                        "Parameter '{}' in the signature of protocol '{}' is not declared in it",
+                        parameter, name
+                    ),
+                )
+                .with_label(public.name.span, "not declared in the parameters section"),
+            );
+            continue;
+        };
+
+        match (public.direction, producer(parameter)) {
+            (ParameterDirection::In, Some((action, span))) => diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticCode::SignatureMismatch,
+                    format!(
+                        "Public parameter '{}' of protocol '{}' is 'in' but '{}' produces it",
+                        parameter, name, action
+                    ),
+                )
+                .with_label(span, "produced here")
+                .with_note_at(public.span, "declared 'in' here"),
+            ),
+            (ParameterDirection::Out, None) => diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticCode::SignatureMismatch,
+                    format!(
+                        "Public parameter '{}' of protocol '{}' is 'out' but nothing in it produces it",
+                        parameter, name
+                    ),
+                )
+                .with_label(public.span, "never produced"),
+            ),
             _ => {}
         }
+        if public.key && !declared.key {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticCode::SignatureMismatch,
+                    format!(
+                        "Parameter '{}' is marked 'key' in the signature of protocol '{}' but not declared as a key",
+                        parameter, name
+                    ),
+                )
+                .with_label(public.span, "marked 'key' here")
+                .with_note_at(declared.name.span, "declared here"),
+            );
+        }
+    }
+
+    for key in protocol.keys() {
+        if !protocol
+            .public_parameter(key.name.as_str())
+            .is_some_and(|public| public.key)
+        {
+            diagnostics.push(
+                Diagnostic::new(
+                    DiagnosticCode::SignatureMismatch,
+                    format!(
+                        "Key parameter '{}' of protocol '{}' must be listed with 'key' in its signature",
+                        key.name, name
+                    ),
+                )
+                .with_label(signature.span, format!("'{}' is not listed as a key", key.name))
+                .with_note_at(key.name.span, "key declared here"),
+            );
+        }
     }
+    diagnostics
+}
 
-    Ok(parameters)
+/// Parameters the enacting protocol must provide: the `in` parameters of the signature or,
+/// without one, those the protocol consumes without ever producing them
+fn public_parameters(protocol: &Protocol) -> Vec<&str> {
+    if let Some(signature) = &protocol.signature {
+        return signature
+            .iter()
+            .filter(|public| public.direction == ParameterDirection::In)
+            .map(|public| public.name.as_str())
+            .collect();
+    }
+    let flows: Vec<_> = protocol
+        .interactions
+        .iter()
+        .flat_map(InteractionItem::flows)
+        .collect();
+    let produced: HashSet<&str> = flows
+        .iter()
+        .filter(|flow| flow.direction == ParameterDirection::Out)
+        .map(|flow| flow.parameter.as_str())
+        .collect();
+
+    let mut public = Vec::new();
+    for flow in &flows {
+        let name = flow.parameter.as_str();
+        if flow.direction == ParameterDirection::In
+            && !produced.contains(name)
+            && !public.contains(&name)
+        {
+            public.push(name);
+        }
+    }
+    public
+}
+
+/// Build a binding error that underlines `span` when rendered
+fn error_at(span: Span, label: &str, message: String) -> anyhow::Error {
+    Diagnostic::new(DiagnosticCode::InvalidBinding, message)
+        .with_label(span, label)
+        .into()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::model::{Flow, RoleBinding};
 
     #[test]
     fn test_protocol_registry_creation() {
@@ -544,26 +933,370 @@ mod tests {
 
     #[test]
     fn test_composition_parameter_types() {
-        let role_param = CompositionParameter {
-            parameter_type: CompositionParameterType::RoleIdentifier,
-            name: "TestRole".to_string(),
-            direction: None,
+        let role_param = Argument::Role(RoleBinding {
+            name: Spanned::new("TestRole".to_string(), Span::default()),
+            alias: None,
+            span: Span::default(),
+        });
+
+        let flow_param = Argument::Parameter(Flow {
+            direction: ParameterDirection::In,
+            parameter: Spanned::new("TestParam".to_string(), Span::default()),
+            alias: None,
+            span: Span::default(),
+        });
+
+        assert!(matches!(role_param, Argument::Role(_)));
+        let Argument::Parameter(flow) = flow_param else {
+            panic!("expected a parameter flow");
         };
+        assert_eq!(flow.direction, ParameterDirection::In);
+        assert_eq!(flow.bound_name(), "TestParam");
+    }
 
-        let flow_param = CompositionParameter {
-            parameter_type: CompositionParameterType::ParameterFlow,
-            name: "TestParam".to_string(),
-            direction: Some("in".to_string()),
-        };
+    #[test]
+    fn test_composition_requires_callee_keys() {
+        let source = r#"
+Main <Protocol>("caller") {
+    roles
+        A <Agent>("agent A"),
+        B <Agent>("agent B")
+
+    parameters
+        ID <String> key ("identifier"),
+        result <String>("result")
+
+    A -> B: start <Action>("start")[in ID, out result]
+    Sub <Enactment>[A, B, in result]
+}
+
+Sub <Protocol>("callee") {
+    roles
+        X <Agent>("agent X"),
+        Y <Agent>("agent Y")
+
+    parameters
+        ID <String> key ("identifier"),
+        result <String>("result")
+
+    X -> Y: finish <Action>("finish")[in ID, in result]
+}
+        "#;
+
+        let program = crate::transpiler::parse_source(source).unwrap();
+        let error = validate_protocol_compositions(&program).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Key parameter 'ID' of 'Sub' is not bound in its enactment"
+        );
+
+        let bound = source.replace(
+            "Sub <Enactment>[A, B, in result]",
+            "Sub <Enactment>[A, B, in ID, in result]",
+        );
+        let program = crate::transpiler::parse_source(&bound).unwrap();
+        assert!(validate_protocol_compositions(&program).is_ok());
+    }
+
+    const PACK: &str = r#"
+Pack <Protocol>("packing") {
+    roles
+        Warehouse <Agent>("warehouse"),
+        Packer <Agent>("packer")
+
+    parameters
+        ID <String> key ("identifier"),
+        purchase_order <String>("order to pack"),
+        tag <String>("package tag")
+
+    Warehouse -> Packer: pack <Action>("pack")[in ID, in purchase_order, out tag]
+}
+"#;
+
+    fn logistics(enactment: &str) -> String {
+        format!(
+            r#"
+Logistics <Protocol>("logistics") {{
+    roles
+        P <Agent>("packer"),
+        W <Agent>("warehouse")
+
+    parameters
+        ID <String> key ("identifier"),
+        order <String>("order"),
+        tag <String>("tag")
+
+    W -> P: notify <Action>("notify")[out ID, out order]
+    {}
+}}
+{}"#,
+            enactment, PACK
+        )
+    }
+
+    #[test]
+    fn test_named_bindings_rename_roles_and_parameters() {
+        let source = logistics(
+            "Pack <Enactment>[P as Packer, W as Warehouse, in ID, in order as purchase_order, out tag]",
+        );
+        let program = crate::transpiler::parse_source(&source).unwrap();
+        validate_protocol_compositions(&program).unwrap();
+
+        let registry = ProtocolRegistry::from_program(&program).unwrap();
+        let mut logistics = (*program.children[0]).clone();
+        registry
+            .resolve_protocol_references(&mut logistics)
+            .unwrap();
+
+        let instance = logistics.find_nodes(AstNodeType::StandardInteraction)[1].clone();
+        let info = instance.get_standard_interaction_info().unwrap();
+        assert_eq!((info.from_role.as_str(), info.to_role.as_str()), ("W", "P"));
+        let flows: Vec<String> = instance
+            .get_parameter_flows()
+            .iter()
+            .filter_map(|flow| flow.get_parameter_flow_info())
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(flows, vec!["ID", "order", "tag"]);
+    }
+
+    #[test]
+    fn test_positional_roles_still_bind_in_order() {
+        let program = crate::transpiler::parse_source(&logistics(
+            "Pack <Enactment>[W, P, in ID, in order as purchase_order, out tag]",
+        ))
+        .unwrap();
+        let program = Program::from_ast(&program).unwrap();
+        let referenced = &program.protocols[1];
+        let enactment = program.protocols[0].enactments().next().unwrap();
+
+        let bindings = bind_enactment(enactment, referenced).unwrap();
+        assert_eq!(bindings.roles["Warehouse"], "W");
+        assert_eq!(bindings.roles["Packer"], "P");
+        assert_eq!(bindings.parameters["purchase_order"], "order");
+        assert_eq!(bindings.parameters["ID"], "ID");
+    }
+
+    #[test]
+    fn test_every_callee_role_and_public_parameter_bound_once() {
+        let cases = [
+            (
+                "Pack <Enactment>[W as Warehouse, P as Warehouse, in ID, in order as purchase_order]",
+                "Role 'Warehouse' of 'Pack' is bound more than once in its enactment",
+            ),
+            (
+                "Pack <Enactment>[W as Warehouse, in ID, in order as purchase_order]",
+                "Role 'Packer' of 'Pack' is not bound in its enactment",
+            ),
+            (
+                "Pack <Enactment>[W as Shipper, P as Packer, in ID, in order as purchase_order]",
+                "Role 'Shipper' is not declared in protocol 'Pack'",
+            ),
+            (
+                "Pack <Enactment>[W, P, in ID, in order]",
+                "Parameter 'order' in composition of 'Pack' is not declared in the referenced protocol",
+            ),
+            (
+                "Pack <Enactment>[W, P, in ID, in order as purchase_order, in tag as purchase_order]",
+                "Parameter 'purchase_order' of 'Pack' is bound more than once in its enactment",
+            ),
+            (
+                "Pack <Enactment>[W, P, in ID, out tag]",
+                "Public parameter 'purchase_order' of 'Pack' is not bound in its enactment",
+            ),
+        ];
+
+        for (enactment, expected) in cases {
+            let program = crate::transpiler::parse_source(&logistics(enactment)).unwrap();
+            let error = validate_protocol_compositions(&program).unwrap_err();
+            assert_eq!(error.to_string(), expected, "for {}", enactment);
+        }
+    }
+
+    fn with_signature(source: &str, signature: &str) -> String {
+        source.replace(
+            "Pack <Protocol>(\"packing\") {",
+            &format!("Pack <Protocol>(\"packing\") {} {{", signature),
+        )
+    }
+
+    #[test]
+    fn test_enactment_binds_only_public_parameters_as_declared() {
+        let enactment = "Pack <Enactment>[W, P, in ID, in order as purchase_order, out tag]";
+        let public = with_signature(
+            &logistics(enactment),
+            "[in ID key, in purchase_order, out tag]",
+        );
+        validate_protocol_compositions(&crate::transpiler::parse_source(&public).unwrap()).unwrap();
+
+        let cases = [
+            (
+                with_signature(&logistics(enactment), "[in ID key, in purchase_order]"),
+                "Parameter 'tag' of 'Pack' is private and cannot be bound in its enactment",
+            ),
+            (
+                public.replacen("out tag]", "in tag]", 1),
+                "Parameter 'tag' is bound 'in' in the enactment of 'Pack' but its signature declares it 'out'",
+            ),
+            (
+                public.replace("in order as purchase_order, ", ""),
+                "Public parameter 'purchase_order' of 'Pack' is not bound in its enactment",
+            ),
+        ];
+        for (source, expected) in cases {
+            let program = crate::transpiler::parse_source(&source).unwrap();
+            let error = validate_protocol_compositions(&program).unwrap_err();
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_signature_agrees_with_protocol_body() {
+        let source = with_signature(PACK, "[in ID, in tag, out purchase_order, out ghost]");
+        let program =
+            Program::from_ast(&crate::transpiler::parse_source(&source).unwrap()).unwrap();
+
+        let messages: Vec<String> = validate_signature(&program.protocols[0])
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Public parameter 'tag' of protocol 'Pack' is 'in' but 'pack' produces it",
+                "Public parameter 'purchase_order' of protocol 'Pack' is 'out' but nothing in it produces it",
+                "Parameter 'ghost' in the signature of protocol 'Pack' is not declared in it",
+                "Key parameter 'ID' of protocol 'Pack' must be listed with 'key' in its signature",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flatten_inlines_enactments_with_fresh_names() {
+        let source = logistics(
+            "Pack <Enactment>[P as Packer, W as Warehouse, in ID, in order as purchase_order, out tag]",
+        )
+        .replace(
+            "tag <String>(\"package tag\")\n",
+            "tag <String>(\"package tag\"),\n        order <String>(\"packing note\")\n",
+        )
+        .replace(
+            "in purchase_order, out tag]\n",
+            "in purchase_order, out tag]\n    Packer -> Warehouse: notify <Action>(\"notify\")[in ID, in tag, out order]\n",
+        );
+        let program = crate::transpiler::parse_source(&source).unwrap();
+        let registry = ProtocolRegistry::from_program(&program).unwrap();
+
+        let flat = registry.flatten("Logistics").unwrap();
+        let parameters: Vec<&str> = flat
+            .parameters
+            .items
+            .iter()
+            .map(|parameter| parameter.name.value.as_str())
+            .collect();
+        assert_eq!(parameters, vec!["ID", "order", "tag", "Pack_order"]);
+        let actions: Vec<&str> = flat
+            .interactions
+            .iter()
+            .filter_map(|item| match item {
+                InteractionItem::Interaction(interaction) => {
+                    Some(interaction.action.value.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(actions, vec!["notify", "pack", "Pack_notify"]);
+
+        let flattened = crate::transpiler::format_program(&Program {
+            imports: Vec::new(),
+            protocols: vec![flat],
+            span: Default::default(),
+        });
+        assert!(flattened.contains("W -> P: pack <Action>(\"pack\")[in ID, in order, out tag]"));
+        assert!(
+            flattened.contains(
+                "P -> W: Pack_notify <Action>(\"notify\")[in ID, in tag, out Pack_order]"
+            )
+        );
+        let reparsed = crate::transpiler::parse_source(&flattened).unwrap();
+        let report = crate::transpiler::validation::validate(&reparsed).unwrap();
+        assert!(!report.has_errors());
+    }
+
+    #[test]
+    fn test_flatten_rejects_recursive_enactment() {
+        let program = crate::transpiler::parse_source(PING_PONG).unwrap();
+        let registry = ProtocolRegistry::from_program(&program).unwrap();
+
+        let error = registry.flatten("Ping").unwrap_err();
+        let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.code, Some(DiagnosticCode::RecursiveEnactment));
+        assert!(registry.flatten("Missing").is_err());
+    }
+
+    const PING_PONG: &str = r#"
+Ping <Protocol>("ping") {
+    roles
+        X <Agent>("pinger"),
+        Y <Agent>("ponger")
+
+    parameters
+        ID <String> key ("identifier"),
+        message <String>("message")
+
+    X -> Y: ping <Action>("ping")[out ID, out message]
+    Pong <Enactment>[X, Y, in ID]
+}
 
+Pong <Protocol>("pong") {
+    roles
+        X <Agent>("pinger"),
+        Y <Agent>("ponger")
+
+    parameters
+        ID <String> key ("identifier"),
+        reply <String>("reply")
+
+    Y -> X: pong <Action>("pong")[in ID, out reply]
+    Ping <Enactment>[X, Y, in ID]
+}
+"#;
+
+    #[test]
+    fn test_recursive_enactment_is_not_expanded() {
+        let program = crate::transpiler::parse_source(PING_PONG).unwrap();
+        let registry = ProtocolRegistry::from_program(&program).unwrap();
+        let mut ping = (*program.children[0]).clone();
+
+        let error = registry.resolve_protocol_references(&mut ping).unwrap_err();
         assert_eq!(
-            role_param.parameter_type,
-            CompositionParameterType::RoleIdentifier
+            error.to_string(),
+            "Protocol 'Ping' enacts itself through 'Ping' -> 'Pong' -> 'Ping' - recursive enactment not allowed"
         );
+        let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.code, Some(DiagnosticCode::RecursiveEnactment));
+    }
+
+    #[test]
+    fn test_enactment_nesting_is_limited() {
+        let source = PING_PONG.replace("    Ping <Enactment>[X, Y, in ID]\n", "");
+        let program = crate::transpiler::parse_source(&source).unwrap();
+        let ping = (*program.children[0]).clone();
+
+        let registry = ProtocolRegistry::from_program(&program)
+            .unwrap()
+            .with_max_nesting(1);
+        registry
+            .resolve_protocol_references(&mut ping.clone())
+            .unwrap();
+
+        let error = registry
+            .with_max_nesting(0)
+            .resolve_protocol_references(&mut ping.clone())
+            .unwrap_err();
         assert_eq!(
-            flow_param.parameter_type,
-            CompositionParameterType::ParameterFlow
+            error.to_string(),
+            "Enactments nest deeper than 0 protocols: 'Ping' -> 'Pong'"
         );
-        assert_eq!(flow_param.direction, Some("in".to_string()));
     }
 }
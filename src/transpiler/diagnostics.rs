@@ -0,0 +1,563 @@
+use crate::protocol::ast::{LineIndex, Span};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// How serious a diagnostic is. Errors reject the protocol, warnings only point at
+/// something that is likely a mistake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Stable identifier of every kind of diagnostic the transpiler reports.
+///
+/// Codes are never renumbered or reused, so they can be matched on by tools and looked up
+/// in the documentation; new kinds get the next free number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticCode {
+    SyntaxError,
+    UndeclaredParameter,
+    UnproducedParameter,
+    MultipleProducers,
+    ConflictingAdornments,
+    CausalCycle,
+    UnsatisfiableNilGuard,
+    UnusedParameter,
+    DeadEndParameter,
+    UnreachableInteraction,
+    DuplicateType,
+    DuplicateField,
+    KeyField,
+    UnknownType,
+    EmptyEnum,
+    DuplicateVariant,
+    RecursiveRecord,
+    UnknownProtocol,
+    SelfEnactment,
+    UnboundKey,
+    InvalidBinding,
+    CompositionTypeMismatch,
+    ImportNotFound,
+    ImportCycle,
+    UnknownImport,
+    DuplicateProtocol,
+    UnknownLint,
+    UnsafeEnactment,
+    StuckEnactment,
+    NondeterministicEnactment,
+    UninformedSender,
+    RecursiveEnactment,
+    EnactmentTooDeep,
+    PrivateParameter,
+    SignatureMismatch,
+    UndeclaredRole,
+    DuplicateAction,
+}
+
+impl DiagnosticCode {
+    pub const ALL: [DiagnosticCode; 37] = [
+        DiagnosticCode::SyntaxError,
+        DiagnosticCode::UndeclaredParameter,
+        DiagnosticCode::UnproducedParameter,
+        DiagnosticCode::MultipleProducers,
+        DiagnosticCode::ConflictingAdornments,
+        DiagnosticCode::CausalCycle,
+        DiagnosticCode::UnsatisfiableNilGuard,
+        DiagnosticCode::UnusedParameter,
+        DiagnosticCode::DeadEndParameter,
+        DiagnosticCode::UnreachableInteraction,
+        DiagnosticCode::DuplicateType,
+        DiagnosticCode::DuplicateField,
+        DiagnosticCode::KeyField,
+        DiagnosticCode::UnknownType,
+        DiagnosticCode::EmptyEnum,
+        DiagnosticCode::DuplicateVariant,
+        DiagnosticCode::RecursiveRecord,
+        DiagnosticCode::UnknownProtocol,
+        DiagnosticCode::SelfEnactment,
+        DiagnosticCode::UnboundKey,
+        DiagnosticCode::InvalidBinding,
+        DiagnosticCode::CompositionTypeMismatch,
+        DiagnosticCode::ImportNotFound,
+        DiagnosticCode::ImportCycle,
+        DiagnosticCode::UnknownImport,
+        DiagnosticCode::DuplicateProtocol,
+        DiagnosticCode::UnknownLint,
+        DiagnosticCode::UnsafeEnactment,
+        DiagnosticCode::StuckEnactment,
+        DiagnosticCode::NondeterministicEnactment,
+        DiagnosticCode::UninformedSender,
+        DiagnosticCode::RecursiveEnactment,
+        DiagnosticCode::EnactmentTooDeep,
+        DiagnosticCode::PrivateParameter,
+        DiagnosticCode::SignatureMismatch,
+        DiagnosticCode::UndeclaredRole,
+        DiagnosticCode::DuplicateAction,
+    ];
+
+    /// Code printed with the diagnostic, e.g. `BMPP0003`
+    pub fn code(&self) -> String {
+        let position = Self::ALL.iter().position(|code| code == self).unwrap_or(0);
+        format!("BMPP{:04}", position + 1)
+    }
+
+    /// Lint-style name, e.g. `dead_end_parameter`
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiagnosticCode::SyntaxError => "syntax_error",
+            DiagnosticCode::UndeclaredParameter => "undeclared_parameter",
+            DiagnosticCode::UnproducedParameter => "unproduced_parameter",
+            DiagnosticCode::MultipleProducers => "multiple_producers",
+            DiagnosticCode::ConflictingAdornments => "conflicting_adornments",
+            DiagnosticCode::CausalCycle => "causal_cycle",
+            DiagnosticCode::UnsatisfiableNilGuard => "unsatisfiable_nil_guard",
+            DiagnosticCode::UnusedParameter => "unused_parameter",
+            DiagnosticCode::DeadEndParameter => "dead_end_parameter",
+            DiagnosticCode::UnreachableInteraction => "unreachable_interaction",
+            DiagnosticCode::DuplicateType => "duplicate_type",
+            DiagnosticCode::DuplicateField => "duplicate_field",
+            DiagnosticCode::KeyField => "key_field",
+            DiagnosticCode::UnknownType => "unknown_type",
+            DiagnosticCode::EmptyEnum => "empty_enum",
+            DiagnosticCode::DuplicateVariant => "duplicate_variant",
+            DiagnosticCode::RecursiveRecord => "recursive_record",
+            DiagnosticCode::UnknownProtocol => "unknown_protocol",
+            DiagnosticCode::SelfEnactment => "self_enactment",
+            DiagnosticCode::UnboundKey => "unbound_key",
+            DiagnosticCode::InvalidBinding => "invalid_binding",
+            DiagnosticCode::CompositionTypeMismatch => "composition_type_mismatch",
+            DiagnosticCode::ImportNotFound => "import_not_found",
+            DiagnosticCode::ImportCycle => "import_cycle",
+            DiagnosticCode::UnknownImport => "unknown_import",
+            DiagnosticCode::DuplicateProtocol => "duplicate_protocol",
+            DiagnosticCode::UnknownLint => "unknown_lint",
+            DiagnosticCode::UnsafeEnactment => "unsafe_enactment",
+            DiagnosticCode::StuckEnactment => "stuck_enactment",
+            DiagnosticCode::NondeterministicEnactment => "nondeterministic_enactment",
+            DiagnosticCode::UninformedSender => "uninformed_sender",
+            DiagnosticCode::RecursiveEnactment => "recursive_enactment",
+            DiagnosticCode::EnactmentTooDeep => "enactment_too_deep",
+            DiagnosticCode::PrivateParameter => "private_parameter",
+            DiagnosticCode::SignatureMismatch => "signature_mismatch",
+            DiagnosticCode::UndeclaredRole => "undeclared_role",
+            DiagnosticCode::DuplicateAction => "duplicate_action",
+        }
+    }
+
+    /// Severity of a diagnostic with this code unless configured otherwise
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            DiagnosticCode::UnusedParameter
+            | DiagnosticCode::DeadEndParameter
+            | DiagnosticCode::UnreachableInteraction
+            | DiagnosticCode::UnknownLint
+            | DiagnosticCode::NondeterministicEnactment => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// Whether the level of the check can be configured. Only checks that warn by default
+    /// are lints; the others reject protocols that cannot be enacted.
+    pub fn is_lint(&self) -> bool {
+        self.default_severity() == Severity::Warning
+    }
+
+    /// Look a code up by its `BMPP0003` form or its lint name
+    pub fn parse(text: &str) -> Option<DiagnosticCode> {
+        Self::ALL
+            .into_iter()
+            .find(|code| code.name() == text || code.code() == text)
+    }
+}
+
+impl Display for DiagnosticCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// Secondary message of a diagnostic, optionally pointing at a related location
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+/// Suggested fix: a message and, when the fix is mechanical, the text to put in place
+/// of a span
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    pub replacement: Option<(Span, String)>,
+}
+
+/// A user-facing error or warning tied to a location in the protocol source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: Option<DiagnosticCode>,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    pub label: Option<String>,
+    pub notes: Vec<Note>,
+    pub suggestion: Option<Suggestion>,
+}
+
+/// Source text a span points into, as returned when rendering against several files
+#[derive(Debug, Clone)]
+pub struct Located<'a> {
+    pub source: &'a str,
+    pub origin: String,
+    /// The span moved to offsets of `source`
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// Diagnostic with a stable code, at the default severity of that code
+    pub fn new(code: DiagnosticCode, message: impl Into<String>) -> Self {
+        Self {
+            code: Some(code),
+            severity: code.default_severity(),
+            ..Self::error(message)
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            code: None,
+            severity: Severity::Error,
+            message: message.into(),
+            span: None,
+            label: None,
+            notes: Vec::new(),
+            suggestion: None,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Attach the source span to underline and a short label printed next to it
+    pub fn with_label(mut self, span: Span, label: impl Into<String>) -> Self {
+        self.span = Some(span);
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Add a note, e.g. explaining why the check applies
+    pub fn with_note(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            message: message.into(),
+            span: None,
+        });
+        self
+    }
+
+    /// Add a note pointing at a related location, e.g. an earlier declaration
+    pub fn with_note_at(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            message: message.into(),
+            span: Some(span),
+        });
+        self
+    }
+
+    /// Suggest a fix described in words
+    pub fn with_help(mut self, message: impl Into<String>) -> Self {
+        self.suggestion = Some(Suggestion {
+            message: message.into(),
+            replacement: None,
+        });
+        self
+    }
+
+    /// Suggest replacing `span` with `text`
+    pub fn with_replacement(
+        mut self,
+        message: impl Into<String>,
+        span: Span,
+        text: impl Into<String>,
+    ) -> Self {
+        self.suggestion = Some(Suggestion {
+            message: message.into(),
+            replacement: Some((span, text.into())),
+        });
+        self
+    }
+
+    /// Move every span of the diagnostic by `offset` bytes
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.span = self.span.map(|span| span.offset(offset));
+        for note in &mut self.notes {
+            note.span = note.span.map(|span| span.offset(offset));
+        }
+        if let Some((span, _)) = self
+            .suggestion
+            .as_mut()
+            .and_then(|suggestion| suggestion.replacement.as_mut())
+        {
+            *span = span.offset(offset);
+        }
+        self
+    }
+
+    /// Render the diagnostic rustc-style, with a snippet of `source` underlining the span
+    pub fn render(&self, source: &str, origin: &str) -> String {
+        self.render_with(|span| {
+            (span.end <= source.len()).then(|| Located {
+                source,
+                origin: origin.to_string(),
+                span,
+            })
+        })
+    }
+
+    /// Render the diagnostic, finding the source of each span with `locate`. Spans it
+    /// cannot place are rendered without a snippet.
+    pub fn render_with<'a>(&self, locate: impl Fn(Span) -> Option<Located<'a>>) -> String {
+        let mut out = match self.code {
+            Some(code) => format!("{}[{}]: {}\n", self.severity, code, self.message),
+            None => format!("{}: {}\n", self.severity, self.message),
+        };
+
+        let located = self.span.and_then(&locate);
+        let gutter = match &located {
+            Some(located) => {
+                let gutter = snippet(&mut out, located, self.label.as_deref());
+                out.push_str(&format!("{} |\n", gutter));
+                gutter
+            }
+            None => {
+                // Without a span, name the file everything else is rendered against
+                let origin = locate(Span::new(0, 0)).map(|located| located.origin);
+                out.push_str(&format!("  --> {}\n", origin.unwrap_or_default()));
+                " ".to_string()
+            }
+        };
+
+        let mut related = String::new();
+        for note in &self.notes {
+            match note.span.and_then(&locate) {
+                Some(located) => {
+                    related.push_str(&format!("note: {}\n", note.message));
+                    snippet(&mut related, &located, None);
+                }
+                None => out.push_str(&format!("{} = note: {}\n", gutter, note.message)),
+            }
+        }
+        if let Some(suggestion) = &self.suggestion {
+            out.push_str(&format!("{} = help: {}\n", gutter, suggestion.message));
+        }
+        if located.is_some() && out.ends_with(&format!("{} |\n", gutter)) {
+            out.truncate(out.len() - gutter.len() - 3);
+        }
+        out.push_str(&related);
+        out
+    }
+}
+
+/// Write the location line and the underlined source line of `located`, returning the
+/// gutter used for the line number column
+fn snippet(out: &mut String, located: &Located, label: Option<&str>) -> String {
+    let Located { source, span, .. } = *located;
+    let index = LineIndex::new(source);
+    let (line, column) = index.line_col(span.start);
+    let line_text = index.line_text(line);
+    let gutter = " ".repeat(line.to_string().len());
+
+    // Underline up to the end of the first line for spans crossing lines
+    let (end_line, end_column) = index.line_col(span.end);
+    let last_column = if end_line == line {
+        end_column
+    } else {
+        line_text.chars().count() + 1
+    };
+    let width = last_column.saturating_sub(column).max(1);
+
+    out.push_str(&format!(
+        "{}--> {}:{}:{}\n",
+        gutter, located.origin, line, column
+    ));
+    out.push_str(&format!("{} |\n", gutter));
+    out.push_str(&format!("{} | {}\n", line, line_text));
+    out.push_str(&format!(
+        "{} | {}{}",
+        gutter,
+        " ".repeat(column - 1),
+        "^".repeat(width)
+    ));
+    if let Some(label) = label {
+        out.push_str(&format!(" {}", label));
+    }
+    out.push('\n');
+    gutter
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Render any error produced by the parser or validators, using a source snippet when
+/// the error carries a span
+pub fn render_error(error: &anyhow::Error, source: &str, origin: &str) -> String {
+    match error.downcast_ref::<Diagnostic>() {
+        Some(diagnostic) => diagnostic.render(source, origin),
+        None => format!("error: {}\n", error),
+    }
+}
+
+/// The name among `candidates` closest to a misspelled `name`, if any is close enough to
+/// be what was meant
+pub fn similar_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let threshold = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two names, ignoring ASCII case
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().map(|c| c.to_ascii_lowercase()).enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_underlines_span() {
+        let source = "first line\n    A -> B: act[in missing]\n";
+        let start = source.find("missing").unwrap();
+        let diagnostic = Diagnostic::error("Parameter 'missing' is not declared")
+            .with_label(Span::new(start, start + "missing".len()), "not declared");
+
+        let rendered = diagnostic.render(source, "test.bmpp");
+
+        assert!(rendered.starts_with("error: Parameter 'missing' is not declared\n"));
+        assert!(rendered.contains(" --> test.bmpp:2:20\n"));
+        assert!(rendered.contains("2 |     A -> B: act[in missing]\n"));
+        assert!(rendered.contains(&format!("  | {}^^^^^^^ not declared\n", " ".repeat(19))));
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let rendered = Diagnostic::error("something failed").render("", "test.bmpp");
+        assert_eq!(rendered, "error: something failed\n  --> test.bmpp\n");
+    }
+
+    #[test]
+    fn test_render_code_notes_and_help() {
+        let source = "parameters\n    ID <String> key (\"id\"),\n    tag <Strng>(\"tag\")\n";
+        let start = source.find("Strng").unwrap();
+        let declared = source.find("ID").unwrap();
+        let diagnostic = Diagnostic::new(DiagnosticCode::UnknownType, "Unknown type 'Strng'")
+            .with_label(Span::new(start, start + 5), "unknown type")
+            .with_note("types are declared in the types section")
+            .with_note_at(Span::new(declared, declared + 2), "first parameter")
+            .with_replacement(
+                "did you mean `String`?",
+                Span::new(start, start + 5),
+                "String",
+            );
+
+        let rendered = diagnostic.render(source, "test.bmpp");
+        let expected = [
+            "error[BMPP0014]: Unknown type 'Strng'",
+            " --> test.bmpp:3:10",
+            "  |",
+            "3 |     tag <Strng>(\"tag\")",
+            "  |          ^^^^^ unknown type",
+            "  |",
+            "  = note: types are declared in the types section",
+            "  = help: did you mean `String`?",
+            "note: first parameter",
+            " --> test.bmpp:2:5",
+            "  |",
+            "2 |     ID <String> key (\"id\"),",
+            "  |     ^^",
+            "",
+        ];
+        assert_eq!(rendered, expected.join("\n"));
+    }
+
+    #[test]
+    fn test_warning_severity_and_offset() {
+        let diagnostic = Diagnostic::new(DiagnosticCode::DeadEndParameter, "never consumed")
+            .with_label(Span::new(1, 2), "here")
+            .with_note_at(Span::new(3, 4), "related")
+            .offset(10);
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert!(!diagnostic.is_error());
+        assert_eq!(diagnostic.span, Some(Span::new(11, 12)));
+        assert_eq!(diagnostic.notes[0].span, Some(Span::new(13, 14)));
+        assert!(
+            Diagnostic::warning("w")
+                .render("", "a.bmpp")
+                .starts_with("warning: w\n")
+        );
+    }
+
+    #[test]
+    fn test_codes_are_stable() {
+        assert_eq!(DiagnosticCode::SyntaxError.code(), "BMPP0001");
+        assert_eq!(DiagnosticCode::UnproducedParameter.code(), "BMPP0003");
+        assert_eq!(DiagnosticCode::DeadEndParameter.code(), "BMPP0009");
+        assert_eq!(
+            DiagnosticCode::parse("dead_end_parameter"),
+            Some(DiagnosticCode::DeadEndParameter)
+        );
+        assert_eq!(
+            DiagnosticCode::parse("BMPP0009"),
+            Some(DiagnosticCode::DeadEndParameter)
+        );
+        assert_eq!(DiagnosticCode::parse("no_such_lint"), None);
+    }
+
+    #[test]
+    fn test_similar_name() {
+        assert_eq!(similar_name("Strng", ["String", "Int"]), Some("String"));
+        assert_eq!(
+            similar_name("ordr_id", ["order_id", "item"]),
+            Some("order_id")
+        );
+        assert_eq!(similar_name("zzz", ["String", "Int"]), None);
+    }
+}
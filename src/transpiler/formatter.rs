@@ -0,0 +1,538 @@
+//! Canonical pretty-printer for BMPP source, used by `bmpp format`.
+//!
+//! The layout is fixed: four spaces per level, one role, parameter, field and interaction
+//! per line, commas at the end of the line, the names and annotations of roles, parameters
+//! and record fields aligned in columns, and a blank line between sections and protocols.
+//!
+//! Comments are read from the trivia of the parsed tree and put back next to the printed
+//! element they were written against: a comment on its own line stays above the element
+//! that follows it, and a comment after code stays at the end of that element's line.
+//! Blank lines around own-line comments are kept as well.
+
+use crate::protocol::ast::AstNode;
+use crate::protocol::model::{
+    Argument, Enactment, Flow, Import, InteractionItem, Parameter, Program, Protocol,
+    PublicParameter, Role, Spanned, TypeDecl,
+};
+use crate::protocol::span::Span;
+use crate::protocol::trivia::Trivia;
+use crate::transpiler::parser::parse_source;
+use anyhow::Result;
+
+const INDENT: &str = "    ";
+
+/// Format BMPP source in the canonical layout, keeping its comments
+pub fn format_source(source: &str) -> Result<String> {
+    let ast = parse_source(source)?;
+    let program = Program::from_ast(&ast)?;
+    let mut printer = Printer::default();
+    printer.program(&program);
+    Ok(printer.finish(collect_comments(&ast)))
+}
+
+/// Format a program built in code, e.g. a flattened protocol, in the canonical layout
+pub fn format_program(program: &Program) -> String {
+    let mut printer = Printer::default();
+    printer.program(program);
+    printer.finish(Vec::new())
+}
+
+/// `//` comment of the parsed source
+#[derive(Debug)]
+struct Comment {
+    span: Span,
+    text: String,
+    /// Written after code on the same line
+    trailing: bool,
+    blank_before: bool,
+    blank_after: bool,
+}
+
+/// Comments of the parsed tree in order, with how they sat among code and blank lines
+fn collect_comments(ast: &AstNode) -> Vec<Comment> {
+    let trivia = ast.all_trivia();
+    trivia
+        .iter()
+        .enumerate()
+        .filter(|(_, piece)| piece.is_comment())
+        .map(|(index, comment)| {
+            let (before, before_start) = whitespace_around(&trivia, index, Side::Before);
+            let (after, _) = whitespace_around(&trivia, index, Side::After);
+            Comment {
+                span: comment.span,
+                text: comment.text.trim_end().to_string(),
+                trailing: before_start > 0 && !before.contains('\n'),
+                blank_before: before_start > 0 && newlines(&before) > 1,
+                blank_after: newlines(&after) > 1,
+            }
+        })
+        .collect()
+}
+
+enum Side {
+    Before,
+    After,
+}
+
+/// Whitespace touching the trivia piece at `index` on one side, with its start offset
+fn whitespace_around(trivia: &[&Trivia], index: usize, side: Side) -> (String, usize) {
+    let mut text = String::new();
+    let mut start = trivia[index].span.start;
+    let mut end = trivia[index].span.end;
+    match side {
+        Side::Before => {
+            for piece in trivia[..index].iter().rev() {
+                if piece.span.end != start || piece.is_comment() {
+                    break;
+                }
+                text.insert_str(0, &piece.text);
+                start = piece.span.start;
+            }
+        }
+        Side::After => {
+            for piece in &trivia[index + 1..] {
+                if piece.span.start != end || piece.is_comment() {
+                    break;
+                }
+                text.push_str(&piece.text);
+                end = piece.span.end;
+            }
+        }
+    }
+    (text, start)
+}
+
+fn newlines(whitespace: &str) -> usize {
+    whitespace.matches('\n').count()
+}
+
+/// Printed line. `anchor` is the source offset of the element it prints; lines without
+/// one (blank lines) never take comments.
+struct Line {
+    depth: usize,
+    text: String,
+    anchor: Option<usize>,
+    /// Closing brace, whose leading comments belong to the block it closes
+    closes: bool,
+}
+
+#[derive(Default)]
+struct Printer {
+    lines: Vec<Line>,
+}
+
+impl Printer {
+    fn line(&mut self, depth: usize, text: String, anchor: usize) {
+        self.lines.push(Line {
+            depth,
+            text,
+            anchor: Some(anchor),
+            closes: false,
+        });
+    }
+
+    fn closing(&mut self, depth: usize, text: String, span: Span) {
+        self.lines.push(Line {
+            depth,
+            text,
+            anchor: Some(span.end.saturating_sub(1)),
+            closes: true,
+        });
+    }
+
+    fn blank(&mut self) {
+        self.lines.push(Line {
+            depth: 0,
+            text: String::new(),
+            anchor: None,
+            closes: false,
+        });
+    }
+
+    fn program(&mut self, program: &Program) {
+        for import in &program.imports {
+            self.line(0, import_line(import), import.span.start);
+        }
+        if !program.imports.is_empty() {
+            self.blank();
+        }
+        for (index, protocol) in program.protocols.iter().enumerate() {
+            if index > 0 {
+                self.blank();
+            }
+            self.protocol(protocol);
+        }
+    }
+
+    fn protocol(&mut self, protocol: &Protocol) {
+        self.line(
+            0,
+            format!(
+                "{} <Protocol>(\"{}\"){} {{",
+                protocol.name,
+                protocol.description,
+                signature_text(protocol.signature.as_deref())
+            ),
+            protocol.span.start,
+        );
+
+        self.line(1, "roles".to_string(), protocol.roles.span.start);
+        let roles = role_lines(&protocol.roles);
+        self.items(2, roles, protocol.roles.iter().map(|role| role.span));
+        self.blank();
+
+        if let Some(types) = &protocol.types {
+            self.line(1, "types".to_string(), types.span.start);
+            for (index, decl) in types.iter().enumerate() {
+                self.type_decl(decl, index + 1 == types.len());
+            }
+            self.blank();
+        }
+
+        self.line(1, "parameters".to_string(), protocol.parameters.span.start);
+        let parameters = parameter_lines(&protocol.parameters);
+        self.items(
+            2,
+            parameters,
+            protocol.parameters.iter().map(|parameter| parameter.span),
+        );
+        self.blank();
+
+        for item in &protocol.interactions {
+            self.line(1, interaction_line(item), item.span().start);
+        }
+        self.closing(0, "}".to_string(), protocol.span);
+    }
+
+    fn type_decl(&mut self, decl: &TypeDecl, last: bool) {
+        self.line(
+            2,
+            format!("{} <Record>(\"{}\") {{", decl.name, decl.description),
+            decl.span.start,
+        );
+        let fields = parameter_lines(&decl.fields);
+        self.items(3, fields, decl.fields.iter().map(|field| field.span));
+        let close = if last { "}" } else { "}," };
+        self.closing(2, close.to_string(), decl.span);
+    }
+
+    /// Comma separated items, one per line
+    fn items(&mut self, depth: usize, texts: Vec<String>, spans: impl Iterator<Item = Span>) {
+        let count = texts.len();
+        for (index, (text, span)) in texts.into_iter().zip(spans).enumerate() {
+            let comma = if index + 1 < count { "," } else { "" };
+            self.line(depth, format!("{}{}", text, comma), span.start);
+        }
+    }
+
+    /// Render the lines with `comments` put back in place
+    fn finish(self, comments: Vec<Comment>) -> String {
+        let mut out: Vec<(usize, String, Vec<String>)> = Vec::new();
+        let mut last_anchored = None;
+        let mut pending = comments.into_iter().peekable();
+
+        for line in self.lines {
+            if let Some(anchor) = line.anchor {
+                let depth = line.depth + usize::from(line.closes);
+                let mut blank_after = false;
+                while let Some(comment) = pending.next_if(|comment| comment.span.start < anchor) {
+                    blank_after = place(&mut out, last_anchored, depth, comment);
+                }
+                if blank_after && !line.closes {
+                    push_blank(&mut out);
+                }
+                last_anchored = Some(out.len());
+            }
+            out.push((line.depth, line.text, Vec::new()));
+        }
+        for comment in pending {
+            place(&mut out, last_anchored, 0, comment);
+        }
+
+        let mut formatted = String::new();
+        for (depth, text, trailing) in out {
+            let indent = INDENT.repeat(depth);
+            let mut trailing = trailing.into_iter();
+            if text.is_empty() {
+                formatted.push('\n');
+                continue;
+            }
+            formatted.push_str(&indent);
+            formatted.push_str(&text);
+            if let Some(comment) = trailing.next() {
+                formatted.push(' ');
+                formatted.push_str(&comment);
+            }
+            formatted.push('\n');
+            for comment in trailing {
+                formatted.push_str(&format!("{}{}\n", indent, comment));
+            }
+        }
+        formatted
+    }
+}
+
+/// Put `comment` after the line at `last_anchored` when it trailed code, or on its own line
+/// at `depth` otherwise. Returns whether a blank line followed it in the source.
+fn place(
+    out: &mut Vec<(usize, String, Vec<String>)>,
+    last_anchored: Option<usize>,
+    depth: usize,
+    comment: Comment,
+) -> bool {
+    if comment.trailing
+        && let Some(index) = last_anchored
+    {
+        out[index].2.push(comment.text);
+        return false;
+    }
+    if comment.blank_before && !out.is_empty() {
+        push_blank(out);
+    }
+    out.push((depth, comment.text, Vec::new()));
+    comment.blank_after
+}
+
+fn push_blank(out: &mut Vec<(usize, String, Vec<String>)>) {
+    if out.last().is_some_and(|(_, text, _)| !text.is_empty()) {
+        out.push((0, String::new(), Vec::new()));
+    }
+}
+
+fn import_line(import: &Import) -> String {
+    if import.names.is_empty() {
+        format!("import \"{}\"", import.path)
+    } else {
+        let names: Vec<&str> = import.names.iter().map(|name| name.as_str()).collect();
+        format!("use {} from \"{}\"", names.join(", "), import.path)
+    }
+}
+
+/// Role declarations with their names padded to a common width
+fn role_lines(roles: &[Role]) -> Vec<String> {
+    let width = roles.iter().map(|role| role.name.value.len()).max();
+    roles
+        .iter()
+        .map(|role| {
+            format!(
+                "{:<width$} <Agent>(\"{}\")",
+                role.name,
+                role.description,
+                width = width.unwrap_or(0)
+            )
+        })
+        .collect()
+}
+
+/// Parameter or field declarations with their names and annotations aligned in columns
+fn parameter_lines(parameters: &[Parameter]) -> Vec<String> {
+    let width = parameters
+        .iter()
+        .map(|parameter| parameter.name.value.len())
+        .max()
+        .unwrap_or(0);
+    let heads: Vec<String> = parameters
+        .iter()
+        .map(|parameter| {
+            let key = if parameter.key { " key " } else { "" };
+            format!(
+                "{:<width$} <{}>{}",
+                parameter.name,
+                parameter.ty.ty(),
+                key,
+                width = width
+            )
+        })
+        .collect();
+    let column = heads.iter().map(String::len).max().unwrap_or(0);
+    heads
+        .into_iter()
+        .zip(parameters)
+        .map(|(head, parameter)| {
+            format!(
+                "{:<column$}(\"{}\")",
+                head,
+                parameter.description,
+                column = column
+            )
+        })
+        .collect()
+}
+
+fn interaction_line(item: &InteractionItem) -> String {
+    match item {
+        InteractionItem::Interaction(interaction) => {
+            let flows: Vec<String> = interaction.flows.iter().map(flow_text).collect();
+            format!(
+                "{} -> {}: {} <Action>(\"{}\")[{}]",
+                interaction.from,
+                interaction.to,
+                interaction.action,
+                interaction.description,
+                flows.join(", ")
+            )
+        }
+        InteractionItem::Enactment(enactment) => enactment_line(enactment),
+    }
+}
+
+fn enactment_line(enactment: &Enactment) -> String {
+    let arguments: Vec<String> = enactment
+        .arguments
+        .iter()
+        .map(|argument| match argument {
+            Argument::Role(binding) => with_alias(binding.name.to_string(), &binding.alias),
+            Argument::Parameter(flow) => flow_text(flow),
+        })
+        .collect();
+    format!(
+        "{} <Enactment>[{}]",
+        enactment.protocol,
+        arguments.join(", ")
+    )
+}
+
+/// ` [in ID key, out tag]`, or nothing without a signature
+fn signature_text(signature: Option<&[PublicParameter]>) -> String {
+    let Some(signature) = signature else {
+        return String::new();
+    };
+    let parameters: Vec<String> = signature
+        .iter()
+        .map(|param| {
+            let key = if param.key { " key" } else { "" };
+            format!("{} {}{}", param.direction, param.name, key)
+        })
+        .collect();
+    format!(" [{}]", parameters.join(", "))
+}
+
+fn flow_text(flow: &Flow) -> String {
+    with_alias(
+        format!("{} {}", flow.direction, flow.parameter),
+        &flow.alias,
+    )
+}
+
+fn with_alias(text: String, alias: &Option<Spanned<String>>) -> String {
+    match alias {
+        Some(alias) => format!("{} as {}", text, alias),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpiler::transpile;
+
+    const STARTER: &str = include_str!("../../examples/STARTER-PROTOCOL.bmpp");
+    const COMPOSED: &str = include_str!("../../examples/STARTER-PROTOCOL_COMPOSED.bmpp");
+
+    #[test]
+    fn test_canonical_layout() {
+        let source = r#"use Ship,Track from "shipping.bmpp"
+Order <Protocol>("order goods") { roles M <Agent>("Merchant"), Warehouse <Agent>("Warehouse")
+  types Item <Record>("line item") { sku <String>("stock unit"), quantity <Int>("how many") },
+        Note <Record>("remark") { text <String>("free text") }
+  parameters order_id <String> key ("order identifier"), items <List<Item>>("ordered items"),
+        status <Enum[open,closed]>("order status")
+  M->Warehouse:order<Action>("place order")[out order_id,out items]
+  Warehouse -> M : close <Action>("close order") [ in order_id , out status ]
+  Ship <Enactment>[Warehouse,M as Carrier,in order_id as ID]
+}"#;
+        let expected = r#"use Ship, Track from "shipping.bmpp"
+
+Order <Protocol>("order goods") {
+    roles
+        M         <Agent>("Merchant"),
+        Warehouse <Agent>("Warehouse")
+
+    types
+        Item <Record>("line item") {
+            sku      <String>("stock unit"),
+            quantity <Int>   ("how many")
+        },
+        Note <Record>("remark") {
+            text <String>("free text")
+        }
+
+    parameters
+        order_id <String> key        ("order identifier"),
+        items    <List<Item>>        ("ordered items"),
+        status   <Enum[open, closed]>("order status")
+
+    M -> Warehouse: order <Action>("place order")[out order_id, out items]
+    Warehouse -> M: close <Action>("close order")[in order_id, out status]
+    Ship <Enactment>[Warehouse, M as Carrier, in order_id as ID]
+}
+"#;
+        assert_eq!(format_source(source).unwrap(), expected);
+        assert_eq!(format_source(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_comments_are_kept() {
+        let source = r#"// Header comment
+
+// about the protocol
+P <Protocol>("p") { // trailing on the header
+    roles
+        // the only role
+        A <Agent>("a"), B <Agent>("b") // after B
+    parameters
+        ID <String> key ("id") // after ID
+
+    // first message
+    A -> B: go <Action>("go")[out ID] // trailing
+    // closing remark
+}
+// end of file
+"#;
+        let expected = r#"// Header comment
+
+// about the protocol
+P <Protocol>("p") { // trailing on the header
+    roles
+        // the only role
+        A <Agent>("a"),
+        B <Agent>("b") // after B
+
+    parameters
+        ID <String> key ("id") // after ID
+
+    // first message
+    A -> B: go <Action>("go")[out ID] // trailing
+    // closing remark
+}
+// end of file
+"#;
+        assert_eq!(format_source(source).unwrap(), expected);
+        assert_eq!(format_source(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_comment_markers_inside_annotations_are_text() {
+        let source = "P <Protocol>(\"see http://example.org\") {\n    roles\n        A <Agent>(\"a\"), B <Agent>(\"b\")\n    parameters\n        ID <String> key (\"id\")\n    A -> B: go <Action>(\"go\")[out ID]\n}\n";
+        let formatted = format_source(source).unwrap();
+        assert!(formatted.starts_with("P <Protocol>(\"see http://example.org\") {\n"));
+        assert_eq!(formatted.matches("//").count(), 1);
+    }
+
+    #[test]
+    fn test_examples_keep_meaning_and_comments() {
+        for source in [STARTER, COMPOSED] {
+            let formatted = format_source(source).unwrap();
+            assert_eq!(format_source(&formatted).unwrap(), formatted);
+            assert_eq!(transpile(&formatted).unwrap(), transpile(source).unwrap());
+
+            let texts = |text: &str| -> Vec<String> {
+                collect_comments(&parse_source(text).unwrap())
+                    .into_iter()
+                    .map(|comment| comment.text)
+                    .collect()
+            };
+            assert_eq!(texts(&formatted), texts(source));
+        }
+    }
+}
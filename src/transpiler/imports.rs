@@ -0,0 +1,541 @@
+//! Resolution of `import "file"` and `use Name from "file"` declarations.
+//!
+//! Imported files are looked up relative to the importing file first and then in every
+//! directory of the `BMPP_PATH` search path. Their protocols are appended to the importing
+//! Program so that validation, composition and codegen see a single tree. Every loaded file
+//! is given its own range of byte offsets, so spans stay unique across files and errors can
+//! be rendered against the right source through the [`SourceMap`] filled while loading.
+
+use crate::protocol::ast::{AstNode, AstNodeType, Span};
+use crate::transpiler::diagnostics::{Diagnostic, DiagnosticCode, Located, render_error};
+use crate::transpiler::parser::parse_source;
+use anyhow::{Result, anyhow};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable listing extra directories to search for imported files
+pub const SEARCH_PATH_VAR: &str = "BMPP_PATH";
+
+/// One loaded file and the offset its spans were moved to
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+    pub offset: usize,
+}
+
+/// All files making up a program, used to render errors against the file they point into
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, returning the offset its spans must be shifted by
+    pub fn add(&mut self, path: PathBuf, source: String) -> usize {
+        // Leave a gap so an end-of-file span never touches the next file
+        let offset = self
+            .files
+            .last()
+            .map(|file| file.offset + file.source.len() + 1)
+            .unwrap_or(0);
+        self.files.push(SourceFile {
+            path,
+            source,
+            offset,
+        });
+        offset
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// The file containing `offset`
+    pub fn file_at(&self, offset: usize) -> Option<&SourceFile> {
+        self.files
+            .iter()
+            .rev()
+            .find(|file| file.offset <= offset && offset <= file.offset + file.source.len())
+    }
+
+    /// Render any error like [`render_error`], picking the file its span points into
+    pub fn render(&self, error: &anyhow::Error) -> String {
+        let Some(main) = self.files.first() else {
+            return format!("error: {}\n", error);
+        };
+        match error.downcast_ref::<Diagnostic>() {
+            Some(diagnostic) => self.render_diagnostic(diagnostic),
+            None => render_error(error, &main.source, &main.path.display().to_string()),
+        }
+    }
+
+    /// Render a diagnostic, showing each of its spans in the file it points into
+    pub fn render_diagnostic(&self, diagnostic: &Diagnostic) -> String {
+        diagnostic.render_with(|span| {
+            let file = self.file_at(span.start)?;
+            (span.end <= file.offset + file.source.len()).then(|| Located {
+                source: &file.source,
+                origin: file.path.display().to_string(),
+                span: Span::new(span.start - file.offset, span.end - file.offset),
+            })
+        })
+    }
+}
+
+/// Loads BMPP files and the files they import
+#[derive(Debug, Clone, Default)]
+pub struct ImportResolver {
+    search_path: Vec<PathBuf>,
+}
+
+/// Protocols of one loaded file and of everything it imports
+#[derive(Debug, Clone)]
+struct LoadedFile {
+    local: Vec<AstNode>,
+    imported: Vec<AstNode>,
+}
+
+impl ImportResolver {
+    pub fn new(search_path: Vec<PathBuf>) -> Self {
+        Self { search_path }
+    }
+
+    /// Resolver searching the directories listed in `BMPP_PATH`
+    pub fn from_env() -> Self {
+        let search_path = std::env::var_os(SEARCH_PATH_VAR)
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        Self::new(search_path)
+    }
+
+    /// Read, parse and resolve the file at `path`, recording every loaded file in `sources`
+    pub fn load(&self, path: &Path, sources: &mut SourceMap) -> Result<AstNode> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read '{}': {}", path.display(), e))?;
+        let offset = sources.add(path.to_path_buf(), source.clone());
+        let mut ast = parse_source(&source).map_err(|e| shift_error(e, offset))?;
+        ast.shift_spans(offset);
+        self.resolve(ast, path, sources)
+    }
+
+    /// Resolve the imports of `ast`, parsed from `path`, appending the imported protocols.
+    ///
+    /// The source of `ast` must already be in `sources`; imported files are added to it so
+    /// that errors pointing into them can be rendered.
+    pub fn resolve(&self, ast: AstNode, path: &Path, sources: &mut SourceMap) -> Result<AstNode> {
+        let mut state = ResolveState {
+            sources,
+            stack: Vec::new(),
+            loaded: HashMap::new(),
+        };
+        if let Ok(canonical) = path.canonicalize() {
+            state.stack.push(canonical);
+        }
+
+        let imported = self.resolve_imports(&ast, path, &mut state)?;
+
+        let mut program = ast;
+        program.children.extend(imported.into_iter().map(Box::new));
+        check_duplicate_protocols(&program)?;
+        Ok(program)
+    }
+
+    /// Protocols brought in by the import declarations of `ast`
+    fn resolve_imports(
+        &self,
+        ast: &AstNode,
+        path: &Path,
+        state: &mut ResolveState<'_>,
+    ) -> Result<Vec<AstNode>> {
+        let mut imported = Vec::new();
+        let mut seen = HashSet::new();
+
+        for import in ast.get_imports() {
+            let Some((import_path, names)) = import.get_import_info() else {
+                continue;
+            };
+            let resolved = self.find_file(&import_path, path).ok_or_else(|| {
+                let base = base_dir(path);
+                let base = if base.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    &base
+                };
+                let mut searched = vec![base.display().to_string()];
+                searched.extend(self.search_path.iter().map(|dir| dir.display().to_string()));
+                error_at(
+                    DiagnosticCode::ImportNotFound,
+                    import.name_span(),
+                    "file not found",
+                    format!(
+                        "Cannot find imported file '{}' (searched {})",
+                        import_path,
+                        searched.join(", ")
+                    ),
+                )
+            })?;
+
+            let loaded = self.load_file(&resolved, import, state)?;
+            let selected = if names.is_empty() {
+                loaded
+                    .local
+                    .iter()
+                    .chain(&loaded.imported)
+                    .cloned()
+                    .collect()
+            } else {
+                select_protocols(&loaded, import, &names, &import_path)?
+            };
+
+            // A file imported along several paths contributes its protocols once
+            for protocol in selected {
+                if seen.insert(protocol.span.start) {
+                    imported.push(protocol);
+                }
+            }
+        }
+
+        Ok(imported)
+    }
+
+    /// Load `path` (once per program), failing on import cycles
+    fn load_file(
+        &self,
+        path: &Path,
+        import: &AstNode,
+        state: &mut ResolveState<'_>,
+    ) -> Result<LoadedFile> {
+        let canonical = path.canonicalize()?;
+        if let Some(position) = state.stack.iter().position(|p| *p == canonical) {
+            let cycle: Vec<String> = state.stack[position..]
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|p| file_name(p))
+                .collect();
+            return Err(error_at(
+                DiagnosticCode::ImportCycle,
+                import.name_span(),
+                "import cycle",
+                format!("Import cycle detected: {}", cycle.join(" -> ")),
+            ));
+        }
+        if let Some(loaded) = state.loaded.get(&canonical) {
+            return Ok(loaded.clone());
+        }
+
+        let source = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read imported file '{}': {}", path.display(), e))?;
+        let offset = state.sources.add(path.to_path_buf(), source.clone());
+        let mut ast = parse_source(&source).map_err(|e| shift_error(e, offset))?;
+        ast.shift_spans(offset);
+
+        state.stack.push(canonical.clone());
+        let imported = self.resolve_imports(&ast, path, state)?;
+        state.stack.pop();
+
+        let loaded = LoadedFile {
+            local: ast
+                .children
+                .into_iter()
+                .filter(|child| child.node_type == AstNodeType::Protocol)
+                .map(|child| *child)
+                .collect(),
+            imported,
+        };
+        state.loaded.insert(canonical, loaded.clone());
+        Ok(loaded)
+    }
+
+    /// Look `import_path` up next to `importer`, then in the search path
+    fn find_file(&self, import_path: &str, importer: &Path) -> Option<PathBuf> {
+        std::iter::once(base_dir(importer))
+            .chain(self.search_path.iter().cloned())
+            .map(|dir| dir.join(import_path))
+            .find(|candidate| candidate.is_file())
+    }
+}
+
+struct ResolveState<'a> {
+    sources: &'a mut SourceMap,
+    /// Files currently being loaded, outermost first
+    stack: Vec<PathBuf>,
+    loaded: HashMap<PathBuf, LoadedFile>,
+}
+
+/// The protocols named by `use ... from`, together with the protocols they enact
+fn select_protocols(
+    loaded: &LoadedFile,
+    import: &AstNode,
+    names: &[String],
+    import_path: &str,
+) -> Result<Vec<AstNode>> {
+    let available: HashMap<String, &AstNode> = loaded
+        .local
+        .iter()
+        .chain(&loaded.imported)
+        .filter_map(|protocol| Some((protocol.get_protocol_name()?, protocol)))
+        .collect();
+
+    let mut selected = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending: Vec<String> = names.to_vec();
+    while let Some(name) = pending.pop() {
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+        let Some(protocol) = available.get(&name) else {
+            let span = import
+                .find_children(AstNodeType::Identifier)
+                .into_iter()
+                .find(|id| id.get_string("name") == Some(&name))
+                .map(|id| id.span)
+                .unwrap_or(import.span);
+            return Err(error_at(
+                DiagnosticCode::UnknownImport,
+                span,
+                "not defined in that file",
+                format!("Protocol '{}' is not defined in '{}'", name, import_path),
+            ));
+        };
+        pending.extend(
+            protocol
+                .find_nodes(AstNodeType::ProtocolReference)
+                .iter()
+                .filter_map(|reference| reference.get_identifier_name()),
+        );
+        selected.push((*protocol).clone());
+    }
+
+    selected.sort_by_key(|protocol| protocol.span.start);
+    Ok(selected)
+}
+
+/// Fails when two different protocols of the resolved program share a name
+fn check_duplicate_protocols(program: &AstNode) -> Result<()> {
+    let mut defined: HashMap<String, &AstNode> = HashMap::new();
+    for protocol in program.find_children(AstNodeType::Protocol) {
+        let Some(name) = protocol.get_protocol_name() else {
+            continue;
+        };
+        if defined.insert(name.clone(), protocol).is_some() {
+            return Err(error_at(
+                DiagnosticCode::DuplicateProtocol,
+                protocol.name_span(),
+                "defined again here",
+                format!("Protocol '{}' is defined more than once", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn base_dir(path: &Path) -> PathBuf {
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Move the span of a parse error of an imported file into program-wide offsets
+fn shift_error(error: anyhow::Error, offset: usize) -> anyhow::Error {
+    match error.downcast::<Diagnostic>() {
+        Ok(diagnostic) => diagnostic.offset(offset).into(),
+        Err(error) => error,
+    }
+}
+
+fn error_at(code: DiagnosticCode, span: Span, label: &str, message: String) -> anyhow::Error {
+    Diagnostic::new(code, message)
+        .with_label(span, label)
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const PACK: &str = r#"Pack <Protocol>("packing") {
+    roles
+        W <Agent>("Warehouse"),
+        P <Agent>("Packer")
+
+    parameters
+        ID <String> key ("order identifier"),
+        tag <String>("package tag")
+
+    W -> P: pack <Action>("pack the order")[in ID, out tag]
+}
+"#;
+
+    fn logistics(import: &str) -> String {
+        format!(
+            r#"{}
+
+Logistics <Protocol>("logistics") {{
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse"),
+        P <Agent>("Packer")
+
+    parameters
+        ID <String> key ("order identifier"),
+        tag <String>("package tag")
+
+    M -> W: notify <Action>("notify order")[in ID]
+    Pack <Enactment>[W, P, in ID, out tag]
+}}
+"#,
+            import
+        )
+    }
+
+    fn protocol_names(ast: &AstNode) -> Vec<String> {
+        ast.find_children(AstNodeType::Protocol)
+            .iter()
+            .filter_map(|p| p.get_protocol_name())
+            .collect()
+    }
+
+    #[test]
+    fn test_import_relative_to_importing_file() -> Result<()> {
+        let dir = tempdir()?;
+        fs::create_dir(dir.path().join("lib"))?;
+        fs::write(dir.path().join("lib/pack.bmpp"), PACK)?;
+        let main = dir.path().join("main.bmpp");
+        fs::write(&main, logistics(r#"import "lib/pack.bmpp""#))?;
+
+        let mut sources = SourceMap::new();
+        let ast = ImportResolver::default().load(&main, &mut sources)?;
+        assert_eq!(protocol_names(&ast), vec!["Logistics", "Pack"]);
+        assert_eq!(sources.files().len(), 2);
+
+        // Spans of imported protocols point into their own file
+        let pack = ast.find_children(AstNodeType::Protocol)[1];
+        let file = sources.file_at(pack.name_span().start).unwrap();
+        assert!(file.path.ends_with("lib/pack.bmpp"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_use_from_search_path() -> Result<()> {
+        let library = tempdir()?;
+        fs::write(library.path().join("pack.bmpp"), PACK)?;
+        let project = tempdir()?;
+        let main = project.path().join("main.bmpp");
+        fs::write(&main, logistics(r#"use Pack from "pack.bmpp""#))?;
+
+        let error = ImportResolver::default()
+            .load(&main, &mut SourceMap::new())
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Cannot find imported file 'pack.bmpp'")
+        );
+
+        let resolver = ImportResolver::new(vec![library.path().to_path_buf()]);
+        let ast = resolver.load(&main, &mut SourceMap::new())?;
+        assert_eq!(protocol_names(&ast), vec!["Logistics", "Pack"]);
+
+        fs::write(&main, logistics(r#"use Packing from "pack.bmpp""#))?;
+        let error = resolver.load(&main, &mut SourceMap::new()).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Protocol 'Packing' is not defined in 'pack.bmpp'")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_import_lists_searched_directories() -> Result<()> {
+        let ast = parse_source(&logistics(r#"import "pack.bmpp""#))?;
+        let library = PathBuf::from("/opt/bmpp");
+        let error = ImportResolver::new(vec![library.clone()])
+            .resolve(ast, Path::new("main.bmpp"), &mut SourceMap::new())
+            .unwrap_err();
+        assert!(error.to_string().contains(&format!(
+            "Cannot find imported file 'pack.bmpp' (searched ., {})",
+            library.display()
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_cycle_is_reported() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(
+            dir.path().join("a.bmpp"),
+            format!("import \"b.bmpp\"\n{}", PACK),
+        )?;
+        fs::write(
+            dir.path().join("b.bmpp"),
+            format!("import \"a.bmpp\"\n{}", PACK.replace("Pack <", "Wrap <")),
+        )?;
+
+        let error = ImportResolver::default()
+            .load(&dir.path().join("a.bmpp"), &mut SourceMap::new())
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Import cycle detected: a.bmpp -> b.bmpp -> a.bmpp"),
+            "{}",
+            error
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_protocol_names_are_reported() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(dir.path().join("pack.bmpp"), PACK)?;
+        let main = dir.path().join("main.bmpp");
+        fs::write(&main, format!("import \"pack.bmpp\"\n\n{}", PACK))?;
+
+        let error = ImportResolver::default()
+            .load(&main, &mut SourceMap::new())
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Protocol 'Pack' is defined more than once")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors_render_against_imported_file() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(
+            dir.path().join("pack.bmpp"),
+            PACK.replace("<String> key", "<String key"),
+        )?;
+        let main = dir.path().join("main.bmpp");
+        fs::write(&main, logistics(r#"import "pack.bmpp""#))?;
+
+        let mut sources = SourceMap::new();
+        let error = ImportResolver::default()
+            .load(&main, &mut sources)
+            .unwrap_err();
+
+        let rendered = sources.render(&error);
+        assert!(rendered.contains("pack.bmpp:7:20"), "{}", rendered);
+        assert!(
+            rendered.contains("7 |         ID <String key"),
+            "{}",
+            rendered
+        );
+        Ok(())
+    }
+}
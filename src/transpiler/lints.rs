@@ -0,0 +1,374 @@
+//! Per-project lint levels and inline suppressions.
+//!
+//! Checks that only warn by default (see [`DiagnosticCode::is_lint`]) can be silenced or
+//! turned into errors by a `bmpp.toml` found next to the protocol files:
+//!
+//! ```toml
+//! [lints]
+//! dead_end_parameter = "allow"
+//! unused_parameter = "deny"
+//! ```
+//!
+//! A single element is exempted with a `// bmpp-allow(dead_end_parameter)` comment, written
+//! on the line above it or at the end of its line. The comment covers the element it is
+//! attached to as trivia, so one above a protocol header covers the whole protocol.
+
+use crate::protocol::ast::{AstNode, Span};
+use crate::transpiler::diagnostics::{Diagnostic, DiagnosticCode, Severity, similar_name};
+use crate::transpiler::validation::ValidationReport;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the project configuration file
+pub const CONFIG_FILE: &str = "bmpp.toml";
+
+/// Marker of an inline suppression comment
+const ALLOW_MARKER: &str = "bmpp-allow(";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Drop the diagnostic
+    Allow,
+    /// Report the diagnostic without failing validation
+    Warn,
+    /// Report the diagnostic as an error
+    Deny,
+}
+
+/// Levels configured for lints; lints left out keep their default level
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintConfig {
+    levels: HashMap<DiagnosticCode, LintLevel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    lints: HashMap<String, LintLevel>,
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the `[lints]` table of a `bmpp.toml` document. Lints are named as in
+    /// diagnostics, e.g. `dead_end_parameter`, or by code, e.g. `BMPP0009`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: ConfigFile = toml::from_str(text)?;
+        let mut config = Self::new();
+        for (name, level) in file.lints {
+            config.set(lint_code(&name)?, level)?;
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// The `bmpp.toml` closest to `input`: in its directory, or else in the nearest parent
+    /// directory that has one
+    pub fn discover(input: &Path) -> Option<PathBuf> {
+        Self::discover_from(&std::env::current_dir().ok()?, input)
+    }
+
+    /// [`LintConfig::discover`] for an `input` relative to `current_dir`, so that the
+    /// parents of the working directory are searched as well
+    fn discover_from(current_dir: &Path, input: &Path) -> Option<PathBuf> {
+        current_dir
+            .join(input)
+            .parent()?
+            .ancestors()
+            .map(|ancestor| ancestor.join(CONFIG_FILE))
+            .find(|candidate| candidate.is_file())
+    }
+
+    /// Configuration of the project `input` belongs to, or the defaults without a `bmpp.toml`
+    pub fn for_input(input: &Path) -> Result<Self> {
+        match Self::discover(input) {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// Set the level of a lint; checks that are errors by default cannot be configured
+    pub fn set(&mut self, code: DiagnosticCode, level: LintLevel) -> Result<()> {
+        if !code.is_lint() {
+            return Err(anyhow!(
+                "'{}' is not a lint: protocols failing this check cannot be enacted, so its level cannot be changed",
+                code.name()
+            ));
+        }
+        self.levels.insert(code, level);
+        Ok(())
+    }
+
+    pub fn level(&self, code: DiagnosticCode) -> LintLevel {
+        match self.levels.get(&code) {
+            Some(level) => *level,
+            None if code.default_severity() == Severity::Error => LintLevel::Deny,
+            None => LintLevel::Warn,
+        }
+    }
+
+    /// Apply the configured levels and the `bmpp-allow` comments of `ast` to `report`
+    pub fn apply(&self, ast: &AstNode, report: ValidationReport) -> ValidationReport {
+        let mut suppressions: Vec<(Span, Vec<DiagnosticCode>)> = Vec::new();
+        let mut unknown = Vec::new();
+        collect_suppressions(ast, &mut suppressions, &mut unknown);
+
+        let mut applied = ValidationReport::new();
+        for mut diagnostic in report.into_diagnostics().into_iter().chain(unknown) {
+            let Some(code) = diagnostic.code.filter(DiagnosticCode::is_lint) else {
+                applied.push(diagnostic);
+                continue;
+            };
+            let suppressed = diagnostic.span.is_some_and(|span| {
+                suppressions.iter().any(|(covered, codes)| {
+                    covered.start <= span.start && span.end <= covered.end && codes.contains(&code)
+                })
+            });
+            if suppressed {
+                continue;
+            }
+            diagnostic.severity = match self.level(code) {
+                LintLevel::Allow => continue,
+                LintLevel::Warn => Severity::Warning,
+                LintLevel::Deny => Severity::Error,
+            };
+            applied.push(diagnostic);
+        }
+        applied
+    }
+}
+
+fn lint_code(name: &str) -> Result<DiagnosticCode> {
+    DiagnosticCode::parse(name).ok_or_else(|| {
+        let names = DiagnosticCode::ALL.iter().map(|code| code.name());
+        match similar_name(name, names) {
+            Some(similar) => anyhow!("Unknown lint '{}' - did you mean '{}'?", name, similar),
+            None => anyhow!("Unknown lint '{}'", name),
+        }
+    })
+}
+
+/// Spans covered by `bmpp-allow` comments with the lints they allow, and a diagnostic for
+/// every name that is not a lint
+fn collect_suppressions(
+    node: &AstNode,
+    suppressions: &mut Vec<(Span, Vec<DiagnosticCode>)>,
+    unknown: &mut Vec<Diagnostic>,
+) {
+    let comments = node
+        .leading_trivia
+        .iter()
+        .chain(&node.trailing_trivia)
+        .filter(|piece| piece.is_comment());
+    for comment in comments {
+        let Some(start) = comment.text.find(ALLOW_MARKER) else {
+            continue;
+        };
+        let names_start = start + ALLOW_MARKER.len();
+        let Some(length) = comment.text[names_start..].find(')') else {
+            continue;
+        };
+
+        let mut codes = Vec::new();
+        let mut offset = names_start;
+        for name in comment.text[names_start..names_start + length].split(',') {
+            let trimmed = name.trim();
+            let name_start = comment.span.start + offset + (name.len() - name.trim_start().len());
+            offset += name.len() + 1;
+            match lint_code(trimmed) {
+                Ok(code) if code.is_lint() => codes.push(code),
+                _ => unknown.push(unknown_lint(
+                    trimmed,
+                    Span::new(name_start, name_start + trimmed.len()),
+                )),
+            }
+        }
+        suppressions.push((node.span, codes));
+    }
+
+    for child in &node.children {
+        collect_suppressions(child, suppressions, unknown);
+    }
+}
+
+fn unknown_lint(name: &str, span: Span) -> Diagnostic {
+    let diagnostic = Diagnostic::new(
+        DiagnosticCode::UnknownLint,
+        format!("Unknown lint '{}' in bmpp-allow comment", name),
+    )
+    .with_label(span, "not a lint");
+    let lints = DiagnosticCode::ALL
+        .iter()
+        .filter(|code| code.is_lint())
+        .map(|code| code.name());
+    match similar_name(name, lints) {
+        Some(similar) => {
+            diagnostic.with_replacement(format!("did you mean `{}`?", similar), span, similar)
+        }
+        None => diagnostic.with_note("only checks that warn by default can be allowed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpiler::parse_source;
+    use crate::transpiler::validation::validate_with;
+    use tempfile::tempdir;
+
+    const SOURCE: &str = r#"
+Quote <Protocol>("outcomes are never consumed") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+
+    parameters
+        ID <String> key ("request"),
+        price <Float>("quoted price"), // bmpp-allow(dead_end_parameter)
+        // bmpp-allow(dead_end_parameter, unused_parameter)
+        note <String>("remark"),
+        spare <String>("never used")
+
+    B -> S: ask <Action>("ask")[in ID]
+    S -> B: quote <Action>("quote")[in ID, out price, out note]
+}
+"#;
+
+    fn codes(report: &ValidationReport) -> Vec<(DiagnosticCode, Severity)> {
+        report
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| (diagnostic.code.unwrap(), diagnostic.severity))
+            .collect()
+    }
+
+    #[test]
+    fn test_inline_allow_covers_the_commented_element() {
+        let ast = parse_source(SOURCE).unwrap();
+        let report = validate_with(&ast, &LintConfig::new()).unwrap();
+        assert_eq!(
+            codes(&report),
+            vec![(DiagnosticCode::UnusedParameter, Severity::Warning)]
+        );
+        let span = report.diagnostics()[0].span.unwrap();
+        assert_eq!(&SOURCE[span.start..span.end], "spare");
+    }
+
+    #[test]
+    fn test_levels_from_config() {
+        let config =
+            LintConfig::parse("[lints]\nunused_parameter = \"deny\"\nBMPP0009 = \"warn\"\n")
+                .unwrap();
+        assert_eq!(
+            config.level(DiagnosticCode::DeadEndParameter),
+            LintLevel::Warn
+        );
+
+        let ast = parse_source(&SOURCE.replace("// bmpp-allow", "// see")).unwrap();
+        let report = validate_with(&ast, &config).unwrap();
+        assert_eq!(
+            codes(&report),
+            vec![
+                (DiagnosticCode::DeadEndParameter, Severity::Warning),
+                (DiagnosticCode::DeadEndParameter, Severity::Warning),
+                (DiagnosticCode::UnusedParameter, Severity::Error),
+            ]
+        );
+        assert!(report.has_errors());
+
+        let allow_all = LintConfig::parse(
+            "[lints]\nunused_parameter = \"allow\"\ndead_end_parameter = \"allow\"\n",
+        )
+        .unwrap();
+        assert!(
+            validate_with(&ast, &allow_all)
+                .unwrap()
+                .diagnostics()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_invalid_configurations() {
+        let error = LintConfig::parse("[lints]\ndead_end_paramter = \"allow\"\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown lint 'dead_end_paramter' - did you mean 'dead_end_parameter'?"
+        );
+
+        let error = LintConfig::parse("[lints]\ncausal_cycle = \"allow\"\n").unwrap_err();
+        assert!(
+            error.to_string().contains("'causal_cycle' is not a lint"),
+            "{}",
+            error
+        );
+
+        assert!(LintConfig::parse("[lints]\nunused_parameter = \"off\"\n").is_err());
+        assert_eq!(LintConfig::parse("").unwrap(), LintConfig::new());
+    }
+
+    #[test]
+    fn test_unknown_names_in_comments_are_reported() {
+        let source = SOURCE.replace("allow(dead_end_parameter)", "allow(dead_end_paramter)");
+        let ast = parse_source(&source).unwrap();
+        let report = validate_with(&ast, &LintConfig::new()).unwrap();
+
+        let unknown = report
+            .diagnostics()
+            .iter()
+            .find(|diagnostic| diagnostic.code == Some(DiagnosticCode::UnknownLint))
+            .unwrap();
+        let span = unknown.span.unwrap();
+        assert_eq!(&source[span.start..span.end], "dead_end_paramter");
+        assert_eq!(
+            unknown.suggestion.as_ref().unwrap().message,
+            "did you mean `dead_end_parameter`?"
+        );
+    }
+
+    #[test]
+    fn test_discover_config_in_parent_directory() {
+        let root = tempdir().unwrap();
+        let nested = root.path().join("protocols").join("shipping");
+        fs::create_dir_all(&nested).unwrap();
+        let input = nested.join("ship.bmpp");
+        assert_eq!(LintConfig::discover(&input), None);
+        assert_eq!(LintConfig::for_input(&input).unwrap(), LintConfig::new());
+
+        let config = root.path().join(CONFIG_FILE);
+        fs::write(&config, "[lints]\ndead_end_parameter = \"allow\"\n").unwrap();
+        assert_eq!(LintConfig::discover(&input), Some(config));
+        assert_eq!(
+            LintConfig::for_input(&input)
+                .unwrap()
+                .level(DiagnosticCode::DeadEndParameter),
+            LintLevel::Allow
+        );
+    }
+
+    #[test]
+    fn test_discover_config_above_the_directory_of_a_bare_file_name() {
+        let root = tempdir().unwrap();
+        let nested = root.path().join("sub");
+        fs::create_dir_all(&nested).unwrap();
+        let config = root.path().join(CONFIG_FILE);
+        fs::write(&config, "[lints]\ndead_end_parameter = \"deny\"\n").unwrap();
+
+        // As for `cd sub && bmpp validate x.bmpp`
+        assert_eq!(
+            LintConfig::discover_from(&nested, Path::new("x.bmpp")),
+            Some(config)
+        );
+    }
+}
@@ -1,10 +1,17 @@
 pub mod codegen;
 pub mod composition;
+pub mod diagnostics;
+pub mod formatter;
+pub mod imports;
+pub mod lints;
 pub mod parser;
 pub mod project_builder;
+pub mod projection;
 pub mod validation;
+pub mod verification;
 
 pub use codegen::BmppCodeGenerator;
+pub use formatter::{format_program, format_source};
 pub use parser::parse_source;
 
 use anyhow::Result;
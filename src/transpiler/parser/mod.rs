@@ -1,8 +1,14 @@
+mod recovery;
+#[allow(clippy::module_inception)]
 pub mod tests;
 
-use crate::protocol::ast::{AstNode, AstNodeType};
-use anyhow::{anyhow, Result};
-use peg::parser;
+pub use recovery::{ParseOutcome, parse_with_recovery};
+
+use crate::protocol::ast::{AstNode, AstNodeType, LineIndex, Span};
+use crate::protocol::trivia::attach_trivia;
+use crate::protocol::types::BmppType;
+use crate::transpiler::diagnostics::{Diagnostic, DiagnosticCode};
+use anyhow::Result;
 
 // PEG grammar embedded in Rust
 peg::parser! {
@@ -15,11 +21,14 @@ peg::parser! {
         rule comment() = "//" [^'\n' | '\r']* ['\n' | '\r']?
 
         // Skip whitespace and comments
-        rule ws() = (comment() / [' ' | '\t' | '\n' | '\r'])*
+        rule ws() = quiet!{(comment() / [' ' | '\t' | '\n' | '\r'])*}
 
         // Basic tokens
+        rule ident_char() = ['a'..='z' | 'A'..='Z' | '0'..='9' | '_']
+
         rule identifier() -> String
-            = n:$(['a'..='z' | 'A'..='Z'] ['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*) { n.to_string() }
+            = quiet!{n:$(['a'..='z' | 'A'..='Z'] ['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*) { n.to_string() }}
+            / expected!("identifier")
 
         rule string_literal() -> String
             = "\"" s:$([^'"']*) "\"" { s.to_string() }
@@ -29,107 +38,177 @@ peg::parser! {
         rule agent_tag() = "<Agent>"
         rule action_tag() = "<Action>"
         rule enactment_tag() = "<Enactment>"
+        rule record_tag() = "<Record>"
 
         // Basic types
         rule basic_type() -> String
             = t:$("String" / "Int" / "Float" / "Bool") { t.to_string() }
 
-        // Direction keywords
+        // Direction keywords (BSPL adornments), which must not run into an identifier
         rule direction() -> String
-            = d:$("in" / "out") { d.to_string() }
+            = d:$("in" / "out" / "nil" / "opt") !ident_char() { d.to_string() }
+
+        // Identifier together with its source span
+        rule spanned_identifier() -> (String, Span)
+            = s:position!() n:identifier() e:position!() { (n, Span::new(s, e)) }
 
         // Annotation: ("text with, commas and; semicolons")
         rule annotation() -> AstNode
-            = "(" ws() s:string_literal() ws() ")" {
-                let mut node = AstNode::new(AstNodeType::Annotation);
-                node.set_string("description", &s);
+            = s:position!() "(" ws() d:string_literal() ws() ")" e:position!() {
+                let mut node = AstNode::with_span(AstNodeType::Annotation, Span::new(s, e));
+                node.set_string("description", &d);
                 node
             }
 
+        // Public parameter of a protocol signature: in ID key, out tag
+        rule public_parameter() -> AstNode
+            = s:position!() dir:$("in" / "out") !ident_char() ws() name:spanned_identifier()
+              key:(ws() key_adornment())? e:position!() {
+                let mut flow_node = AstNode::with_span(AstNodeType::ParameterFlow, Span::new(s, e));
+                flow_node.set_string("direction", dir);
+                if key.is_some() {
+                    flow_node.set_string("key", "true");
+                }
+                flow_node.children.push(Box::new(named_node(AstNodeType::Identifier, name)));
+                flow_node
+            }
+
+        // Protocol signature: [in ID key, in order, out package]
+        rule protocol_signature() -> AstNode
+            = s:position!() "[" ws() params:(public_parameter() ** (ws() "," ws())) ws() "]" e:position!() {
+                let mut signature_node = AstNode::with_span(AstNodeType::ProtocolSignature, Span::new(s, e));
+                for param in params {
+                    signature_node.children.push(Box::new(param));
+                }
+                signature_node
+            }
+
         // Protocol name
         rule protocol_name() -> AstNode
-            = name:identifier() {
-                let mut node = AstNode::new(AstNodeType::ProtocolName);
-                node.set_string("name", &name);
-                node
-            }
+            = name:spanned_identifier() { named_node(AstNodeType::ProtocolName, name) }
 
         // Role reference
         rule role_ref() -> AstNode
-            = name:identifier() {
-                let mut node = AstNode::new(AstNodeType::RoleRef);
-                node.set_string("name", &name);
-                node
-            }
+            = name:spanned_identifier() { named_node(AstNodeType::RoleRef, name) }
 
         // Action name
         rule action_name() -> AstNode
-            = name:identifier() {
-                let mut node = AstNode::new(AstNodeType::ActionName);
-                node.set_string("name", &name);
-                node
-            }
+            = name:spanned_identifier() { named_node(AstNodeType::ActionName, name) }
 
         // Role declaration: RoleName <Agent>("description")
         rule role_decl() -> AstNode
-            = name:identifier() ws() agent_tag() ws() ann:annotation() {
-                let mut role_node = AstNode::new(AstNodeType::RoleDecl);
-
-                let mut identifier_node = AstNode::new(AstNodeType::Identifier);
-                identifier_node.set_string("name", &name);
-                role_node.children.push(Box::new(identifier_node));
-
+            = s:position!() name:spanned_identifier() ws() agent_tag() ws() ann:annotation() e:position!() {
+                let mut role_node = AstNode::with_span(AstNodeType::RoleDecl, Span::new(s, e));
+                role_node.children.push(Box::new(named_node(AstNodeType::Identifier, name)));
                 role_node.children.push(Box::new(ann));
                 role_node
             }
 
         // Roles section: roles Role1 <Agent>("desc"), Role2 <Agent>("desc")
         rule roles_section() -> AstNode
-            = "roles" ws() roles:(role_decl() ** (ws() "," ws())) {
-                let mut roles_node = AstNode::new(AstNodeType::RolesSection);
+            = s:position!() "roles" ws() roles:(role_decl() ** (ws() "," ws())) e:position!() {
+                let mut roles_node = AstNode::with_span(AstNodeType::RolesSection, Span::new(s, e));
                 for role in roles {
                     roles_node.children.push(Box::new(role));
                 }
                 roles_node
             }
 
-        // Parameter declaration: paramName <Type>("description")
-        rule parameter_decl() -> AstNode
-            = name:identifier() ws() "<" ws() typ:basic_type() ws() ">" ws() ann:annotation() {
-                let mut param_node = AstNode::new(AstNodeType::ParameterDecl);
+        // Parameter type: paramName <Type>
+        rule parameter_type() -> AstNode
+            = s:position!() typ:basic_type() !ident_char() e:position!() {
+                let mut type_node = AstNode::with_span(AstNodeType::BasicType, Span::new(s, e));
+                type_node.set_string("type", &typ);
+                type_node
+            }
 
-                let mut identifier_node = AstNode::new(AstNodeType::Identifier);
-                identifier_node.set_string("name", &name);
-                param_node.children.push(Box::new(identifier_node));
+        // Enumerated type: Enum[pending, shipped, lost]
+        rule enum_type() -> AstNode
+            = s:position!() "Enum" ws() "[" ws() variants:(spanned_identifier() ** (ws() "," ws())) ws() "]" e:position!() {
+                let mut type_node = AstNode::with_span(AstNodeType::EnumType, Span::new(s, e));
+                for variant in variants {
+                    type_node.children.push(Box::new(named_node(AstNodeType::Identifier, variant)));
+                }
+                set_canonical_type(&mut type_node);
+                type_node
+            }
 
-                let mut type_node = AstNode::new(AstNodeType::BasicType);
-                type_node.set_string("type", &typ);
-                param_node.children.push(Box::new(type_node));
+        // List type: List<Type>
+        rule list_type() -> AstNode
+            = s:position!() "List" ws() "<" ws() element:type_expr() ws() ">" e:position!() {
+                let mut type_node = AstNode::with_span(AstNodeType::ListType, Span::new(s, e));
+                type_node.children.push(Box::new(element));
+                set_canonical_type(&mut type_node);
+                type_node
+            }
+
+        // Reference to a record type declared in the types section
+        rule type_ref() -> AstNode
+            = name:spanned_identifier() {
+                let mut type_node = named_node(AstNodeType::TypeRef, name);
+                set_canonical_type(&mut type_node);
+                type_node
+            }
 
+        rule type_expr() -> AstNode
+            = enum_type() / list_type() / parameter_type() / type_ref()
+
+        // Key adornment: marks a parameter as (part of) the protocol key
+        rule key_adornment() = "key" !ident_char()
+
+        // Parameter declaration: paramName <Type> [key] ("description")
+        rule parameter_decl() -> AstNode
+            = s:position!() name:spanned_identifier() ws() "<" ws() typ:type_expr() ws() ">" ws()
+              key:(key_adornment() ws())? ann:annotation() e:position!() {
+                let mut param_node = AstNode::with_span(AstNodeType::ParameterDecl, Span::new(s, e));
+                if key.is_some() {
+                    param_node.set_string("key", "true");
+                }
+                param_node.children.push(Box::new(named_node(AstNodeType::Identifier, name)));
+                param_node.children.push(Box::new(typ));
                 param_node.children.push(Box::new(ann));
                 param_node
             }
 
+        // Record type declaration: Name <Record>("description") { field <Type>("description"), ... }
+        rule type_decl() -> AstNode
+            = s:position!() name:spanned_identifier() ws() record_tag() ws() ann:annotation() ws()
+              "{" ws() fields:(parameter_decl() ** (ws() "," ws())) ws() "}" e:position!() {
+                let mut type_node = AstNode::with_span(AstNodeType::TypeDecl, Span::new(s, e));
+                type_node.children.push(Box::new(named_node(AstNodeType::Identifier, name)));
+                type_node.children.push(Box::new(ann));
+                for field in fields {
+                    type_node.children.push(Box::new(field));
+                }
+                type_node
+            }
+
+        // Types section: types Name <Record>("desc") { ... }, ...
+        rule types_section() -> AstNode
+            = s:position!() "types" !ident_char() ws() decls:(type_decl() ** (ws() "," ws())) e:position!() {
+                let mut types_node = AstNode::with_span(AstNodeType::TypesSection, Span::new(s, e));
+                for decl in decls {
+                    types_node.children.push(Box::new(decl));
+                }
+                types_node
+            }
+
         // Parameters section
         rule parameters_section() -> AstNode
-            = "parameters" ws() params:(parameter_decl() ** (ws() "," ws())) {
-                let mut params_node = AstNode::new(AstNodeType::ParametersSection);
+            = s:position!() "parameters" ws() params:(parameter_decl() ** (ws() "," ws())) e:position!() {
+                let mut params_node = AstNode::with_span(AstNodeType::ParametersSection, Span::new(s, e));
                 for param in params {
                     params_node.children.push(Box::new(param));
                 }
                 params_node
             }
 
-        // Parameter flow: in paramName or out paramName
+        // Parameter flow: in, out, nil or opt followed by paramName
         rule parameter_flow() -> AstNode
-            = dir:direction() ws() name:identifier() {
-                let mut flow_node = AstNode::new(AstNodeType::ParameterFlow);
+            = s:position!() dir:direction() ws() name:spanned_identifier() e:position!() {
+                let mut flow_node = AstNode::with_span(AstNodeType::ParameterFlow, Span::new(s, e));
                 flow_node.set_string("direction", &dir);
-
-                let mut identifier_node = AstNode::new(AstNodeType::Identifier);
-                identifier_node.set_string("name", &name);
-                flow_node.children.push(Box::new(identifier_node));
-
+                flow_node.children.push(Box::new(named_node(AstNodeType::Identifier, name)));
                 flow_node
             }
 
@@ -141,9 +220,9 @@ peg::parser! {
 
         // Standard interaction: RoleRef -> RoleRef : ActionName <Action> Annotation [ParameterFlowList]
         rule standard_interaction() -> AstNode
-            = from:role_ref() ws() "->" ws() to:role_ref() ws() ":" ws() action:action_name() ws()
-              action_tag() ws() ann:annotation() ws() flows:parameter_flow_list() {
-                let mut interaction_node = AstNode::new(AstNodeType::StandardInteraction);
+            = s:position!() from:role_ref() ws() "->" ws() to:role_ref() ws() ":" ws() action:action_name() ws()
+              action_tag() ws() ann:annotation() ws() flows:parameter_flow_list() e:position!() {
+                let mut interaction_node = AstNode::with_span(AstNodeType::StandardInteraction, Span::new(s, e));
 
                 // Add role references as children
                 interaction_node.children.push(Box::new(from));
@@ -165,21 +244,23 @@ peg::parser! {
 
         // Protocol reference for composition
         rule protocol_reference() -> AstNode
-            = name:identifier() ws() enactment_tag() {
-                let mut protocol_ref_node = AstNode::new(AstNodeType::ProtocolReference);
-                let mut identifier_node = AstNode::new(AstNodeType::Identifier);
-                identifier_node.set_string("name", &name);
-                protocol_ref_node.children.push(Box::new(identifier_node));
+            = s:position!() name:spanned_identifier() ws() enactment_tag() e:position!() {
+                let mut protocol_ref_node = AstNode::with_span(AstNodeType::ProtocolReference, Span::new(s, e));
+                protocol_ref_node.children.push(Box::new(named_node(AstNodeType::Identifier, name)));
                 protocol_ref_node
             }
 
         // Composition parameter: either just identifier or direction + identifier
+        // Binding of an enactment argument to a callee role or parameter: as CalleeName
+        rule binding_alias() -> AstNode
+            = ws() "as" !ident_char() ws() name:spanned_identifier() {
+                named_node(AstNodeType::BindingAlias, name)
+            }
+
         rule composition_parameter() -> AstNode
-            = flow:parameter_flow() { flow }
-            / name:identifier() {
-                let mut identifier_node = AstNode::new(AstNodeType::Identifier);
-                identifier_node.set_string("name", &name);
-                identifier_node
+            = flow:parameter_flow() alias:binding_alias()? { with_alias(flow, alias) }
+            / name:spanned_identifier() alias:binding_alias()? {
+                with_alias(named_node(AstNodeType::Identifier, name), alias)
             }
 
         // Composition parameter list
@@ -188,9 +269,9 @@ peg::parser! {
 
         // Protocol composition: ProtocolReference [CompositionParameterList]
         rule protocol_composition() -> AstNode
-            = protocol_ref:protocol_reference() ws()
-              "[" ws() params:composition_parameter_list()? ws() "]" {
-                let mut composition_node = AstNode::new(AstNodeType::ProtocolComposition);
+            = s:position!() protocol_ref:protocol_reference() ws()
+              "[" ws() params:composition_parameter_list()? ws() "]" e:position!() {
+                let mut composition_node = AstNode::with_span(AstNodeType::ProtocolComposition, Span::new(s, e));
 
                 // Add protocol reference
                 composition_node.children.push(Box::new(protocol_ref));
@@ -208,56 +289,168 @@ peg::parser! {
         // Interaction item: either standard interaction or protocol composition
         rule interaction_item() -> AstNode
             = item:(standard_interaction() / protocol_composition()) {
-                let mut item_node = AstNode::new(AstNodeType::InteractionItem);
+                let mut item_node = AstNode::with_span(AstNodeType::InteractionItem, item.span);
                 item_node.children.push(Box::new(item));
                 item_node
             }
 
         // Interactions section - contains multiple interaction items
         rule interactions_section() -> AstNode
-            = items:interaction_item() ++ ws() {
-                let mut interactions_node = AstNode::new(AstNodeType::InteractionSection);
+            = s:position!() items:interaction_item() ++ ws() e:position!() {
+                let mut interactions_node = AstNode::with_span(AstNodeType::InteractionSection, Span::new(s, e));
                 for item in items {
                     interactions_node.children.push(Box::new(item));
                 }
                 interactions_node
             }
 
-        // Complete protocol: ProtocolName <Protocol> Annotation { RolesSection ParametersSection InteractionsSection }
+        // Complete protocol: ProtocolName <Protocol> Annotation [Signature] { RolesSection ParametersSection InteractionsSection }
         rule protocol() -> AstNode
-            = name:protocol_name() ws() protocol_tag() ws() ann:annotation() ws() "{" ws()
+            = s:position!() name:protocol_name() ws() protocol_tag() ws() ann:annotation() ws()
+              signature:(signature:protocol_signature() ws() { signature })? "{" ws()
               roles:roles_section() ws()
+              types:(types:types_section() ws() { types })?
               params:parameters_section() ws()
               interactions:interactions_section() ws()
-              "}" {
-                let mut protocol_node = AstNode::new(AstNodeType::Protocol);
+              "}" e:position!() {
+                let mut protocol_node = AstNode::with_span(AstNodeType::Protocol, Span::new(s, e));
                 protocol_node.children.push(Box::new(name));
                 protocol_node.children.push(Box::new(ann));
+                if let Some(signature) = signature {
+                    protocol_node.children.push(Box::new(signature));
+                }
                 protocol_node.children.push(Box::new(roles));
+                if let Some(types) = types {
+                    protocol_node.children.push(Box::new(types));
+                }
                 protocol_node.children.push(Box::new(params));
                 protocol_node.children.push(Box::new(interactions));
                 protocol_node
             }
 
         // Program: one or more protocols (with optional comments)
+        // Path of an imported file: "relative/or/search/path.bmpp"
+        rule import_path() -> AstNode
+            = s:position!() path:string_literal() e:position!() {
+                let mut path_node = AstNode::with_span(AstNodeType::ImportPath, Span::new(s, e));
+                path_node.set_string("path", &path);
+                path_node
+            }
+
+        // Import declaration: import "file.bmpp" or use Name1, Name2 from "file.bmpp"
+        rule import_decl() -> AstNode
+            = s:position!() "import" !ident_char() ws() path:import_path() e:position!() {
+                import_node(Vec::new(), path, Span::new(s, e))
+            }
+            / s:position!() "use" !ident_char() ws() names:(spanned_identifier() ++ (ws() "," ws()))
+              ws() "from" !ident_char() ws() path:import_path() e:position!() {
+                import_node(names, path, Span::new(s, e))
+            }
+
         pub rule program() -> AstNode
-            = ws() protocols:protocol() ++ ws() ws() {
-                let mut program_node = AstNode::new(AstNodeType::Program);
+            = s:position!() ws() imports:(import_decl() ** ws()) ws() protocols:protocol() ++ ws() ws() e:position!() {
+                let mut program_node = AstNode::with_span(AstNodeType::Program, Span::new(s, e));
+                for import in imports {
+                    program_node.children.push(Box::new(import));
+                }
                 for protocol in protocols {
                     program_node.children.push(Box::new(protocol));
                 }
                 program_node
             }
+
+        // Fragment entry points used by the recovering parser to re-parse one piece at a time
+        pub rule imports_fragment() -> Vec<AstNode>
+            = ws() imports:(import_decl() ** ws()) ws() { imports }
+
+        pub rule protocol_header() -> (AstNode, AstNode, Option<AstNode>)
+            = ws() name:protocol_name() ws() protocol_tag() ws() ann:annotation() ws()
+              signature:(signature:protocol_signature() ws() { signature })? "{" { (name, ann, signature) }
+
+        pub rule roles_fragment() -> AstNode
+            = ws() roles:roles_section() ws() { roles }
+
+        pub rule role_fragment() -> AstNode
+            = ws() role:role_decl() ws() { role }
+
+        pub rule types_fragment() -> AstNode
+            = ws() types:types_section() ws() { types }
+
+        pub rule type_decl_fragment() -> AstNode
+            = ws() decl:type_decl() ws() { decl }
+
+        pub rule parameters_fragment() -> AstNode
+            = ws() params:parameters_section() ws() { params }
+
+        pub rule parameter_fragment() -> AstNode
+            = ws() param:parameter_decl() ws() { param }
+
+        pub rule interaction_fragment() -> AstNode
+            = ws() item:interaction_item() ws() { item }
+    }
+}
+
+/// Build a leaf node carrying a `name` property
+fn named_node(node_type: AstNodeType, (name, span): (String, Span)) -> AstNode {
+    let mut node = AstNode::with_span(node_type, span);
+    node.set_string("name", &name);
+    node
+}
+
+fn with_alias(mut node: AstNode, alias: Option<AstNode>) -> AstNode {
+    if let Some(alias) = alias {
+        node.span = node.span.to(alias.span);
+        node.children.push(Box::new(alias));
     }
+    node
 }
 
-// Updated parser function with better error handling
+fn import_node(names: Vec<(String, Span)>, path: AstNode, span: Span) -> AstNode {
+    let mut node = AstNode::with_span(AstNodeType::ImportDecl, span);
+    if let Some(path) = path.get_string("path") {
+        node.set_string("path", path);
+    }
+    for name in names {
+        node.children
+            .push(Box::new(named_node(AstNodeType::Identifier, name)));
+    }
+    node.children.push(Box::new(path));
+    node
+}
+
+/// Store the canonical spelling of a type node (e.g. `List<Enum[a, b]>`) as its `type`
+fn set_canonical_type(type_node: &mut AstNode) {
+    if let Some(ty) = BmppType::from_node(type_node) {
+        type_node.set_string("type", &ty.to_string());
+    }
+}
+
+/// Parse BMPP source into a Program AST with spans, lines, columns and trivia filled in
 pub fn parse_source(source: &str) -> Result<AstNode> {
     match bmpp_parser::program(source) {
-        Ok(ast) => Ok(ast),
-        Err(e) => {
-            // Enhanced error reporting
-            Err(anyhow!("Parse error at position {}: {}", e.location, e))
+        Ok(mut ast) => {
+            ast.assign_positions(&LineIndex::new(source));
+            attach_trivia(&mut ast, source);
+            Ok(ast)
         }
+        Err(e) => Err(syntax_error(source, e.location.offset, &e.expected.to_string()).into()),
     }
 }
+
+/// Build the diagnostic for a syntax error at byte `offset` of `source`
+fn syntax_error(source: &str, offset: usize, expected: &str) -> Diagnostic {
+    let (line, column) = LineIndex::new(source).line_col(offset);
+    let end = source[offset..]
+        .chars()
+        .next()
+        .map(|c| offset + c.len_utf8())
+        .unwrap_or(offset);
+    Diagnostic::new(
+        DiagnosticCode::SyntaxError,
+        format!(
+            "Parse error at line {}, column {}: expected {}",
+            line, column, expected
+        ),
+    )
+    .with_label(Span::new(offset, end), "unexpected input here")
+}
@@ -0,0 +1,521 @@
+//! Error-recovering parse mode.
+//!
+//! The strict grammar stops at the first failure. When that happens the source is cut into
+//! fragments at protocol headers, the `roles` / `types` / `parameters` keywords and
+//! interaction lines, and every fragment is parsed on its own so that independent mistakes
+//! are all reported while the fragments that do parse still end up in the returned AST.
+
+use super::{bmpp_parser, parse_source, syntax_error};
+use crate::protocol::ast::{AstNode, AstNodeType, LineIndex, Span};
+use crate::transpiler::diagnostics::Diagnostic;
+use peg::error::ParseError;
+use peg::str::LineCol;
+
+/// A possibly partial Program together with every syntax error found while parsing it
+#[derive(Debug, Clone)]
+pub struct ParseOutcome {
+    pub ast: AstNode,
+    pub errors: Vec<Diagnostic>,
+}
+
+impl ParseOutcome {
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+}
+
+/// Parse BMPP source, recovering from syntax errors instead of stopping at the first one.
+///
+/// Valid input yields exactly the AST of [`parse_source`] and no errors.
+pub fn parse_with_recovery(source: &str) -> ParseOutcome {
+    match parse_source(source) {
+        Ok(ast) => ParseOutcome {
+            ast,
+            errors: Vec::new(),
+        },
+        Err(strict_error) => {
+            let mut recovery = Recovery {
+                source,
+                errors: Vec::new(),
+            };
+            let ast = recovery.program();
+
+            // Fragment parsing can miss errors the strict parser saw (e.g. no protocol at all)
+            let mut errors = recovery.errors;
+            if errors.is_empty()
+                && let Ok(diagnostic) = strict_error.downcast::<Diagnostic>()
+            {
+                errors.push(diagnostic);
+            }
+
+            ParseOutcome { ast, errors }
+        }
+    }
+}
+
+struct Recovery<'a> {
+    source: &'a str,
+    errors: Vec<Diagnostic>,
+}
+
+impl Recovery<'_> {
+    fn program(&mut self) -> AstNode {
+        let mut program = AstNode::with_span(AstNodeType::Program, Span::new(0, self.source.len()));
+
+        // Only import declarations may precede the first protocol
+        let starts = protocol_starts(self.source);
+        let leading_end = starts.first().copied().unwrap_or(self.source.len());
+        if let Some(imports) = self.fragment(
+            0,
+            leading_end,
+            bmpp_parser::imports_fragment,
+            "protocol declaration",
+        ) {
+            for import in imports {
+                program.children.push(Box::new(import));
+            }
+        }
+        if starts.is_empty() && self.errors.is_empty() {
+            self.error_at(
+                first_code(self.source, 0, leading_end).unwrap_or(leading_end),
+                "protocol declaration",
+            );
+        }
+
+        for (index, &start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().unwrap_or(self.source.len());
+            program.children.push(Box::new(self.protocol(start, end)));
+        }
+
+        program.assign_positions(&LineIndex::new(self.source));
+        program
+    }
+
+    /// Recover a protocol declared between `start` and the next protocol header
+    fn protocol(&mut self, start: usize, end: usize) -> AstNode {
+        let code = code_chars(self.source, start, end);
+        let open = code.iter().find(|(_, c)| *c == '{').map(|(i, _)| *i);
+        let close = code.iter().rev().find(|(_, c)| *c == '}').map(|(i, _)| *i);
+
+        // Header: everything up to and including the opening brace
+        let header_end = open.map(|i| i + 1).unwrap_or(end);
+        let (name, annotation, signature) =
+            match self.fragment(start, header_end, bmpp_parser::protocol_header, "\"{\"") {
+                Some(header) => header,
+                None => {
+                    let (name, span) = leading_identifier(self.source, start);
+                    let mut name_node = AstNode::with_span(AstNodeType::ProtocolName, span);
+                    name_node.set_string("name", &name);
+                    let mut annotation =
+                        AstNode::with_span(AstNodeType::Annotation, Span::new(span.end, span.end));
+                    annotation.set_string("description", "");
+                    (name_node, annotation, None)
+                }
+            };
+
+        let trimmed_end = self.source[..end].trim_end().len();
+        let closed = close.filter(|close| open.is_some_and(|open| *close > open));
+        let body_end = match closed {
+            Some(close) => {
+                if let Some(offset) = first_code(self.source, close + 1, end) {
+                    self.error_at(offset, "protocol declaration");
+                }
+                close
+            }
+            None => {
+                if open.is_some() {
+                    self.error_at(trimmed_end, "\"}\"");
+                }
+                trimmed_end
+            }
+        };
+        let protocol_end = closed.map(|close| close + 1).unwrap_or(body_end);
+
+        let mut protocol =
+            AstNode::with_span(AstNodeType::Protocol, Span::new(start, protocol_end));
+        protocol.children.push(Box::new(name));
+        protocol.children.push(Box::new(annotation));
+        if let Some(signature) = signature {
+            protocol.children.push(Box::new(signature));
+        }
+        if open.is_some() {
+            for section in self.body(header_end, body_end) {
+                protocol.children.push(Box::new(section));
+            }
+        } else {
+            let empty = Span::new(protocol_end, protocol_end);
+            protocol.children.push(Box::new(AstNode::with_span(
+                AstNodeType::RolesSection,
+                empty,
+            )));
+            protocol.children.push(Box::new(AstNode::with_span(
+                AstNodeType::ParametersSection,
+                empty,
+            )));
+            protocol.children.push(Box::new(AstNode::with_span(
+                AstNodeType::InteractionSection,
+                empty,
+            )));
+        }
+        protocol
+    }
+
+    /// Recover the roles, optional types, parameters and interactions sections of a protocol
+    /// body, in source order
+    fn body(&mut self, start: usize, end: usize) -> Vec<AstNode> {
+        let roles_at = find_keyword(self.source, start, end, "roles");
+        let params_at = find_keyword(self.source, roles_at.unwrap_or(start), end, "parameters");
+        let types_at = find_keyword(
+            self.source,
+            roles_at.unwrap_or(start),
+            params_at.unwrap_or(end),
+            "types",
+        );
+        let interactions_at =
+            first_interaction_line(self.source, params_at.or(roles_at).unwrap_or(start), end)
+                .unwrap_or(end);
+
+        // The roles keyword must come first in the body
+        let first = first_code(self.source, start, end);
+        if roles_at.is_none() || first != roles_at {
+            self.error_at(first.unwrap_or(end), "\"roles\"");
+        }
+
+        let roles_end = types_at.or(params_at).unwrap_or(interactions_at);
+        let (roles, roles_ok) = match roles_at {
+            Some(at) => self.section(
+                at,
+                roles_end,
+                "roles",
+                AstNodeType::RolesSection,
+                bmpp_parser::roles_fragment,
+                bmpp_parser::role_fragment,
+            ),
+            None => (self.empty_section(AstNodeType::RolesSection, start), false),
+        };
+
+        let types = types_at.map(|at| {
+            self.section(
+                at,
+                params_at.unwrap_or(interactions_at),
+                "types",
+                AstNodeType::TypesSection,
+                bmpp_parser::types_fragment,
+                bmpp_parser::type_decl_fragment,
+            )
+            .0
+        });
+
+        let params = match params_at {
+            Some(at) => {
+                self.section(
+                    at,
+                    interactions_at,
+                    "parameters",
+                    AstNodeType::ParametersSection,
+                    bmpp_parser::parameters_fragment,
+                    bmpp_parser::parameter_fragment,
+                )
+                .0
+            }
+            None => {
+                // A misspelled keyword already shows up as an error inside the roles section
+                if roles_ok {
+                    self.error_at(interactions_at, "\"parameters\"");
+                }
+                self.empty_section(AstNodeType::ParametersSection, interactions_at)
+            }
+        };
+
+        let interactions = self.interactions(interactions_at, end);
+        let mut sections = vec![roles];
+        sections.extend(types);
+        sections.extend([params, interactions]);
+        sections
+    }
+
+    /// Parse a `roles`, `types` or `parameters` section, falling back to one declaration at a
+    /// time
+    fn section(
+        &mut self,
+        start: usize,
+        end: usize,
+        keyword: &str,
+        node_type: AstNodeType,
+        whole: fn(&str) -> Result<AstNode, ParseError<LineCol>>,
+        item: fn(&str) -> Result<AstNode, ParseError<LineCol>>,
+    ) -> (AstNode, bool) {
+        let end = self.source[..end].trim_end().len().max(start);
+        let text = &self.source[start..end];
+        let whole_error = match whole(text) {
+            Ok(mut section) => {
+                section.shift_spans(start);
+                return (section, true);
+            }
+            Err(e) => e,
+        };
+
+        let mut section = AstNode::with_span(node_type, Span::new(start, end));
+        let errors_before = self.errors.len();
+        for (piece_start, piece_end) in split_top_level(self.source, start + keyword.len(), end) {
+            if let Some(node) = self.fragment(piece_start, piece_end, item, "\",\"") {
+                section.children.push(Box::new(node));
+            }
+        }
+        if self.errors.len() == errors_before {
+            self.error_at(
+                start + whole_error.location.offset,
+                &whole_error.expected.to_string(),
+            );
+        }
+        (section, false)
+    }
+
+    /// Parse every interaction line between `start` and `end` independently
+    fn interactions(&mut self, start: usize, end: usize) -> AstNode {
+        let mut section =
+            AstNode::with_span(AstNodeType::InteractionSection, Span::new(start, start));
+        for (item_start, item_end) in interaction_items(self.source, start, end) {
+            if let Some(item) = self.fragment(
+                item_start,
+                item_end,
+                bmpp_parser::interaction_fragment,
+                "a new line",
+            ) {
+                section.children.push(Box::new(item));
+            }
+        }
+        if let (Some(first), Some(last)) = (section.children.first(), section.children.last()) {
+            section.span = first.span.to(last.span);
+        }
+        if first_code(self.source, start, end).is_none() {
+            self.error_at(end, "interaction");
+        }
+        section
+    }
+
+    /// Run `rule` on `source[start..end]`, recording a shifted error on failure.
+    ///
+    /// `separator` replaces peg's "expected EOF" when the fragment parsed but more text
+    /// follows it, which almost always means a missing separator.
+    fn fragment<T: ShiftSpans>(
+        &mut self,
+        start: usize,
+        end: usize,
+        rule: fn(&str) -> Result<T, ParseError<LineCol>>,
+        separator: &str,
+    ) -> Option<T> {
+        match rule(&self.source[start..end]) {
+            Ok(mut node) => {
+                node.shift_spans(start);
+                Some(node)
+            }
+            Err(e) => {
+                let expected = if e.expected.tokens().eq(["EOF"]) {
+                    separator.to_string()
+                } else {
+                    e.expected.to_string()
+                };
+                self.error_at(start + e.location.offset, &expected);
+                None
+            }
+        }
+    }
+
+    fn empty_section(&self, node_type: AstNodeType, at: usize) -> AstNode {
+        AstNode::with_span(node_type, Span::new(at, at))
+    }
+
+    fn error_at(&mut self, offset: usize, expected: &str) {
+        self.errors
+            .push(syntax_error(self.source, offset, expected));
+    }
+}
+
+/// Fragment results whose spans need moving back into the coordinates of the whole source
+trait ShiftSpans {
+    fn shift_spans(&mut self, offset: usize);
+}
+
+impl ShiftSpans for Vec<AstNode> {
+    fn shift_spans(&mut self, offset: usize) {
+        for node in self {
+            node.shift_spans(offset);
+        }
+    }
+}
+
+impl ShiftSpans for AstNode {
+    fn shift_spans(&mut self, offset: usize) {
+        AstNode::shift_spans(self, offset);
+    }
+}
+
+impl ShiftSpans for (AstNode, AstNode, Option<AstNode>) {
+    fn shift_spans(&mut self, offset: usize) {
+        self.0.shift_spans(offset);
+        self.1.shift_spans(offset);
+        if let Some(signature) = &mut self.2 {
+            signature.shift_spans(offset);
+        }
+    }
+}
+
+/// Characters of `source[start..end]` outside string literals and `//` comments, with offsets
+fn code_chars(source: &str, start: usize, end: usize) -> Vec<(usize, char)> {
+    let mut code = Vec::new();
+    let mut chars = source[start..end].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek().is_some_and(|(_, next)| *next == '/') => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ => code.push((start + i, c)),
+        }
+    }
+    code
+}
+
+/// Offset of the first non-whitespace code character in `source[start..end]`
+fn first_code(source: &str, start: usize, end: usize) -> Option<usize> {
+    code_chars(source, start, end)
+        .into_iter()
+        .find(|(_, c)| !c.is_whitespace())
+        .map(|(i, _)| i)
+}
+
+/// Lines of `source[start..end]` as `(line_start, line_end)` byte ranges without terminators
+fn lines(source: &str, start: usize, end: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut offset = start;
+    source[start..end].split_inclusive('\n').map(move |line| {
+        let line_start = offset;
+        offset += line.len();
+        (
+            line_start,
+            line_start + line.trim_end_matches(['\n', '\r']).len(),
+        )
+    })
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The identifier starting at `offset` (after leading spaces and tabs) and its span
+fn leading_identifier(source: &str, offset: usize) -> (String, Span) {
+    let text = &source[offset..];
+    let start = offset + (text.len() - text.trim_start_matches([' ', '\t']).len());
+    let name: String = source[start..]
+        .chars()
+        .take_while(|c| is_ident_char(*c))
+        .collect();
+    (name.clone(), Span::new(start, start + name.len()))
+}
+
+/// Whether the line at `offset` starts with `Name <tag>` (ignoring surrounding whitespace)
+fn line_starts_with_tagged_name(source: &str, offset: usize, tag: &str) -> bool {
+    let (name, span) = leading_identifier(source, offset);
+    !name.is_empty()
+        && source[span.end..]
+            .trim_start_matches([' ', '\t'])
+            .starts_with(tag)
+}
+
+/// Offsets of every protocol header (`Name <Protocol>` at the start of a line)
+fn protocol_starts(source: &str) -> Vec<usize> {
+    lines(source, 0, source.len())
+        .filter(|(start, _)| line_starts_with_tagged_name(source, *start, "<Protocol>"))
+        .map(|(start, end)| {
+            start + (source[start..end].len() - source[start..end].trim_start().len())
+        })
+        .collect()
+}
+
+/// Offset of `keyword` when it is the first word of a line within `source[start..end]`
+fn find_keyword(source: &str, start: usize, end: usize, keyword: &str) -> Option<usize> {
+    lines(source, start, end).find_map(|(line_start, line_end)| {
+        let line = &source[line_start..line_end];
+        let at = line_start + (line.len() - line.trim_start().len());
+        let after = source[at..line_end].strip_prefix(keyword)?;
+        after
+            .chars()
+            .next()
+            .is_none_or(|c| !is_ident_char(c))
+            .then_some(at)
+    })
+}
+
+/// Whether the line at `offset` looks like `A -> B: ...` or `Name <Enactment>[...]`
+fn is_interaction_line(source: &str, offset: usize) -> bool {
+    let (name, span) = leading_identifier(source, offset);
+    let rest = source[span.end..].trim_start_matches([' ', '\t']);
+    !name.is_empty() && (rest.starts_with("->") || rest.starts_with("<Enactment>"))
+}
+
+/// Start of the first interaction line within `source[start..end]`
+fn first_interaction_line(source: &str, start: usize, end: usize) -> Option<usize> {
+    lines(source, start, end)
+        .find(|(line_start, _)| is_interaction_line(source, *line_start))
+        .map(|(line_start, _)| line_start)
+}
+
+/// Byte ranges of the interaction items in `source[start..end]`.
+///
+/// Every non-blank, non-comment line starts a new item unless the previous item still has an
+/// unclosed `(` or `[`, in which case the line continues it.
+fn interaction_items(source: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut items: Vec<(usize, usize)> = Vec::new();
+    let mut depth = 0i32;
+    for (line_start, line_end) in lines(source, start, end) {
+        let code = code_chars(source, line_start, line_end);
+        if code.iter().all(|(_, c)| c.is_whitespace()) {
+            continue;
+        }
+        match items.last_mut() {
+            Some(item) if depth > 0 => item.1 = line_end,
+            _ => {
+                depth = 0;
+                items.push((line_start, line_end));
+            }
+        }
+        for (_, c) in code {
+            match c {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+    items
+}
+
+/// Split `source[start..end]` at commas that are not nested in brackets or string literals
+fn split_top_level(source: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    let mut piece_start = start;
+    let mut depth = 0i32;
+    for (i, c) in code_chars(source, start, end) {
+        match c {
+            '(' | '[' | '<' | '{' => depth += 1,
+            ')' | ']' | '>' | '}' => depth -= 1,
+            ',' if depth <= 0 => {
+                pieces.push((piece_start, i));
+                piece_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    pieces.push((piece_start, end));
+    pieces
+}
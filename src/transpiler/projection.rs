@@ -0,0 +1,217 @@
+//! Projection of protocols onto their roles.
+//!
+//! The projection of a protocol onto one of its roles is that role's local view: the
+//! messages it sends and receives, the peers on the other end of them and the parameters
+//! they carry. Enactments are inlined first, so the view covers the messages the role
+//! exchanges in enacted protocols too.
+//!
+//! A projection is a protocol itself, with the name and description of the projected one,
+//! so it can be printed, parsed back and handed to code generation like any other. Every
+//! parameter of the view is public: `out` when a message of the view produces it, and `in`
+//! when the view only consumes it because messages the role takes no part in produce it.
+//! Peers are kept with the messages they exchange with the role only, so what they learn
+//! elsewhere is not part of the view and it is not meant to be enacted on its own.
+
+use crate::protocol::ast::ParameterDirection;
+use crate::protocol::model::{
+    InteractionItem, Protocol, PublicParameter, Section, TypeExpr, TypeKind,
+};
+use crate::transpiler::composition::ProtocolRegistry;
+use anyhow::{Result, anyhow};
+use std::collections::HashSet;
+
+/// Project protocol `name` of the registry onto `role`, after inlining its enactments
+pub fn project(registry: &ProtocolRegistry, name: &str, role: &str) -> Result<Protocol> {
+    project_protocol(&registry.flatten(name)?, role)
+}
+
+/// Project a protocol onto `role`; enactments left in it are dropped, so compositions
+/// should be flattened first
+pub fn project_protocol(protocol: &Protocol, role: &str) -> Result<Protocol> {
+    if protocol.role(role).is_none() {
+        return Err(anyhow!(
+            "Role '{}' is not declared in protocol '{}'",
+            role,
+            protocol.name
+        ));
+    }
+
+    let messages: Vec<_> = protocol
+        .messages()
+        .filter(|message| message.from.as_str() == role || message.to.as_str() == role)
+        .collect();
+    let peers: HashSet<&str> = messages
+        .iter()
+        .flat_map(|message| [message.from.as_str(), message.to.as_str()])
+        .collect();
+    let carried: HashSet<&str> = messages
+        .iter()
+        .flat_map(|message| &message.flows)
+        .map(|flow| flow.parameter.as_str())
+        .collect();
+    let produced: HashSet<&str> = messages
+        .iter()
+        .flat_map(|message| message.parameters(ParameterDirection::Out))
+        .collect();
+    let consumed: HashSet<&str> = messages
+        .iter()
+        .flat_map(|message| message.parameters(ParameterDirection::In))
+        .collect();
+
+    let mut local = protocol.clone();
+    local
+        .roles
+        .items
+        .retain(|r| peers.contains(r.name.as_str()) || r.name.as_str() == role);
+    local
+        .parameters
+        .items
+        .retain(|param| param.key || carried.contains(param.name.as_str()));
+    local.interactions.items = messages
+        .iter()
+        .map(|&message| InteractionItem::Interaction(message.clone()))
+        .collect();
+
+    let used = used_records(protocol, local.parameters.iter().map(|param| &param.ty));
+    if let Some(types) = &mut local.types {
+        types.items.retain(|decl| used.contains(decl.name.as_str()));
+    }
+    if local
+        .types
+        .as_ref()
+        .is_some_and(|types| types.items.is_empty())
+    {
+        local.types = None;
+    }
+
+    let signature = local
+        .parameters
+        .iter()
+        .filter_map(|param| {
+            let name = param.name.as_str();
+            let direction = if produced.contains(name) {
+                ParameterDirection::Out
+            } else if param.key || consumed.contains(name) {
+                ParameterDirection::In
+            } else {
+                return None;
+            };
+            Some(PublicParameter {
+                direction,
+                name: param.name.clone(),
+                key: param.key,
+                span: param.span,
+            })
+        })
+        .collect();
+    let span = protocol
+        .signature
+        .as_ref()
+        .map_or(protocol.name.span, |signature| signature.span);
+    local.signature = Some(Section::new(signature, span));
+    Ok(local)
+}
+
+/// Names of the record types `types` refer to, directly or through the fields of other
+/// records
+fn used_records<'a>(
+    protocol: &Protocol,
+    types: impl Iterator<Item = &'a TypeExpr>,
+) -> HashSet<String> {
+    let mut used = HashSet::new();
+    let mut pending: Vec<TypeExpr> = types.cloned().collect();
+    while let Some(ty) = pending.pop() {
+        match ty.kind {
+            TypeKind::List(inner) => pending.push(*inner),
+            TypeKind::Named(name) => {
+                if let Some(decl) = protocol.type_decls().iter().find(|d| d.name.value == name)
+                    && used.insert(name)
+                {
+                    pending.extend(decl.fields.iter().map(|field| field.ty.clone()));
+                }
+            }
+            TypeKind::Basic(_) | TypeKind::Enum(_) => {}
+        }
+    }
+    used
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::model::Program;
+    use crate::transpiler::{composition, format_program, parse_source};
+
+    const LOGISTICS: &str = include_str!("../../examples/STARTER-PROTOCOL_COMPOSED.bmpp");
+
+    fn projection(role: &str) -> Protocol {
+        let program = parse_source(LOGISTICS).unwrap();
+        let registry = ProtocolRegistry::from_program(&program).unwrap();
+        project(&registry, "Logistics", role).unwrap()
+    }
+
+    fn actions(protocol: &Protocol) -> Vec<&str> {
+        protocol
+            .messages()
+            .map(|message| message.action.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_projection_keeps_the_messages_of_the_role() {
+        let courier = projection("C");
+        assert_eq!(
+            actions(&courier),
+            vec!["GetVehicle", "SendVehicle", "LoadVehicle", "Enroute"]
+        );
+        let roles: Vec<&str> = courier.roles.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(roles, vec!["W", "L", "C"]);
+
+        let signature: Vec<String> = courier
+            .signature
+            .as_deref()
+            .unwrap()
+            .iter()
+            .map(|public| format!("{:?} {}", public.direction, public.name))
+            .collect();
+        assert_eq!(
+            signature,
+            vec![
+                "In ID",
+                "In order",
+                "Out route",
+                "Out Load_type",
+                "Out Load_vehicle",
+                "In Load_package",
+                "Out Load_loaded"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_projection_prints_as_bmpp_with_a_consistent_signature() {
+        for role in ["M", "W", "P", "L", "S", "C"] {
+            let local = projection(role);
+            assert!(composition::validate_signature(&local).is_empty());
+
+            let source = format_program(&Program {
+                imports: Vec::new(),
+                protocols: vec![local.clone()],
+                span: Default::default(),
+            });
+            let reparsed = Program::from_ast(&parse_source(&source).unwrap()).unwrap();
+            assert_eq!(actions(&reparsed.protocols[0]), actions(&local));
+        }
+    }
+
+    #[test]
+    fn test_projection_rejects_unknown_role() {
+        let program = parse_source(LOGISTICS).unwrap();
+        let registry = ProtocolRegistry::from_program(&program).unwrap();
+        let error = project(&registry, "Logistics", "X").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Role 'X' is not declared in protocol 'Logistics'"
+        );
+    }
+}
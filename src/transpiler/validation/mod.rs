@@ -1,492 +1,777 @@
-use crate::protocol::ast::{AstNode, AstNodeType};
-use anyhow::{anyhow, Result};
-use std::collections::{HashMap, HashSet};
-
-pub mod errors;
+use crate::protocol::ast::{AstNode, ParameterDirection, Span};
+use crate::protocol::model::{
+    Enactment, Flow, InteractionItem, Program, Protocol, Spanned, TypeDecl, TypeExpr, TypeKind,
+};
+use crate::transpiler::composition::{
+    bind_enactment, enactment_path, enactment_types, validate_signature,
+};
+use crate::transpiler::diagnostics::{Diagnostic, DiagnosticCode, similar_name};
+use crate::transpiler::lints::LintConfig;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+mod report;
+#[allow(clippy::module_inception)]
 mod tests;
 
-/// Validates parameter flow consistency in BMPP protocols according to BSPL standard
-pub fn validate_parameter_flow(ast: &AstNode) -> Result<()> {
-    if ast.node_type != AstNodeType::Program {
-        return Err(anyhow!("Expected Program node"));
-    }
+pub use report::ValidationReport;
 
-    for protocol_node in &ast.children {
-        if protocol_node.node_type == AstNodeType::Protocol {
-            validate_protocol_parameter_flow(protocol_node)?;
-        }
-    }
+/// Types every protocol can use without declaring them
+const BASIC_TYPES: [&str; 4] = ["String", "Int", "Float", "Bool"];
 
-    Ok(())
+/// Runs every check on a program and collects what they find, errors and warnings alike,
+/// instead of stopping at the first error. Lints allowed by `bmpp-allow` comments are left
+/// out.
+pub fn validate(ast: &AstNode) -> Result<ValidationReport> {
+    validate_with(ast, &LintConfig::new())
 }
 
-fn validate_protocol_parameter_flow(protocol_node: &AstNode) -> Result<()> {
-    let protocol_name = extract_protocol_name(protocol_node)?;
+/// [`validate`] with the lint levels of a project, usually read from its `bmpp.toml`
+pub fn validate_with(ast: &AstNode, lints: &LintConfig) -> Result<ValidationReport> {
+    let program = Program::from_ast(ast)?;
+    Ok(lints.apply(ast, validate_program(&program)))
+}
 
-    let mut declared_parameters = HashSet::new();
-    let mut parameter_info: HashMap<String, ParameterInfo> = HashMap::new();
+/// Every diagnostic of an already built model, at default levels. The model carries no
+/// comments, so `bmpp-allow` suppressions are not applied.
+pub fn validate_program(program: &Program) -> ValidationReport {
+    let mut report = ValidationReport::new();
+    check_roles_and_actions(program, &mut report);
+    check_parameter_flow(program, &mut report);
+    check_compositions(program, &mut report);
+    report
+}
 
-    for child in &protocol_node.children {
-        if child.node_type == AstNodeType::ParametersSection {
-            extract_parameters(child, &mut declared_parameters, &mut parameter_info)?;
+/// Errors leaving a program ill-formed: undeclared roles and parameters, actions named
+/// twice and enactments of unknown protocols. What the other checks and the model checker
+/// find is only meaningful without them.
+pub fn validate_structure(program: &Program) -> ValidationReport {
+    let mut report = ValidationReport::new();
+    check_roles_and_actions(program, &mut report);
+    for protocol in &program.protocols {
+        for interaction in &protocol.interactions {
+            for flow in interaction.flows() {
+                if protocol.parameter(flow.parameter.as_str()).is_none() {
+                    report.push(undeclared_parameter(flow, interaction, protocol));
+                }
+            }
+        }
+        for enactment in protocol.enactments() {
+            if program.protocol(enactment.protocol.as_str()).is_none() {
+                report.push(unknown_protocol(enactment, protocol, program));
+            }
         }
     }
+    report
+}
 
-    let mut interactions = Vec::new();
+/// Rejects messages and enactments naming roles the protocol does not declare, and
+/// messages reusing the action of an earlier one
+fn check_roles_and_actions(program: &Program, report: &mut ValidationReport) {
+    for protocol in &program.protocols {
+        let mut actions: HashMap<&str, Span> = HashMap::new();
+        for interaction in &protocol.interactions {
+            let roles: Vec<&Spanned<String>> = match interaction {
+                InteractionItem::Interaction(message) => vec![&message.from, &message.to],
+                InteractionItem::Enactment(enactment) => {
+                    enactment.roles().map(|role| &role.name).collect()
+                }
+            };
+            for role in roles {
+                if protocol.roles.iter().any(|r| r.name.value == role.value) {
+                    continue;
+                }
+                let mut diagnostic = Diagnostic::new(
+                    DiagnosticCode::UndeclaredRole,
+                    format!(
+                        "Role '{}' of interaction '{}' is not declared in protocol '{}'",
+                        role,
+                        interaction.name(),
+                        protocol.name
+                    ),
+                )
+                .with_label(role.span, "not declared in the roles section");
+                let declared = protocol.roles.iter().map(|r| r.name.as_str());
+                diagnostic = match similar_name(role.as_str(), declared) {
+                    Some(similar) => diagnostic.with_replacement(
+                        format!("did you mean `{}`?", similar),
+                        role.span,
+                        similar,
+                    ),
+                    None => {
+                        diagnostic.with_help(format!("declare '{}' in the roles section", role))
+                    }
+                };
+                report.push(diagnostic);
+            }
 
-    for child in &protocol_node.children {
-        if child.node_type == AstNodeType::InteractionSection {
-            extract_interactions(
-                child,
-                &mut interactions,
-                &declared_parameters,
-                &mut parameter_info,
-                &protocol_name,
-            )?;
+            let InteractionItem::Interaction(message) = interaction else {
+                continue;
+            };
+            if let Some(first) = actions.insert(message.action.as_str(), message.action.span) {
+                report.push(
+                    Diagnostic::new(
+                        DiagnosticCode::DuplicateAction,
+                        format!(
+                            "Action '{}' is defined more than once in protocol '{}'",
+                            message.action, protocol.name
+                        ),
+                    )
+                    .with_label(message.action.span, "defined again here")
+                    .with_note_at(first, "first defined here")
+                    .with_help("give each message of the protocol its own action name"),
+                );
+            }
         }
     }
+}
 
-    // First validate unreachable interactions before other checks
-    validate_unreachable_interactions(&parameter_info, &interactions, &protocol_name)?;
-    validate_flow_consistency(&parameter_info, &interactions, &protocol_name)?;
-    validate_causality(&parameter_info, &interactions, &protocol_name)?;
-    validate_completeness(&parameter_info, &protocol_name)?;
-    validate_enactability(&interactions, &parameter_info, &protocol_name)?;
+/// Validates parameter flow consistency in BMPP protocols according to BSPL standard,
+/// failing with the first error found
+pub fn validate_parameter_flow(ast: &AstNode) -> Result<()> {
+    validate_parameter_flow_with(ast, &LintConfig::new())
+}
 
-    Ok(())
+/// [`validate_parameter_flow`] with the lint levels of a project, so denied lints fail it
+pub fn validate_parameter_flow_with(ast: &AstNode, lints: &LintConfig) -> Result<()> {
+    let program = Program::from_ast(ast)?;
+    let mut report = ValidationReport::new();
+    check_parameter_flow(&program, &mut report);
+    lints.apply(ast, report).into_result().map(drop)
 }
 
-fn extract_protocol_name(protocol_node: &AstNode) -> Result<String> {
-    for child in &protocol_node.children {
-        if child.node_type == AstNodeType::ProtocolName {
-            if let Some(name) = child.get_string("name") {
-                return Ok(name.clone());
-            }
-        }
+fn check_parameter_flow(program: &Program, report: &mut ValidationReport) {
+    for protocol in &program.protocols {
+        validate_protocol_parameter_flow(program, protocol, report);
     }
-    Err(anyhow!("Protocol name not found"))
 }
 
-fn extract_parameters(
-    params_section: &AstNode,
-    declared_parameters: &mut HashSet<String>,
-    parameter_info: &mut HashMap<String, ParameterInfo>,
-) -> Result<()> {
-    for param_decl in &params_section.children {
-        if param_decl.node_type == AstNodeType::ParameterDecl {
-            let (name, param_type) = extract_parameter_info(param_decl)?;
-            declared_parameters.insert(name.clone());
-            parameter_info.insert(
-                name.clone(),
+fn validate_protocol_parameter_flow(
+    program: &Program,
+    protocol: &Protocol,
+    report: &mut ValidationReport,
+) {
+    let protocol_name = protocol.name.as_str();
+    validate_types(protocol, report);
+
+    let mut parameter_info: HashMap<String, ParameterInfo> = protocol
+        .parameters
+        .iter()
+        .map(|param| {
+            (
+                param.name.value.clone(),
                 ParameterInfo {
-                    name: name.clone(),
-                    param_type,
                     producers: HashSet::new(),
                     consumers: HashSet::new(),
+                    guards: HashSet::new(),
+                    input: protocol.is_input(param.name.as_str()),
                 },
-            );
-        }
+            )
+        })
+        .collect();
+
+    let interactions = &protocol.interactions;
+    for interaction in interactions {
+        validate_and_update_parameter_usage(interaction, &mut parameter_info, protocol, report);
     }
-    Ok(())
+
+    // First validate unreachable interactions before other checks
+    validate_unreachable_interactions(&parameter_info, protocol, report);
+    validate_flow_consistency(&parameter_info, protocol, report);
+    validate_causality(&parameter_info, interactions, protocol_name, report);
+    validate_completeness(&parameter_info, protocol, report);
+    validate_enactability(
+        program,
+        interactions,
+        &parameter_info,
+        protocol_name,
+        report,
+    );
 }
 
-fn extract_parameter_info(param_decl: &AstNode) -> Result<(String, String)> {
-    let mut name = None;
-    let mut param_type = None;
+/// Validates the record declarations of the types section and every type used by a
+/// parameter or record field
+fn validate_types(protocol: &Protocol, report: &mut ValidationReport) {
+    let protocol_name = protocol.name.as_str();
+    let declarations = protocol.type_decls();
+
+    let mut records: HashMap<&str, &TypeDecl> = HashMap::new();
+    for decl in declarations {
+        if let Some(first) = records.get(decl.name.as_str()) {
+            report.push(
+                Diagnostic::new(
+                    DiagnosticCode::DuplicateType,
+                    format!(
+                        "Type '{}' is declared more than once in protocol '{}'",
+                        decl.name, protocol_name
+                    ),
+                )
+                .with_label(decl.name.span, "declared again here")
+                .with_note_at(first.name.span, "first declared here"),
+            );
+            continue;
+        }
+        records.insert(decl.name.as_str(), decl);
+    }
 
-    for child in &param_decl.children {
-        match child.node_type {
-            AstNodeType::Identifier => {
-                if let Some(param_name) = child.get_string("name") {
-                    name = Some(param_name.clone());
-                }
+    for decl in declarations {
+        let record = decl.name.as_str();
+        let mut fields: HashMap<&str, Span> = HashMap::new();
+        for field in &decl.fields {
+            let first = *fields.entry(field.name.as_str()).or_insert(field.name.span);
+            if first != field.name.span {
+                report.push(
+                    Diagnostic::new(
+                        DiagnosticCode::DuplicateField,
+                        format!(
+                            "Field '{}' is declared more than once in record type '{}'",
+                            field.name, record
+                        ),
+                    )
+                    .with_label(field.name.span, "declared again here")
+                    .with_note_at(first, "first declared here"),
+                );
             }
-            AstNodeType::BasicType => {
-                if let Some(type_name) = child.get_string("type") {
-                    param_type = Some(type_name.clone());
-                }
+            if field.key {
+                report.push(
+                    Diagnostic::new(
+                        DiagnosticCode::KeyField,
+                        format!(
+                            "Field '{}' of record type '{}' cannot be a key - only protocol parameters can",
+                            field.name, record
+                        ),
+                    )
+                    .with_label(field.name.span, "key adornment on a field")
+                    .with_help("remove `key` from the field, or make it a protocol parameter"),
+                );
             }
-            _ => {}
+            validate_type_use(
+                &field.ty,
+                &records,
+                &format!("field '{}.{}'", record, field.name),
+                report,
+            );
         }
     }
 
-    match (name, param_type) {
-        (Some(n), Some(t)) => Ok((n, t)),
-        _ => Err(anyhow!("Failed to extract parameter information")),
+    for param in &protocol.parameters {
+        validate_type_use(
+            &param.ty,
+            &records,
+            &format!("parameter '{}' in protocol '{}'", param.name, protocol_name),
+            report,
+        );
     }
-}
 
-fn extract_interactions(
-    interaction_section: &AstNode,
-    interactions: &mut Vec<InteractionInfo>,
-    declared_parameters: &HashSet<String>,
-    parameter_info: &mut HashMap<String, ParameterInfo>,
-    protocol_name: &str,
-) -> Result<()> {
-    for interaction_item in &interaction_section.children {
-        if interaction_item.node_type == AstNodeType::InteractionItem {
-            for child in &interaction_item.children {
-                match child.node_type {
-                    AstNodeType::StandardInteraction => {
-                        let interaction = extract_standard_interaction(child)?;
-                        validate_and_update_parameter_usage(
-                            &interaction,
-                            declared_parameters,
-                            parameter_info,
-                            protocol_name,
-                        )?;
-                        interactions.push(interaction);
-                    }
-                    AstNodeType::ProtocolComposition => {
-                        let interaction = extract_composition_interaction(child)?;
-                        validate_and_update_parameter_usage(
-                            &interaction,
-                            declared_parameters,
-                            parameter_info,
-                            protocol_name,
-                        )?;
-                        interactions.push(interaction);
-                    }
-                    _ => {}
-                }
-            }
+    for decl in declarations {
+        if let Some(path) = find_embedding_cycle(decl.name.as_str(), &records) {
+            report.push(
+                Diagnostic::new(
+                    DiagnosticCode::RecursiveRecord,
+                    format!(
+                        "Record type '{}' contains itself by value ({}) - use a List to nest it",
+                        decl.name,
+                        path.join(" -> ")
+                    ),
+                )
+                .with_label(decl.name.span, "record contains itself"),
+            );
         }
     }
-    Ok(())
 }
 
-fn validate_and_update_parameter_usage(
-    interaction: &InteractionInfo,
-    declared_parameters: &HashSet<String>,
-    parameter_info: &mut HashMap<String, ParameterInfo>,
-    protocol_name: &str,
-) -> Result<()> {
-    for flow in &interaction.parameter_flows {
-        if !declared_parameters.contains(&flow.parameter) {
-            return Err(anyhow!(
-                "Parameter '{}' used in interaction '{}' is not declared in protocol '{}'",
-                flow.parameter,
-                interaction.action,
-                protocol_name
-            ));
-        }
-
-        if let Some(param_info) = parameter_info.get_mut(&flow.parameter) {
-            match flow.direction.as_str() {
-                "out" => {
-                    param_info.producers.insert(interaction.action.clone());
-                }
-                "in" => {
-                    param_info.consumers.insert(interaction.action.clone());
-                }
-                _ => {
-                    return Err(anyhow!(
-                        "Invalid parameter direction '{}' for parameter '{}' in interaction '{}'",
-                        flow.direction,
-                        flow.parameter,
-                        interaction.action
-                    ));
+/// Checks the type of a parameter or field declaration: referenced records must exist and
+/// enums need distinct variants
+fn validate_type_use(
+    ty: &TypeExpr,
+    records: &HashMap<&str, &TypeDecl>,
+    subject: &str,
+    report: &mut ValidationReport,
+) {
+    match &ty.kind {
+        TypeKind::Named(name) => {
+            if !records.contains_key(name.as_str()) {
+                let mut diagnostic = Diagnostic::new(
+                    DiagnosticCode::UnknownType,
+                    format!(
+                        "Unknown type '{}' for {} - expected String, Int, Float, Bool, Enum[...], List<...> or a type from the types section",
+                        name, subject
+                    ),
+                )
+                .with_label(ty.span, "unknown type");
+                let known = BASIC_TYPES.into_iter().chain(records.keys().copied());
+                if let Some(similar) = similar_name(name, known) {
+                    diagnostic = diagnostic.with_replacement(
+                        format!("did you mean `{}`?", similar),
+                        ty.span,
+                        similar,
+                    );
                 }
+                report.push(diagnostic);
             }
         }
-    }
-    Ok(())
-}
-
-#[derive(Debug, Clone)]
-struct ParameterInfo {
-    name: String,
-    param_type: String,
-    producers: HashSet<String>,
-    consumers: HashSet<String>,
-}
-
-#[derive(Debug, Clone)]
-struct InteractionInfo {
-    action: String,
-    from_role: String,
-    to_role: String,
-    parameter_flows: Vec<ParameterFlow>,
-}
-
-#[derive(Debug, Clone)]
-struct ParameterFlow {
-    direction: String,
-    parameter: String,
-}
-
-fn extract_standard_interaction(node: &AstNode) -> Result<InteractionInfo> {
-    let mut from_role = "Unknown".to_string();
-    let mut to_role = "Unknown".to_string();
-    let mut action = "unknown_action".to_string();
-    let mut parameter_flows = Vec::new();
-
-    for child in &node.children {
-        match child.node_type {
-            AstNodeType::RoleRef => {
-                if let Some(name) = child.get_string("name") {
-                    if from_role == "Unknown" {
-                        from_role = name.clone();
-                    } else if to_role == "Unknown" {
-                        to_role = name.clone();
-                    }
-                }
+        TypeKind::Enum(variants) => {
+            if variants.is_empty() {
+                report.push(
+                    Diagnostic::new(
+                        DiagnosticCode::EmptyEnum,
+                        format!("Enum type of {} has no variants", subject),
+                    )
+                    .with_label(ty.span, "no variants"),
+                );
             }
-            AstNodeType::ActionName => {
-                if let Some(name) = child.get_string("name") {
-                    action = name.clone();
+            let mut seen: HashMap<&str, Span> = HashMap::new();
+            for variant in variants {
+                let first = *seen.entry(variant.as_str()).or_insert(variant.span);
+                if first != variant.span {
+                    report.push(
+                        Diagnostic::new(
+                            DiagnosticCode::DuplicateVariant,
+                            format!("Enum type of {} repeats variant '{}'", subject, variant),
+                        )
+                        .with_label(variant.span, "repeated variant")
+                        .with_note_at(first, "first listed here"),
+                    );
                 }
             }
-            AstNodeType::Identifier => {
-                if let Some(role_type) = child.get_string("role") {
-                    if let Some(name) = child.get_string("name") {
-                        match role_type.as_str() {
-                            "from" => from_role = name.clone(),
-                            "to" => to_role = name.clone(),
-                            "action" => action = name.clone(),
-                            _ => {}
-                        }
-                    }
-                } else if let Some(name) = child.get_string("name") {
-                    if from_role == "Unknown" {
-                        from_role = name.clone();
-                    } else if to_role == "Unknown" {
-                        to_role = name.clone();
-                    } else if action == "unknown_action" {
-                        action = name.clone();
-                    }
-                }
-            }
-            AstNodeType::ParameterFlow => {
-                let param_flow = extract_parameter_flow(child)?;
-                parameter_flows.push(param_flow);
-            }
-            _ => {}
         }
+        TypeKind::List(element) => validate_type_use(element, records, subject, report),
+        TypeKind::Basic(_) => {}
     }
-
-    Ok(InteractionInfo {
-        action,
-        from_role,
-        to_role,
-        parameter_flows,
-    })
 }
 
-fn extract_composition_interaction(node: &AstNode) -> Result<InteractionInfo> {
-    let mut protocol_name = "UnknownProtocol".to_string();
-    let mut roles = Vec::new();
-    let mut parameter_flows = Vec::new();
+/// Path of record names leading from `record` back to itself through fields embedded by
+/// value, if any. Lists break the cycle since they can be empty.
+fn find_embedding_cycle(record: &str, records: &HashMap<&str, &TypeDecl>) -> Option<Vec<String>> {
+    let mut path = vec![record.to_string()];
+    let mut visited = HashSet::new();
+    if dfs_embedding(record, record, records, &mut path, &mut visited) {
+        Some(path)
+    } else {
+        None
+    }
+}
 
-    for child in &node.children {
-        match child.node_type {
-            AstNodeType::ProtocolReference => {
-                for ref_child in &child.children {
-                    if ref_child.node_type == AstNodeType::Identifier {
-                        if let Some(name) = ref_child.get_string("name") {
-                            protocol_name = name.clone();
-                        }
-                    }
-                }
-            }
-            AstNodeType::Identifier => {
-                if let Some(name) = child.get_string("name") {
-                    roles.push(name.clone());
-                }
-            }
-            AstNodeType::ParameterFlow => {
-                let param_flow = extract_parameter_flow(child)?;
-                parameter_flows.push(param_flow);
-            }
-            _ => {}
+fn dfs_embedding(
+    target: &str,
+    current: &str,
+    records: &HashMap<&str, &TypeDecl>,
+    path: &mut Vec<String>,
+    visited: &mut HashSet<String>,
+) -> bool {
+    if !visited.insert(current.to_string()) {
+        return false;
+    }
+    let Some(decl) = records.get(current) else {
+        return false;
+    };
+    for field in &decl.fields {
+        let Some(embedded) = field.ty.ty().embedded_name().map(str::to_string) else {
+            continue;
+        };
+        path.push(embedded.clone());
+        if embedded == target || dfs_embedding(target, &embedded, records, path, visited) {
+            return true;
         }
+        path.pop();
     }
+    false
+}
 
-    if roles.len() < 2 {
-        roles.resize(2, "System".to_string());
+fn validate_and_update_parameter_usage(
+    interaction: &InteractionItem,
+    parameter_info: &mut HashMap<String, ParameterInfo>,
+    protocol: &Protocol,
+    report: &mut ValidationReport,
+) {
+    let action = interaction.name();
+    for flow in interaction.flows() {
+        let Some(param_info) = parameter_info.get_mut(flow.parameter.as_str()) else {
+            report.push(undeclared_parameter(flow, interaction, protocol));
+            continue;
+        };
+
+        let users = match flow.direction {
+            ParameterDirection::Out => &mut param_info.producers,
+            ParameterDirection::In => &mut param_info.consumers,
+            ParameterDirection::Nil | ParameterDirection::Opt => &mut param_info.guards,
+        };
+        users.insert(action.value.clone());
     }
+}
 
-    Ok(InteractionInfo {
-        action: protocol_name,
-        from_role: roles[0].clone(),
-        to_role: roles[1].clone(),
-        parameter_flows,
-    })
+fn undeclared_parameter(
+    flow: &Flow,
+    interaction: &InteractionItem,
+    protocol: &Protocol,
+) -> Diagnostic {
+    let diagnostic = Diagnostic::new(
+        DiagnosticCode::UndeclaredParameter,
+        format!(
+            "Parameter '{}' used in interaction '{}' is not declared in protocol '{}'",
+            flow.parameter,
+            interaction.name(),
+            protocol.name
+        ),
+    )
+    .with_label(
+        flow.parameter.span,
+        "not declared in the parameters section",
+    );
+    let declared = protocol.parameters.iter().map(|param| param.name.as_str());
+    match similar_name(flow.parameter.as_str(), declared) {
+        Some(similar) => diagnostic.with_replacement(
+            format!("did you mean `{}`?", similar),
+            flow.parameter.span,
+            similar,
+        ),
+        None => diagnostic.with_help(format!(
+            "declare '{}' in the parameters section",
+            flow.parameter
+        )),
+    }
 }
 
-fn extract_parameter_flow(node: &AstNode) -> Result<ParameterFlow> {
-    let mut direction = "unknown".to_string();
-    let mut parameter = "unknown".to_string();
+#[derive(Debug, Clone)]
+struct ParameterInfo {
+    producers: HashSet<String>,
+    consumers: HashSet<String>,
+    /// Interactions adorning the parameter with `nil` or `opt`
+    guards: HashSet<String>,
+    /// A key or an `in` parameter of the signature, which the enactment can provide
+    input: bool,
+}
 
-    if let Some(dir) = node.get_string("direction") {
-        direction = dir.clone();
+impl ParameterInfo {
+    fn is_unused(&self) -> bool {
+        self.producers.is_empty() && self.consumers.is_empty() && self.guards.is_empty()
     }
 
-    for child in &node.children {
-        if child.node_type == AstNodeType::Identifier {
-            if let Some(name) = child.get_string("name") {
-                parameter = name.clone();
-            }
-        }
+    /// Consumed without any interaction producing it or the enactment providing it
+    fn is_unproduced(&self) -> bool {
+        self.producers.is_empty() && !self.input
     }
+}
 
-    Ok(ParameterFlow {
-        direction,
-        parameter,
-    })
+/// Role sending an interaction; an enactment counts its first role argument
+fn sender(interaction: &InteractionItem) -> &str {
+    interaction.roles().first().copied().unwrap_or("System")
+}
+
+/// Role receiving an interaction; an enactment counts its second role argument
+fn receiver(interaction: &InteractionItem) -> &str {
+    interaction.roles().get(1).copied().unwrap_or("System")
 }
 
 /// Validates that no interactions are unreachable due to consuming unproducible parameters
 fn validate_unreachable_interactions(
     parameters: &HashMap<String, ParameterInfo>,
-    interactions: &[InteractionInfo],
-    protocol_name: &str,
-) -> Result<()> {
+    protocol: &Protocol,
+    report: &mut ValidationReport,
+) {
     // Check each interaction for unreachable conditions
-    for interaction in interactions {
-        for flow in &interaction.parameter_flows {
-            if flow.direction == "in" {
-                if let Some(param_info) = parameters.get(&flow.parameter) {
-                    // If parameter has no producers and is not pre-protocol knowledge,
-                    // then this interaction is unreachable
-                    if param_info.producers.is_empty()
-                        && !is_pre_protocol_parameter(&flow.parameter)
-                    {
-                        return Err(anyhow!(
-                            "Interaction '{}' is unreachable because parameter '{}' is consumed but never produced in protocol '{}' - BSPL completeness violation",
-                            interaction.action, flow.parameter, protocol_name
-                        ));
-                    }
+    for interaction in &protocol.interactions {
+        for flow in interaction.flows() {
+            if flow.direction == ParameterDirection::In
+                && let Some(param_info) = parameters.get(flow.parameter.as_str())
+                // If parameter has no producers and is not a key known before the enactment,
+                // then this interaction is unreachable
+                && param_info.is_unproduced()
+            {
+                let mut diagnostic = Diagnostic::new(
+                    DiagnosticCode::UnproducedParameter,
+                    format!(
+                        "Interaction '{}' is unreachable because parameter '{}' is consumed but never produced in protocol '{}' - BSPL completeness violation",
+                        interaction.name(),
+                        flow.parameter,
+                        protocol.name
+                    ),
+                )
+                .with_label(flow.parameter.span, "no interaction produces this parameter")
+                .with_help(format!(
+                    "add `out {}` to an interaction enabled before '{}', or declare '{}' as a key",
+                    flow.parameter,
+                    interaction.name(),
+                    flow.parameter
+                ));
+                if let Some(param) = protocol.parameter(flow.parameter.as_str()) {
+                    diagnostic = diagnostic.with_note_at(param.name.span, "declared here");
                 }
+                report.push(diagnostic);
             }
         }
     }
-
-    Ok(())
 }
 
 /// Validates basic flow consistency according to BSPL rules with parallel process support
 fn validate_flow_consistency(
     parameters: &HashMap<String, ParameterInfo>,
-    interactions: &[InteractionInfo],
-    protocol_name: &str,
-) -> Result<()> {
-    for (param_name, param_info) in parameters {
-        // Check for multiple producers - but allow parallel branches
-        if param_info.producers.len() > 1 {
-            // Check if these are legitimate parallel branches
-            if !is_valid_parallel_production(param_name, &param_info.producers, interactions) {
-                let producers: Vec<String> = param_info.producers.iter().cloned().collect();
-                return Err(anyhow!(
-                    "Parameter '{}' is produced by multiple interactions {:?} in protocol '{}' - BSPL safety violation",
-                    param_name, producers, protocol_name
-                ));
+    protocol: &Protocol,
+    report: &mut ValidationReport,
+) {
+    let interactions = &protocol.interactions;
+    validate_adornment_conflicts(interactions, protocol.name.as_str(), report);
+
+    // Parameters in declaration order so that diagnostics come out in a stable order
+    for param in &protocol.parameters {
+        let param_name = param.name.as_str();
+        let Some(param_info) = parameters.get(param_name) else {
+            continue;
+        };
+        // Several producers are safe only when no enactment can fire two of them
+        let Some((first, second)) =
+            conflicting_producers(&param_info.producers, interactions, parameters)
+        else {
+            continue;
+        };
+        let mut diagnostic = Diagnostic::new(
+            DiagnosticCode::MultipleProducers,
+            format!(
+                "Parameter '{}' is produced by multiple interactions in protocol '{}': '{}' and '{}' can both be enacted - BSPL safety violation",
+                param_name,
+                protocol.name,
+                first.name(),
+                second.name()
+            ),
+        );
+        let flows: Vec<&Flow> = [first, second]
+            .into_iter()
+            .flat_map(|i| i.flows())
+            .filter(|flow| {
+                flow.parameter.as_str() == param_name && flow.direction == ParameterDirection::Out
+            })
+            .collect();
+        if let Some((last, earlier)) = flows.split_last() {
+            diagnostic = diagnostic.with_label(last.parameter.span, "produced again here");
+            for flow in earlier {
+                diagnostic = diagnostic.with_note_at(flow.parameter.span, "also produced here");
             }
         }
+        let reason = if sender(first) == sender(second) {
+            format!(
+                "both are sent by '{}' and neither has a `nil` guard on a parameter the other makes known",
+                sender(first)
+            )
+        } else {
+            format!(
+                "'{}' is sent by '{}' and '{}' by '{}', and nothing they depend on rules the other out",
+                first.name(),
+                sender(first),
+                second.name(),
+                sender(second)
+            )
+        };
+        report.push(diagnostic.with_note(reason).with_help(
+            "produce the parameter in one interaction only, or make the producers mutually exclusive",
+        ));
+    }
+}
 
-        if param_info.producers.is_empty() && param_info.consumers.is_empty() {
-            println!(
-                "Warning: Parameter '{}' is declared but never used in protocol '{}'",
-                param_name, protocol_name
-            );
+/// Rejects interactions that adorn the same parameter more than once, e.g. `nil x` with `out x`
+fn validate_adornment_conflicts(
+    interactions: &[InteractionItem],
+    protocol_name: &str,
+    report: &mut ValidationReport,
+) {
+    for interaction in interactions {
+        let mut seen: HashMap<&str, &Flow> = HashMap::new();
+        for flow in interaction.flows() {
+            let previous = *seen.entry(flow.parameter.as_str()).or_insert(flow);
+            if previous.direction != flow.direction {
+                report.push(
+                    Diagnostic::new(
+                        DiagnosticCode::ConflictingAdornments,
+                        format!(
+                            "Parameter '{}' cannot be both '{}' and '{}' in interaction '{}' of protocol '{}'",
+                            flow.parameter,
+                            previous.direction,
+                            flow.direction,
+                            interaction.name(),
+                            protocol_name
+                        ),
+                    )
+                    .with_label(
+                        flow.parameter.span,
+                        format!("already adorned '{}' in this interaction", previous.direction),
+                    )
+                    .with_note_at(previous.parameter.span, "first adorned here"),
+                );
+            }
         }
     }
-
-    Ok(())
 }
 
-/// Determines if multiple producers represent valid parallel branches
-fn is_valid_parallel_production(
-    parameter_name: &str,
+/// The first two producers of a parameter, in declaration order, that cannot be shown to be
+/// mutually exclusive
+fn conflicting_producers<'a>(
     producers: &HashSet<String>,
-    interactions: &[InteractionInfo],
-) -> bool {
-    if producers.len() <= 1 {
-        return true;
-    }
-
-    // Find the producer interactions
-    let producer_interactions: Vec<&InteractionInfo> = interactions
+    interactions: &'a [InteractionItem],
+    parameters: &HashMap<String, ParameterInfo>,
+) -> Option<(&'a InteractionItem, &'a InteractionItem)> {
+    let producing: Vec<&InteractionItem> = interactions
         .iter()
-        .filter(|i| producers.contains(&i.action))
+        .filter(|i| producers.contains(i.name().as_str()))
         .collect();
-
-    if producer_interactions.is_empty() {
-        return false;
-    }
-
-    // Check if all producers originate from the same role (parallel branch pattern)
-    let first_role = &producer_interactions[0].from_role;
-    let same_origin_role = producer_interactions
+    let mut exclusion = Exclusion::new(interactions, parameters);
+    producing
         .iter()
-        .all(|i| &i.from_role == first_role);
+        .enumerate()
+        .flat_map(|(n, first)| {
+            producing[n + 1..]
+                .iter()
+                .map(move |second| (*first, *second))
+        })
+        .find(|(first, second)| !exclusion.mutually_exclusive(first, second))
+}
 
-    if !same_origin_role {
-        return false;
+/// Proves that two interactions never both happen in one enactment.
+///
+/// Two interactions are mutually exclusive when
+/// - they are sent by the same role and each has a `nil` guard on a parameter known once the
+///   other one has happened, so the role never sends the second after the first, or
+/// - each depends on a parameter, and those two parameters are only produced by mutually
+///   exclusive interactions, so the enabling conditions are disjoint.
+///
+/// Anything else, e.g. two unguarded producers of the same role, may both fire.
+struct Exclusion<'a> {
+    interactions: &'a [InteractionItem],
+    parameters: &'a HashMap<String, ParameterInfo>,
+    /// Pairs whose proof is under way, taken as not exclusive to cut recursion
+    pending: HashSet<(String, String)>,
+    decided: HashMap<(String, String), bool>,
+}
+
+impl<'a> Exclusion<'a> {
+    fn new(
+        interactions: &'a [InteractionItem],
+        parameters: &'a HashMap<String, ParameterInfo>,
+    ) -> Self {
+        Self {
+            interactions,
+            parameters,
+            pending: HashSet::new(),
+            decided: HashMap::new(),
+        }
     }
 
-    // Check if producers have independent enabling conditions
-    // (no shared input parameters beyond pre-protocol knowledge)
-    let mut shared_inputs = HashSet::new();
-    let mut first_iteration = true;
+    fn mutually_exclusive(&mut self, a: &InteractionItem, b: &InteractionItem) -> bool {
+        let (a_name, b_name) = (a.name().as_str(), b.name().as_str());
+        if a_name == b_name {
+            return false;
+        }
+        let pair = if a_name < b_name {
+            (a_name.to_string(), b_name.to_string())
+        } else {
+            (b_name.to_string(), a_name.to_string())
+        };
+        if let Some(exclusive) = self.decided.get(&pair) {
+            return *exclusive;
+        }
+        if !self.pending.insert(pair.clone()) {
+            return false;
+        }
+
+        let known_a = self.known_after(a);
+        let known_b = self.known_after(b);
+        let exclusive = (sender(a) == sender(b)
+            && guarded_against(b, &known_a)
+            && guarded_against(a, &known_b))
+            || known_a.iter().any(|y| {
+                known_b
+                    .iter()
+                    .any(|w| y != w && self.exclusive_parameters(y, w))
+            });
 
-    for producer in &producer_interactions {
-        let mut current_inputs = HashSet::new();
+        self.pending.remove(&pair);
+        self.decided.insert(pair, exclusive);
+        exclusive
+    }
 
-        for flow in &producer.parameter_flows {
-            if flow.direction == "in" && !is_pre_protocol_parameter(&flow.parameter) {
-                current_inputs.insert(flow.parameter.clone());
-            }
+    /// Whether no enactment can bind both parameters: every producer of one excludes every
+    /// producer of the other
+    fn exclusive_parameters(&mut self, y: &str, w: &str) -> bool {
+        let (Some(y_info), Some(w_info)) = (self.parameters.get(y), self.parameters.get(w)) else {
+            return false;
+        };
+        if y_info.producers.is_empty() || w_info.producers.is_empty() {
+            return false;
         }
+        let interactions = self.interactions;
+        let producers_of = |info: &ParameterInfo| -> Vec<&'a InteractionItem> {
+            interactions
+                .iter()
+                .filter(|i| info.producers.contains(i.name().as_str()))
+                .collect()
+        };
+        let (y_producers, w_producers) = (producers_of(y_info), producers_of(w_info));
+        y_producers
+            .iter()
+            .all(|p| w_producers.iter().all(|q| self.mutually_exclusive(p, q)))
+    }
 
-        if first_iteration {
-            shared_inputs = current_inputs;
-            first_iteration = false;
-        } else {
-            shared_inputs = shared_inputs
-                .intersection(&current_inputs)
-                .cloned()
+    /// Parameters known once `interaction` has happened: those known whenever it is enabled
+    /// and those it produces, in a stable order
+    fn known_after(&self, interaction: &InteractionItem) -> BTreeSet<String> {
+        let mut known: BTreeSet<String> =
+            necessarily_known(interaction, self.parameters, self.interactions)
+                .into_keys()
                 .collect();
-        }
+        known.extend(
+            interaction
+                .flows()
+                .into_iter()
+                .filter(|flow| flow.direction == ParameterDirection::Out)
+                .map(|flow| flow.parameter.value.clone()),
+        );
+        known
     }
+}
 
-    // Valid parallel branches should share minimal input dependencies
-    // or have the same input (like broadcasting the same initial data)
-    true
+/// Whether `interaction` requires one of the `known` parameters to be unknown to its
+/// sender: a `nil` parameter, or an `out` parameter of a message. Two messages of one
+/// sender producing the same parameter therefore exclude each other.
+fn guarded_against(interaction: &InteractionItem, known: &BTreeSet<String>) -> bool {
+    let message = matches!(interaction, InteractionItem::Interaction(_));
+    interaction.flows().into_iter().any(|flow| {
+        (flow.direction == ParameterDirection::Nil
+            || (message && flow.direction == ParameterDirection::Out))
+            && known.contains(flow.parameter.as_str())
+    })
 }
 
 /// Validates causality constraints according to BSPL with parallel branch support
 fn validate_causality(
     parameters: &HashMap<String, ParameterInfo>,
-    interactions: &[InteractionInfo],
+    interactions: &[InteractionItem],
     protocol_name: &str,
-) -> Result<()> {
+    report: &mut ValidationReport,
+) {
     // Build precedence graph considering parallel branches
     let mut precedence_graph: HashMap<String, Vec<String>> = HashMap::new();
 
     for interaction in interactions {
-        precedence_graph.insert(interaction.action.clone(), Vec::new());
+        precedence_graph.insert(interaction.name().value.clone(), Vec::new());
     }
 
     // Build precedence relationships with parallel branch awareness
     for interaction in interactions {
-        for flow in &interaction.parameter_flows {
-            if flow.direction == "in" {
-                if let Some(param_info) = parameters.get(&flow.parameter) {
-                    for producer in &param_info.producers {
-                        if producer != &interaction.action {
-                            // Check if this creates a valid precedence or parallel relationship
-                            if !is_parallel_branch_relationship(
-                                producer,
-                                &interaction.action,
-                                interactions,
-                                parameters,
-                            ) {
-                                precedence_graph
-                                    .entry(producer.clone())
-                                    .or_default()
-                                    .push(interaction.action.clone());
-                            }
+        for flow in interaction.flows() {
+            if flow.direction == ParameterDirection::In
+                && let Some(param_info) = parameters.get(flow.parameter.as_str())
+            {
+                for producer in &param_info.producers {
+                    if producer != interaction.name().as_str() {
+                        // Check if this creates a valid precedence or parallel relationship
+                        if !is_parallel_branch_relationship(
+                            producer,
+                            interaction.name().as_str(),
+                            interactions,
+                            parameters,
+                        ) {
+                            precedence_graph
+                                .entry(producer.clone())
+                                .or_default()
+                                .push(interaction.name().value.clone());
                         }
                     }
                 }
@@ -499,7 +784,7 @@ fn validate_causality(
     let mut ordered_interactions = Vec::new();
 
     for interaction in interactions {
-        in_degree.insert(interaction.action.clone(), 0);
+        in_degree.insert(interaction.name().value.clone(), 0);
     }
 
     for successors in precedence_graph.values() {
@@ -532,51 +817,166 @@ fn validate_causality(
     if ordered_interactions.len() != interactions.len() {
         let remaining: Vec<String> = interactions
             .iter()
-            .map(|i| i.action.clone())
+            .map(|i| i.name().value.clone())
             .filter(|action| !ordered_interactions.contains(action))
             .collect();
 
-        let cycle_path = find_cycle_path(&precedence_graph, &remaining);
+        let cycle = find_cycle_path(&precedence_graph, &remaining);
+        let mut diagnostic = Diagnostic::new(
+            DiagnosticCode::CausalCycle,
+            format!(
+                "Circular dependency detected in protocol '{}': {} - BSPL causality violation",
+                protocol_name,
+                cycle.join(" -> ")
+            ),
+        );
 
-        return Err(anyhow!(
-            "Circular dependency detected in protocol '{}': {} - BSPL causality violation",
-            protocol_name,
-            cycle_path
-        ));
+        // Underline the input of the second interaction that waits on the first one
+        let dependency = match cycle.as_slice() {
+            [producer, consumer, ..] => interactions
+                .iter()
+                .find(|i| i.name().as_str() == consumer)
+                .and_then(|i| {
+                    i.flows().into_iter().find(|flow| {
+                        flow.direction == ParameterDirection::In
+                            && parameters
+                                .get(flow.parameter.as_str())
+                                .is_some_and(|p| p.producers.contains(producer))
+                    })
+                })
+                .map(|flow| (flow.parameter.span, format!("waits on '{}'", producer))),
+            _ => None,
+        };
+
+        if let Some((span, label)) = dependency {
+            diagnostic = diagnostic.with_label(span, label);
+        }
+        report.push(diagnostic);
+    }
+
+    validate_nil_guards(parameters, interactions, protocol_name, report);
+}
+
+/// Rejects interactions whose `nil` parameters are necessarily known once their `in`
+/// parameters are, since such interactions can never be enabled
+fn validate_nil_guards(
+    parameters: &HashMap<String, ParameterInfo>,
+    interactions: &[InteractionItem],
+    protocol_name: &str,
+    report: &mut ValidationReport,
+) {
+    for interaction in interactions {
+        let known = necessarily_known(interaction, parameters, interactions);
+
+        for flow in interaction.flows() {
+            if flow.direction == ParameterDirection::Nil
+                && let Some(via) = known.get(flow.parameter.as_str())
+            {
+                report.push(
+                    Diagnostic::new(
+                        DiagnosticCode::UnsatisfiableNilGuard,
+                        format!(
+                            "Interaction '{}' can never be enabled in protocol '{}': it requires '{}' to be unknown, but '{}' is always known once '{}' is - BSPL causality violation",
+                            interaction.name(),
+                            protocol_name,
+                            flow.parameter,
+                            flow.parameter,
+                            via
+                        ),
+                    )
+                    .with_label(flow.parameter.span, format!("always known once '{}' is", via)),
+                );
+            }
+        }
+    }
+}
+
+/// Parameters that are known whenever `interaction` is enabled, each mapped to the `in`
+/// parameter of the interaction that implies it.
+///
+/// A parameter is implied by a known one when every producer of the known parameter also
+/// consumes or produces it.
+fn necessarily_known(
+    interaction: &InteractionItem,
+    parameters: &HashMap<String, ParameterInfo>,
+    interactions: &[InteractionItem],
+) -> HashMap<String, String> {
+    let mut known: HashMap<String, String> = HashMap::new();
+    let mut pending: Vec<(String, String)> = interaction
+        .flows()
+        .iter()
+        .filter(|flow| flow.direction == ParameterDirection::In)
+        .map(|flow| (flow.parameter.value.clone(), flow.parameter.value.clone()))
+        .collect();
+
+    while let Some((parameter, via)) = pending.pop() {
+        if known.contains_key(&parameter) {
+            continue;
+        }
+        known.insert(parameter.clone(), via.clone());
+
+        let Some(info) = parameters.get(&parameter) else {
+            continue;
+        };
+        let producers: Vec<&InteractionItem> = interactions
+            .iter()
+            .filter(|i| {
+                info.producers.contains(i.name().as_str()) && i.name() != interaction.name()
+            })
+            .collect();
+        let Some((first, rest)) = producers.split_first() else {
+            continue;
+        };
+
+        for flow in first.flows() {
+            let bound = |i: &InteractionItem| {
+                i.flows().into_iter().any(|f| {
+                    f.parameter == flow.parameter
+                        && (f.direction == ParameterDirection::In
+                            || f.direction == ParameterDirection::Out)
+                })
+            };
+            if (flow.direction == ParameterDirection::In
+                || flow.direction == ParameterDirection::Out)
+                && rest.iter().all(|i| bound(i))
+            {
+                pending.push((flow.parameter.value.clone(), via.clone()));
+            }
+        }
     }
 
-    Ok(())
+    known
 }
 
 /// Determines if two interactions represent parallel branches rather than sequential dependency
 fn is_parallel_branch_relationship(
     producer: &str,
     consumer: &str,
-    interactions: &[InteractionInfo],
+    interactions: &[InteractionItem],
     parameters: &HashMap<String, ParameterInfo>,
 ) -> bool {
-    let producer_interaction = interactions.iter().find(|i| i.action == producer);
-    let consumer_interaction = interactions.iter().find(|i| i.action == consumer);
+    let producer_interaction = interactions.iter().find(|i| i.name().as_str() == producer);
+    let consumer_interaction = interactions.iter().find(|i| i.name().as_str() == consumer);
 
     if let (Some(prod), Some(cons)) = (producer_interaction, consumer_interaction) {
         // Check if they originate from the same role and target different roles (parallel dispatch)
-        if prod.from_role == cons.from_role && prod.to_role != cons.to_role {
+        if sender(prod) == sender(cons) && receiver(prod) != receiver(cons) {
             // Check if they produce the same parameter (parallel branches)
-            let prod_outputs: HashSet<&String> = prod
-                .parameter_flows
+            let prod_outputs: HashSet<&str> = prod
+                .flows()
                 .iter()
-                .filter(|f| f.direction == "out")
-                .map(|f| &f.parameter)
+                .filter(|f| f.direction == ParameterDirection::Out)
+                .map(|f| f.parameter.as_str())
                 .collect();
 
-            let cons_inputs: HashSet<&String> = cons
-                .parameter_flows
+            let cons_inputs: HashSet<&str> = cons
+                .flows()
                 .iter()
-                .filter(|f| f.direction == "in")
-                .map(|f| &f.parameter)
+                .filter(|f| f.direction == ParameterDirection::In)
+                .map(|f| f.parameter.as_str())
                 .collect();
 
-            let shared_params: HashSet<&String> =
+            let shared_params: HashSet<&str> =
                 prod_outputs.intersection(&cons_inputs).cloned().collect();
 
             // If they share parameters, check if this is a broadcast scenario
@@ -587,8 +987,8 @@ fn is_parallel_branch_relationship(
                         if param_info.producers.len() > 1 {
                             let producers_from_same_role = interactions
                                 .iter()
-                                .filter(|i| param_info.producers.contains(&i.action))
-                                .map(|i| &i.from_role)
+                                .filter(|i| param_info.producers.contains(i.name().as_str()))
+                                .map(sender)
                                 .collect::<HashSet<_>>();
 
                             if producers_from_same_role.len() == 1 {
@@ -604,19 +1004,22 @@ fn is_parallel_branch_relationship(
     false
 }
 
-fn find_cycle_path(graph: &HashMap<String, Vec<String>>, remaining_nodes: &[String]) -> String {
+fn find_cycle_path(
+    graph: &HashMap<String, Vec<String>>,
+    remaining_nodes: &[String],
+) -> Vec<String> {
     if remaining_nodes.is_empty() {
-        return "unknown cycle".to_string();
+        return vec!["unknown cycle".to_string()];
     }
 
     let mut visited = HashSet::new();
     let mut path = Vec::new();
 
     if let Some(cycle) = dfs_find_cycle(&remaining_nodes[0], graph, &mut visited, &mut path) {
-        return cycle.join(" -> ");
+        return cycle;
     }
 
-    remaining_nodes.join(" -> ")
+    remaining_nodes.to_vec()
 }
 
 fn dfs_find_cycle(
@@ -651,232 +1054,572 @@ fn dfs_find_cycle(
     None
 }
 
-/// Validates protocol completeness according to BSPL
+/// Warns about parameters that are never used, or produced without anything consuming them
 fn validate_completeness(
+    parameters: &HashMap<String, ParameterInfo>,
+    protocol: &Protocol,
+    report: &mut ValidationReport,
+) {
+    for param in &protocol.parameters {
+        let Some(param_info) = parameters.get(param.name.as_str()) else {
+            continue;
+        };
+
+        if param_info.is_unused() {
+            report.push(
+                Diagnostic::new(
+                    DiagnosticCode::UnusedParameter,
+                    format!(
+                        "Parameter '{}' is declared but never used in protocol '{}'",
+                        param.name, protocol.name
+                    ),
+                )
+                .with_label(param.name.span, "never used")
+                .with_help("use it in an interaction or remove the declaration"),
+            );
+        } else if !param_info.producers.is_empty() && param_info.consumers.is_empty() {
+            report.push(
+                Diagnostic::new(
+                    DiagnosticCode::DeadEndParameter,
+                    format!(
+                        "Parameter '{}' is produced but never consumed in protocol '{}' - potential completeness issue",
+                        param.name, protocol.name
+                    ),
+                )
+                .with_label(param.name.span, "never consumed")
+                .with_note("this is expected when the parameter is an outcome of the protocol"),
+            );
+        }
+    }
+}
+
+/// Warns about interactions that can never be enabled because they depend, directly or
+/// through other interactions, on a parameter nothing produces
+fn validate_enactability(
+    program: &Program,
+    interactions: &[InteractionItem],
     parameters: &HashMap<String, ParameterInfo>,
     protocol_name: &str,
-) -> Result<()> {
-    let mut orphaned_parameters = Vec::new();
-    let mut dead_end_parameters = Vec::new();
+    report: &mut ValidationReport,
+) {
+    // Interactions consuming an undeclared or unproduced parameter are already errors
+    let reported: HashSet<&str> = interactions
+        .iter()
+        .filter(|interaction| {
+            interaction.flows().iter().any(|flow| {
+                flow.direction == ParameterDirection::In
+                    && parameters
+                        .get(flow.parameter.as_str())
+                        .is_none_or(ParameterInfo::is_unproduced)
+            })
+        })
+        .map(|interaction| interaction.name().as_str())
+        .collect();
 
-    for (param_name, param_info) in parameters {
-        if !param_info.producers.is_empty() && param_info.consumers.is_empty() {
-            dead_end_parameters.push(param_name.clone());
-        }
+    let knowledge = RoleKnowledge::new(program, interactions, parameters);
 
-        if param_info.producers.is_empty() && param_info.consumers.is_empty() {
-            orphaned_parameters.push(param_name.clone());
+    for interaction in interactions {
+        let action = interaction.name();
+        if knowledge.enabled.contains(action.as_str()) || reported.contains(action.as_str()) {
+            continue;
         }
-    }
-
-    if !dead_end_parameters.is_empty() {
-        println!("Warning: Parameters {:?} are produced but never consumed in protocol '{}' - potential completeness issue", 
-                dead_end_parameters, protocol_name);
-    }
 
-    if !orphaned_parameters.is_empty() {
-        println!(
-            "Warning: Parameters {:?} are never used in protocol '{}' - completeness issue",
-            orphaned_parameters, protocol_name
+        // A parameter some role learns, but not one that needs it
+        let uninformed = interaction
+            .flows()
+            .into_iter()
+            .filter(|flow| {
+                flow.direction == ParameterDirection::In
+                    && knowledge.known_anywhere(flow.parameter.as_str())
+            })
+            .find_map(|flow| {
+                knowledge
+                    .needed_by(interaction, flow.parameter.as_str())
+                    .into_iter()
+                    .find(|role| !knowledge.knows(role, flow.parameter.as_str()))
+                    .map(|role| (flow, role))
+            });
+        let Some((flow, sender)) = uninformed else {
+            report.push(
+                Diagnostic::new(
+                    DiagnosticCode::UnreachableInteraction,
+                    format!(
+                        "Interaction '{}' may be unreachable in protocol '{}'",
+                        action, protocol_name
+                    ),
+                )
+                .with_label(
+                    action.span,
+                    "no enabled interaction produces all of its inputs",
+                ),
+            );
+            continue;
+        };
+
+        let parameter = flow.parameter.as_str();
+        let mut diagnostic = Diagnostic::new(
+            DiagnosticCode::UninformedSender,
+            format!(
+                "Role '{}' cannot send '{}' in protocol '{}': it never learns '{}' - BSPL enactability violation",
+                sender, action, protocol_name, parameter
+            ),
+        )
+        .with_label(
+            flow.parameter.span,
+            format!("'{}' never learns '{}'", sender, parameter),
         );
+        let carriers = knowledge.carriers(parameter);
+        for (carrier, flow) in &carriers {
+            let receivers = carrier.roles()[1..].join("', '");
+            diagnostic = diagnostic.with_note_at(
+                flow.parameter.span,
+                format!("'{}' only reaches '{}' here", parameter, receivers),
+            );
+        }
+        let help = match carriers.first() {
+            Some((carrier, _)) => format!(
+                "'{}' must first receive a message carrying '{}', e.g. from '{}' after '{}'",
+                sender,
+                parameter,
+                carrier.roles()[1],
+                carrier.name()
+            ),
+            None => format!(
+                "'{}' must first receive a message carrying '{}'",
+                sender, parameter
+            ),
+        };
+        report.push(diagnostic.with_help(help));
     }
+}
 
-    Ok(())
+/// What each role can come to know in an enactment: keys and `in` parameters of the
+/// signature that nothing produces, which the enactment provides to every role, the
+/// parameters it produces and the ones carried by messages addressed to it. Only
+/// interactions whose roles know the `in` parameters they need are counted.
+struct RoleKnowledge<'a> {
+    interactions: &'a [InteractionItem],
+    provided: HashSet<&'a str>,
+    /// Caller roles needing each `in` parameter of an enactment, by enactment and parameter
+    enactment_inputs: HashMap<&'a str, HashMap<&'a str, Vec<&'a str>>>,
+    known: HashMap<&'a str, HashSet<&'a str>>,
+    /// Interactions their sender can send
+    enabled: HashSet<&'a str>,
 }
 
-/// Validates protocol enactability according to BSPL
-fn validate_enactability(
-    interactions: &[InteractionInfo],
-    parameters: &HashMap<String, ParameterInfo>,
-    protocol_name: &str,
-) -> Result<()> {
-    for interaction in interactions {
-        if interaction.from_role == "Unknown" || interaction.to_role == "Unknown" {
-            return Err(anyhow!(
-                "Interaction '{}' has undefined roles in protocol '{}' - enactability violation",
-                interaction.action,
-                protocol_name
-            ));
-        }
-
-        for flow in &interaction.parameter_flows {
-            if flow.direction == "in" {
-                if let Some(param_info) = parameters.get(&flow.parameter) {
-                    if param_info.producers.is_empty()
-                        && !is_pre_protocol_parameter(&flow.parameter)
-                    {
-                        return Err(anyhow!(
-                            "Interaction '{}' requires parameter '{}' but it's never produced - enactability violation",
-                            interaction.action, flow.parameter
-                        ));
-                    }
+impl<'a> RoleKnowledge<'a> {
+    fn new(
+        program: &'a Program,
+        interactions: &'a [InteractionItem],
+        parameters: &'a HashMap<String, ParameterInfo>,
+    ) -> Self {
+        let mut knowledge = Self {
+            interactions,
+            enactment_inputs: interactions
+                .iter()
+                .filter_map(|interaction| match interaction {
+                    InteractionItem::Enactment(enactment) => Some((
+                        enactment.protocol.as_str(),
+                        enactment_inputs(&program.protocols, enactment),
+                    )),
+                    InteractionItem::Interaction(_) => None,
+                })
+                .collect(),
+            provided: parameters
+                .iter()
+                .filter(|(_, info)| info.input && info.producers.is_empty())
+                .map(|(name, _)| name.as_str())
+                .collect(),
+            known: HashMap::new(),
+            enabled: HashSet::new(),
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for interaction in interactions {
+                let action = interaction.name().as_str();
+                let sender = sender(interaction);
+                if knowledge.enabled.contains(action)
+                    || !interaction
+                        .flows()
+                        .iter()
+                        .filter(|flow| flow.direction == ParameterDirection::In)
+                        .all(|flow| {
+                            let parameter = flow.parameter.as_str();
+                            knowledge
+                                .needed_by(interaction, parameter)
+                                .iter()
+                                .all(|role| knowledge.knows(role, parameter))
+                        })
+                {
+                    continue;
                 }
+
+                let carried = knowledge.carried(interaction);
+                let produced = interaction
+                    .flows()
+                    .into_iter()
+                    .filter(|flow| flow.direction == ParameterDirection::Out)
+                    .map(|flow| flow.parameter.as_str());
+                knowledge.known.entry(sender).or_default().extend(produced);
+                for receiver in interaction.roles().into_iter().skip(1) {
+                    knowledge
+                        .known
+                        .entry(receiver)
+                        .or_default()
+                        .extend(carried.iter().copied());
+                }
+                knowledge.enabled.insert(action);
+                changed = true;
             }
         }
+        knowledge
     }
 
-    let mut executable_interactions = HashSet::new();
-    let mut changed = true;
+    /// Roles that must know the `in` parameter `parameter` of `interaction` for it to
+    /// start: the sender of a message, or the roles an enactment binds to callee roles
+    /// consuming it
+    fn needed_by(&self, interaction: &'a InteractionItem, parameter: &str) -> Vec<&'a str> {
+        self.enactment_inputs
+            .get(interaction.name().as_str())
+            .and_then(|inputs| inputs.get(parameter))
+            .cloned()
+            .unwrap_or_else(|| vec![sender(interaction)])
+    }
 
-    for interaction in interactions {
-        let has_unresolved_deps = interaction
-            .parameter_flows
-            .iter()
-            .filter(|flow| flow.direction == "in")
-            .any(|flow| {
-                if let Some(param_info) = parameters.get(&flow.parameter) {
-                    param_info.producers.is_empty() && !is_pre_protocol_parameter(&flow.parameter)
-                } else {
-                    true
-                }
-            });
+    fn knows(&self, role: &str, parameter: &str) -> bool {
+        self.provided.contains(parameter)
+            || self
+                .known
+                .get(role)
+                .is_some_and(|known| known.contains(parameter))
+    }
 
-        if !has_unresolved_deps {
-            executable_interactions.insert(interaction.action.clone());
-        }
+    fn known_anywhere(&self, parameter: &str) -> bool {
+        self.known.values().any(|known| known.contains(parameter))
     }
 
-    while changed {
-        changed = false;
-        for interaction in interactions {
-            if !executable_interactions.contains(&interaction.action) {
-                let can_execute = interaction
-                    .parameter_flows
-                    .iter()
-                    .filter(|flow| flow.direction == "in")
-                    .all(|flow| {
-                        if let Some(param_info) = parameters.get(&flow.parameter) {
-                            if is_pre_protocol_parameter(&flow.parameter) {
-                                return true;
-                            }
-                            param_info
-                                .producers
-                                .iter()
-                                .any(|producer| executable_interactions.contains(producer))
-                        } else {
-                            false
-                        }
-                    });
+    /// Parameters a message carries: its `in` and `out` ones, and the `opt` ones its sender
+    /// knows
+    fn carried(&self, interaction: &'a InteractionItem) -> Vec<&'a str> {
+        let sender = sender(interaction);
+        interaction
+            .flows()
+            .into_iter()
+            .filter(|flow| match flow.direction {
+                ParameterDirection::In | ParameterDirection::Out => true,
+                ParameterDirection::Opt => self.knows(sender, flow.parameter.as_str()),
+                ParameterDirection::Nil => false,
+            })
+            .map(|flow| flow.parameter.as_str())
+            .collect()
+    }
 
-                if can_execute {
-                    executable_interactions.insert(interaction.action.clone());
-                    changed = true;
+    /// Enabled messages carrying `parameter`, with the flow that carries it
+    fn carriers(&self, parameter: &str) -> Vec<(&'a InteractionItem, &'a Flow)> {
+        self.interactions
+            .iter()
+            .filter(|i| self.enabled.contains(i.name().as_str()) && i.roles().len() > 1)
+            .filter_map(|i| {
+                i.flows()
+                    .into_iter()
+                    .find(|flow| {
+                        flow.parameter.as_str() == parameter
+                            && matches!(
+                                flow.direction,
+                                ParameterDirection::In
+                                    | ParameterDirection::Out
+                                    | ParameterDirection::Opt
+                            )
+                    })
+                    .map(|flow| (i, flow))
+            })
+            .collect()
+    }
+}
+
+/// Caller roles of an enactment needing each of its `in` parameters: those bound to the
+/// callee roles that consume the parameter in a message they send before any message of
+/// the callee brings it to them. Parameters no callee role needs are left out, as are all
+/// of them when the callee is unknown or the bindings do not resolve, which composition
+/// checks report.
+pub(crate) fn enactment_inputs<'a>(
+    protocols: &'a [Protocol],
+    enactment: &'a Enactment,
+) -> HashMap<&'a str, Vec<&'a str>> {
+    let mut inputs: HashMap<&str, Vec<&str>> = HashMap::new();
+    let Some(callee) = protocols
+        .iter()
+        .find(|protocol| protocol.name.as_str() == enactment.protocol.as_str())
+    else {
+        return inputs;
+    };
+    let Ok(bindings) = bind_enactment(enactment, callee) else {
+        return inputs;
+    };
+    let caller_role = |callee_role: &str| {
+        let bound = bindings.roles.get(callee_role)?;
+        enactment
+            .roles()
+            .map(|role| role.name.as_str())
+            .find(|role| role == bound)
+    };
+
+    // Callee roles needing each callee parameter, following the messages in source order
+    let mut learned: HashSet<(&str, &str)> = HashSet::new();
+    let mut needed: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for interaction in &callee.interactions {
+        let sender = sender(interaction);
+        for flow in interaction.flows() {
+            let parameter = flow.parameter.as_str();
+            if flow.direction == ParameterDirection::In && !learned.contains(&(sender, parameter)) {
+                needed.entry(parameter).or_default().insert(sender);
+            }
+        }
+        for role in interaction.roles() {
+            for flow in interaction.flows() {
+                if matches!(
+                    flow.direction,
+                    ParameterDirection::In | ParameterDirection::Out
+                ) {
+                    learned.insert((role, flow.parameter.as_str()));
                 }
             }
         }
     }
 
-    for interaction in interactions {
-        if !executable_interactions.contains(&interaction.action) {
-            println!(
-                "Warning: Interaction '{}' may be unreachable in protocol '{}'",
-                interaction.action, protocol_name
-            );
+    for flow in enactment.flows() {
+        if flow.direction != ParameterDirection::In {
+            continue;
+        }
+        let Some(roles) = needed.get(flow.bound_name()) else {
+            continue;
+        };
+        let mut roles: Vec<&str> = roles.iter().filter_map(|role| caller_role(role)).collect();
+        roles.sort_unstable();
+        roles.dedup();
+        if !roles.is_empty() {
+            inputs.insert(flow.parameter.as_str(), roles);
         }
     }
-
-    Ok(())
+    inputs
 }
 
-/// Determines if a parameter represents pre-protocol knowledge
-fn is_pre_protocol_parameter(param_name: &str) -> bool {
-    matches!(
-        param_name.to_uppercase().as_str(),
-        "ID" | "TIMESTAMP" | "NONCE" | "SESSION_ID"
-    )
-}
-
-/// Additional BSPL validation for protocol composition
+/// Additional BSPL validation for protocol composition, failing with the first error found
 pub fn validate_protocol_composition(ast: &AstNode) -> Result<()> {
-    if ast.node_type != AstNodeType::Program {
-        return Err(anyhow!("Expected Program node"));
-    }
+    let program = Program::from_ast(ast)?;
+    let mut report = ValidationReport::new();
+    check_compositions(&program, &mut report);
+    report.into_result().map(drop)
+}
 
-    let mut protocol_registry: HashMap<String, AstNode> = HashMap::new();
+fn check_compositions(program: &Program, report: &mut ValidationReport) {
+    let protocol_registry: HashMap<&str, &Protocol> = program
+        .protocols
+        .iter()
+        .map(|protocol| (protocol.name.as_str(), protocol))
+        .collect();
 
-    for protocol_node in &ast.children {
-        if protocol_node.node_type == AstNodeType::Protocol {
-            let protocol_name = extract_protocol_name(protocol_node)?;
-            protocol_registry.insert(protocol_name, (**protocol_node).clone());
+    for protocol in &program.protocols {
+        for diagnostic in validate_signature(protocol) {
+            report.push(diagnostic);
         }
-    }
-
-    for protocol_node in &ast.children {
-        if protocol_node.node_type == AstNodeType::Protocol {
-            validate_composition_references(protocol_node, &protocol_registry)?;
+        for enactment in protocol.enactments() {
+            validate_single_composition(program, enactment, protocol, &protocol_registry, report);
         }
     }
 
-    Ok(())
+    let mut finished = HashSet::new();
+    for protocol in &program.protocols {
+        validate_enactment_cycles(
+            protocol,
+            &protocol_registry,
+            &mut Vec::new(),
+            &mut finished,
+            report,
+        );
+    }
 }
 
-fn validate_composition_references(
-    protocol_node: &AstNode,
-    protocol_registry: &HashMap<String, AstNode>,
-) -> Result<()> {
-    let protocol_name = extract_protocol_name(protocol_node)?;
+/// Reports each cycle of enactments through other protocols once, at the enactment closing
+/// it. `enacting` holds the protocols being walked, each with the enactment leading to the
+/// next one; protocols enacting themselves directly are reported on their own.
+fn validate_enactment_cycles<'a>(
+    protocol: &'a Protocol,
+    protocol_registry: &HashMap<&str, &'a Protocol>,
+    enacting: &mut Vec<(&'a str, Option<&'a Enactment>)>,
+    finished: &mut HashSet<&'a str>,
+    report: &mut ValidationReport,
+) {
+    let name = protocol.name.as_str();
+    if finished.contains(name) {
+        return;
+    }
+    enacting.push((name, None));
 
-    for child in &protocol_node.children {
-        if child.node_type == AstNodeType::InteractionSection {
-            for interaction_item in &child.children {
-                if interaction_item.node_type == AstNodeType::InteractionItem {
-                    for item_child in &interaction_item.children {
-                        if item_child.node_type == AstNodeType::ProtocolComposition {
-                            validate_single_composition(
-                                item_child,
-                                protocol_registry,
-                                &protocol_name,
-                            )?;
-                        }
-                    }
-                }
+    for enactment in protocol.enactments() {
+        let callee = enactment.protocol.as_str();
+        if callee == name {
+            continue;
+        }
+        if let Some(start) = enacting.iter().position(|(enacted, _)| *enacted == callee) {
+            let path: Vec<String> = enacting[start..]
+                .iter()
+                .map(|(enacted, _)| enacted.to_string())
+                .collect();
+            let mut diagnostic = Diagnostic::new(
+                DiagnosticCode::RecursiveEnactment,
+                format!(
+                    "Protocol '{}' enacts itself through {} - recursive enactment not allowed",
+                    callee,
+                    enactment_path(&path, callee)
+                ),
+            )
+            .with_label(enactment.protocol.span, "closes the cycle");
+            for (enacted, through) in &enacting[start..enacting.len() - 1] {
+                let through = through.expect("every protocol but the last enacts the next");
+                diagnostic = diagnostic.with_note_at(
+                    through.protocol.span,
+                    format!("'{}' enacts '{}' here", enacted, through.protocol),
+                );
             }
+            report.push(diagnostic);
+            continue;
         }
+        let Some(referenced_protocol) = protocol_registry.get(callee) else {
+            continue;
+        };
+        enacting.last_mut().expect("pushed above").1 = Some(enactment);
+        validate_enactment_cycles(
+            referenced_protocol,
+            protocol_registry,
+            enacting,
+            finished,
+            report,
+        );
     }
 
-    Ok(())
+    enacting.pop();
+    finished.insert(name);
 }
 
-fn validate_single_composition(
-    composition_node: &AstNode,
-    protocol_registry: &HashMap<String, AstNode>,
+/// Checks that every key of the enacted protocol is bound by a parameter of the enactment,
+/// returning whether they all are
+fn validate_composition_keys(
+    enactment: &Enactment,
+    referenced_protocol: &Protocol,
     parent_protocol_name: &str,
-) -> Result<()> {
-    let mut referenced_protocol_name = None;
-
-    for child in &composition_node.children {
-        if child.node_type == AstNodeType::ProtocolReference {
-            for ref_child in &child.children {
-                if ref_child.node_type == AstNodeType::Identifier {
-                    if let Some(name) = ref_child.get_string("name") {
-                        referenced_protocol_name = Some(name.clone());
-                    }
-                }
-            }
+    report: &mut ValidationReport,
+) -> bool {
+    let bound: HashSet<&str> = enactment.flows().map(|flow| flow.bound_name()).collect();
+
+    let mut all_bound = true;
+    for key in referenced_protocol.keys() {
+        if !bound.contains(key.name.as_str()) {
+            all_bound = false;
+            report.push(
+                Diagnostic::new(
+                    DiagnosticCode::UnboundKey,
+                    format!(
+                        "Enactment of '{}' in protocol '{}' does not bind its key parameter '{}'",
+                        referenced_protocol.name, parent_protocol_name, key.name
+                    ),
+                )
+                .with_label(
+                    enactment.protocol.span,
+                    format!("key '{}' is not bound", key.name),
+                )
+                .with_note_at(key.name.span, "key declared here")
+                .with_help(format!("pass `in {}` to the enactment", key.name)),
+            );
         }
     }
+    all_bound
+}
 
-    if let Some(ref_name) = referenced_protocol_name {
-        if !protocol_registry.contains_key(&ref_name) {
-            return Err(anyhow!(
-                "Protocol '{}' references unknown protocol '{}' in composition",
-                parent_protocol_name,
-                ref_name
-            ));
-        }
+/// Checks that every parameter bound by an enactment has the same type in both protocols
+fn validate_composition_types(
+    enactment: &Enactment,
+    parent_protocol: &Protocol,
+    referenced_protocol: &Protocol,
+    report: &mut ValidationReport,
+) {
+    let (_, mismatches) = enactment_types(enactment, parent_protocol, referenced_protocol);
+    for mismatch in mismatches {
+        report.push(mismatch);
+    }
+}
 
-        if ref_name == parent_protocol_name {
-            return Err(anyhow!(
-                "Protocol '{}' cannot reference itself in composition - direct recursion not allowed",
-                parent_protocol_name
-            ));
-        }
-    } else {
-        return Err(anyhow!(
-            "Protocol composition in '{}' has no valid protocol reference",
-            parent_protocol_name
-        ));
+fn unknown_protocol(
+    enactment: &Enactment,
+    parent_protocol: &Protocol,
+    program: &Program,
+) -> Diagnostic {
+    let reference_span = enactment.protocol.span;
+    let diagnostic = Diagnostic::new(
+        DiagnosticCode::UnknownProtocol,
+        format!(
+            "Protocol '{}' references unknown protocol '{}' in composition",
+            parent_protocol.name, enactment.protocol
+        ),
+    )
+    .with_label(reference_span, "no protocol with this name");
+    let known = program
+        .protocols
+        .iter()
+        .map(|protocol| protocol.name.as_str());
+    match similar_name(enactment.protocol.as_str(), known) {
+        Some(similar) => diagnostic.with_replacement(
+            format!("did you mean `{}`?", similar),
+            reference_span,
+            similar,
+        ),
+        None => diagnostic,
     }
+}
 
-    Ok(())
+fn validate_single_composition(
+    program: &Program,
+    enactment: &Enactment,
+    parent_protocol: &Protocol,
+    protocol_registry: &HashMap<&str, &Protocol>,
+    report: &mut ValidationReport,
+) {
+    let parent_protocol_name = parent_protocol.name.as_str();
+    let ref_name = enactment.protocol.as_str();
+    let reference_span = enactment.protocol.span;
+
+    let Some(referenced_protocol) = protocol_registry.get(ref_name) else {
+        report.push(unknown_protocol(enactment, parent_protocol, program));
+        return;
+    };
+
+    if ref_name == parent_protocol_name {
+        report.push(
+            Diagnostic::new(
+                DiagnosticCode::SelfEnactment,
+                format!(
+                    "Protocol '{}' cannot reference itself in composition - direct recursion not allowed",
+                    parent_protocol_name
+                ),
+            )
+            .with_label(reference_span, "self-reference"),
+        );
+        return;
+    }
+
+    // Binding would only report the same unbound keys again
+    if !validate_composition_keys(enactment, referenced_protocol, parent_protocol_name, report) {
+        return;
+    }
+    if let Err(error) = bind_enactment(enactment, referenced_protocol) {
+        report.push(match error.downcast::<Diagnostic>() {
+            Ok(diagnostic) => diagnostic,
+            Err(error) => Diagnostic::new(DiagnosticCode::InvalidBinding, error.to_string())
+                .with_label(reference_span, "invalid enactment"),
+        });
+        return;
+    }
+    validate_composition_types(enactment, parent_protocol, referenced_protocol, report);
 }
@@ -1,7 +1,12 @@
 #[cfg(test)]
 mod tests {
+    use crate::protocol::model::Program;
+    use crate::transpiler::diagnostics::{Diagnostic, DiagnosticCode};
     use crate::transpiler::parser::parse_source;
-    use crate::transpiler::validation::{validate_parameter_flow, validate_protocol_composition};
+    use crate::transpiler::validation::{
+        validate, validate_parameter_flow, validate_protocol_composition, validate_structure,
+    };
+    use crate::transpiler::verification::verify;
     use anyhow::Result;
 
     #[test]
@@ -163,7 +168,7 @@ IDProtocol <Protocol>("protocol with special ID parameter handling") {
         B <Agent>("agent B")
     
     parameters
-        ID <String>("special identifier parameter")
+        ID <String> key ("special identifier parameter")
     
     A -> B: action1 <Action>("uses ID without explicit producer")[in ID]
 }
@@ -173,12 +178,156 @@ IDProtocol <Protocol>("protocol with special ID parameter handling") {
         let result = validate_parameter_flow(&ast);
         assert!(
             result.is_ok(),
-            "ID parameter should be handled specially: {:?}",
+            "Key parameter ID should be handled specially: {:?}",
             result
         );
         Ok(())
     }
 
+    #[test]
+    fn test_custom_key_name_is_known_before_enactment() -> Result<()> {
+        let bmpp_source = r#"
+OrderProtocol <Protocol>("protocol keyed by order_id") {
+    roles
+        Buyer <Agent>("buyer"),
+        Seller <Agent>("seller")
+
+    parameters
+        order_id <String> key ("order identifier"),
+        item <String>("requested item"),
+        price <Float>("quoted price")
+
+    Buyer -> Seller: request <Action>("request a quote")[in order_id, out item]
+    Seller -> Buyer: quote <Action>("quote a price")[in order_id, in item, out price]
+}
+        "#;
+
+        let ast = parse_source(bmpp_source)?;
+        let result = validate_parameter_flow(&ast);
+        assert!(
+            result.is_ok(),
+            "Declared key 'order_id' should not need a producer: {:?}",
+            result
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_id_without_key_adornment_needs_producer() {
+        let bmpp_source = r#"
+PlainIDProtocol <Protocol>("ID is an ordinary parameter here") {
+    roles
+        A <Agent>("agent A"),
+        B <Agent>("agent B")
+
+    parameters
+        ID <String>("ordinary parameter that happens to be called ID")
+
+    A -> B: action1 <Action>("consumes ID")[in ID]
+}
+        "#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let result = validate_parameter_flow(&ast);
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("consumed but never produced")
+        );
+    }
+
+    #[test]
+    fn test_nil_guards_model_mutually_exclusive_replies() -> Result<()> {
+        let bmpp_source = r#"
+Quote <Protocol>("seller either accepts or rejects a request") {
+    roles
+        B <Agent>("buyer"),
+        S <Agent>("seller")
+
+    parameters
+        ID <String> key ("request identifier"),
+        item <String>("requested item"),
+        price <Float>("quoted price"),
+        reason <String>("rejection reason"),
+        done <Bool>("buyer closed the request")
+
+    B -> S: rfq <Action>("request a quote")[in ID, out item]
+    S -> B: accept <Action>("quote a price")[in ID, in item, nil reason, out price]
+    S -> B: reject <Action>("decline the request")[in ID, in item, nil price, out reason]
+    B -> S: close <Action>("close the request")[in ID, in item, opt price, opt reason, out done]
+}
+        "#;
+
+        let ast = parse_source(bmpp_source)?;
+        let result = validate_parameter_flow(&ast);
+        assert!(
+            result.is_ok(),
+            "nil-guarded replies should validate: {:?}",
+            result
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_nil_parameter_always_known_is_never_enabled() {
+        let bmpp_source = r#"
+Haggle <Protocol>("counter offer guarded by a parameter that is always known") {
+    roles
+        B <Agent>("buyer"),
+        S <Agent>("seller")
+
+    parameters
+        ID <String> key ("request identifier"),
+        price <Float>("quoted price"),
+        terms <String>("quote terms"),
+        counter <Float>("counter offer")
+
+    S -> B: quote <Action>("quote with terms")[in ID, out price, out terms]
+    B -> S: haggle <Action>("counter before any price")[in ID, in terms, nil price, out counter]
+}
+        "#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let result = validate_parameter_flow(&ast);
+        assert!(result.is_err());
+        let error = result.unwrap_err().to_string();
+        assert!(
+            error.contains("Interaction 'haggle' can never be enabled"),
+            "{}",
+            error
+        );
+        assert!(error.contains("'price' is always known once 'terms' is"));
+    }
+
+    #[test]
+    fn test_conflicting_adornments_in_one_interaction() {
+        let bmpp_source = r#"
+Conflict <Protocol>("parameter both nil and out") {
+    roles
+        A <Agent>("agent A"),
+        B <Agent>("agent B")
+
+    parameters
+        ID <String> key ("identifier"),
+        x <String>("parameter")
+
+    A -> B: act <Action>("contradictory adornments")[in ID, nil x, out x]
+}
+        "#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let result = validate_parameter_flow(&ast);
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Parameter 'x' cannot be both 'nil' and 'out'")
+        );
+    }
+
     #[test]
     fn test_undeclared_parameter_usage() {
         let bmpp_source = r#"
@@ -326,7 +475,7 @@ CausalityChainProtocol <Protocol>("protocol with proper causality chain") {
             result
         );
 
-        if let Ok(_) = result {
+        if result.is_ok() {
             println!("✅ Causality chain validation passed");
             println!("Execution order: step1 (A->B) → step2 (B->C) → step3 (C->A)");
             println!("Parameter flow: input → intermediate → output");
@@ -410,6 +559,126 @@ ParallelProtocol <Protocol>("protocol with parallel causality") {
         Ok(())
     }
 
+    #[test]
+    fn test_structure_errors_of_ill_formed_programs() {
+        let source = r#"
+Bad <Protocol>("ill-formed protocol") {
+    roles
+        A <Agent>("A"),
+        B <Agent>("B")
+
+    parameters
+        ID <String> key ("identifier"),
+        x <String>("x")
+
+    A -> B: go <Action>("go")[out ID, out x, out y]
+    Z -> B: go <Action>("go again")[in ID, out x]
+    Missing <Enactment>[A, B, in ID]
+}
+"#;
+        let ast = parse_source(source).unwrap();
+        let program = Program::from_ast(&ast).unwrap();
+        let report = validate_structure(&program);
+        let codes: Vec<Option<DiagnosticCode>> =
+            report.errors().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                Some(DiagnosticCode::UndeclaredRole),
+                Some(DiagnosticCode::DuplicateAction),
+                Some(DiagnosticCode::UndeclaredParameter),
+                Some(DiagnosticCode::UnknownProtocol),
+            ]
+        );
+
+        let errors: Vec<&Diagnostic> = report.errors().collect();
+        let span = errors[0].span.unwrap();
+        assert_eq!(&source[span.start..span.end], "Z");
+        assert_eq!(
+            errors[1].message,
+            "Action 'go' is defined more than once in protocol 'Bad'"
+        );
+        let span = errors[1].span.unwrap();
+        assert!(span.start > source.find("Z -> B").unwrap());
+
+        // The full validation reports the new checks as well
+        let report = validate(&ast).unwrap();
+        assert!(
+            report
+                .errors()
+                .any(|diagnostic| diagnostic.code == Some(DiagnosticCode::UndeclaredRole))
+        );
+    }
+
+    #[test]
+    fn test_producers_of_same_role_exclude_each_other() {
+        let bmpp_source = r#"
+Purchase <Protocol>("buyer accepting or rejecting an offer") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+
+    parameters
+        ID <String> key ("offer"),
+        item <String>("offered item"),
+        resp <String>("response to the offer"),
+        done <String>("offer closed")
+
+    S -> B: offer <Action>("offer an item")[out ID, out item]
+    B -> S: accept <Action>("accept the offer")[in ID, in item, out resp]
+    B -> S: reject <Action>("reject the offer")[in ID, in item, out resp]
+    S -> B: close <Action>("close the offer")[in ID, in resp, out done]
+}
+"#;
+
+        // Sending one of them binds `resp`, which the other may only produce while unknown
+        let ast = parse_source(bmpp_source).unwrap();
+        let report = validate(&ast).unwrap();
+        assert!(!report.has_errors(), "{:#?}", report);
+
+        // The model checker, which applies the same rule, agrees
+        let verifications = verify(&ast, 64).unwrap();
+        assert!(verifications[0].violations.is_empty());
+    }
+
+    #[test]
+    fn test_producers_of_different_roles_need_disjoint_enabling_conditions() {
+        let protocol = |refund_input: &str| {
+            format!(
+                r#"
+Purchase <Protocol>("outcome decided by either party") {{
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+
+    parameters
+        ID <String> key ("order"),
+        accepted <String>("offer accepted"),
+        rejected <String>("offer rejected"),
+        outcome <String>("final outcome")
+
+    B -> S: accept <Action>("accept")[in ID, out accepted, nil rejected]
+    B -> S: reject <Action>("reject")[in ID, out rejected, nil accepted]
+    S -> B: ship <Action>("ship")[in ID, in accepted, out outcome]
+    B -> S: refund <Action>("refund")[in ID, in {}, out outcome]
+}}
+"#,
+                refund_input
+            )
+        };
+
+        // The buyer's `nil` guards exclude accept and reject, so ship and refund never both happen
+        let ast = parse_source(&protocol("rejected")).unwrap();
+        assert!(validate_parameter_flow(&ast).is_ok());
+
+        // Both can happen once the offer is accepted
+        let ast = parse_source(&protocol("accepted")).unwrap();
+        let error = validate_parameter_flow(&ast).unwrap_err();
+        let diagnostic = error.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(diagnostic.code, Some(DiagnosticCode::MultipleProducers));
+        assert!(diagnostic.message.contains("'ship' and 'refund'"));
+    }
+
     #[test]
     fn test_protocol_composition_validation() -> Result<()> {
         let bmpp_source = r#"
@@ -423,7 +692,7 @@ MainProtocol <Protocol>("protocol with composition") {
         result <String>("result")
     
     A -> B: start <Action>("start action")[out id]
-    SubProtocol <Enactment>[A, B, in id, out result]
+    SubProtocol <Enactment>[A, B, in id as input, out result as output]
 }
 
 SubProtocol <Protocol>("sub protocol") {
@@ -460,6 +729,46 @@ SubProtocol <Protocol>("sub protocol") {
         Ok(())
     }
 
+    #[test]
+    fn test_enactment_must_bind_callee_keys() {
+        let bmpp_source = r#"
+MainProtocol <Protocol>("protocol enacting a keyed sub protocol") {
+    roles
+        A <Agent>("agent A"),
+        B <Agent>("agent B")
+
+    parameters
+        id <String> key ("identifier"),
+        result <String>("result")
+
+    SubProtocol <Enactment>[A, B, out result]
+    A -> B: finish <Action>("use the result")[in id, in result]
+}
+
+SubProtocol <Protocol>("sub protocol keyed by request") {
+    roles
+        X <Agent>("agent X"),
+        Y <Agent>("agent Y")
+
+    parameters
+        request <String> key ("request identifier"),
+        result <String>("result")
+
+    X -> Y: process <Action>("process data")[in request, out result]
+}
+        "#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let result = validate_protocol_composition(&ast);
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("does not bind its key parameter 'request'")
+        );
+    }
+
     #[test]
     fn test_invalid_protocol_reference() {
         let bmpp_source = r#"
@@ -732,4 +1041,682 @@ LinearMultiParamProtocol <Protocol>("linear protocol with multiple parameters pe
         );
         Ok(())
     }
+
+    #[test]
+    fn test_validation_error_points_at_offending_flow() {
+        let bmpp_source = r#"
+SpanProtocol <Protocol>("protocol with an undeclared parameter") {
+    roles
+        A <Agent>("agent A"),
+        B <Agent>("agent B")
+
+    parameters
+        declared_param <String>("parameter that is declared")
+
+    A -> B: action1 <Action>("uses undeclared param")[out declared_param, in ghost]
+}
+        "#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let error = validate_parameter_flow(&ast).unwrap_err();
+        let diagnostic = error
+            .downcast_ref::<Diagnostic>()
+            .expect("validation errors should carry a span");
+
+        let span = diagnostic.span.unwrap();
+        assert_eq!(&bmpp_source[span.start..span.end], "ghost");
+
+        let rendered = diagnostic.render(bmpp_source, "span.bmpp");
+        assert!(rendered.contains("--> span.bmpp:10:78"), "{}", rendered);
+        assert!(rendered.contains("^^^^^ not declared"), "{}", rendered);
+    }
+
+    const TYPED_CALLEE: &str = r#"
+Ship <Protocol>("shipping sub-protocol") {
+    roles
+        W <Agent>("Warehouse"),
+        C <Agent>("Carrier")
+
+    parameters
+        ID <String> key ("shipment identifier"),
+        weight <Float>("parcel weight"),
+        label <String>("shipping label")
+
+    W -> C: book <Action>("book carrier")[in ID, in weight, out label]
+}
+"#;
+
+    #[test]
+    fn test_structured_types_are_valid() -> Result<()> {
+        let bmpp_source = r#"
+Shipping <Protocol>("shipping with structured data") {
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse")
+
+    types
+        Item <Record>("line item") {
+            sku <String>("stock keeping unit"),
+            parts <List<Item>>("sub-items of a bundle")
+        }
+
+    parameters
+        ID <String> key ("shipment identifier"),
+        items <List<Item>>("ordered items"),
+        status <Enum[pending, delivered]>("shipment status")
+
+    M -> W: ship <Action>("request shipment")[in ID, out items]
+    W -> M: track <Action>("report status")[in ID, in items, out status]
+}
+"#;
+
+        let ast = parse_source(bmpp_source)?;
+        validate_parameter_flow(&ast)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_type_is_rejected() {
+        let bmpp_source = r#"
+Typo <Protocol>("misspelled type") {
+    roles
+        A <Agent>("A"),
+        B <Agent>("B")
+
+    parameters
+        x <List<Strng>>("x")
+
+    A -> B: act <Action>("act")[out x]
+}
+"#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let error = validate_parameter_flow(&ast).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Unknown type 'Strng' for parameter 'x' in protocol 'Typo'"),
+            "{}",
+            error
+        );
+        let span = error.downcast_ref::<Diagnostic>().unwrap().span.unwrap();
+        assert_eq!(&bmpp_source[span.start..span.end], "Strng");
+    }
+
+    #[test]
+    fn test_enum_variants_must_be_distinct() {
+        let bmpp_source = r#"
+Status <Protocol>("repeated variant") {
+    roles
+        A <Agent>("A"),
+        B <Agent>("B")
+
+    parameters
+        status <Enum[open, closed, open]>("status")
+
+    A -> B: act <Action>("act")[out status]
+}
+"#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let error = validate_parameter_flow(&ast).unwrap_err();
+        assert!(
+            error.to_string().contains("repeats variant 'open'"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_record_cannot_contain_itself() {
+        let bmpp_source = r#"
+Nested <Protocol>("records embedding each other") {
+    roles
+        A <Agent>("A"),
+        B <Agent>("B")
+
+    types
+        Outer <Record>("outer") {
+            inner <Inner>("inner")
+        },
+        Inner <Record>("inner") {
+            outer <Outer>("outer")
+        }
+
+    parameters
+        x <Outer>("x")
+
+    A -> B: act <Action>("act")[out x]
+}
+"#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let error = validate_parameter_flow(&ast).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Record type 'Outer' contains itself by value (Outer -> Inner -> Outer)"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_enactment_parameter_types_must_match() {
+        let bmpp_source = format!(
+            r#"
+Order <Protocol>("order with shipping") {{
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse"),
+        C <Agent>("Carrier")
+
+    parameters
+        ID <String> key ("order identifier"),
+        weight <Int>("parcel weight"),
+        label <String>("shipping label")
+
+    M -> W: order <Action>("place order")[in ID, out weight]
+    Ship <Enactment>[W, C, in ID, in weight, out label]
+}}
+{}"#,
+            TYPED_CALLEE
+        );
+
+        let ast = parse_source(&bmpp_source).unwrap();
+        let error = validate_protocol_composition(&ast).unwrap_err();
+        assert!(
+            error.to_string().contains(
+                "Parameter 'weight' has type 'Int' in protocol 'Order' but type 'Float' in enacted protocol 'Ship'"
+            ),
+            "{}",
+            error
+        );
+
+        let fixed = bmpp_source.replace("weight <Int>", "weight <Float>");
+        assert!(validate_protocol_composition(&parse_source(&fixed).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_renamed_enactment_parameters_are_checked_against_callee() {
+        let bmpp_source = format!(
+            r#"
+Order <Protocol>("order with shipping") {{
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse"),
+        C <Agent>("Carrier")
+
+    parameters
+        order_id <String> key ("order identifier"),
+        mass <Int>("parcel weight"),
+        label <String>("shipping label")
+
+    M -> W: order <Action>("place order")[in order_id, out mass]
+    Ship <Enactment>[W, C, in order_id as ID, in mass as weight, out label]
+}}
+{}"#,
+            TYPED_CALLEE
+        );
+
+        let ast = parse_source(&bmpp_source).unwrap();
+        let error = validate_protocol_composition(&ast).unwrap_err();
+        assert!(
+            error.to_string().contains(
+                "Parameter 'mass' has type 'Int' in protocol 'Order' but type 'Float' in enacted protocol 'Ship' (bound as 'weight')"
+            ),
+            "{}",
+            error
+        );
+
+        let fixed = bmpp_source.replace("mass <Int>", "mass <Float>");
+        assert!(validate_protocol_composition(&parse_source(&fixed).unwrap()).is_ok());
+
+        let unbound_key = fixed.replace("in order_id as ID, ", "in order_id, ");
+        let error =
+            validate_protocol_composition(&parse_source(&unbound_key).unwrap()).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("does not bind its key parameter 'ID'"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_enactment_types_unify_structurally() {
+        let bmpp_source = r#"
+Order <Protocol>("order with shipping") {
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse"),
+        C <Agent>("Carrier")
+
+    types
+        Address <Record>("delivery address") {
+            street <String>("street"),
+            zip <Int>("postal code")
+        }
+
+    parameters
+        ID <String> key ("order identifier"),
+        address <Address>("delivery address"),
+        speed <Enum[express, standard]>("delivery speed"),
+        label <String>("shipping label")
+
+    M -> W: order <Action>("place order")[in ID, out address, out speed]
+    Ship <Enactment>[W, C, in ID, in address, in speed, out label]
+}
+
+Ship <Protocol>("shipping sub-protocol") {
+    roles
+        W <Agent>("Warehouse"),
+        C <Agent>("Carrier")
+
+    types
+        Address <Record>("delivery address") {
+            street <String>("street"),
+            zip <String>("postal code")
+        }
+
+    parameters
+        ID <String> key ("shipment identifier"),
+        address <Address>("delivery address"),
+        speed <Enum[standard, express]>("delivery speed"),
+        label <String>("shipping label")
+
+    W -> C: book <Action>("book carrier")[in ID, in address, in speed, out label]
+}
+"#;
+
+        // Enum variants unify in any order; the record differs in a field
+        let ast = parse_source(bmpp_source).unwrap();
+        let error = validate_protocol_composition(&ast).unwrap_err();
+        assert!(
+            error.to_string().contains(
+                "Parameter 'address' has type 'Address' in protocol 'Order' that does not match the one in enacted protocol 'Ship': field 'zip' of record 'Address' is 'Int' but 'String' in 'Ship'"
+            ),
+            "{}",
+            error
+        );
+        let notes: Vec<&str> = error
+            .downcast_ref::<Diagnostic>()
+            .unwrap()
+            .notes
+            .iter()
+            .map(|note| {
+                let span = note.span.unwrap();
+                &bmpp_source[span.start..span.end]
+            })
+            .collect();
+        assert_eq!(notes, ["Int", "String"]);
+
+        let fixed = bmpp_source.replace("zip <Int>", "zip <String>");
+        assert!(validate_protocol_composition(&parse_source(&fixed).unwrap()).is_ok());
+
+        let extra = fixed.replacen(
+            "zip <String>(\"postal code\")",
+            "zip <String>(\"postal code\"),\n            city <String>(\"city\")",
+            1,
+        );
+        let error = validate_protocol_composition(&parse_source(&extra).unwrap()).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("record 'Address' has no field 'city' in 'Ship'"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_mutually_recursive_enactments_are_reported_once() {
+        let protocol = |name: &str, enacted: &str| {
+            format!(
+                r#"
+{name} <Protocol>("part of a cycle") {{
+    roles
+        A <Agent>("A"),
+        B <Agent>("B")
+
+    parameters
+        ID <String> key ("identifier"),
+        step <String>("step")
+
+    A -> B: start <Action>("start")[in ID, out step]
+    {enacted} <Enactment>[A, B, in ID]
+}}
+"#
+            )
+        };
+        let bmpp_source = [
+            protocol("First", "Second"),
+            protocol("Second", "Third"),
+            protocol("Third", "First"),
+        ]
+        .concat();
+
+        let ast = parse_source(&bmpp_source).unwrap();
+        let report = validate(&ast).unwrap();
+        let cycles: Vec<&Diagnostic> = report
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| diagnostic.code == Some(DiagnosticCode::RecursiveEnactment))
+            .collect();
+        assert_eq!(cycles.len(), 1, "{:?}", report.diagnostics());
+        assert_eq!(
+            cycles[0].message,
+            "Protocol 'First' enacts itself through 'First' -> 'Second' -> 'Third' -> 'First' - recursive enactment not allowed"
+        );
+        let notes: Vec<&str> = cycles[0]
+            .notes
+            .iter()
+            .map(|note| note.message.as_str())
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                "'First' enacts 'Second' here",
+                "'Second' enacts 'Third' here"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_signature_inputs_are_provided_by_the_enactment() {
+        let bmpp_source = r#"
+Pack <Protocol>("packing") [in ID key, in order, out tag] {
+    roles
+        W <Agent>("Warehouse"),
+        P <Agent>("Packer")
+
+    parameters
+        ID <String> key ("identifier"),
+        order <String>("order to pack"),
+        tag <String>("package tag")
+
+    W -> P: pack <Action>("pack")[in ID, in order, out tag]
+}
+"#;
+
+        let report = validate(&parse_source(bmpp_source).unwrap()).unwrap();
+        assert_eq!(report.errors().count(), 0, "{:?}", report.diagnostics());
+
+        let private = bmpp_source.replace(" [in ID key, in order, out tag]", "");
+        let report = validate(&parse_source(&private).unwrap()).unwrap();
+        let codes: Vec<_> = report
+            .errors()
+            .filter_map(|diagnostic| diagnostic.code)
+            .collect();
+        assert_eq!(codes, [DiagnosticCode::UnproducedParameter]);
+    }
+
+    #[test]
+    fn test_report_collects_every_diagnostic() {
+        let bmpp_source = r#"
+Collect <Protocol>("several problems at once") {
+    roles
+        A <Agent>("A"),
+        B <Agent>("B")
+
+    parameters
+        ID <String> key ("identifier"),
+        order <Strng>("order"),
+        status <String>("status"),
+        spare <String>("never used")
+
+    A -> B: open <Action>("open")[in ID, out order, out status]
+    B -> A: close <Action>("close")[in ID, in ordr, in missing]
+}
+"#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let report = validate(&ast).unwrap();
+        let codes: Vec<DiagnosticCode> = report
+            .diagnostics()
+            .iter()
+            .filter_map(|diagnostic| diagnostic.code)
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                DiagnosticCode::UnknownType,
+                DiagnosticCode::UndeclaredParameter,
+                DiagnosticCode::UndeclaredParameter,
+                DiagnosticCode::DeadEndParameter,
+                DiagnosticCode::DeadEndParameter,
+                DiagnosticCode::UnusedParameter,
+            ]
+        );
+        assert_eq!(report.errors().count(), 3);
+        assert_eq!(report.warnings().count(), 3);
+
+        // Misspelled names come with a mechanical fix
+        let replacement = |index: usize| {
+            let suggestion = report.diagnostics()[index].suggestion.as_ref().unwrap();
+            suggestion
+                .replacement
+                .as_ref()
+                .map(|(_, text)| text.as_str())
+        };
+        assert_eq!(replacement(0), Some("String"));
+        assert_eq!(replacement(1), Some("order"));
+        assert_eq!(replacement(2), None);
+
+        // Warnings point at the parameter declaration
+        let unused = &report.diagnostics()[5];
+        let span = unused.span.unwrap();
+        assert_eq!(&bmpp_source[span.start..span.end], "spare");
+        assert!(
+            unused
+                .render(bmpp_source, "c.bmpp")
+                .starts_with("warning[BMPP0008]")
+        );
+
+        // The first-error entry points report the first error of the collected ones
+        let error = validate_parameter_flow(&ast).unwrap_err();
+        assert_eq!(error.downcast_ref::<Diagnostic>(), report.errors().next());
+    }
+
+    #[test]
+    fn test_warnings_do_not_fail_validation() {
+        let bmpp_source = r#"
+Quote <Protocol>("final outcome is never consumed") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+
+    parameters
+        ID <String> key ("request"),
+        price <Float>("quoted price")
+
+    B -> S: ask <Action>("ask")[in ID]
+    S -> B: quote <Action>("quote")[in ID, out price]
+}
+"#;
+
+        let ast = parse_source(bmpp_source).unwrap();
+        let report = validate(&ast).unwrap();
+        assert!(!report.has_errors());
+        let warning = report.warnings().next().unwrap();
+        assert_eq!(warning.code, Some(DiagnosticCode::DeadEndParameter));
+        assert!(
+            warning
+                .message
+                .contains("'price' is produced but never consumed")
+        );
+        assert!(report.into_result().is_ok());
+        assert!(validate_parameter_flow(&ast).is_ok());
+    }
+
+    #[test]
+    fn test_sender_must_learn_its_inputs() {
+        let protocol = |found_to: &str| {
+            format!(
+                r#"
+Load <Protocol>("loading a vehicle") {{
+    roles
+        W <Agent>("Warehouse"),
+        L <Agent>("Loader"),
+        S <Agent>("Scanner"),
+        C <Agent>("Courier")
+
+    parameters
+        ID <String> key ("loading"),
+        tag <String>("package tag"),
+        package <String>("located package"),
+        loaded <String>("loading done")
+
+    W -> S: FindPackage <Action>("find the package")[in ID, out tag]
+    S -> {}: FoundPackage <Action>("package found")[in ID, in tag, out package]
+    L -> C: LoadVehicle <Action>("load the vehicle")[in ID, in package, out loaded]
+}}
+"#,
+                found_to
+            )
+        };
+
+        let source = protocol("W");
+        let ast = parse_source(&source).unwrap();
+        let report = validate(&ast).unwrap();
+        let errors: Vec<&Diagnostic> = report.errors().collect();
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+
+        let error = errors[0];
+        assert_eq!(error.code, Some(DiagnosticCode::UninformedSender));
+        assert_eq!(
+            error.message,
+            "Role 'L' cannot send 'LoadVehicle' in protocol 'Load': it never learns 'package' - BSPL enactability violation"
+        );
+        let span = error.span.unwrap();
+        assert!(source[..span.start].ends_with("[in ID, in "));
+        // The note points at the message that carries the parameter to another role
+        let note = &error.notes[0];
+        assert_eq!(note.message, "'package' only reaches 'W' here");
+        assert!(source[..note.span.unwrap().start].ends_with("in tag, out "));
+        assert_eq!(
+            error.suggestion.as_ref().unwrap().message,
+            "'L' must first receive a message carrying 'package', e.g. from 'W' after 'FoundPackage'"
+        );
+
+        let ast = parse_source(&protocol("L")).unwrap();
+        assert!(validate_parameter_flow(&ast).is_ok());
+    }
+
+    #[test]
+    fn test_enactment_inputs_are_needed_by_the_callee_roles_consuming_them() {
+        let protocol = |roles: &str, consumer: &str| {
+            format!(
+                r#"
+Shop <Protocol>("shop delivering an order") {{
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller"),
+        C <Agent>("Courier")
+
+    parameters
+        ID <String> key ("order"),
+        addr <String>("delivery address"),
+        done <Bool>("delivered")
+
+    B -> S: order <Action>("place an order")[in ID, out addr]
+    Deliver <Enactment>[{}, in ID, in addr as dest, out done]
+}}
+
+Deliver <Protocol>("delivery") [in ID key, in dest, out done] {{
+    roles
+        Sender <Agent>("sender"),
+        Carrier <Agent>("carrier")
+
+    parameters
+        ID <String> key ("order"),
+        dest <String>("destination"),
+        done <Bool>("delivered")
+
+    {} -> {}: ship <Action>("ship the parcel")[in ID, in dest, out done]
+}}
+"#,
+                roles,
+                consumer,
+                if consumer == "Sender" {
+                    "Carrier"
+                } else {
+                    "Sender"
+                }
+            )
+        };
+
+        // The seller, bound to the sender of the message consuming 'dest', knows the address
+        // whatever the order of the bindings
+        for roles in ["C as Carrier, S as Sender", "S as Sender, C as Carrier"] {
+            let ast = parse_source(&protocol(roles, "Sender")).unwrap();
+            let report = validate(&ast).unwrap();
+            assert_eq!(report.errors().count(), 0, "{:#?}", report);
+        }
+
+        let ast = parse_source(&protocol("S as Sender, C as Carrier", "Carrier")).unwrap();
+        let report = validate(&ast).unwrap();
+        let errors: Vec<&Diagnostic> = report.errors().collect();
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert_eq!(
+            errors[0].message,
+            "Role 'C' cannot send 'Deliver' in protocol 'Shop': it never learns 'addr' - BSPL enactability violation"
+        );
+    }
+
+    #[test]
+    fn test_composition_errors_are_collected_with_notes() {
+        let bmpp_source = format!(
+            r#"
+Order <Protocol>("order with two broken enactments") {{
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse"),
+        C <Agent>("Carrier")
+
+    parameters
+        order_id <String> key ("order identifier"),
+        mass <Int>("parcel weight"),
+        label <String>("shipping label")
+
+    M -> W: order <Action>("place order")[in order_id, out mass]
+    Ship <Enactment>[W, C, in order_id as ID, in mass as weight, out label]
+    Shp <Enactment>[W, C, in order_id as ID]
+}}
+{}"#,
+            TYPED_CALLEE
+        );
+
+        let ast = parse_source(&bmpp_source).unwrap();
+        let report = validate(&ast).unwrap();
+        // The callee consumes parameters only its enactor provides, which the flow checks
+        // of the callee on its own report as well
+        let errors: Vec<&Diagnostic> = report
+            .errors()
+            .filter(|error| error.code != Some(DiagnosticCode::UnproducedParameter))
+            .collect();
+        assert_eq!(errors.len(), 2, "{:#?}", errors);
+
+        assert_eq!(
+            errors[0].code,
+            Some(DiagnosticCode::CompositionTypeMismatch)
+        );
+        let notes: Vec<&str> = errors[0]
+            .notes
+            .iter()
+            .map(|note| {
+                let span = note.span.unwrap();
+                &bmpp_source[span.start..span.end]
+            })
+            .collect();
+        assert_eq!(notes, vec!["Int", "Float"]);
+
+        assert_eq!(errors[1].code, Some(DiagnosticCode::UnknownProtocol));
+        assert!(
+            errors[1]
+                .render(&bmpp_source, "o.bmpp")
+                .contains("= help: did you mean `Ship`?"),
+        );
+    }
 }
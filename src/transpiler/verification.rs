@@ -0,0 +1,800 @@
+//! Bounded model checking of protocol enactments.
+//!
+//! Where validation looks at the precedence graph of a protocol, [`verify_protocol`] runs it:
+//! it enumerates every history of one enactment under BSPL information semantics, up to a
+//! number of steps. A step is a role sending a message or the recipients receiving it.
+//!
+//! - Each role only knows the parameters provided by the enactment (its keys and the `in`
+//!   parameters of its signature), the parameters it produced and those carried by the
+//!   messages it received.
+//! - A role may send a message once, when it knows every `in` parameter and none of the `out`
+//!   and `nil` ones. The message carries its `in` and `out` parameters, and its `opt` ones
+//!   when the sender knows them.
+//! - Messages are received in any order, at any time after they are sent.
+//!
+//! An enactment is complete once nothing is in transit and every produced parameter is
+//! bound, or can no longer be bound because each of its producers has been ruled out. Three
+//! kinds of violations are reported, each with the history that leads to it:
+//!
+//! - safety: a parameter bound by two messages, because their senders did not know about
+//!   each other's message
+//! - liveness: a history after which nothing can be sent although the enactment is not
+//!   complete
+//! - non-determinism: a message sent after a parameter its `nil` guard excludes was bound
+//!   elsewhere, so which of the two happens depends on message timing
+
+use crate::protocol::ast::{AstNode, ParameterDirection, Span};
+use crate::protocol::model::{InteractionItem, Program, Protocol};
+use crate::transpiler::diagnostics::{Diagnostic, DiagnosticCode};
+use crate::transpiler::validation::ValidationReport;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Number of steps explored when no bound is given
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    Safety,
+    Liveness,
+    Nondeterminism,
+}
+
+/// One step of a history
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// A role sends a message, or starts an enactment, carrying `parameters`
+    Send {
+        from: String,
+        to: Vec<String>,
+        action: String,
+        parameters: Vec<String>,
+    },
+    /// The recipients of a message receive it
+    Receive { to: Vec<String>, action: String },
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Step::Send {
+                from,
+                to,
+                action,
+                parameters,
+            } => write!(
+                f,
+                "{} -> {}: {} [{}]",
+                from,
+                to.join(", "),
+                action,
+                parameters.join(", ")
+            ),
+            Step::Receive { to, action } if to.len() == 1 => {
+                write!(f, "{} receives {}", to[0], action)
+            }
+            Step::Receive { to, action } => write!(f, "{} receive {}", to.join(", "), action),
+        }
+    }
+}
+
+/// A violation found while exploring, with the history that leads to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub trace: Vec<Step>,
+    /// The violation as a diagnostic, listing the history in its notes
+    pub diagnostic: Diagnostic,
+}
+
+/// Outcome of exploring the enactments of one protocol
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    pub protocol: String,
+    /// Distinct states reached
+    pub states: usize,
+    /// Whether every history ended within the depth bound
+    pub exhaustive: bool,
+    /// The shortest history found for each violation
+    pub violations: Vec<Violation>,
+}
+
+impl Verification {
+    pub fn report(&self) -> ValidationReport {
+        let mut report = ValidationReport::new();
+        for violation in &self.violations {
+            report.push(violation.diagnostic.clone());
+        }
+        report
+    }
+}
+
+/// Explore the enactments of every protocol of a program, `max_depth` steps deep
+pub fn verify(ast: &AstNode, max_depth: usize) -> Result<Vec<Verification>> {
+    let program = Program::from_ast(ast)?;
+    Ok(verify_program(&program, max_depth))
+}
+
+/// [`verify`] on an already built model
+pub fn verify_program(program: &Program, max_depth: usize) -> Vec<Verification> {
+    program
+        .protocols
+        .iter()
+        .map(|protocol| verify_protocol(protocol, max_depth))
+        .collect()
+}
+
+pub fn verify_protocol(protocol: &Protocol, max_depth: usize) -> Verification {
+    Model::new(protocol).explore(max_depth)
+}
+
+/// A message or enactment of the protocol, with roles and parameters numbered
+struct Transition<'a> {
+    item: &'a InteractionItem,
+    sender: usize,
+    receivers: Vec<usize>,
+    ins: Vec<usize>,
+    outs: Vec<usize>,
+    nils: Vec<usize>,
+    opts: Vec<usize>,
+}
+
+struct Model<'a> {
+    protocol: &'a Protocol,
+    roles: Vec<String>,
+    parameters: Vec<String>,
+    transitions: Vec<Transition<'a>>,
+    /// Transitions producing each parameter
+    producers: Vec<Vec<usize>>,
+    /// Keys nothing produces, which every role knows when the enactment starts
+    provided: BTreeSet<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct State {
+    /// Parameters each role knows
+    knowledge: Vec<BTreeSet<usize>>,
+    sent: BTreeSet<usize>,
+    /// Messages sent but not received yet, with the parameters they carry
+    in_transit: BTreeMap<usize, BTreeSet<usize>>,
+    /// Transition that first bound each parameter
+    bound: BTreeMap<usize, usize>,
+}
+
+/// A violation found in a state, before its history is known
+struct Finding {
+    kind: ViolationKind,
+    /// Identifies the violation, so that each one is reported for its shortest history only
+    key: String,
+    diagnostic: Diagnostic,
+}
+
+impl<'a> Model<'a> {
+    fn new(protocol: &'a Protocol) -> Self {
+        let mut roles: Vec<String> = protocol
+            .roles
+            .iter()
+            .map(|role| role.name.value.clone())
+            .collect();
+        let mut parameters: Vec<String> = protocol
+            .parameters
+            .iter()
+            .map(|param| param.name.value.clone())
+            .collect();
+
+        let mut transitions = Vec::new();
+        for item in protocol.interactions.iter() {
+            let item_roles = item.roles();
+            let sender = intern(&mut roles, item_roles.first().copied().unwrap_or("System"));
+            let receivers = item_roles
+                .iter()
+                .skip(1)
+                .map(|role| intern(&mut roles, role))
+                .filter(|role| *role != sender)
+                .collect();
+            let mut transition = Transition {
+                item,
+                sender,
+                receivers,
+                ins: Vec::new(),
+                outs: Vec::new(),
+                nils: Vec::new(),
+                opts: Vec::new(),
+            };
+            for flow in item.flows() {
+                let parameter = intern(&mut parameters, flow.parameter.as_str());
+                match flow.direction {
+                    ParameterDirection::In => transition.ins.push(parameter),
+                    ParameterDirection::Out => transition.outs.push(parameter),
+                    ParameterDirection::Nil => transition.nils.push(parameter),
+                    ParameterDirection::Opt => transition.opts.push(parameter),
+                }
+            }
+            transitions.push(transition);
+        }
+
+        let mut producers = vec![Vec::new(); parameters.len()];
+        for (index, transition) in transitions.iter().enumerate() {
+            for parameter in &transition.outs {
+                producers[*parameter].push(index);
+            }
+        }
+        let provided = (0..parameters.len())
+            .filter(|p| protocol.is_input(&parameters[*p]) && producers[*p].is_empty())
+            .collect();
+
+        Self {
+            protocol,
+            roles,
+            parameters,
+            transitions,
+            producers,
+            provided,
+        }
+    }
+
+    /// Breadth-first search of the states of an enactment, so that the first history found
+    /// for a violation is a shortest one
+    fn explore(&self, max_depth: usize) -> Verification {
+        let initial = State {
+            knowledge: vec![self.provided.clone(); self.roles.len()],
+            sent: BTreeSet::new(),
+            in_transit: BTreeMap::new(),
+            bound: BTreeMap::new(),
+        };
+        let mut states = vec![initial.clone()];
+        // The state each one was first reached from, with the step taken, and its depth
+        let mut parents: Vec<Option<(usize, Step)>> = vec![None];
+        let mut depths = vec![0];
+        let mut index = HashMap::from([(initial, 0)]);
+        let mut queue = VecDeque::from([0]);
+
+        let mut exhaustive = true;
+        let mut reported = HashSet::new();
+        let mut violations = Vec::new();
+        let mut record = |finding: Finding, trace: Vec<Step>| {
+            if reported.insert(finding.key) {
+                let diagnostic =
+                    trace
+                        .iter()
+                        .enumerate()
+                        .fold(finding.diagnostic, |diagnostic, (n, step)| {
+                            diagnostic.with_note(format!("step {}: {}", n + 1, step))
+                        });
+                violations.push(Violation {
+                    kind: finding.kind,
+                    trace,
+                    diagnostic,
+                });
+            }
+        };
+
+        while let Some(current) = queue.pop_front() {
+            let successors = self.successors(&states[current]);
+            if successors.is_empty() {
+                if let Some(finding) = self.stuck(&states[current]) {
+                    record(finding, trace(&parents, current));
+                }
+                continue;
+            }
+            if depths[current] == max_depth {
+                exhaustive = false;
+                continue;
+            }
+
+            for (step, next, findings) in successors {
+                if !findings.is_empty() {
+                    let mut history = trace(&parents, current);
+                    history.push(step.clone());
+                    for finding in findings {
+                        record(finding, history.clone());
+                    }
+                }
+                if index.contains_key(&next) {
+                    continue;
+                }
+                index.insert(next.clone(), states.len());
+                queue.push_back(states.len());
+                states.push(next);
+                parents.push(Some((current, step)));
+                depths.push(depths[current] + 1);
+            }
+        }
+
+        Verification {
+            protocol: self.protocol.name.value.clone(),
+            states: states.len(),
+            exhaustive,
+            violations,
+        }
+    }
+
+    /// Every step possible in `state`: sends in declaration order, then receptions
+    fn successors(&self, state: &State) -> Vec<(Step, State, Vec<Finding>)> {
+        let mut successors = Vec::new();
+        for (index, transition) in self.transitions.iter().enumerate() {
+            if self.enabled(state, index) {
+                successors.push(self.send(state, index, transition));
+            }
+        }
+        for (index, carried) in &state.in_transit {
+            let transition = &self.transitions[*index];
+            let mut next = state.clone();
+            next.in_transit.remove(index);
+            for receiver in &transition.receivers {
+                next.knowledge[*receiver].extend(carried);
+            }
+            let step = Step::Receive {
+                to: self.role_names(&transition.receivers),
+                action: transition.item.name().value.clone(),
+            };
+            successors.push((step, next, Vec::new()));
+        }
+        successors
+    }
+
+    fn enabled(&self, state: &State, index: usize) -> bool {
+        let transition = &self.transitions[index];
+        let known = &state.knowledge[transition.sender];
+        !state.sent.contains(&index)
+            && transition.ins.iter().all(|p| known.contains(p))
+            && !transition
+                .outs
+                .iter()
+                .chain(&transition.nils)
+                .any(|p| known.contains(p))
+    }
+
+    fn send(
+        &self,
+        state: &State,
+        index: usize,
+        transition: &Transition,
+    ) -> (Step, State, Vec<Finding>) {
+        let known = &state.knowledge[transition.sender];
+        let mut findings = Vec::new();
+        for parameter in &transition.outs {
+            if let Some(first) = state.bound.get(parameter) {
+                findings.push(self.unsafe_binding(*parameter, *first, index));
+            }
+        }
+        for parameter in &transition.nils {
+            if let Some(first) = state.bound.get(parameter) {
+                findings.push(self.stale_guard(*parameter, *first, index));
+            }
+        }
+
+        let mut carried: BTreeSet<usize> = transition
+            .ins
+            .iter()
+            .chain(&transition.outs)
+            .copied()
+            .collect();
+        carried.extend(transition.opts.iter().filter(|p| known.contains(p)));
+
+        let mut next = state.clone();
+        next.sent.insert(index);
+        next.knowledge[transition.sender].extend(&transition.outs);
+        for parameter in &transition.outs {
+            next.bound.entry(*parameter).or_insert(index);
+        }
+        if !transition.receivers.is_empty() {
+            next.in_transit.insert(index, carried.clone());
+        }
+
+        let step = Step::Send {
+            from: self.roles[transition.sender].clone(),
+            to: self.role_names(&transition.receivers),
+            action: transition.item.name().value.clone(),
+            parameters: carried
+                .iter()
+                .map(|p| self.parameters[*p].clone())
+                .collect(),
+        };
+        (step, next, findings)
+    }
+
+    /// Transitions that can never happen from `state` on: already sent, blocked by what
+    /// their sender knows, or waiting for a parameter nothing can bind anymore
+    fn ruled_out(&self, state: &State) -> Vec<bool> {
+        let mut ruled_out: Vec<bool> = self
+            .transitions
+            .iter()
+            .enumerate()
+            .map(|(index, transition)| {
+                let known = &state.knowledge[transition.sender];
+                state.sent.contains(&index)
+                    || transition
+                        .outs
+                        .iter()
+                        .chain(&transition.nils)
+                        .any(|p| known.contains(p))
+            })
+            .collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, transition) in self.transitions.iter().enumerate() {
+                if !ruled_out[index]
+                    && transition
+                        .ins
+                        .iter()
+                        .any(|p| !self.bindable(state, *p, &ruled_out))
+                {
+                    ruled_out[index] = true;
+                    changed = true;
+                }
+            }
+        }
+        ruled_out
+    }
+
+    fn bindable(&self, state: &State, parameter: usize, ruled_out: &[bool]) -> bool {
+        self.provided.contains(&parameter)
+            || state.bound.contains_key(&parameter)
+            || self.producers[parameter].iter().any(|t| !ruled_out[*t])
+    }
+
+    /// Liveness violation of a state nothing can happen in, if the enactment is not complete
+    fn stuck(&self, state: &State) -> Option<Finding> {
+        let ruled_out = self.ruled_out(state);
+        let missing: Vec<usize> = (0..self.parameters.len())
+            .filter(|p| {
+                !state.bound.contains_key(p) && self.producers[*p].iter().any(|t| !ruled_out[*t])
+            })
+            .collect();
+        let first = *missing.first()?;
+
+        let names: Vec<String> = missing
+            .iter()
+            .map(|p| format!("'{}'", self.parameters[*p]))
+            .collect();
+        let mut diagnostic = Diagnostic::new(
+            DiagnosticCode::StuckEnactment,
+            format!(
+                "An enactment of protocol '{}' can get stuck before {} {} bound - BSPL liveness violation",
+                self.protocol.name,
+                names.join(", "),
+                if names.len() == 1 { "is" } else { "are" }
+            ),
+        );
+
+        // Point at what keeps the first producer of the first missing parameter waiting
+        let producer = self.producers[first]
+            .iter()
+            .find(|t| !ruled_out[**t])
+            .map(|t| &self.transitions[*t]);
+        if let Some(transition) = producer {
+            let known = &state.knowledge[transition.sender];
+            let sender = &self.roles[transition.sender];
+            let waiting = transition.ins.iter().find(|p| !known.contains(p));
+            if let Some(parameter) = waiting {
+                let label = if state.bound.contains_key(parameter) {
+                    format!(
+                        "'{}' never learns '{}'",
+                        sender, self.parameters[*parameter]
+                    )
+                } else {
+                    format!("'{}' is never bound", self.parameters[*parameter])
+                };
+                let span = self.flow_span(transition, *parameter, ParameterDirection::In);
+                diagnostic = diagnostic.with_label(span, label);
+            }
+        }
+        if diagnostic.span.is_none() {
+            diagnostic = diagnostic.with_label(self.protocol.name.span, "cannot complete");
+        }
+
+        Some(Finding {
+            kind: ViolationKind::Liveness,
+            key: format!("liveness:{:?}", missing),
+            diagnostic,
+        })
+    }
+
+    fn unsafe_binding(&self, parameter: usize, first: usize, second: usize) -> Finding {
+        let (first, second) = (&self.transitions[first], &self.transitions[second]);
+        let name = &self.parameters[parameter];
+        let diagnostic = Diagnostic::new(
+            DiagnosticCode::UnsafeEnactment,
+            format!(
+                "Parameter '{}' can be bound twice in one enactment of protocol '{}', by '{}' and by '{}' - BSPL safety violation",
+                name,
+                self.protocol.name,
+                first.item.name(),
+                second.item.name()
+            ),
+        )
+        .with_label(
+            self.flow_span(second, parameter, ParameterDirection::Out),
+            format!(
+                "'{}' does not know '{}' is already bound",
+                self.roles[second.sender], name
+            ),
+        );
+        Finding {
+            kind: ViolationKind::Safety,
+            key: format!("safety:{}", parameter),
+            diagnostic,
+        }
+    }
+
+    fn stale_guard(&self, parameter: usize, first: usize, second: usize) -> Finding {
+        let (first, second) = (&self.transitions[first], &self.transitions[second]);
+        let name = &self.parameters[parameter];
+        let diagnostic = Diagnostic::new(
+            DiagnosticCode::NondeterministicEnactment,
+            format!(
+                "Interaction '{}' of protocol '{}' can be sent after '{}' has bound '{}' - which of the two happens depends on message timing",
+                second.item.name(),
+                self.protocol.name,
+                first.item.name(),
+                name
+            ),
+        )
+        .with_label(
+            self.flow_span(second, parameter, ParameterDirection::Nil),
+            format!(
+                "'{}' may not know '{}' yet",
+                self.roles[second.sender], name
+            ),
+        );
+        Finding {
+            kind: ViolationKind::Nondeterminism,
+            key: format!("nondeterminism:{}:{}", parameter, second.item.name()),
+            diagnostic,
+        }
+    }
+
+    /// Span of the flow adorning `parameter` with `direction` in `transition`
+    fn flow_span(
+        &self,
+        transition: &Transition,
+        parameter: usize,
+        direction: ParameterDirection,
+    ) -> Span {
+        transition
+            .item
+            .flows()
+            .into_iter()
+            .find(|flow| {
+                flow.direction == direction && flow.parameter.as_str() == self.parameters[parameter]
+            })
+            .map(|flow| flow.parameter.span)
+            .unwrap_or_else(|| transition.item.span())
+    }
+
+    fn role_names(&self, roles: &[usize]) -> Vec<String> {
+        roles.iter().map(|role| self.roles[*role].clone()).collect()
+    }
+}
+
+/// Index of `name` in `names`, adding it when missing
+fn intern(names: &mut Vec<String>, name: &str) -> usize {
+    match names.iter().position(|n| n == name) {
+        Some(index) => index,
+        None => {
+            names.push(name.to_string());
+            names.len() - 1
+        }
+    }
+}
+
+/// Steps leading from the initial state to `state`
+fn trace(parents: &[Option<(usize, Step)>], mut state: usize) -> Vec<Step> {
+    let mut steps = Vec::new();
+    while let Some((parent, step)) = &parents[state] {
+        steps.push(step.clone());
+        state = *parent;
+    }
+    steps.reverse();
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpiler::parse_source;
+
+    fn verify_source(source: &str, max_depth: usize) -> Verification {
+        let ast = parse_source(source).unwrap();
+        verify(&ast, max_depth).unwrap().remove(0)
+    }
+
+    fn kinds(verification: &Verification) -> Vec<ViolationKind> {
+        verification
+            .violations
+            .iter()
+            .map(|violation| violation.kind)
+            .collect()
+    }
+
+    const PURCHASE: &str = r#"
+Purchase <Protocol>("request and quote") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+
+    parameters
+        ID <String> key ("request"),
+        item <String>("item"),
+        price <Float>("price")
+
+    B -> S: request <Action>("request")[in ID, out item]
+    S -> B: quote <Action>("quote")[in ID, in item, out price]
+}
+"#;
+
+    #[test]
+    fn test_linear_protocol_has_no_violations() {
+        let verification = verify_source(PURCHASE, DEFAULT_MAX_DEPTH);
+        assert!(verification.violations.is_empty(), "{:#?}", verification);
+        assert!(verification.exhaustive);
+        // start, request sent, request received, quote sent, quote received
+        assert_eq!(verification.states, 5);
+
+        let truncated = verify_source(PURCHASE, 2);
+        assert!(!truncated.exhaustive);
+        assert!(truncated.violations.is_empty());
+    }
+
+    #[test]
+    fn test_safety_violation_with_counterexample() {
+        let verification = verify_source(
+            r#"
+Decide <Protocol>("either party decides") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+
+    parameters
+        ID <String> key ("order"),
+        outcome <String>("decision")
+
+    B -> S: accept <Action>("accept")[in ID, out outcome]
+    S -> B: cancel <Action>("cancel")[in ID, out outcome]
+}
+"#,
+            DEFAULT_MAX_DEPTH,
+        );
+        assert_eq!(kinds(&verification), vec![ViolationKind::Safety]);
+
+        let violation = &verification.violations[0];
+        let trace: Vec<String> = violation.trace.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            trace,
+            vec![
+                "B -> S: accept [ID, outcome]",
+                "S -> B: cancel [ID, outcome]"
+            ]
+        );
+        assert_eq!(
+            violation.diagnostic.message,
+            "Parameter 'outcome' can be bound twice in one enactment of protocol 'Decide', by 'accept' and by 'cancel' - BSPL safety violation"
+        );
+        assert_eq!(
+            violation.diagnostic.notes[1].message,
+            "step 2: S -> B: cancel [ID, outcome]"
+        );
+    }
+
+    #[test]
+    fn test_race_on_nil_guards_is_nondeterministic() {
+        let verification = verify_source(
+            r#"
+Offer <Protocol>("buyer accepts while seller withdraws") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+
+    parameters
+        ID <String> key ("offer"),
+        offer <String>("offer"),
+        accepted <String>("acceptance"),
+        withdrawn <String>("withdrawal")
+
+    S -> B: propose <Action>("propose")[in ID, out offer]
+    B -> S: accept <Action>("accept")[in ID, in offer, out accepted, nil withdrawn]
+    S -> B: withdraw <Action>("withdraw")[in ID, in offer, out withdrawn, nil accepted]
+}
+"#,
+            DEFAULT_MAX_DEPTH,
+        );
+        assert_eq!(
+            kinds(&verification),
+            vec![ViolationKind::Nondeterminism, ViolationKind::Nondeterminism]
+        );
+        let violation = &verification.violations[0];
+        assert_eq!(
+            violation.diagnostic.severity,
+            DiagnosticCode::NondeterministicEnactment.default_severity()
+        );
+        assert!(!verification.report().has_errors());
+        // The seller withdraws before the buyer, unaware of it, accepts
+        let trace: Vec<String> = violation.trace.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            trace,
+            vec![
+                "S -> B: propose [ID, offer]",
+                "S -> B: withdraw [ID, offer, withdrawn]",
+                "B receives propose",
+                "B -> S: accept [ID, offer, accepted]",
+            ]
+        );
+        assert_eq!(
+            violation.diagnostic.label.as_deref(),
+            Some("'B' may not know 'withdrawn' yet")
+        );
+        assert!(
+            verification.violations[1]
+                .diagnostic
+                .message
+                .starts_with("Interaction 'withdraw' of protocol 'Offer' can be sent after 'accept' has bound 'accepted'")
+        );
+    }
+
+    #[test]
+    fn test_liveness_violation_when_a_role_never_learns_a_parameter() {
+        let source = r#"
+Ship <Protocol>("the seller is never told about the payment") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller"),
+        P <Agent>("Payment provider")
+
+    parameters
+        ID <String> key ("order"),
+        paid <String>("payment"),
+        shipped <String>("shipment")
+
+    B -> P: pay <Action>("pay")[in ID, out paid]
+    S -> B: ship <Action>("ship")[in ID, in paid, out shipped]
+}
+"#;
+        let verification = verify_source(source, DEFAULT_MAX_DEPTH);
+        assert_eq!(kinds(&verification), vec![ViolationKind::Liveness]);
+
+        let violation = &verification.violations[0];
+        assert_eq!(
+            violation.diagnostic.message,
+            "An enactment of protocol 'Ship' can get stuck before 'shipped' is bound - BSPL liveness violation"
+        );
+        assert_eq!(
+            violation.diagnostic.label.as_deref(),
+            Some("'S' never learns 'paid'")
+        );
+        let span = violation.diagnostic.span.unwrap();
+        assert!(source[..span.start].ends_with("ship <Action>(\"ship\")[in ID, in "));
+        assert_eq!(violation.trace.len(), 2);
+    }
+
+    #[test]
+    fn test_excluded_branches_complete_the_enactment() {
+        let verification = verify_source(
+            r#"
+Purchase <Protocol>("buyer accepts or rejects") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+
+    parameters
+        ID <String> key ("order"),
+        accepted <String>("offer accepted"),
+        rejected <String>("offer rejected"),
+        shipped <String>("shipment"),
+        refunded <String>("refund")
+
+    B -> S: accept <Action>("accept")[in ID, out accepted, nil rejected]
+    B -> S: reject <Action>("reject")[in ID, out rejected, nil accepted]
+    S -> B: ship <Action>("ship")[in ID, in accepted, out shipped]
+    S -> B: refund <Action>("refund")[in ID, in rejected, out refunded]
+}
+"#,
+            DEFAULT_MAX_DEPTH,
+        );
+        assert!(verification.violations.is_empty(), "{:#?}", verification);
+        assert!(verification.exhaustive);
+    }
+}
@@ -168,32 +168,32 @@ SubProcess <Protocol>("Sub-process protocol") {
             if protocol.node_type == AstNodeType::Protocol {
                 let name = protocol.get_protocol_name().unwrap_or_default();
 
-                if name == "MainProtocol" {
-                    if let Some(interactions_section) = protocol.get_interactions_section() {
-                        let items = interactions_section.get_interaction_items();
+                if name == "MainProtocol"
+                    && let Some(interactions_section) = protocol.get_interactions_section()
+                {
+                    let items = interactions_section.get_interaction_items();
 
-                        assert_eq!(items.len(), 3);
+                    assert_eq!(items.len(), 3);
 
-                        let composition_item = &items[1];
-                        let composition = composition_item.get_protocol_composition();
-                        assert!(composition.is_some(), "Should find protocol composition");
+                    let composition_item = &items[1];
+                    let composition = composition_item.get_protocol_composition();
+                    assert!(composition.is_some(), "Should find protocol composition");
 
-                        let comp = composition.unwrap();
-                        let protocol_ref = comp.get_protocol_reference().unwrap();
-                        let ref_name = protocol_ref.get_identifier_name().unwrap();
-                        assert_eq!(ref_name, "SubProcess");
+                    let comp = composition.unwrap();
+                    let protocol_ref = comp.get_protocol_reference().unwrap();
+                    let ref_name = protocol_ref.get_identifier_name().unwrap();
+                    assert_eq!(ref_name, "SubProcess");
 
-                        let param_flows = comp.get_parameter_flows();
-                        assert_eq!(param_flows.len(), 2);
+                    let param_flows = comp.get_parameter_flows();
+                    assert_eq!(param_flows.len(), 2);
 
-                        let flows: Vec<_> = param_flows
-                            .iter()
-                            .filter_map(|pf| pf.get_parameter_flow_info())
-                            .collect();
+                    let flows: Vec<_> = param_flows
+                        .iter()
+                        .filter_map(|pf| pf.get_parameter_flow_info())
+                        .collect();
 
-                        assert!(flows.contains(&("in".to_string(), "input".to_string())));
-                        assert!(flows.contains(&("out".to_string(), "output".to_string())));
-                    }
+                    assert!(flows.contains(&("in".to_string(), "input".to_string())));
+                    assert!(flows.contains(&("out".to_string(), "output".to_string())));
                 }
             }
         }
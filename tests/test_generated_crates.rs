@@ -0,0 +1,308 @@
+use anyhow::Result;
+use bmpp_agents::transpiler::{codegen::BmppCodeGenerator, parser::parse_source};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+const CARGO_TOML: &str = r#"
+[package]
+name = "generated_protocol"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+serde_json = "1.0"
+anyhow = "1.0"
+"#;
+
+/// Build generated code as a crate of its own and run `tests` against it, returning
+/// whether it passed and what cargo reported
+fn cargo_test(lib: &str, tests: &str) -> Result<(bool, String)> {
+    let dir = tempdir()?;
+    fs::create_dir_all(dir.path().join("src"))?;
+    fs::create_dir_all(dir.path().join("tests"))?;
+    fs::write(dir.path().join("Cargo.toml"), CARGO_TOML)?;
+    fs::write(dir.path().join("src/lib.rs"), lib)?;
+    fs::write(dir.path().join("tests/generated.rs"), tests)?;
+
+    // The dependencies are those of this crate, so they are available offline. Generated
+    // code must not warn, e.g. about arguments it never uses.
+    let output = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .args(["test", "--offline", "--quiet"])
+        .current_dir(dir.path())
+        .env("RUSTFLAGS", "-D warnings")
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join("generated"),
+        )
+        .output()?;
+    Ok((
+        output.status.success(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    ))
+}
+
+#[test]
+fn test_transpiled_examples_build() -> Result<()> {
+    for example in [
+        include_str!("../examples/STARTER-PROTOCOL.bmpp"),
+        include_str!("../examples/STARTER-PROTOCOL_COMPOSED.bmpp"),
+    ] {
+        let generated_code = BmppCodeGenerator::new().generate(&parse_source(example)?)?;
+        let (success, stderr) = cargo_test(&generated_code, "")?;
+        assert!(success, "{}", stderr);
+    }
+
+    // Parameters named after keywords keep their name on the wire
+    let generated_code = BmppCodeGenerator::new().generate(&parse_source(include_str!(
+        "../examples/STARTER-PROTOCOL_COMPOSED.bmpp"
+    ))?)?;
+    let (success, stderr) = cargo_test(
+        &generated_code,
+        r#"
+use generated_protocol::*;
+
+#[test]
+fn keyword_fields_serialize_under_their_name() {
+    let message = SendVehicle {
+        id: "1".to_string(),
+        r#type: "van".to_string(),
+        vehicle: "V-1".to_string(),
+    };
+    let json = serde_json::to_value(LoadMessage::SendVehicle(message)).unwrap();
+    assert_eq!(json["type"], "van");
+    assert_eq!(json["action"], "SendVehicle");
+}
+"#,
+    )?;
+    assert!(success, "{}", stderr);
+
+    Ok(())
+}
+
+const SHOP: &str = r#"
+Shop <Protocol>("purchase with shipping") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+    parameters
+        ID <String> key ("purchase identifier"),
+        item <String>("requested item"),
+        speed <Enum[express, standard]>("delivery speed"),
+        price <Float>("quoted price"),
+        label <String>("shipping label")
+
+    B -> S: request <Action>("request an item")[in ID, out item, out speed]
+    S -> B: quote <Action>("quote a price")[in ID, in item, out price]
+    Ship <Enactment>[S, B, in ID, in speed, out label]
+}
+
+Ship <Protocol>("shipping") [in ID key, in speed, out label] {
+    roles
+        S <Agent>("Seller"),
+        B <Agent>("Buyer")
+    parameters
+        ID <String> key ("shipment identifier"),
+        speed <Enum[standard, express]>("delivery speed"),
+        label <String>("shipping label")
+
+    S -> B: ship <Action>("ship the item")[in ID, in speed, out label]
+}
+"#;
+
+#[test]
+fn test_generated_protocols_follow_the_enactment_history() -> Result<()> {
+    let generated_code = BmppCodeGenerator::new().generate(&parse_source(SHOP)?)?;
+    let (success, stderr) = cargo_test(
+        &generated_code,
+        r#"
+use generated_protocol::*;
+
+fn request(id: &str) -> Request {
+    Request { id: id.to_string(), item: "book".to_string(), speed: ShopSpeed::Express }
+}
+
+fn quote(id: &str) -> Quote {
+    Quote { id: id.to_string(), item: "book".to_string(), price: 9.5 }
+}
+
+#[test]
+fn keys_are_known_when_an_enactment_starts() {
+    let mut shop = ShopProtocol::new();
+    assert!(shop.request(request("1")).is_ok());
+    assert_eq!(
+        shop.request(request("1")),
+        Err(ProtocolError::Bound { action: "request", parameter: "item" })
+    );
+    assert_eq!(
+        shop.quote(quote("2")),
+        Err(ProtocolError::Unknown { action: "quote", parameter: "item" })
+    );
+    assert!(shop.quote(quote("1")).is_ok());
+}
+
+#[test]
+fn enactments_start_with_their_inputs_and_complete_with_their_outputs() {
+    let mut shop = ShopProtocol::new();
+    assert_eq!(
+        shop.enact_ship("1".to_string(), ShopSpeed::Express).err(),
+        Some(ProtocolError::Unknown { action: "enact_ship", parameter: "speed" })
+    );
+    shop.request(request("1")).unwrap();
+
+    let mut ship = shop.enact_ship("1".to_string(), ShopSpeed::Express).unwrap();
+    assert_eq!(ship.speed, ShipSpeed::Express);
+    assert_eq!(
+        ship.start_enactment("1".to_string(), ShipSpeed::Standard),
+        Err(ProtocolError::Bound { action: "start_enactment", parameter: "ID" })
+    );
+    let shipped = Ship { id: "1".to_string(), speed: ShipSpeed::Express, label: "L-1".to_string() };
+    assert!(ship.ship(shipped).is_ok());
+
+    // The label is only known to the purchase once the enactment reports it
+    assert_eq!(shop.label, "");
+    shop.complete_ship("1".to_string(), "L-1".to_string()).unwrap();
+    assert_eq!(shop.label, "L-1");
+    assert_eq!(
+        shop.complete_ship("1".to_string(), "L-2".to_string()),
+        Err(ProtocolError::Bound { action: "complete_ship", parameter: "label" })
+    );
+    assert_eq!(
+        shop.enact_ship("1".to_string(), ShopSpeed::Express).err(),
+        Some(ProtocolError::Bound { action: "enact_ship", parameter: "label" })
+    );
+}
+
+#[test]
+fn inputs_of_a_sub_protocol_are_known_without_starting_it() {
+    let mut ship = ShipProtocol::new();
+    let shipped = Ship { id: "2".to_string(), speed: ShipSpeed::Standard, label: "L-2".to_string() };
+    assert!(ship.ship(shipped).is_ok());
+}
+"#,
+    )?;
+    assert!(success, "{}", stderr);
+
+    Ok(())
+}
+
+const DECISION: &str = r#"
+Decision <Protocol>("accept or reject a proposal") {
+    roles
+        B <Agent>("Buyer"),
+        S <Agent>("Seller")
+    parameters
+        ID <String> key ("proposal identifier"),
+        outcome <String>("decision on the proposal"),
+        accepted <Bool>("whether the proposal was accepted"),
+        rejected <Bool>("whether the proposal was rejected"),
+        closed <Bool>("whether the proposal was closed")
+
+    B -> S: accept <Action>("accept the proposal")[in ID, out outcome, out accepted, nil rejected]
+    B -> S: reject <Action>("reject the proposal")[in ID, out outcome, out rejected, nil accepted]
+    S -> B: close <Action>("close the proposal")[in ID, in outcome, out closed]
+}
+"#;
+
+#[test]
+fn test_typestate_needs_any_producer_of_an_input() -> Result<()> {
+    let generated_code = BmppCodeGenerator::new()
+        .with_typestate(true)
+        .generate(&parse_source(DECISION)?)?;
+    let (success, stderr) = cargo_test(
+        &generated_code,
+        r#"
+use generated_protocol::*;
+
+fn close() -> Close {
+    Close { id: "1".to_string(), outcome: "done".to_string(), closed: true }
+}
+
+#[test]
+fn close_after_either_decision() {
+    let accept = Accept { id: "1".to_string(), outcome: "yes".to_string(), accepted: true };
+    let reject = Reject { id: "1".to_string(), outcome: "no".to_string(), rejected: true };
+    let _ = DecisionSState::new().receive_accept(&accept).send_close(close());
+    let _ = DecisionSState::new().receive_reject(&reject).send_close(close());
+}
+"#,
+    )?;
+    assert!(success, "{}", stderr);
+
+    Ok(())
+}
+
+#[test]
+fn test_typestate_rejects_sends_excluded_by_nil_guards() -> Result<()> {
+    let generated_code = BmppCodeGenerator::new()
+        .with_typestate(true)
+        .generate(&parse_source(DECISION)?)?;
+    let (success, stderr) = cargo_test(
+        &generated_code,
+        r#"
+use generated_protocol::*;
+
+#[test]
+fn accept_after_reject() {
+    let accept = Accept { id: "1".to_string(), outcome: "yes".to_string(), accepted: true };
+    let reject = Reject { id: "1".to_string(), outcome: "no".to_string(), rejected: true };
+    let (buyer, _) = DecisionBState::new().send_reject(reject);
+    let _ = buyer.send_accept(accept);
+}
+"#,
+    )?;
+    assert!(!success);
+    assert!(
+        stderr.contains("no method named `send_accept` found"),
+        "{}",
+        stderr
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_typestate_orders_enactments_by_their_inputs() -> Result<()> {
+    let generated_code = BmppCodeGenerator::new()
+        .with_typestate(true)
+        .generate(&parse_source(include_str!(
+            "../examples/STARTER-PROTOCOL_COMPOSED.bmpp"
+        ))?)?;
+    let warehouse = |events: &str| {
+        format!(
+            r#"
+use generated_protocol::*;
+
+#[test]
+fn deliver() {{
+    let order = NotifyOrder {{ id: "1".to_string(), order: "o".to_string() }};
+    let deliver = Deliver {{
+        id: "1".to_string(),
+        route: "r".to_string(),
+        delivery: "d".to_string(),
+    }};
+    let _ = LogisticsWState::new()
+        .receive_notify_order(&order){}
+        .send_deliver(deliver);
+}}
+"#,
+            events
+        )
+    };
+
+    let (success, stderr) = cargo_test(&generated_code, &warehouse(".enact_pack().enact_load()"))?;
+    assert!(success, "{}", stderr);
+
+    // Load needs the tag, which only Pack produces
+    let (success, stderr) = cargo_test(&generated_code, &warehouse(".enact_load()"))?;
+    assert!(!success);
+    assert!(
+        stderr.contains("no method named `enact_load` found"),
+        "{}",
+        stderr
+    );
+
+    Ok(())
+}
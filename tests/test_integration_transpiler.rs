@@ -40,15 +40,14 @@ SimpleExchange <Protocol>("a basic data exchange protocol for testing") {
     fs::write(&lib_rs_path, &generated_code)?;
 
     // Write main binary file (for executable projects)
-    let main_rs_content = format!(
-        r#"
+    let main_rs_content = r#"
 // Generated main file for SimpleExchange protocol
 use anyhow::Result;
 
 mod lib;
 use lib::SimpleExchangeProtocol;
 
-fn main() -> Result<()> {{
+fn main() -> Result<()> {
     let mut protocol = SimpleExchangeProtocol::new();
     
     println!("Initializing SimpleExchange protocol...");
@@ -60,9 +59,9 @@ fn main() -> Result<()> {{
     println!("Protocol execution completed successfully!");
     
     Ok(())
-}}
+}
 "#
-    );
+    .to_string();
 
     let main_rs_path = src_dir.join("main.rs");
     fs::write(&main_rs_path, &main_rs_content)?;
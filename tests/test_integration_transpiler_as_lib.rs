@@ -206,3 +206,233 @@ Auction <Protocol>("a three-party auction protocol") {
 
     Ok(())
 }
+
+const OFFER: &str = r#"
+Offer <Protocol>("offer and acceptance") {
+    roles
+        Seller <Agent>("the party making the offer"),
+        Buyer <Agent>("the party considering it")
+
+    parameters
+        ID <String> key ("offer identifier"),
+        price <Float>("offered price"),
+        note <String>("remark from the buyer"),
+        accepted <Bool>("whether the offer was accepted")
+
+    Seller -> Buyer: MakeOffer <Action>("propose a price")[out ID, out price]
+    Buyer -> Seller: AcceptOffer <Action>("accept the price")[in ID, in price, opt note, out accepted]
+}
+"#;
+
+#[test]
+fn test_role_traits_emit_sends_and_handle_receptions() -> Result<()> {
+    let ast = parse_source(OFFER)?;
+    let generated_code = BmppCodeGenerator::new().generate(&ast)?;
+
+    assert!(
+        generated_code.contains("/// the party making the offer\npub trait OfferSellerAgent {")
+    );
+    assert!(generated_code.contains(
+        "    /// propose a price (to Buyer)\n    fn emit_make_offer(&mut self) -> Result<MakeOffer>;"
+    ));
+    assert!(generated_code.contains(
+        "    /// accept the price (from Buyer)\n    fn on_accept_offer(&mut self, message: AcceptOffer) -> Result<()>;"
+    ));
+    assert!(generated_code.contains("pub trait OfferBuyerAgent {"));
+    assert!(generated_code.contains(
+        "    fn emit_accept_offer(&mut self, id: String, price: f64, note: Option<String>) -> Result<AcceptOffer>;"
+    ));
+    assert!(
+        generated_code
+            .contains("    fn on_make_offer(&mut self, message: MakeOffer) -> Result<()>;")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_keyword_parameters_are_escaped() -> Result<()> {
+    let bmpp_source = r#"
+Load <Protocol>("loading") {
+    roles
+        W <Agent>("Warehouse"),
+        C <Agent>("Courier")
+
+    parameters
+        ID <String> key ("loading identifier"),
+        type <String>("vehicle type"),
+        self <String>("courier reference"),
+        vehicle <String>("vehicle")
+
+    W -> C: SendVehicle <Action>("send a vehicle")[in ID, in type, opt self, out vehicle]
+}
+"#;
+    let ast = parse_source(bmpp_source)?;
+    let generated_code = BmppCodeGenerator::new().generate(&ast)?;
+
+    assert!(generated_code.contains(
+        "    fn emit_send_vehicle(&mut self, id: String, r#type: String, self_: Option<String>) -> Result<SendVehicle>;"
+    ));
+    assert!(generated_code.contains(
+        "    /// vehicle type\n    pub r#type: String,\n    /// courier reference\n    #[serde(rename = \"self\")]\n    pub self_: Option<String>,"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_message_struct_per_action() -> Result<()> {
+    let ast = parse_source(OFFER)?;
+    let generated_code = BmppCodeGenerator::new().generate(&ast)?;
+
+    assert!(generated_code.contains(
+        "/// accept the price\n#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\npub struct AcceptOffer {\n    /// offer identifier\n    pub id: String,\n    /// offered price\n    pub price: f64,\n    /// remark from the buyer\n    pub note: Option<String>,\n    /// whether the offer was accepted\n    pub accepted: bool,\n}"
+    ));
+    assert!(generated_code.contains(
+        "#[serde(tag = \"action\")]\npub enum OfferMessage {\n    MakeOffer(MakeOffer),\n    AcceptOffer(AcceptOffer),\n}"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_messages_are_guarded_by_the_enactment_history() -> Result<()> {
+    let ast = parse_source(OFFER)?;
+    let generated_code = BmppCodeGenerator::new().generate(&ast)?;
+
+    assert!(generated_code.contains("pub enum ProtocolError {"));
+    assert!(generated_code.contains("    pub history: HashMap<String, HashSet<String>>,"));
+    assert!(generated_code.contains(
+        "    pub fn acceptoffer(&mut self, message: AcceptOffer) -> Result<OfferMessage, ProtocolError> {"
+    ));
+    assert!(generated_code.contains(
+        "        let key = serde_json::to_string(&(&message.id,)).unwrap_or_default();\n        self.bind(\"AcceptOffer\", key, &[\"ID\", \"price\"], &[\"accepted\"], &[])?;\n        Ok(OfferMessage::AcceptOffer(message))"
+    ));
+    assert!(generated_code.contains("    pub fn receive(&mut self, message: &OfferMessage) {"));
+    assert!(generated_code.contains(
+        "            OfferMessage::AcceptOffer(message) => (serde_json::to_string(&(&message.id,)).unwrap_or_default(), &[\"ID\", \"price\", \"accepted\"]),"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_typestate_requires_preceding_messages_before_a_send() -> Result<()> {
+    let ast = parse_source(OFFER)?;
+    let default_code = BmppCodeGenerator::new().generate(&ast)?;
+    assert!(!default_code.contains("pub struct Pending;"));
+    assert!(!default_code.contains("OfferBuyerState"));
+
+    let generated_code = BmppCodeGenerator::new()
+        .with_typestate(true)
+        .generate(&ast)?;
+    assert!(generated_code.contains("pub struct Pending;"));
+    assert!(generated_code.contains("pub struct Done;"));
+    assert!(generated_code.contains("pub struct OfferBuyerState<E0 = Pending, E1 = Pending> {"));
+    assert!(generated_code.contains(
+        "impl<E1> OfferBuyerState<Pending, E1> {\n    /// propose a price\n    pub fn receive_make_offer(self, _message: &MakeOffer) -> OfferBuyerState<Done, E1> {"
+    ));
+    assert!(generated_code.contains(
+        "impl OfferBuyerState<Done, Pending> {\n    /// accept the price\n    pub fn send_accept_offer(self, message: AcceptOffer) -> (OfferBuyerState<Done, Done>, OfferMessage) {"
+    ));
+    // Receiving the acceptance would tell the seller the price the offer binds
+    assert!(generated_code.contains(
+        "impl OfferSellerState<Pending, Pending> {\n    /// propose a price\n    pub fn send_make_offer(self, message: MakeOffer) -> (OfferSellerState<Done, Pending>, OfferMessage) {"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_message_names_do_not_collide() -> Result<()> {
+    let bmpp_source = r#"
+Shop <Protocol>("shop") {
+    roles
+        A <Agent>("A"),
+        B <Agent>("B")
+    types
+        Order <Record>("order details") {
+            item <String>("item")
+        }
+    parameters
+        ID <String> key ("identifier"),
+        details <Order>("details")
+    A -> B: order <Action>("order")[out ID, out details]
+}
+"#;
+
+    let generated_code = BmppCodeGenerator::new().generate(&parse_source(bmpp_source)?)?;
+
+    assert!(generated_code.contains("pub struct Order {"));
+    assert!(generated_code.contains("pub struct ShopOrder {"));
+    assert!(generated_code.contains("    #[serde(rename = \"order\")]\n    ShopOrder(ShopOrder),"));
+
+    Ok(())
+}
+
+#[test]
+fn test_structured_type_generation() -> Result<()> {
+    let bmpp_source = r#"
+Shipping <Protocol>("shipping with structured data") {
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse")
+
+    types
+        Address <Record>("postal address") {
+            street <String>("street and number"),
+            kind <Enum[home, office]>("kind of address")
+        }
+
+    parameters
+        ID <String> key ("shipment identifier"),
+        destination <Address>("where to deliver"),
+        weights <List<Float>>("parcel weights"),
+        status <Enum[pending, in_transit]>("shipment status")
+
+    M -> W: ship <Action>("request shipment")[in ID, out destination, out weights]
+    W -> M: track <Action>("report status")[in ID, in destination, out status]
+}
+"#;
+
+    let ast = parse_source(bmpp_source)?;
+    let generated_code = BmppCodeGenerator::new().generate(&ast)?;
+
+    assert!(generated_code.contains("pub struct Address {"));
+    assert!(generated_code.contains("pub kind: AddressKind,"));
+    assert!(generated_code.contains("pub enum AddressKind {"));
+    assert!(generated_code.contains("pub enum ShippingStatus {"));
+    assert!(generated_code.contains("#[serde(rename = \"in_transit\")]\n    InTransit,"));
+    assert!(generated_code.contains("    #[default]\n    #[serde(rename = \"pending\")]"));
+    assert!(generated_code.contains("pub destination: Address,"));
+    assert!(generated_code.contains("pub weights: Vec<f64>,"));
+    assert!(generated_code.contains("status: ShippingStatus,"));
+    assert!(generated_code.contains("destination: Default::default(),"));
+    assert!(generated_code.contains(
+        "pub fn ship(&mut self, message: Ship) -> Result<ShippingMessage, ProtocolError> {"
+    ));
+    assert_eq!(generated_code.matches("pub struct Address {").count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_unknown_type_is_a_codegen_error() {
+    let bmpp_source = r#"
+Typo <Protocol>("misspelled type") {
+    roles
+        A <Agent>("A")
+    parameters
+        x <Strng>("x")
+    A -> A: act <Action>("act")[out x]
+}
+"#;
+
+    let ast = parse_source(bmpp_source).unwrap();
+    let error = BmppCodeGenerator::new().generate(&ast).unwrap_err();
+    assert!(
+        error.to_string().contains("Unknown type 'Strng'"),
+        "{}",
+        error
+    );
+}
@@ -1,4 +1,7 @@
 use anyhow::Result;
+use bmpp_agents::protocol::model::Program;
+use bmpp_agents::transpiler::composition::ProtocolRegistry;
+use bmpp_agents::transpiler::projection::project;
 use bmpp_agents::transpiler::{codegen::BmppCodeGenerator, parser::parse_source};
 
 #[test]
@@ -121,3 +124,118 @@ fn test_nested_protocol_composition() -> Result<()> {
 
     Ok(())
 }
+
+const SHIPPING: &str = r#"
+Order <Protocol>("order with shipping") {
+    roles
+        M <Agent>("Merchant"),
+        W <Agent>("Warehouse"),
+        C <Agent>("Carrier")
+    parameters
+        ID <String> key ("order identifier"),
+        weight <Float>("parcel weight"),
+        speed <Enum[express, standard]>("delivery speed"),
+        label <String>("shipping label")
+
+    M -> W: order <Action>("place order")[in ID, out weight, out speed]
+    Ship <Enactment>[W, C, in ID, in weight, in speed, out label]
+}
+
+Ship <Protocol>("shipping sub-protocol") {
+    roles
+        W <Agent>("Warehouse"),
+        C <Agent>("Carrier")
+    parameters
+        ID <String> key ("shipment identifier"),
+        weight <Float>("parcel weight"),
+        speed <Enum[standard, express]>("delivery speed"),
+        label <String>("shipping label")
+
+    W -> C: book <Action>("book carrier")[in ID, in weight, in speed, out label]
+}
+"#;
+
+#[test]
+fn test_composition_method_uses_unified_types() -> Result<()> {
+    let ast = parse_source(SHIPPING)?;
+    let generated_code = BmppCodeGenerator::new().generate(&ast)?;
+
+    assert!(
+        generated_code.contains(
+            "pub fn enact_ship(&mut self, id: String, weight: f64, speed: OrderSpeed) -> Result<ShipProtocol, ProtocolError> {"
+        ),
+        "{}",
+        generated_code
+    );
+    assert!(generated_code.contains(
+        "pub fn complete_ship(&mut self, id: String, label: String) -> Result<(), ProtocolError> {"
+    ));
+    // Values the enacted protocol does not take as inputs are handed over as its fields
+    assert!(generated_code.contains(
+        "        enactment.start_enactment(id)?;\n        enactment.weight = weight;\n        enactment.speed = serde_json::to_value(&speed).and_then(serde_json::from_value).unwrap_or_default();\n        Ok(enactment)\n"
+    ));
+    assert!(!generated_code.contains("println!"));
+
+    Ok(())
+}
+
+#[test]
+fn test_role_traits_require_the_enacted_roles() -> Result<()> {
+    let ast = parse_source(SHIPPING)?;
+    let generated_code = BmppCodeGenerator::new().generate(&ast)?;
+
+    assert!(generated_code.contains("pub trait OrderMAgent {"));
+    assert!(generated_code.contains("pub trait OrderWAgent: ShipWAgent {"));
+    assert!(generated_code.contains("pub trait OrderCAgent: ShipCAgent {}"));
+    assert!(generated_code.contains(
+        "    fn emit_book(&mut self, id: String, weight: f64, speed: ShipSpeed) -> Result<Book>;"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_composition_method_rejects_mismatched_types() {
+    let mismatched = SHIPPING.replacen("weight <Float>", "weight <Int>", 1);
+    let ast = parse_source(&mismatched).unwrap();
+    let error = BmppCodeGenerator::new().generate(&ast).unwrap_err();
+
+    assert!(
+        error.to_string().contains(
+            "Parameter 'weight' has type 'Int' in protocol 'Order' but type 'Float' in enacted protocol 'Ship'"
+        ),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_projection_generates_code_for_one_role() -> Result<()> {
+    let ast = parse_source(SHIPPING)?;
+    let registry = ProtocolRegistry::from_program(&ast)?;
+    let carrier = project(&registry, "Order", "C")?;
+
+    let generated_code = BmppCodeGenerator::new()
+        .with_role("C")
+        .with_typestate(true)
+        .generate_program(&Program {
+            imports: Vec::new(),
+            protocols: vec![carrier],
+            span: ast.span,
+        })?;
+
+    assert!(
+        generated_code.contains("pub fn book(&mut self"),
+        "{}",
+        generated_code
+    );
+    assert!(!generated_code.contains("pub fn order(&mut self"));
+    assert!(!generated_code.contains("enact_ship"));
+    // Peers of the role keep their messages but not their traits or typestates
+    assert!(generated_code.contains("pub trait OrderCAgent {"));
+    assert!(generated_code.contains("pub struct OrderCState<"));
+    assert!(!generated_code.contains("OrderWAgent"));
+    assert!(!generated_code.contains("OrderWState"));
+
+    Ok(())
+}